@@ -0,0 +1,295 @@
+use crate::client::TrafficClient;
+
+use anyhow::Result;
+use clap::{Args, Subcommand};
+use safe_traffic_common::transport::AlertSignals;
+use std::fmt::Write;
+
+/// 动作生效耗时告警的默认阈值，毫秒
+const DEFAULT_LATENCY_THRESHOLD_MS: u64 = 1000;
+
+/// 告警参数
+#[derive(Args, Debug)]
+pub struct AlertsArgs {
+    #[command(subcommand)]
+    pub command: AlertsCommand,
+}
+
+/// 告警子命令
+#[derive(Subcommand, Debug)]
+pub enum AlertsCommand {
+    /// Evaluate the prebuilt alerts against the running daemon
+    Preview {
+        /// Fire the latency alert when the p99 enforcement latency exceeds this many milliseconds
+        #[arg(long, default_value_t = DEFAULT_LATENCY_THRESHOLD_MS)]
+        latency_threshold_ms: u64,
+    },
+    /// Print the alert signals in Prometheus text format (e.g. for the node_exporter textfile collector)
+    Metrics,
+    /// Print Prometheus alerting rules for the exported signals (does not contact the daemon)
+    Rules {
+        /// Fire the latency alert when the p99 enforcement latency exceeds this many milliseconds
+        #[arg(long, default_value_t = DEFAULT_LATENCY_THRESHOLD_MS)]
+        latency_threshold_ms: u64,
+    },
+}
+
+/// 预置告警的定义
+struct Definition {
+    name: &'static str,
+    summary: &'static str,
+    severity: &'static str,
+    /// 持续多久才告警，Prometheus 时长格式
+    hold: &'static str,
+}
+
+const ATTACK_IN_PROGRESS: Definition = Definition {
+    name: "SafeTrafficAttackInProgress",
+    summary: "an incident is open: the daemon is acting on a burst of sources",
+    severity: "warning",
+    hold: "0m",
+};
+
+const SLOW_ENFORCEMENT: Definition = Definition {
+    name: "SafeTrafficSlowEnforcement",
+    summary: "p99 time from detection to an installed nft rule is above the threshold",
+    severity: "warning",
+    hold: "5m",
+};
+
+const RULE_DIVERGENCE: Definition = Definition {
+    name: "SafeTrafficRuleDivergence",
+    summary: "rules tracked by the daemon are missing from the nftables chain",
+    severity: "critical",
+    hold: "2m",
+};
+
+const MANAGEMENT_LOCKOUT: Definition = Definition {
+    name: "SafeTrafficManagementLockout",
+    summary: "sources are locked out of the management API after repeated authentication failures",
+    severity: "warning",
+    hold: "0m",
+};
+
+/// 一条预置告警的本地评估结果
+pub struct Alert {
+    pub name: &'static str,
+    pub firing: bool,
+    pub value: String,
+    pub summary: &'static str,
+}
+
+/// 按预置告警评估派生指标
+pub fn evaluate(signals: &AlertSignals, latency_threshold_ms: u64) -> Vec<Alert> {
+    let alert = |definition: &Definition, firing: bool, value: String| Alert {
+        name: definition.name,
+        firing,
+        value,
+        summary: definition.summary,
+    };
+    vec![
+        alert(
+            &ATTACK_IN_PROGRESS,
+            signals.attack_in_progress,
+            signals.attack_in_progress.to_string(),
+        ),
+        alert(
+            &SLOW_ENFORCEMENT,
+            signals
+                .enforcement_latency_p99_ms
+                .is_some_and(|ms| ms > latency_threshold_ms),
+            signals
+                .enforcement_latency_p99_ms
+                .map(|ms| format!("{}ms", ms))
+                .unwrap_or_else(|| "no actions yet".to_string()),
+        ),
+        alert(
+            &RULE_DIVERGENCE,
+            signals.divergence_detected(),
+            format!("{} missing rules", signals.divergent_rules),
+        ),
+        alert(
+            &MANAGEMENT_LOCKOUT,
+            signals.management_locked_out > 0,
+            format!(
+                "{} locked, {} failures",
+                signals.management_locked_out, signals.management_auth_failures
+            ),
+        ),
+    ]
+}
+
+/// 以 Prometheus 文本格式输出派生指标
+pub fn metrics(signals: &AlertSignals) -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: String| {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        let _ = writeln!(out, "{} {}", name, value);
+    };
+    let mut gauge = |name: &str, help: &str, value: String| metric(name, "gauge", help, value);
+    gauge(
+        "safe_traffic_attack_in_progress",
+        "1 while an incident is open",
+        (signals.attack_in_progress as u8).to_string(),
+    );
+    if let Some(ms) = signals.enforcement_latency_p99_ms {
+        gauge(
+            "safe_traffic_enforcement_latency_p99_seconds",
+            "p99 time from detection to an installed nft rule over recent actions",
+            (ms as f64 / 1000.0).to_string(),
+        );
+    }
+    gauge(
+        "safe_traffic_divergence_detected",
+        "1 when rules tracked by the daemon are missing from nftables",
+        (signals.divergence_detected() as u8).to_string(),
+    );
+    gauge(
+        "safe_traffic_divergent_rules",
+        "rules tracked by the daemon that are missing from nftables",
+        signals.divergent_rules.to_string(),
+    );
+    gauge(
+        "safe_traffic_management_locked_out",
+        "sources currently locked out of the management API",
+        signals.management_locked_out.to_string(),
+    );
+    metric(
+        "safe_traffic_management_auth_failures_total",
+        "counter",
+        "failed authentications on the management API since the daemon started",
+        signals.management_auth_failures.to_string(),
+    );
+    metric(
+        "safe_traffic_management_lockouts_total",
+        "counter",
+        "sources locked out of the management API since the daemon started",
+        signals.management_lockouts.to_string(),
+    );
+    metric(
+        "safe_traffic_dropped_bytes_total",
+        "counter",
+        "bytes dropped by the daemon's rules, kept across restarts",
+        signals.dropped_bytes.to_string(),
+    );
+    metric(
+        "safe_traffic_dropped_packets_total",
+        "counter",
+        "packets dropped by the daemon's rules, kept across restarts",
+        signals.dropped_packets.to_string(),
+    );
+    metric(
+        "safe_traffic_dropped_bytes_today",
+        "gauge",
+        "bytes dropped by the daemon's rules so far today (UTC)",
+        signals.dropped_bytes_today.to_string(),
+    );
+    out
+}
+
+/// Prometheus 告警规则，表达式与 metrics 输出的指标对应
+pub fn rules(latency_threshold_ms: u64) -> String {
+    let mut out = String::from("groups:\n  - name: safe-traffic\n    rules:\n");
+    for (definition, expr) in [
+        (
+            &ATTACK_IN_PROGRESS,
+            "safe_traffic_attack_in_progress == 1".to_string(),
+        ),
+        (
+            &SLOW_ENFORCEMENT,
+            format!(
+                "safe_traffic_enforcement_latency_p99_seconds > {}",
+                latency_threshold_ms as f64 / 1000.0
+            ),
+        ),
+        (
+            &RULE_DIVERGENCE,
+            "safe_traffic_divergence_detected == 1".to_string(),
+        ),
+        (
+            &MANAGEMENT_LOCKOUT,
+            "safe_traffic_management_locked_out > 0".to_string(),
+        ),
+    ] {
+        let _ = writeln!(out, "      - alert: {}", definition.name);
+        let _ = writeln!(out, "        expr: {}", expr);
+        let _ = writeln!(out, "        for: {}", definition.hold);
+        let _ = writeln!(out, "        labels:");
+        let _ = writeln!(out, "          severity: {}", definition.severity);
+        let _ = writeln!(out, "        annotations:");
+        let _ = writeln!(out, "          summary: \"{}\"", definition.summary);
+    }
+    out
+}
+
+/// 查询守护进程并执行告警子命令
+pub async fn run(client: &mut TrafficClient, command: AlertsCommand) -> Result<()> {
+    match command {
+        AlertsCommand::Preview {
+            latency_threshold_ms,
+        } => {
+            let signals = client.get_alerts().await?;
+            println!("{:<30} {:<8} {:<20} SUMMARY", "ALERT", "STATE", "VALUE");
+            for alert in evaluate(&signals, latency_threshold_ms) {
+                println!(
+                    "{:<30} {:<8} {:<20} {}",
+                    alert.name,
+                    if alert.firing { "FIRING" } else { "ok" },
+                    alert.value,
+                    alert.summary
+                );
+            }
+        }
+        AlertsCommand::Metrics => print!("{}", metrics(&client.get_alerts().await?)),
+        AlertsCommand::Rules {
+            latency_threshold_ms,
+        } => print!("{}", rules(latency_threshold_ms)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate_alerts() {
+        let signals = AlertSignals {
+            attack_in_progress: true,
+            enforcement_latency_p99_ms: Some(250),
+            divergent_rules: 2,
+            management_auth_failures: 7,
+            management_lockouts: 1,
+            management_locked_out: 1,
+            dropped_bytes: 1_500_000,
+            dropped_packets: 1000,
+            dropped_bytes_today: 3000,
+        };
+        let firing: Vec<&str> = evaluate(&signals, 200)
+            .iter()
+            .filter(|alert| alert.firing)
+            .map(|alert| alert.name)
+            .collect();
+        assert_eq!(
+            firing,
+            [
+                ATTACK_IN_PROGRESS.name,
+                SLOW_ENFORCEMENT.name,
+                RULE_DIVERGENCE.name,
+                MANAGEMENT_LOCKOUT.name
+            ]
+        );
+        assert!(!evaluate(&signals, 1000)[1].firing);
+
+        let text = metrics(&signals);
+        assert!(text.contains("safe_traffic_attack_in_progress 1\n"));
+        assert!(text.contains("safe_traffic_enforcement_latency_p99_seconds 0.25\n"));
+        assert!(text.contains("safe_traffic_divergent_rules 2\n"));
+        assert!(text.contains("# TYPE safe_traffic_management_auth_failures_total counter\n"));
+        assert!(text.contains("safe_traffic_management_lockouts_total 1\n"));
+        assert!(text.contains("safe_traffic_dropped_bytes_total 1500000\n"));
+        assert!(text.contains("safe_traffic_dropped_bytes_today 3000\n"));
+        assert!(rules(1000).contains("safe_traffic_enforcement_latency_p99_seconds > 1\n"));
+    }
+}
@@ -1,11 +1,19 @@
 use safe_traffic_common::{
-    transport::{Request, Response, ResponseData},
-    utils::FirewallRule,
+    events::{Event, Incident},
+    rule_id::RuleId,
+    transport::{
+        AccountingCounter, ActionHistory, AlertSignals, DashboardSnapshot, EventQuery, Explanation,
+        FlowEntry, Inspection, IntegrationHealth, LogFilter, LogLine, PauseTarget, Request,
+        Response, ResponseData, RuleFilter, SavingsDay, SystemRule, TargetedPause,
+        TemporaryExclude, WindowSnapshot,
+    },
+    utils::{ExcludedTraffic, FirewallRule},
 };
 
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use std::{net::IpAddr, path::Path};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
 use tokio::net::UnixStream;
 
 pub struct TrafficClient {
@@ -36,30 +44,41 @@ impl TrafficClient {
         kbps: u64,
         burst: Option<u64>,
         seconds: Option<u64>,
-    ) -> Result<String> {
+        idempotency_key: Option<String>,
+    ) -> Result<RuleId> {
         let request = Request::Limit {
             ip,
             kbps,
             burst,
             seconds,
+            idempotency_key,
         };
         match self.send_request(request).await? {
-            Response::Success(ResponseData::Message(rule_id)) => Ok(rule_id),
+            Response::Success(ResponseData::Message(rule_id)) => rule_id.parse(),
             Response::Error { message } => Err(anyhow::anyhow!(message)),
             _ => Err(anyhow::anyhow!("Unexpected response format")),
         }
     }
 
-    pub async fn ban(&mut self, ip: IpAddr, seconds: Option<u64>) -> Result<String> {
-        let request = Request::Ban { ip, seconds };
+    pub async fn ban(
+        &mut self,
+        ip: IpAddr,
+        seconds: Option<u64>,
+        idempotency_key: Option<String>,
+    ) -> Result<RuleId> {
+        let request = Request::Ban {
+            ip,
+            seconds,
+            idempotency_key,
+        };
         match self.send_request(request).await? {
-            Response::Success(ResponseData::Message(rule_id)) => Ok(rule_id),
+            Response::Success(ResponseData::Message(rule_id)) => rule_id.parse(),
             Response::Error { message } => Err(anyhow::anyhow!(message)),
             _ => Err(anyhow::anyhow!("Unexpected response format")),
         }
     }
 
-    pub async fn unblock(&mut self, rule_id: String) -> Result<()> {
+    pub async fn unblock(&mut self, rule_id: RuleId) -> Result<()> {
         let request = Request::Unblock { rule_id };
         match self.send_request(request).await? {
             Response::Success(_) => Ok(()),
@@ -67,11 +86,55 @@ impl TrafficClient {
         }
     }
 
-    pub async fn exclude(&mut self, ip: IpAddr) -> Result<()> {
-        let request = Request::Exclude { ip };
+    /// 解除所有符合条件的规则，返回被解除的规则 ID
+    pub async fn unblock_matching(&mut self, filter: RuleFilter) -> Result<Vec<RuleId>> {
+        let request = Request::UnblockMatching { filter };
         match self.send_request(request).await? {
-            Response::Success(_) => Ok(()),
+            Response::Success(ResponseData::StringList(ids)) => {
+                ids.iter().map(|id| id.parse()).collect()
+            }
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
+        }
+    }
+
+    pub async fn extend(&mut self, rule_id: RuleId, seconds: u64) -> Result<String> {
+        let request = Request::Extend { rule_id, seconds };
+        match self.send_request(request).await? {
+            Response::Success(ResponseData::Message(msg)) => Ok(msg),
             Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
+        }
+    }
+
+    /// 加入白名单，seconds 为空时永久生效
+    pub async fn exclude(&mut self, ip: IpAddr, seconds: Option<u64>) -> Result<String> {
+        let request = Request::Exclude { ip, seconds };
+        match self.send_request(request).await? {
+            Response::Success(ResponseData::Message(msg)) => Ok(msg),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
+        }
+    }
+
+    pub async fn remove_exclude(&mut self, ip: IpAddr) -> Result<String> {
+        let request = Request::RemoveExclude { ip };
+        match self.send_request(request).await? {
+            Response::Success(ResponseData::Message(msg)) => Ok(msg),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
+        }
+    }
+
+    pub async fn get_excludes(&mut self) -> Result<Vec<IpAddr>> {
+        let request = Request::GetExcludes;
+        match self.send_request(request).await? {
+            Response::Success(ResponseData::StringList(ips)) => ips
+                .iter()
+                .map(|ip| ip.parse().map_err(anyhow::Error::from))
+                .collect(),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
         }
     }
 
@@ -81,7 +144,222 @@ impl TrafficClient {
 
         match response {
             Response::Success(ResponseData::RuleList(rules)) => Ok(Some(rules)),
-            Response::Success(ResponseData::StringList(_rules)) => Ok(None),
+            Response::Success(data) if data.is_empty_list() => Ok(None),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
+        }
+    }
+
+    /// 获取 nftables 链中实际存在的规则
+    pub async fn get_system_rules(&mut self) -> Result<Vec<SystemRule>> {
+        let request = Request::GetSystemRules;
+        match self.send_request(request).await? {
+            Response::Success(ResponseData::SystemRules(rules)) => Ok(rules),
+            Response::Success(data) if data.is_empty_list() => Ok(Vec::new()),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
+        }
+    }
+
+    /// 获取带有效期的白名单
+    pub async fn get_temporary_excludes(&mut self) -> Result<Vec<TemporaryExclude>> {
+        let request = Request::GetTemporaryExcludes;
+        match self.send_request(request).await? {
+            Response::Success(ResponseData::TemporaryExcludes(excludes)) => Ok(excludes),
+            Response::Success(data) if data.is_empty_list() => Ok(Vec::new()),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
+        }
+    }
+
+    pub async fn get_excluded_stats(&mut self) -> Result<Vec<ExcludedTraffic>> {
+        let request = Request::GetExcludedStats;
+        match self.send_request(request).await? {
+            Response::Success(ResponseData::ExcludedStats(stats)) => Ok(stats),
+            Response::Success(data) if data.is_empty_list() => Ok(Vec::new()),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
+        }
+    }
+
+    pub async fn get_incidents(&mut self) -> Result<Vec<Incident>> {
+        let request = Request::GetIncidents;
+        match self.send_request(request).await? {
+            Response::Success(ResponseData::Incidents(incidents)) => Ok(incidents),
+            Response::Success(data) if data.is_empty_list() => Ok(Vec::new()),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
+        }
+    }
+
+    /// 获取 NFQUEUE 逐包检查中的 IP
+    pub async fn get_inspections(&mut self) -> Result<Vec<Inspection>> {
+        let request = Request::GetInspections;
+        match self.send_request(request).await? {
+            Response::Success(ResponseData::Inspections(inspections)) => Ok(inspections),
+            Response::Success(data) if data.is_empty_list() => Ok(Vec::new()),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
+        }
+    }
+
+    /// 汇总守护进程对某个 IP 的处置依据
+    pub async fn explain(&mut self, ip: IpAddr) -> Result<Explanation> {
+        let request = Request::Explain { ip };
+        match self.send_request(request).await? {
+            Response::Success(ResponseData::Explanation(explanation)) => Ok(explanation),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
+        }
+    }
+
+    /// 获取规则引擎中某个 IP 的滑动窗口数据
+    pub async fn get_window(&mut self, ip: IpAddr) -> Result<WindowSnapshot> {
+        let request = Request::GetWindow { ip };
+        match self.send_request(request).await? {
+            Response::Success(ResponseData::Window(window)) => Ok(window),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
+        }
+    }
+
+    /// 开始或停止跟踪规则引擎对某个 IP 的评估
+    pub async fn trace(&mut self, ip: Option<IpAddr>) -> Result<String> {
+        let request = Request::Trace { ip };
+        match self.send_request(request).await? {
+            Response::Success(ResponseData::Message(msg)) => Ok(msg),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
+        }
+    }
+
+    /// 获取连接跟踪中的流，ip 为空时返回全部
+    pub async fn get_flows(&mut self, ip: Option<IpAddr>) -> Result<Vec<FlowEntry>> {
+        let request = Request::GetFlows { ip };
+        match self.send_request(request).await? {
+            Response::Success(ResponseData::Flows(flows)) => Ok(flows),
+            Response::Success(data) if data.is_empty_list() => Ok(Vec::new()),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
+        }
+    }
+
+    /// 获取统计规则的累计计数
+    pub async fn get_accounting(&mut self) -> Result<Vec<AccountingCounter>> {
+        match self.send_request(Request::GetAccounting).await? {
+            Response::Success(ResponseData::Accounting(counters)) => Ok(counters),
+            Response::Success(data) if data.is_empty_list() => Ok(Vec::new()),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
+        }
+    }
+
+    pub async fn get_savings(&mut self) -> Result<Vec<SavingsDay>> {
+        match self.send_request(Request::GetSavings).await? {
+            Response::Success(ResponseData::Savings(days)) => Ok(days),
+            Response::Success(data) if data.is_empty_list() => Ok(Vec::new()),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
+        }
+    }
+
+    pub async fn get_health(&mut self) -> Result<Vec<IntegrationHealth>> {
+        match self.send_request(Request::GetHealth).await? {
+            Response::Success(ResponseData::Health(health)) => Ok(health),
+            Response::Success(data) if data.is_empty_list() => Ok(Vec::new()),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
+        }
+    }
+
+    /// 按条件查询事件，守护进程配置了事件归档时包括已归档的事件
+    pub async fn query_events(&mut self, query: EventQuery) -> Result<Vec<Event>> {
+        match self.send_request(Request::QueryEvents { query }).await? {
+            Response::Success(ResponseData::Events(events)) => Ok(events),
+            Response::Success(data) if data.is_empty_list() => Ok(Vec::new()),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
+        }
+    }
+
+    /// 分页读取某个 IP 的处置历史，按时间倒序
+    pub async fn get_history(
+        &mut self,
+        ip: IpAddr,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> Result<ActionHistory> {
+        match self
+            .send_request(Request::GetHistory { ip, offset, limit })
+            .await?
+        {
+            Response::Success(ResponseData::History(history)) => Ok(history),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
+        }
+    }
+
+    /// 读取守护进程最近的日志，follow 时持续读取直到守护进程关闭连接；每条日志交给 on_line
+    pub async fn tail_logs(
+        &mut self,
+        filter: LogFilter,
+        follow: bool,
+        mut on_line: impl FnMut(LogLine) -> Result<()>,
+    ) -> Result<()> {
+        let request = serde_json::to_vec(&Request::TailLogs { filter, follow })?;
+        self.stream.write_all(&request).await?;
+
+        let mut lines = BufReader::new(&mut self.stream).lines();
+        let header = lines
+            .next_line()
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Connection closed before the response"))?;
+        match serde_json::from_str(&header)? {
+            Response::Success(_) => {}
+            Response::Error { message } => return Err(anyhow::anyhow!(message)),
+        }
+        while let Some(line) = lines.next_line().await? {
+            on_line(serde_json::from_str(&line)?)?;
+        }
+        Ok(())
+    }
+
+    /// 订阅之后记录的事件，订阅占用整个连接
+    pub async fn subscribe_events(mut self) -> Result<EventStream> {
+        let request = serde_json::to_vec(&Request::SubscribeEvents)?;
+        self.stream.write_all(&request).await?;
+
+        let mut lines = BufReader::new(self.stream).lines();
+        let header = lines
+            .next_line()
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Connection closed before the response"))?;
+        match serde_json::from_str(&header)? {
+            Response::Success(_) => Ok(EventStream { lines }),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+        }
+    }
+
+    /// 获取时间范围内仍保留在守护进程事件缓冲中的事件
+    pub async fn get_events(
+        &mut self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Event>> {
+        let request = Request::GetEvents { since, until };
+        match self.send_request(request).await? {
+            Response::Success(ResponseData::Events(events)) => Ok(events),
+            Response::Success(data) if data.is_empty_list() => Ok(Vec::new()),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
+        }
+    }
+
+    /// 获取防火墙状态描述
+    pub async fn status(&mut self) -> Result<String> {
+        let request = Request::Status;
+        match self.send_request(request).await? {
+            Response::Success(ResponseData::Message(msg)) => Ok(msg),
             Response::Error { message } => Err(anyhow::anyhow!(message)),
             _ => Err(anyhow::anyhow!("Unexpected response format")),
         }
@@ -131,6 +409,75 @@ impl TrafficClient {
             _ => Err(anyhow::anyhow!("Unexpected response format")),
         }
     }
+
+    /// 暂停对单个 IP 或单条规则的执行，seconds 为空时直到手动恢复
+    pub async fn pause_target(
+        &mut self,
+        target: PauseTarget,
+        seconds: Option<u64>,
+    ) -> Result<String> {
+        let request = Request::PauseTarget { target, seconds };
+        match self.send_request(request).await? {
+            Response::Success(ResponseData::Message(msg)) => Ok(msg),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
+        }
+    }
+
+    /// 恢复对单个 IP 或单条规则的执行
+    pub async fn resume_target(&mut self, target: PauseTarget) -> Result<String> {
+        let request = Request::ResumeTarget { target };
+        match self.send_request(request).await? {
+            Response::Success(ResponseData::Message(msg)) => Ok(msg),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
+        }
+    }
+
+    /// 获取生效中的定向暂停
+    pub async fn get_pauses(&mut self) -> Result<Vec<TargetedPause>> {
+        let request = Request::GetPauses;
+        match self.send_request(request).await? {
+            Response::Success(ResponseData::Pauses(pauses)) => Ok(pauses),
+            Response::Success(data) if data.is_empty_list() => Ok(Vec::new()),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
+        }
+    }
+
+    pub async fn dashboard(&mut self) -> Result<DashboardSnapshot> {
+        let request = Request::Dashboard;
+        match self.send_request(request).await? {
+            Response::Success(ResponseData::Dashboard(snapshot)) => Ok(snapshot),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
+        }
+    }
+
+    /// 获取面向告警的派生指标
+    pub async fn get_alerts(&mut self) -> Result<AlertSignals> {
+        let request = Request::GetAlerts;
+        match self.send_request(request).await? {
+            Response::Success(ResponseData::Alerts(signals)) => Ok(signals),
+            Response::Error { message } => Err(anyhow::anyhow!(message)),
+            _ => Err(anyhow::anyhow!("Unexpected response format")),
+        }
+    }
+}
+
+/// 事件订阅，逐行读取守护进程推送的事件
+pub struct EventStream {
+    lines: Lines<BufReader<UnixStream>>,
+}
+
+impl EventStream {
+    /// 下一条事件，守护进程关闭连接时返回 None
+    pub async fn next(&mut self) -> Result<Option<Event>> {
+        match self.lines.next_line().await? {
+            Some(line) => Ok(Some(serde_json::from_str(&line)?)),
+            None => Ok(None),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -168,15 +515,22 @@ mod tests {
         let request = Request::Ban {
             ip: IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)),
             seconds: Some(3600),
+            idempotency_key: None,
         };
 
         let json = serde_json::to_string(&request).unwrap();
+        assert!(!json.contains("idempotency_key"));
         let deserialized: Request = serde_json::from_str(&json).unwrap();
 
         match deserialized {
-            Request::Ban { ip, seconds } => {
+            Request::Ban {
+                ip,
+                seconds,
+                idempotency_key,
+            } => {
                 assert_eq!(ip, IpAddr::V4(Ipv4Addr::new(192, 168, 1, 1)));
                 assert_eq!(seconds, Some(3600));
+                assert_eq!(idempotency_key, None);
             }
             _ => panic!("Unexpected request type"),
         }
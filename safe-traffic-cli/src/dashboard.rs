@@ -0,0 +1,251 @@
+use crate::client::TrafficClient;
+
+use anyhow::Result;
+use ratatui::{
+    crossterm::event::{self, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style},
+    text::Line,
+    widgets::{Block, Borders, List, ListItem, Paragraph, Sparkline},
+    DefaultTerminal, Frame,
+};
+use safe_traffic_common::{events::Event, transport::DashboardSnapshot, utils::RunState};
+use std::{
+    collections::VecDeque,
+    path::Path,
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc::{self, error::TryRecvError};
+
+/// 每条规则保留的命中历史长度（刷新次数）
+const HISTORY_LEN: usize = 120;
+/// 事件栏保留的事件数
+const EVENTS_LEN: usize = 20;
+/// 等待按键的最长时间，订阅到的事件最迟这么久后显示
+const INPUT_POLL: Duration = Duration::from_millis(100);
+
+/// 仪表盘状态，保存最近一次快照、每条规则的命中增量历史与订阅到的事件
+#[derive(Default)]
+struct DashboardState {
+    snapshot: Option<DashboardSnapshot>,
+    last_hits: Vec<u64>,
+    history: Vec<VecDeque<u64>>,
+    events: VecDeque<Event>,
+    events_closed: bool,
+    error: Option<String>,
+}
+
+impl DashboardState {
+    fn update(&mut self, snapshot: DashboardSnapshot) {
+        // 第一次快照中的最近事件作为事件栏的初始内容，之后的事件来自订阅
+        if self.snapshot.is_none() {
+            for event in &snapshot.recent_events {
+                self.push_event(event.clone());
+            }
+        }
+        if self.history.len() != snapshot.rule_hits.len() {
+            self.history = vec![VecDeque::with_capacity(HISTORY_LEN); snapshot.rule_hits.len()];
+            self.last_hits = snapshot.rule_hits.clone();
+        }
+
+        for (i, hits) in snapshot.rule_hits.iter().enumerate() {
+            let history = &mut self.history[i];
+            if history.len() >= HISTORY_LEN {
+                history.pop_front();
+            }
+            history.push_back(hits.saturating_sub(self.last_hits[i]));
+        }
+
+        self.last_hits = snapshot.rule_hits.clone();
+        self.snapshot = Some(snapshot);
+        self.error = None;
+    }
+
+    fn push_event(&mut self, event: Event) {
+        if self.events.len() >= EVENTS_LEN {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+}
+
+/// 运行仪表盘，按 q 或 Esc 退出；事件经单独的连接订阅，到达后立即显示，
+/// 状态与规则命中按 interval 刷新，命中历史的每一格对应相同的时长
+pub async fn run(client: &mut TrafficClient, socket: &Path, interval: Duration) -> Result<()> {
+    // 先订阅再取第一次快照，两者之间记录的事件不会丢失，但可能重复
+    let mut stream = TrafficClient::connect(socket)
+        .await?
+        .subscribe_events()
+        .await?;
+    let (tx, rx) = mpsc::unbounded_channel();
+    let forward = tokio::spawn(async move {
+        while let Ok(Some(event)) = stream.next().await {
+            if tx.send(event).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut terminal = ratatui::init();
+    let result = run_loop(&mut terminal, client, rx, interval).await;
+    ratatui::restore();
+    forward.abort();
+    result
+}
+
+async fn run_loop(
+    terminal: &mut DefaultTerminal,
+    client: &mut TrafficClient,
+    mut events: mpsc::UnboundedReceiver<Event>,
+    interval: Duration,
+) -> Result<()> {
+    let mut state = DashboardState::default();
+    let mut refreshed: Option<Instant> = None;
+
+    loop {
+        if refreshed.is_none_or(|at| at.elapsed() >= interval) {
+            match client.dashboard().await {
+                Ok(snapshot) => state.update(snapshot),
+                Err(e) => state.error = Some(e.to_string()),
+            }
+            refreshed = Some(Instant::now());
+        }
+        loop {
+            match events.try_recv() {
+                Ok(event) => state.push_event(event),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    state.events_closed = true;
+                    break;
+                }
+            }
+        }
+
+        terminal.draw(|frame| draw(frame, &state))?;
+
+        if event::poll(INPUT_POLL.min(interval))? {
+            if let event::Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press
+                    && matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)
+                {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, state: &DashboardState) {
+    let [status_area, rules_area, events_area] = Layout::vertical([
+        Constraint::Length(6),
+        Constraint::Min(3),
+        Constraint::Length(12),
+    ])
+    .areas(frame.area());
+
+    draw_status(frame, status_area, state);
+    draw_rule_hits(frame, rules_area, state);
+    draw_events(frame, events_area, state);
+}
+
+fn draw_status(frame: &mut Frame, area: Rect, state: &DashboardState) {
+    let mut lines = Vec::new();
+
+    if let Some(snapshot) = &state.snapshot {
+        let (engine, color) = match snapshot.engine_state {
+            RunState::Running => ("running", Color::Green),
+            RunState::Paused => ("paused", Color::Yellow),
+            RunState::Stopped => ("stopped", Color::Red),
+        };
+        lines.push(Line::styled(
+            format!(
+                "Engine: {}    nftables: {}",
+                engine,
+                if snapshot.nft_available {
+                    "available"
+                } else {
+                    "unavailable (mock mode)"
+                }
+            ),
+            Style::default().fg(color),
+        ));
+        lines.push(Line::from(format!(
+            "Executor pool: {} idle processes, {} permits free",
+            snapshot.pool_size, snapshot.available_executors
+        )));
+        lines.push(Line::from(format!(
+            "Active rules: {} bans, {} limits",
+            snapshot.ban_rules, snapshot.limit_rules
+        )));
+        lines.push(Line::from(format!(
+            "Tracked sources: {}, about {} bytes each",
+            snapshot.tracked_sources, snapshot.bytes_per_source
+        )));
+        if snapshot.deferred_actions > 0 {
+            lines.push(Line::styled(
+                format!(
+                    "Deferred actions: {} waiting for nftables",
+                    snapshot.deferred_actions
+                ),
+                Style::default().fg(Color::Yellow),
+            ));
+        }
+    } else {
+        lines.push(Line::from("Waiting for daemon..."));
+    }
+
+    if let Some(error) = &state.error {
+        lines.push(Line::styled(
+            format!("Error: {}", error),
+            Style::default().fg(Color::Red),
+        ));
+    }
+    if state.events_closed {
+        lines.push(Line::styled(
+            "Event stream closed, recent events are no longer updated",
+            Style::default().fg(Color::Red),
+        ));
+    }
+
+    let block = Block::default()
+        .title(" safe-traffic (q to quit) ")
+        .borders(Borders::ALL);
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn draw_rule_hits(frame: &mut Frame, area: Rect, state: &DashboardState) {
+    if state.history.is_empty() {
+        let block = Block::default().title(" Rule hits ").borders(Borders::ALL);
+        frame.render_widget(Paragraph::new("No rules configured").block(block), area);
+        return;
+    }
+
+    let rows = Layout::vertical(vec![Constraint::Length(3); state.history.len()]).split(area);
+
+    for (i, (history, row)) in state.history.iter().zip(rows.iter()).enumerate() {
+        let data: Vec<u64> = history.iter().copied().collect();
+        let total = state.last_hits.get(i).copied().unwrap_or(0);
+        let block = Block::default()
+            .title(format!(" rule #{} ({} hits) ", i, total))
+            .borders(Borders::ALL);
+        let sparkline = Sparkline::default()
+            .block(block)
+            .data(&data)
+            .style(Style::default().fg(Color::Cyan));
+        frame.render_widget(sparkline, *row);
+    }
+}
+
+fn draw_events(frame: &mut Frame, area: Rect, state: &DashboardState) {
+    let items: Vec<ListItem> = state
+        .events
+        .iter()
+        .rev()
+        .map(|event| ListItem::new(event.to_string()))
+        .collect();
+
+    let block = Block::default()
+        .title(" Recent events ")
+        .borders(Borders::ALL);
+    frame.render_widget(List::new(items).block(block), area);
+}
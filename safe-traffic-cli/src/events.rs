@@ -0,0 +1,122 @@
+use crate::{client::TrafficClient, report::parse_time};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use clap::{Args, Subcommand};
+use safe_traffic_common::{config::parse_ip, events::EventKind, transport::EventQuery};
+use std::net::IpAddr;
+
+/// 事件参数
+#[derive(Args, Debug)]
+pub struct EventsArgs {
+    #[command(subcommand)]
+    pub command: EventsCommand,
+}
+
+/// 事件子命令
+#[derive(Subcommand, Debug)]
+pub enum EventsCommand {
+    /// Search events by source, rule, action and time range
+    Query(QueryArgs),
+    /// Every action ever taken on an address, newest first, one page at a time
+    History(HistoryArgs),
+}
+
+/// 事件查询条件
+#[derive(Args, Debug)]
+pub struct QueryArgs {
+    /// Only events about this IP address
+    #[arg(long, value_parser = parse_ip)]
+    pub ip: Option<IpAddr>,
+    /// Only events of this rule: a rule ID, or the name or index of a configured rule
+    #[arg(long)]
+    pub rule: Option<String>,
+    /// Only events of this kind (ban, limit, unblock, warn, ...)
+    #[arg(long)]
+    pub action: Option<EventKind>,
+    /// Start of the range, RFC 3339 or "YYYY-MM-DD HH:MM:SS" in UTC
+    #[arg(long, value_parser = parse_time)]
+    pub since: Option<DateTime<Utc>>,
+    /// End of the range, same format as --since
+    #[arg(long, value_parser = parse_time)]
+    pub until: Option<DateTime<Utc>>,
+    /// Only print the most recent N matching events
+    #[arg(long)]
+    pub limit: Option<usize>,
+    /// Print one JSON object per line
+    #[arg(long)]
+    pub json: bool,
+}
+
+/// 处置历史的分页参数
+#[derive(Args, Debug)]
+pub struct HistoryArgs {
+    /// IP address to look up
+    #[arg(value_parser = parse_ip)]
+    pub ip: IpAddr,
+    /// Skip the N most recent actions
+    #[arg(long, default_value_t = 0)]
+    pub offset: usize,
+    /// Actions per page (default 50)
+    #[arg(long)]
+    pub limit: Option<usize>,
+    /// Print the page as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+pub async fn run(client: &mut TrafficClient, command: EventsCommand) -> Result<()> {
+    match command {
+        EventsCommand::Query(args) => {
+            let events = client
+                .query_events(EventQuery {
+                    ip: args.ip,
+                    rule: args.rule,
+                    kind: args.action,
+                    since: args.since,
+                    until: args.until,
+                    limit: args.limit,
+                })
+                .await?;
+            for event in &events {
+                if args.json {
+                    println!("{}", serde_json::to_string(event)?);
+                } else {
+                    // 事件自身只显示时分秒，归档中的事件跨越多天
+                    println!("{} {}", event.time.format("%Y-%m-%d"), event);
+                }
+            }
+            if !args.json && events.is_empty() {
+                println!("No matching events");
+            }
+        }
+        EventsCommand::History(args) => {
+            let history = client.get_history(args.ip, args.offset, args.limit).await?;
+            if args.json {
+                println!("{}", serde_json::to_string_pretty(&history)?);
+                return Ok(());
+            }
+            if history.events.is_empty() {
+                println!(
+                    "No actions recorded for {} at offset {} ({} in total)",
+                    history.ip, history.offset, history.total
+                );
+                return Ok(());
+            }
+            println!(
+                "Actions on {}: {}-{} of {}, newest first",
+                history.ip,
+                history.offset + 1,
+                history.offset + history.events.len(),
+                history.total
+            );
+            for event in &history.events {
+                println!("{} {}", event.time.format("%Y-%m-%d"), event);
+            }
+            if let Some(next) = history.next_offset {
+                println!("More: events history {} --offset {}", history.ip, next);
+            }
+        }
+    }
+    Ok(())
+}
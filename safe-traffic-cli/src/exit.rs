@@ -0,0 +1,156 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use std::{fmt::Display, io};
+
+/// 守护进程返回的、表示对象不存在的错误信息片段
+const NOT_FOUND_MESSAGES: [&str; 4] = [
+    "fail to get rule by id",
+    "maybe not exist",
+    "unknown rule",
+    "no traffic has been observed",
+];
+/// 守护进程执行 nft 等命令时权限不足的错误信息片段
+const PERMISSION_MESSAGES: [&str; 2] = ["Operation not permitted", "Permission denied"];
+
+/// 错误输出格式
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable message on stderr
+    #[default]
+    Text,
+    /// One JSON error envelope on stdout
+    Json,
+}
+
+/// 失败类别，每类对应固定的退出码，供脚本分支判断；2 留给 clap 的参数错误
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Failure {
+    /// 其他错误，退出码 1
+    Error,
+    /// 无法连接守护进程，退出码 3
+    ConnectionFailed,
+    /// 规则、IP 或配置中的规则不存在，退出码 4
+    NotFound,
+    /// 无权访问控制套接字，或守护进程无权执行 nft，退出码 5
+    PermissionDenied,
+    /// 只完成了一部分，退出码 6
+    PartialSuccess,
+}
+
+impl Failure {
+    pub fn code(self) -> i32 {
+        match self {
+            Failure::Error => 1,
+            Failure::ConnectionFailed => 3,
+            Failure::NotFound => 4,
+            Failure::PermissionDenied => 5,
+            Failure::PartialSuccess => 6,
+        }
+    }
+
+    /// 按错误链中的 io 错误或守护进程返回的信息归类
+    pub fn classify(e: &anyhow::Error) -> Self {
+        let io_kind = e
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<io::Error>())
+            .map(io::Error::kind);
+        if io_kind == Some(io::ErrorKind::PermissionDenied) {
+            return Failure::PermissionDenied;
+        }
+        let message = format!("{:#}", e);
+        if NOT_FOUND_MESSAGES.iter().any(|m| message.contains(m)) {
+            Failure::NotFound
+        } else if PERMISSION_MESSAGES.iter().any(|m| message.contains(m)) {
+            Failure::PermissionDenied
+        } else {
+            Failure::Error
+        }
+    }
+}
+
+/// 错误信封，`--output json` 时输出到 stdout
+#[derive(Serialize, Debug)]
+struct Envelope<'a> {
+    error: ErrorBody<'a>,
+}
+
+#[derive(Serialize, Debug)]
+struct ErrorBody<'a> {
+    kind: Failure,
+    exit_code: i32,
+    message: &'a str,
+}
+
+/// 按输出格式报告失败并以该类别的退出码退出
+pub fn exit_with(output: OutputFormat, failure: Failure, message: &str) -> ! {
+    match output {
+        OutputFormat::Text => eprintln!("{}", message),
+        OutputFormat::Json => {
+            let envelope = Envelope {
+                error: ErrorBody {
+                    kind: failure,
+                    exit_code: failure.code(),
+                    message,
+                },
+            };
+            println!("{}", serde_json::to_string(&envelope).unwrap_or_default());
+        }
+    }
+    std::process::exit(failure.code())
+}
+
+/// 报告 `<context>: <错误>` 并按错误归类退出
+pub fn fail(output: OutputFormat, context: impl Display, e: anyhow::Error) -> ! {
+    let failure = Failure::classify(&e);
+    exit_with(output, failure, &format!("{}: {}", context, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_failures() {
+        let classify = |e: anyhow::Error| Failure::classify(&e);
+        assert_eq!(
+            classify(anyhow::anyhow!(
+                "fail to get rule by id: 00000000-0000-0000-0000-000000000000"
+            )),
+            Failure::NotFound
+        );
+        assert_eq!(
+            classify(anyhow::anyhow!("unknown rule: flood")),
+            Failure::NotFound
+        );
+        assert_eq!(
+            classify(anyhow::Error::new(io::Error::from(
+                io::ErrorKind::PermissionDenied
+            ))),
+            Failure::PermissionDenied
+        );
+        assert_eq!(
+            classify(anyhow::anyhow!(
+                "Error: Could not process rule: Operation not permitted"
+            )),
+            Failure::PermissionDenied
+        );
+        assert_eq!(
+            classify(anyhow::anyhow!(
+                "manual ban of 8d exceeds max_manual_ban of 7d"
+            )),
+            Failure::Error
+        );
+        assert_eq!(
+            serde_json::to_value(Envelope {
+                error: ErrorBody {
+                    kind: Failure::NotFound,
+                    exit_code: Failure::NotFound.code(),
+                    message: "gone",
+                },
+            })
+            .unwrap(),
+            serde_json::json!({ "error": { "kind": "not_found", "exit_code": 4, "message": "gone" } })
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use anyhow::{Context, Result};
+use clap::{Args, ValueEnum};
+use safe_traffic_common::utils::{parse_duration, parse_rate};
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpStream, UdpSocket},
+    time,
+};
+
+/// 流量生成参数
+#[derive(Args, Debug)]
+pub struct GenerateArgs {
+    /// Address to send traffic to
+    #[arg(long, value_name = "IP")]
+    pub target: IpAddr,
+    /// Destination port
+    #[arg(long, default_value_t = 9000)]
+    pub port: u16,
+    /// Total send rate, tc style (e.g. 800kbit, 50mbit, 1gbit, 64kbps)
+    #[arg(long, value_parser = parse_rate, default_value = "10mbit")]
+    pub rate: u64,
+    /// Send pattern
+    #[arg(long, value_enum, default_value_t = Pattern::Steady)]
+    pub pattern: Pattern,
+    /// Transport protocol
+    #[arg(long, value_enum, default_value_t = Protocol::Udp)]
+    pub protocol: Protocol,
+    /// Number of concurrent flows sharing the rate
+    #[arg(long, default_value_t = 1)]
+    pub concurrency: usize,
+    /// How long to generate traffic (e.g. 30s, 5m)
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, default_value = "10s")]
+    pub duration: u64,
+    /// UDP payload size / TCP write size in bytes
+    #[arg(long, default_value_t = 1200)]
+    pub packet_size: usize,
+}
+
+/// 发送节奏
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pattern {
+    /// Spread the rate evenly, every 10ms
+    Steady,
+    /// Send each second's budget at once at the start of the second
+    Burst,
+}
+
+impl Pattern {
+    fn tick(self) -> Duration {
+        match self {
+            Pattern::Steady => Duration::from_millis(10),
+            Pattern::Burst => Duration::from_secs(1),
+        }
+    }
+}
+
+/// 传输协议
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    Udp,
+    Tcp,
+}
+
+/// 单条流的发送端
+enum Flow {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+}
+
+impl Flow {
+    async fn open(protocol: Protocol, target: SocketAddr) -> Result<Self> {
+        match protocol {
+            Protocol::Udp => {
+                let bind: SocketAddr = match target {
+                    SocketAddr::V4(_) => "0.0.0.0:0".parse()?,
+                    SocketAddr::V6(_) => "[::]:0".parse()?,
+                };
+                let socket = UdpSocket::bind(bind).await?;
+                socket.connect(target).await?;
+                Ok(Flow::Udp(socket))
+            }
+            Protocol::Tcp => {
+                let stream = TcpStream::connect(target)
+                    .await
+                    .with_context(|| format!("failed to connect to {}", target))?;
+                Ok(Flow::Tcp(stream))
+            }
+        }
+    }
+
+    /// 发送一个数据块，返回实际发送的字节数
+    async fn send(&mut self, chunk: &[u8]) -> usize {
+        match self {
+            // 目标端口未监听时，ICMP 错误会让下一次发送失败，重试一次即可
+            Flow::Udp(socket) => match socket.send(chunk).await {
+                Ok(n) => n,
+                Err(_) => socket.send(chunk).await.unwrap_or(0),
+            },
+            Flow::Tcp(stream) => match stream.write_all(chunk).await {
+                Ok(()) => chunk.len(),
+                Err(_) => 0,
+            },
+        }
+    }
+}
+
+/// 生成流量直到时长耗尽，打印发送统计
+pub async fn run(args: GenerateArgs) -> Result<()> {
+    let target = SocketAddr::new(args.target, args.port);
+    let concurrency = args.concurrency.max(1);
+    let packet_size = args.packet_size.max(1);
+    let duration = Duration::from_secs(args.duration);
+    println!(
+        "Generating {:?} {:?} traffic to {} at {} bytes/s over {} flow(s) for {}s",
+        args.pattern, args.protocol, target, args.rate, concurrency, args.duration
+    );
+
+    let mut flows = Vec::with_capacity(concurrency);
+    for _ in 0..concurrency {
+        flows.push(Flow::open(args.protocol, target).await?);
+    }
+
+    let started = Instant::now();
+    let tick = args.pattern.tick();
+    // 每条流每个周期的发送预算
+    let budget = (args.rate as f64 / concurrency as f64 * tick.as_secs_f64()) as usize;
+    let tasks: Vec<_> = flows
+        .into_iter()
+        .map(|mut flow| {
+            tokio::spawn(async move {
+                let chunk = vec![0u8; packet_size];
+                let deadline = started + duration;
+                let mut interval = time::interval(tick);
+                let mut sent = 0u64;
+                let mut carry = 0usize;
+                while Instant::now() < deadline {
+                    interval.tick().await;
+                    // 不足一个数据块的预算累积到下个周期
+                    carry += budget;
+                    while carry >= packet_size && Instant::now() < deadline {
+                        sent += flow.send(&chunk).await as u64;
+                        carry -= packet_size;
+                    }
+                }
+                sent
+            })
+        })
+        .collect();
+
+    let mut total = 0u64;
+    for task in tasks {
+        total += task.await?;
+    }
+    let elapsed = started.elapsed().as_secs_f64();
+    println!(
+        "Sent {} bytes in {:.1}s ({:.0} bytes/s, {:.2} mbit/s)",
+        total,
+        elapsed,
+        total as f64 / elapsed,
+        total as f64 * 8.0 / elapsed / 1_000_000.0
+    );
+    Ok(())
+}
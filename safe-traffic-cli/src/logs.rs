@@ -0,0 +1,78 @@
+use crate::client::TrafficClient;
+
+use anyhow::Result;
+use clap::Args;
+use safe_traffic_common::{
+    config::{parse_ip, LogLevel},
+    transport::LogFilter,
+};
+use std::net::IpAddr;
+
+/// 日志参数
+#[derive(Args, Debug)]
+pub struct LogsArgs {
+    /// Keep streaming new log lines until interrupted
+    #[arg(short, long)]
+    pub follow: bool,
+    /// Most verbose level to show (error, warn, info, debug, trace); the daemon raises its own level while following
+    #[arg(long, default_value = "info")]
+    pub level: LogLevel,
+    /// Only lines matching KEY=VALUE: ip=<address>, rule=<name or index>, target=<module path substring>; repeatable
+    #[arg(long, value_name = "KEY=VALUE", value_parser = parse_condition)]
+    pub filter: Vec<Condition>,
+    /// Print one JSON object per line
+    #[arg(long)]
+    pub json: bool,
+}
+
+/// 单个筛选条件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    Ip(IpAddr),
+    Rule(String),
+    Target(String),
+}
+
+fn parse_condition(s: &str) -> Result<Condition> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("expected KEY=VALUE, got {:?}", s))?;
+    match key {
+        "ip" => Ok(Condition::Ip(parse_ip(value)?)),
+        "rule" => Ok(Condition::Rule(value.to_string())),
+        "target" => Ok(Condition::Target(value.to_string())),
+        _ => anyhow::bail!("unknown filter {:?}, expected ip, rule or target", key),
+    }
+}
+
+impl LogsArgs {
+    /// 合并为守护进程端的筛选条件，同一键出现多次时以最后一次为准
+    pub fn to_filter(&self) -> LogFilter {
+        let mut filter = LogFilter {
+            level: Some(self.level),
+            ..LogFilter::default()
+        };
+        for condition in &self.filter {
+            match condition {
+                Condition::Ip(ip) => filter.ip = Some(*ip),
+                Condition::Rule(rule) => filter.rule = Some(rule.clone()),
+                Condition::Target(target) => filter.target = Some(target.clone()),
+            }
+        }
+        filter
+    }
+}
+
+pub async fn run(client: &mut TrafficClient, args: LogsArgs) -> Result<()> {
+    let json = args.json;
+    client
+        .tail_logs(args.to_filter(), args.follow, |line| {
+            if json {
+                println!("{}", serde_json::to_string(&line)?);
+            } else {
+                println!("{}", line);
+            }
+            Ok(())
+        })
+        .await
+}
@@ -1,12 +1,37 @@
+mod alerts;
+mod bundle;
 mod client;
+mod dashboard;
+mod events;
+mod exit;
+mod generate;
+mod logs;
+mod report;
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{ArgGroup, Parser, Subcommand};
 use std::net::IpAddr;
 use std::path::PathBuf;
+use std::time::Duration;
 
 // 假设这些类型在你的项目中已定义
 // 如果需要，请调整导入路径
+use crate::alerts::{AlertsArgs, AlertsCommand};
+use crate::bundle::SupportBundleArgs;
 use crate::client::TrafficClient;
+use crate::events::EventsArgs;
+use crate::exit::{Failure, OutputFormat};
+use crate::generate::GenerateArgs;
+use crate::logs::LogsArgs;
+use crate::report::ReportArgs;
+use safe_traffic_common::{
+    config::{parse_ip, parse_network},
+    rule_id::RuleId,
+    transport::{FlowEntry, PauseTarget, RuleFilter},
+    utils::{format_duration, parse_duration},
+};
+
+/// explain 中展示的流数
+const EXPLAIN_FLOWS: usize = 5;
 
 #[derive(Parser)]
 #[command(name = "traffic-cli")]
@@ -17,6 +42,10 @@ struct Cli {
     #[arg(short, long, default_value = "/run/traffic.sock")]
     socket: PathBuf,
 
+    /// How failures are reported: text on stderr, or a JSON envelope on stdout
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -26,7 +55,7 @@ enum Commands {
     /// Limit traffic for a specific IP address
     Limit {
         /// IP address to limit
-        #[arg(value_name = "IP")]
+        #[arg(value_name = "IP", value_parser = parse_ip)]
         ip: IpAddr,
         /// Speed limit in kbps
         #[arg(short, long)]
@@ -37,68 +66,259 @@ enum Commands {
         /// Duration in seconds
         #[arg(short, long)]
         seconds: Option<u64>,
+        /// Key that makes retries of this request return the first result instead of adding a rule
+        #[arg(long, value_name = "KEY")]
+        idempotency_key: Option<String>,
     },
     /// Ban an IP address for a specific duration
     Ban {
         /// IP address to ban
-        #[arg(value_name = "IP")]
+        #[arg(value_name = "IP", value_parser = parse_ip)]
         ip: IpAddr,
-        /// Duration in seconds
-        #[arg(short, long)]
-        seconds: Option<u64>,
+        /// How long the ban lasts, e.g. 30m, 2h or 7d
+        #[arg(
+            short = 's',
+            long,
+            visible_alias = "seconds",
+            value_name = "DURATION",
+            value_parser = parse_duration,
+            default_value = "1h"
+        )]
+        ttl: u64,
+        /// Ban until removed by hand instead of expiring after the TTL
+        #[arg(long, conflicts_with = "ttl")]
+        permanent: bool,
+        /// Key that makes retries of this request return the first result instead of adding a rule
+        #[arg(long, value_name = "KEY")]
+        idempotency_key: Option<String>,
     },
-    /// Remove a ban or limit rule by rule ID
+    /// Remove a ban or limit rule by rule ID, or every rule matching a filter
     Unblock {
         /// Rule ID to remove
+        #[arg(value_name = "RULE_ID", required_unless_present_any = ["all", "matching"])]
+        rule_id: Option<RuleId>,
+        /// Remove all rules
+        #[arg(long, conflicts_with_all = ["rule_id", "matching"])]
+        all: bool,
+        /// Remove rules whose IP falls within this network (e.g. 203.0.113.0/24)
+        #[arg(long, value_name = "CIDR", value_parser = parse_cidr, conflicts_with = "rule_id")]
+        matching: Option<String>,
+    },
+    /// Remove rules that have been active for longer than a duration
+    Purge {
+        /// Minimum age of rules to remove (e.g. 90s, 10m, 7d)
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        older_than: u64,
+        /// Only remove rules whose IP falls within this network
+        #[arg(long, value_name = "CIDR", value_parser = parse_cidr)]
+        matching: Option<String>,
+    },
+    /// Extend the duration of an existing timed ban or limit
+    Extend {
+        /// Rule ID to extend
         #[arg(value_name = "RULE_ID")]
-        rule_id: String,
+        rule_id: RuleId,
+        /// Seconds to add to the rule's duration
+        #[arg(short, long)]
+        seconds: u64,
     },
 
     /// add exclude ip
     Exclude {
         /// ip to exclude
-        #[arg(value_name = "ip")]
+        #[arg(value_name = "ip", value_parser = parse_ip)]
+        ip: IpAddr,
+        /// Remove the exclusion automatically after this long (e.g. 2h); permanent if omitted
+        #[arg(long = "for", value_name = "DURATION", value_parser = parse_duration)]
+        duration: Option<u64>,
+    },
+    /// Remove an IP from the global exclude list
+    Unexclude {
+        /// IP to remove from the exclude list
+        #[arg(value_name = "IP", value_parser = parse_ip)]
         ip: IpAddr,
     },
+    /// List globally excluded IPs
+    Excludes,
 
     /// List all active firewall rules
-    List,
+    List {
+        /// Only show timed rules expiring within this duration (e.g. 90s, 10m, 2h)
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        expiring_within: Option<u64>,
+    },
+    /// Show traffic generated by excluded (allowlisted) sources
+    ExcludedStats,
+    /// List recent incidents (bursts of actions grouped together)
+    Incidents,
+    /// Show per-packet NFQUEUE inspection of IPs hit by an Inspect rule
+    Inspections,
+    /// Explain how the daemon currently treats an IP: rules, reputation, observed SNI and events
+    Explain {
+        /// IP address to explain
+        #[arg(value_name = "IP", value_parser = parse_ip)]
+        ip: IpAddr,
+    },
+    /// Show the traffic window the rule engine holds for an IP: raw per-second slots and per-rule averages
+    Window {
+        /// IP address to show
+        #[arg(value_name = "IP", value_parser = parse_ip)]
+        ip: IpAddr,
+    },
+    /// Log each rule evaluation for an IP at debug level (follow it with `logs --follow --level debug --filter ip=IP`)
+    Trace {
+        /// IP address to trace; replaces the IP traced before
+        #[arg(value_name = "IP", value_parser = parse_ip, required_unless_present = "off")]
+        ip: Option<IpAddr>,
+        /// Stop tracing
+        #[arg(long, conflicts_with = "ip")]
+        off: bool,
+    },
+    /// Show per-service traffic totals of accepted traffic (requires [[accounting]] in the daemon config)
+    Accounting,
+    /// Show traffic dropped by the daemon's rules per day (UTC), i.e. bandwidth saved upstream
+    Savings,
+    /// Show the health score of webhooks, cloud metadata, upstream blocklist and BGP
+    Health,
+    /// Show conntrack flows recorded by the daemon (requires [flows] in the daemon config)
+    Flows {
+        /// Only show flows from or to this IP
+        #[arg(long, value_name = "IP", value_parser = parse_ip)]
+        ip: Option<IpAddr>,
+        /// Show a histogram of destination ports by bytes instead of individual flows
+        #[arg(long)]
+        ports: bool,
+    },
+    /// List the rules currently installed in the nftables chain
+    SystemRules,
     /// Ping the traffic daemon
     Ping,
     /// clean up all rules
     Flush,
     /// stop daemon
     Stop,
-    /// pause updating rules  
-    Pause,
-    /// resume updating rules  
-    Resume,
+    /// pause updating rules, or only enforcement against one IP or one rule
+    #[command(group(ArgGroup::new("target").args(["ip", "rule"])))]
+    Pause {
+        /// Only stop acting on this IP address
+        #[arg(long, value_name = "IP", value_parser = parse_ip)]
+        ip: Option<IpAddr>,
+        /// Only suspend this rule (its name, or its index in the config)
+        #[arg(long, value_name = "RULE")]
+        rule: Option<String>,
+        /// Resume the IP or rule automatically after this long (e.g. 30m)
+        #[arg(long = "for", value_name = "DURATION", value_parser = parse_duration, requires = "target")]
+        duration: Option<u64>,
+    },
+    /// resume updating rules, or enforcement against one IP or one rule
+    #[command(group(ArgGroup::new("target").args(["ip", "rule"])))]
+    Resume {
+        /// Resume acting on this IP address
+        #[arg(long, value_name = "IP", value_parser = parse_ip)]
+        ip: Option<IpAddr>,
+        /// Resume this rule (its name, or its index in the config)
+        #[arg(long, value_name = "RULE")]
+        rule: Option<String>,
+    },
+    /// List the IPs and rules whose enforcement is paused
+    Pauses,
+    /// interactive status dashboard
+    Dashboard {
+        /// Refresh interval in seconds
+        #[arg(short, long, default_value_t = 1)]
+        interval: u64,
+    },
+    /// Generate UDP/TCP load toward a target to validate rules (does not contact the daemon)
+    Generate(GenerateArgs),
+    /// Generate a Markdown/HTML report for an incident or time range
+    Report(ReportArgs),
+    /// Prebuilt alerts: preview them, export their signals, or print Prometheus alerting rules
+    Alerts(AlertsArgs),
+    /// Search recorded events, including the on-disk archive when the daemon keeps one
+    Events(EventsArgs),
+    /// Show recent daemon log lines, or follow them live with --follow, filtered on the daemon side
+    Logs(LogsArgs),
+    /// Collect sanitized config, status, recent events, the nft ruleset and version info into a tarball for bug reports
+    SupportBundle(SupportBundleArgs),
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    let output = cli.output;
+
+    // 生成流量与输出告警规则不需要连接守护进程
+    let command = match cli.command {
+        Commands::Generate(args) => {
+            if let Err(e) = generate::run(args).await {
+                exit::fail(output, "Failed to generate traffic", e);
+            }
+            return Ok(());
+        }
+        Commands::Alerts(AlertsArgs {
+            command: AlertsCommand::Rules {
+                latency_threshold_ms,
+            },
+        }) => {
+            print!("{}", alerts::rules(latency_threshold_ms));
+            return Ok(());
+        }
+        Commands::SupportBundle(args) => {
+            // 守护进程不可达时也要打包其余部分，由 bundle 自行连接
+            match bundle::run(&cli.socket, args).await {
+                Ok((path, failed)) if failed.is_empty() => {
+                    println!("Support bundle written to {}", path.display())
+                }
+                Ok((path, failed)) => exit::exit_with(
+                    output,
+                    Failure::PartialSuccess,
+                    &format!(
+                        "Support bundle written to {} without {}",
+                        path.display(),
+                        failed.join(", ")
+                    ),
+                ),
+                Err(e) => {
+                    exit::fail(output, "Failed to create support bundle", e);
+                }
+            }
+            return Ok(());
+        }
+        command => command,
+    };
 
     // 连接到 traffic daemon
     let mut client = match TrafficClient::connect(&cli.socket).await {
         Ok(client) => client,
         Err(e) => {
-            eprintln!(
-                "Failed to connect to traffic daemon at {:?}: {}",
-                cli.socket, e
+            // 套接字权限不足单独归类，其余连接错误都视为守护进程不可达
+            let failure = match Failure::classify(&e) {
+                Failure::PermissionDenied => Failure::PermissionDenied,
+                _ => Failure::ConnectionFailed,
+            };
+            exit::exit_with(
+                output,
+                failure,
+                &format!(
+                    "Failed to connect to traffic daemon at {:?}: {}",
+                    cli.socket, e
+                ),
             );
-            std::process::exit(1);
         }
     };
 
     // 执行命令
-    match cli.command {
+    match command {
         Commands::Limit {
             ip,
             kbps,
             burst,
             seconds,
-        } => match client.limit(ip, kbps, burst, seconds).await {
+            idempotency_key,
+        } => match client
+            .limit(ip, kbps, burst, seconds, idempotency_key)
+            .await
+        {
             Ok(rule_id) => {
                 println!("Traffic limit applied successfully!");
                 println!("Rule ID: {}", rule_id);
@@ -109,64 +329,142 @@ async fn main() -> Result<()> {
                 }
             }
             Err(e) => {
-                eprintln!("Failed to apply traffic limit: {}", e);
-                std::process::exit(1);
+                exit::fail(output, "Failed to apply traffic limit", e);
             }
         },
 
-        Commands::Ban { ip, seconds } => match client.ban(ip, seconds).await {
-            Ok(rule_id) => {
-                println!("IP banned successfully!");
+        Commands::Ban {
+            ip,
+            ttl,
+            permanent,
+            idempotency_key,
+        } => {
+            let seconds = (!permanent).then_some(ttl);
+            match client.ban(ip, seconds, idempotency_key).await {
+                Ok(rule_id) => {
+                    println!("IP banned successfully!");
+                    println!("Rule ID: {}", rule_id);
+                    println!("IP: {}", ip);
+                    println!(
+                        "Duration: {}",
+                        seconds
+                            .map(format_duration)
+                            .unwrap_or("permanent".to_string())
+                    );
+                }
+                Err(e) => {
+                    exit::fail(output, "Failed to ban IP", e);
+                }
+            }
+        }
+
+        Commands::Unblock {
+            rule_id: Some(rule_id),
+            ..
+        } => match client.unblock(rule_id.clone()).await {
+            Ok(()) => {
+                println!("Rule removed successfully!");
                 println!("Rule ID: {}", rule_id);
-                println!("IP: {}", ip);
-                println!(
-                    "Duration: {} seconds",
-                    seconds
-                        .map(|s| s.to_string())
-                        .unwrap_or("infinity".to_string())
-                );
             }
             Err(e) => {
-                eprintln!("Failed to ban IP: {}", e);
-                std::process::exit(1);
+                exit::fail(output, "Failed to remove rule", e);
             }
         },
 
-        Commands::Unblock { rule_id } => match client.unblock(rule_id.clone()).await {
-            Ok(()) => {
-                println!("Rule removed successfully!");
-                println!("Rule ID: {}", rule_id);
+        Commands::Unblock { matching, .. } => {
+            let filter = RuleFilter {
+                network: matching,
+                older_than: None,
+            };
+            unblock_matching(&mut client, filter, output).await;
+        }
+
+        Commands::Purge {
+            older_than,
+            matching,
+        } => {
+            let filter = RuleFilter {
+                network: matching,
+                older_than: Some(older_than),
+            };
+            unblock_matching(&mut client, filter, output).await;
+        }
+
+        Commands::Extend { rule_id, seconds } => match client.extend(rule_id, seconds).await {
+            Ok(msg) => {
+                println!("{}", msg);
             }
             Err(e) => {
-                eprintln!("Failed to remove rule: {}", e);
-                std::process::exit(1);
+                exit::fail(output, "Failed to extend rule", e);
             }
         },
 
-        Commands::Exclude { ip } => match client.exclude(ip).await {
-            Ok(()) => {
-                println!("exclude ip {} successfully!", ip);
+        Commands::Exclude { ip, duration } => match client.exclude(ip, duration).await {
+            Ok(msg) => {
+                println!("{}", msg);
             }
             Err(e) => {
-                eprintln!("Failed to exclude ip: {}", e);
-                std::process::exit(1);
+                exit::fail(output, "Failed to exclude ip", e);
             }
         },
 
-        Commands::List => match client.get_active_rules().await {
+        Commands::Unexclude { ip } => match client.remove_exclude(ip).await {
+            Ok(msg) => {
+                println!("{}", msg);
+            }
+            Err(e) => {
+                exit::fail(output, "Failed to remove excluded ip", e);
+            }
+        },
+
+        Commands::Excludes => match client.get_excludes().await {
+            Ok(ips) => {
+                // 旧版守护进程不支持临时白名单，全部视为永久
+                let temporary = client.get_temporary_excludes().await.unwrap_or_default();
+                if ips.is_empty() {
+                    println!("No excluded IPs.");
+                } else {
+                    println!("Excluded IPs ({}):", ips.len());
+                    for ip in ips {
+                        match temporary.iter().find(|exclude| exclude.ip == ip) {
+                            Some(exclude) => println!("  {} (until {})", ip, exclude.until),
+                            None => println!("  {}", ip),
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                exit::fail(output, "Failed to get excluded ips", e);
+            }
+        },
+
+        Commands::List { expiring_within } => match client.get_active_rules().await {
             Ok(rules) => {
-                if let Some(rules) = rules {
+                if let Some(mut rules) = rules {
+                    if let Some(within) = expiring_within {
+                        rules.retain(|rule| rule.remaining_secs.is_some_and(|left| left <= within));
+                    }
+                    rules.sort_by_key(|rule| rule.remaining_secs.unwrap_or(u64::MAX));
+
                     println!("Active firewall rules:");
                     println!(
-                        "{:<36} {:<15} {:<12} {:<20}",
-                        "Rule ID", "IP", "Type", "Created At"
+                        "{:<36} {:<15} {:<12} {:<20} {:<12} Reason",
+                        "Rule ID", "IP", "Type", "Created At", "Remaining"
                     );
-                    println!("{}", "-".repeat(90));
+                    println!("{}", "-".repeat(103));
 
                     for rule in rules {
+                        let remaining = rule
+                            .remaining_secs
+                            .map(format_duration)
+                            .unwrap_or("permanent".to_string());
+                        let reason = rule
+                            .reason
+                            .map(|reason| reason.to_string())
+                            .unwrap_or("manual".to_string());
                         println!(
-                            "{:<36} {:<15} {:<12} {:<20}",
-                            rule.id, rule.ip, rule.rule_type, rule.created_at
+                            "{:<36} {:<15} {:<12} {:<20} {:<12} {}",
+                            rule.id, rule.ip, rule.rule_type, rule.created_at, remaining, reason
                         );
                     }
                 } else {
@@ -174,8 +472,365 @@ async fn main() -> Result<()> {
                 }
             }
             Err(e) => {
-                eprintln!("Failed to get active rules: {}", e);
-                std::process::exit(1);
+                exit::fail(output, "Failed to get active rules", e);
+            }
+        },
+
+        Commands::SystemRules => match client.get_system_rules().await {
+            Ok(rules) => {
+                if rules.is_empty() {
+                    println!("No rules installed in the nftables chain.");
+                } else {
+                    println!("nftables rules ({}):", rules.len());
+                    for rule in rules {
+                        println!("{}", rule);
+                    }
+                }
+            }
+            Err(e) => {
+                exit::fail(output, "Failed to get system rules", e);
+            }
+        },
+
+        Commands::ExcludedStats => match client.get_excluded_stats().await {
+            Ok(stats) => {
+                if stats.is_empty() {
+                    println!("No traffic from excluded IPs recorded.");
+                } else {
+                    println!("Traffic from excluded IPs:");
+                    println!(
+                        "{:<40} {:>16} {:>12} {:>10} {:<20}",
+                        "IP", "Total Bytes", "Last B/s", "Over Limit", "Last Seen"
+                    );
+                    println!("{}", "-".repeat(102));
+
+                    for entry in stats {
+                        println!(
+                            "{:<40} {:>16} {:>12} {:>10} {:<20}",
+                            entry.ip,
+                            entry.total_bytes,
+                            entry.last_bps,
+                            entry.suppressed_hits,
+                            entry.last_seen.format("%Y-%m-%d %H:%M:%S")
+                        );
+                    }
+                }
+            }
+            Err(e) => {
+                exit::fail(output, "Failed to get excluded traffic stats", e);
+            }
+        },
+
+        Commands::Incidents => match client.get_incidents().await {
+            Ok(incidents) => {
+                if incidents.is_empty() {
+                    println!("No incidents recorded.");
+                } else {
+                    println!(
+                        "{:>6} {:<20} {:<20} {:>8} {:>8} {:>14} {:<20}",
+                        "ID", "Started", "Ended", "Actions", "IPs", "Peak B/s", "Top Network"
+                    );
+                    println!("{}", "-".repeat(102));
+
+                    for incident in incidents {
+                        println!(
+                            "{:>6} {:<20} {:<20} {:>8} {:>8} {:>14} {:<20}",
+                            incident.id,
+                            incident.started_at.format("%Y-%m-%d %H:%M:%S"),
+                            incident
+                                .ended_at
+                                .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+                                .unwrap_or("ongoing".to_string()),
+                            incident.actions,
+                            incident.total_ips,
+                            incident.peak_bps,
+                            incident
+                                .top_networks
+                                .first()
+                                .map(|(network, _)| network.as_str())
+                                .unwrap_or("-")
+                        );
+                    }
+                }
+            }
+            Err(e) => {
+                exit::fail(output, "Failed to get incidents", e);
+            }
+        },
+
+        Commands::Inspections => match client.get_inspections().await {
+            Ok(inspections) => {
+                if inspections.is_empty() {
+                    println!("No IPs under inspection.");
+                } else {
+                    println!(
+                        "{:<40} {:>10} {:>10} {:>16} {:<8} {:<30}",
+                        "IP", "Packets", "Dropped", "Top Size", "Verdict", "SNI"
+                    );
+                    println!("{}", "-".repeat(119));
+
+                    for inspection in inspections {
+                        println!(
+                            "{:<40} {:>10} {:>10} {:>16} {:<8} {:<30}",
+                            inspection.ip,
+                            inspection.packets,
+                            inspection.dropped,
+                            inspection
+                                .dominant_size
+                                .map(|(size, share)| format!("{}B {:.0}%", size, share * 100.0))
+                                .unwrap_or("-".to_string()),
+                            if inspection.dropping {
+                                "drop"
+                            } else {
+                                "accept"
+                            },
+                            if inspection.sni.is_empty() {
+                                "-".to_string()
+                            } else {
+                                inspection.sni.join(",")
+                            }
+                        );
+                    }
+                }
+            }
+            Err(e) => {
+                exit::fail(output, "Failed to get inspections", e);
+            }
+        },
+
+        Commands::Explain { ip } => match client.explain(ip).await {
+            Ok(explanation) => {
+                println!("IP:         {}", explanation.ip);
+                println!(
+                    "Excluded:   {}",
+                    if explanation.excluded { "yes" } else { "no" }
+                );
+                println!("Reputation: {:.2}", explanation.reputation);
+
+                if explanation.rules.is_empty() {
+                    println!("Rules:      none");
+                } else {
+                    println!("Rules:");
+                    for rule in &explanation.rules {
+                        let remaining = rule
+                            .remaining_secs
+                            .map(format_duration)
+                            .unwrap_or("permanent".to_string());
+                        println!("  {:<36} {:<40} {}", rule.id, rule.rule_type, remaining);
+                        if let Some(reason) = &rule.reason {
+                            println!("    reason: {}", reason);
+                        }
+                    }
+                }
+
+                match &explanation.inspection {
+                    Some(inspection) => {
+                        println!(
+                            "Inspection: {} packets, {} dropped, verdict {}",
+                            inspection.packets,
+                            inspection.dropped,
+                            if inspection.dropping {
+                                "drop"
+                            } else {
+                                "accept"
+                            }
+                        );
+                        if let Some((size, share)) = inspection.dominant_size {
+                            println!("  top payload size {}B ({:.0}%)", size, share * 100.0);
+                        }
+                        if !inspection.sni.is_empty() {
+                            println!("  SNI: {}", inspection.sni.join(", "));
+                        }
+                    }
+                    None => println!("Inspection: not inspected"),
+                }
+
+                if !explanation.flows.is_empty() {
+                    println!("Top flows:");
+                    for flow in explanation.flows.iter().take(EXPLAIN_FLOWS) {
+                        println!("  {}", flow);
+                    }
+                }
+
+                if !explanation.events.is_empty() {
+                    println!("Recent events:");
+                    for event in &explanation.events {
+                        println!("  {}", event);
+                    }
+                }
+            }
+            Err(e) => {
+                exit::fail(output, format!("Failed to explain {}", ip), e);
+            }
+        },
+
+        Commands::Trace { ip, .. } => match client.trace(ip).await {
+            Ok(msg) => println!("{}", msg),
+            Err(e) => exit::fail(output, "Failed to trace", e),
+        },
+
+        Commands::Window { ip } => match client.get_window(ip).await {
+            Ok(window) => {
+                let join = |slots: &[u64]| {
+                    slots
+                        .iter()
+                        .map(u64::to_string)
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                };
+                println!("IP:          {}", window.ip);
+                println!(
+                    "Last sample: rx {} B/s (new {}), tx {} B/s (new {})",
+                    window.rx_delta, window.rx_new_delta, window.tx_delta, window.tx_new_delta
+                );
+                println!("Samples:     {}", window.samples);
+                println!("Slots (B/s, oldest first):");
+                println!("  all:         {}", join(&window.slots));
+                println!("  new:         {}", join(&window.new_slots));
+                println!("  established: {}", join(&window.established_slots));
+
+                if window.averages.is_empty() {
+                    println!("Rules:       none configured");
+                } else {
+                    println!(
+                        "{:<4} {:<20} {:<8} {:>7} {:>14} {:>14} {:<4}",
+                        "#", "Rule", "Metric", "Window", "Average B/s", "Threshold", "Warm"
+                    );
+                    for average in &window.averages {
+                        println!(
+                            "{:<4} {:<20} {:<8} {:>6}s {:>14} {:>14} {:<4}{}",
+                            average.rule,
+                            average.rule_name.as_deref().unwrap_or("-"),
+                            average.metric,
+                            average.window_secs,
+                            average.average_bps,
+                            average.threshold_bps,
+                            if average.warm { "yes" } else { "no" },
+                            if average.average_bps > average.threshold_bps {
+                                "  over"
+                            } else {
+                                ""
+                            }
+                        );
+                    }
+                }
+            }
+            Err(e) => {
+                exit::fail(output, format!("Failed to get window of {}", ip), e);
+            }
+        },
+
+        Commands::Accounting => match client.get_accounting().await {
+            Ok(counters) if counters.is_empty() => println!("No accounting rules configured."),
+            Ok(counters) => {
+                println!(
+                    "{:<20} {:>16} {:>12} {:>16} {:>12}",
+                    "Name", "RX Bytes", "RX Packets", "TX Bytes", "TX Packets"
+                );
+                println!("{}", "-".repeat(80));
+                for counter in counters {
+                    println!(
+                        "{:<20} {:>16} {:>12} {:>16} {:>12}",
+                        counter.name,
+                        counter.rx_bytes,
+                        counter.rx_packets,
+                        counter.tx_bytes,
+                        counter.tx_packets
+                    );
+                }
+            }
+            Err(e) => {
+                exit::fail(output, "Failed to get accounting counters", e);
+            }
+        },
+
+        Commands::Savings => match client.get_savings().await {
+            Ok(days) if days.is_empty() => println!("No traffic dropped yet."),
+            Ok(days) => {
+                println!("{:<12} {:>16} {:>12}", "Date", "Bytes", "Packets");
+                println!("{}", "-".repeat(42));
+                for day in &days {
+                    println!("{:<12} {:>16} {:>12}", day.date, day.bytes, day.packets);
+                }
+                let bytes: u64 = days.iter().map(|day| day.bytes).sum();
+                let packets: u64 = days.iter().map(|day| day.packets).sum();
+                println!("{:<12} {:>16} {:>12}", "Total", bytes, packets);
+            }
+            Err(e) => {
+                exit::fail(output, "Failed to get dropped traffic", e);
+            }
+        },
+        Commands::Health => match client.get_health().await {
+            Ok(health) if health.is_empty() => println!("No integrations configured."),
+            Ok(health) => {
+                println!(
+                    "{:<24} {:>6} {:>6} {:>7} {:>9} {:<20} Last error",
+                    "Integration", "Score", "Calls", "Failed", "Latency", "Last success"
+                );
+                println!("{}", "-".repeat(96));
+                for integration in health {
+                    let latency = integration
+                        .latency_ms
+                        .map(|ms| format!("{}ms", ms))
+                        .unwrap_or("-".to_string());
+                    let last_success = integration
+                        .last_success
+                        .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+                        .unwrap_or("-".to_string());
+                    let score = if integration.degraded {
+                        format!("{}!", integration.score)
+                    } else {
+                        integration.score.to_string()
+                    };
+                    println!(
+                        "{:<24} {:>6} {:>6} {:>7} {:>9} {:<20} {}",
+                        integration.integration,
+                        score,
+                        integration.calls,
+                        integration.failures,
+                        latency,
+                        last_success,
+                        integration.last_error.as_deref().unwrap_or("-")
+                    );
+                }
+            }
+            Err(e) => {
+                exit::fail(output, "Failed to get integration health", e);
+            }
+        },
+        Commands::Flows { ip, ports } => match client.get_flows(ip).await {
+            Ok(flows) if flows.is_empty() => println!("No flows recorded."),
+            Ok(flows) if ports => {
+                println!(
+                    "{:<10} {:>8} {:>16} {:>12}",
+                    "Port", "Flows", "Bytes", "Packets"
+                );
+                println!("{}", "-".repeat(49));
+                for (port, count, bytes, packets) in port_histogram(&flows) {
+                    let port = port.map(|p| p.to_string()).unwrap_or("-".to_string());
+                    println!("{:<10} {:>8} {:>16} {:>12}", port, count, bytes, packets);
+                }
+            }
+            Ok(flows) => {
+                println!(
+                    "{:<6} {:<40} {:<40} {:>6} {:>16} {:>12}",
+                    "Proto", "Source", "Destination", "Port", "Bytes", "Packets"
+                );
+                println!("{}", "-".repeat(125));
+                for flow in flows {
+                    println!(
+                        "{:<6} {:<40} {:<40} {:>6} {:>16} {:>12}",
+                        flow.proto,
+                        flow.src,
+                        flow.dst,
+                        flow.dport.map(|p| p.to_string()).unwrap_or("-".to_string()),
+                        flow.bytes,
+                        flow.packets
+                    );
+                }
+            }
+            Err(e) => {
+                exit::fail(output, "Failed to get flows", e);
             }
         },
 
@@ -184,8 +839,7 @@ async fn main() -> Result<()> {
                 println!("Pong! Traffic daemon is responding.");
             }
             Err(e) => {
-                eprintln!("Failed to ping traffic daemon: {}", e);
-                std::process::exit(1);
+                exit::fail(output, "Failed to ping traffic daemon", e);
             }
         },
 
@@ -194,8 +848,7 @@ async fn main() -> Result<()> {
                 println!("{}", msg);
             }
             Err(e) => {
-                eprintln!("Failed to remove rules: {}", e);
-                std::process::exit(1);
+                exit::fail(output, "Failed to remove rules", e);
             }
         },
 
@@ -204,39 +857,153 @@ async fn main() -> Result<()> {
                 println!("{}", msg);
             }
             Err(e) => {
-                eprintln!("Failed to stop: {}", e);
-                std::process::exit(1);
+                exit::fail(output, "Failed to stop", e);
             }
         },
 
-        Commands::Pause => match client.pause().await {
-            Ok(msg) => {
-                println!("{}", msg);
+        Commands::Pause { ip, rule, duration } => match pause_target(ip, rule) {
+            Some(target) => match client.pause_target(target, duration).await {
+                Ok(msg) => {
+                    println!("{}", msg);
+                }
+                Err(e) => {
+                    exit::fail(output, "Failed to pause", e);
+                }
+            },
+            None => match client.pause().await {
+                Ok(msg) => {
+                    println!("{}", msg);
+                }
+                Err(e) => {
+                    exit::fail(output, "Failed to pause", e);
+                }
+            },
+        },
+
+        Commands::Resume { ip, rule } => match pause_target(ip, rule) {
+            Some(target) => match client.resume_target(target).await {
+                Ok(msg) => {
+                    println!("{}", msg);
+                }
+                Err(e) => {
+                    exit::fail(output, "Failed to resume", e);
+                }
+            },
+            None => match client.resume().await {
+                Ok(msg) => {
+                    println!("{}", msg);
+                }
+                Err(e) => {
+                    exit::fail(output, "Failed to resume", e);
+                }
+            },
+        },
+
+        Commands::Pauses => match client.get_pauses().await {
+            Ok(pauses) => {
+                if pauses.is_empty() {
+                    println!("No targeted pauses.");
+                }
+                for pause in pauses {
+                    println!("{}", pause);
+                }
             }
             Err(e) => {
-                eprintln!("Failed to pause: {}", e);
-                std::process::exit(1);
+                exit::fail(output, "Failed to get pauses", e);
             }
         },
 
-        Commands::Resume => match client.resume().await {
-            Ok(msg) => {
-                println!("{}", msg);
+        Commands::Dashboard { interval } => {
+            if let Err(e) = dashboard::run(
+                &mut client,
+                &cli.socket,
+                Duration::from_secs(interval.max(1)),
+            )
+            .await
+            {
+                exit::fail(output, "Dashboard failed", e);
             }
-            Err(e) => {
-                eprintln!("Failed to resume: {}", e);
-                std::process::exit(1);
+        }
+
+        Commands::Report(args) => {
+            if let Err(e) = report::run(&mut client, args).await {
+                exit::fail(output, "Failed to generate report", e);
             }
-        },
+        }
+
+        Commands::Alerts(args) => {
+            if let Err(e) = alerts::run(&mut client, args.command).await {
+                exit::fail(output, "Failed to evaluate alerts", e);
+            }
+        }
+
+        Commands::Events(args) => {
+            if let Err(e) = events::run(&mut client, args.command).await {
+                exit::fail(output, "Failed to query events", e);
+            }
+        }
+
+        Commands::Logs(args) => {
+            if let Err(e) = logs::run(&mut client, args).await {
+                exit::fail(output, "Failed to read daemon logs", e);
+            }
+        }
+
+        Commands::Generate(_) | Commands::SupportBundle(_) => {
+            unreachable!("handled before connecting")
+        }
     }
 
     Ok(())
 }
 
+/// --ip 与 --rule 至多给出一个，均未给出时作用于整个引擎
+fn pause_target(ip: Option<IpAddr>, rule: Option<String>) -> Option<PauseTarget> {
+    ip.map(PauseTarget::Ip).or(rule.map(PauseTarget::Rule))
+}
+
+/// 按目的端口汇总流数、字节数与报文数，按字节数降序排列
+fn port_histogram(flows: &[FlowEntry]) -> Vec<(Option<u16>, usize, u64, u64)> {
+    let mut ports: Vec<(Option<u16>, usize, u64, u64)> = Vec::new();
+    for flow in flows {
+        match ports.iter_mut().find(|(port, ..)| *port == flow.dport) {
+            Some((_, count, bytes, packets)) => {
+                *count += 1;
+                *bytes += flow.bytes;
+                *packets += flow.packets;
+            }
+            None => ports.push((flow.dport, 1, flow.bytes, flow.packets)),
+        }
+    }
+    ports.sort_by_key(|(_, _, bytes, _)| std::cmp::Reverse(*bytes));
+    ports
+}
+
+/// 校验网段参数，原样传给守护进程
+fn parse_cidr(s: &str) -> Result<String> {
+    parse_network(s)?;
+    Ok(s.to_string())
+}
+
+async fn unblock_matching(client: &mut TrafficClient, filter: RuleFilter, output: OutputFormat) {
+    match client.unblock_matching(filter.clone()).await {
+        Ok(ids) => {
+            println!("Removed {} {}", ids.len(), filter);
+            for id in ids {
+                println!("  {}", id);
+            }
+        }
+        Err(e) => {
+            exit::fail(output, format!("Failed to remove {}", filter), e);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use clap::CommandFactory;
+    use safe_traffic_common::{config::LogLevel, events::EventKind};
 
     #[test]
     fn verify_cli() {
@@ -262,6 +1029,7 @@ mod tests {
                 kbps,
                 burst,
                 seconds: _seconds,
+                ..
             } => {
                 assert_eq!(ip.to_string(), "192.168.1.1");
                 assert_eq!(kbps, 1000);
@@ -271,17 +1039,368 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_events_query_parsing() {
+        let args = vec![
+            "traffic-cli",
+            "events",
+            "query",
+            "--ip",
+            "203.0.113.7",
+            "--action",
+            "ban",
+            "--since",
+            "2026-01-01 00:00:00",
+        ];
+
+        let cli = Cli::try_parse_from(args).unwrap();
+        match cli.command {
+            Commands::Events(events::EventsArgs {
+                command: events::EventsCommand::Query(query),
+            }) => {
+                assert_eq!(query.ip, Some("203.0.113.7".parse().unwrap()));
+                assert_eq!(query.action, Some(EventKind::Ban));
+                assert_eq!(
+                    query.since.unwrap().to_rfc3339(),
+                    "2026-01-01T00:00:00+00:00"
+                );
+                assert!(query.until.is_none());
+            }
+            _ => panic!("Expected Events command"),
+        }
+        assert!(
+            Cli::try_parse_from(["traffic-cli", "events", "query", "--action", "nope"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_events_history_parsing() {
+        let args = vec![
+            "traffic-cli",
+            "events",
+            "history",
+            "203.0.113.7",
+            "--offset",
+            "50",
+        ];
+
+        let cli = Cli::try_parse_from(args).unwrap();
+        match cli.command {
+            Commands::Events(events::EventsArgs {
+                command: events::EventsCommand::History(history),
+            }) => {
+                assert_eq!(history.ip, "203.0.113.7".parse::<IpAddr>().unwrap());
+                assert_eq!(history.offset, 50);
+                assert_eq!(history.limit, None);
+            }
+            _ => panic!("Expected Events command"),
+        }
+    }
+
+    #[test]
+    fn test_support_bundle_parsing() {
+        let cli =
+            Cli::try_parse_from(["traffic-cli", "support-bundle", "-o", "/tmp/b.tar.gz"]).unwrap();
+        match cli.command {
+            Commands::SupportBundle(args) => {
+                assert_eq!(args.output, Some(PathBuf::from("/tmp/b.tar.gz")));
+                assert_eq!(
+                    args.config,
+                    PathBuf::from("/etc/safe-server-traffic/default.toml")
+                );
+                assert_eq!(args.events, 500);
+            }
+            _ => panic!("Expected SupportBundle command"),
+        }
+    }
+
+    #[test]
+    fn test_logs_parsing() {
+        let cli = Cli::try_parse_from([
+            "traffic-cli",
+            "logs",
+            "--follow",
+            "--level",
+            "debug",
+            "--filter",
+            "ip=198.51.100.7",
+            "--filter",
+            "rule=flood",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Logs(args) => {
+                assert!(args.follow);
+                let filter = args.to_filter();
+                assert_eq!(filter.level, Some(LogLevel::Debug));
+                assert_eq!(filter.ip, Some("198.51.100.7".parse().unwrap()));
+                assert_eq!(filter.rule.as_deref(), Some("flood"));
+                assert_eq!(filter.target, None);
+            }
+            _ => panic!("Expected Logs command"),
+        }
+        assert!(Cli::try_parse_from(["traffic-cli", "logs", "--filter", "host=a"]).is_err());
+        assert!(Cli::try_parse_from(["traffic-cli", "logs", "--level", "loud"]).is_err());
+    }
+
+    #[test]
+    fn test_output_format_parsing() {
+        let cli = Cli::try_parse_from(["traffic-cli", "list"]).unwrap();
+        assert_eq!(cli.output, OutputFormat::Text);
+        let cli = Cli::try_parse_from(["traffic-cli", "--output", "json", "ping"]).unwrap();
+        assert_eq!(cli.output, OutputFormat::Json);
+        assert!(Cli::try_parse_from(["traffic-cli", "--output", "yaml", "ping"]).is_err());
+    }
+
+    #[test]
+    fn test_list_expiring_within_parsing() {
+        let args = vec!["traffic-cli", "list", "--expiring-within", "10m"];
+
+        let cli = Cli::try_parse_from(args).unwrap();
+        match cli.command {
+            Commands::List { expiring_within } => assert_eq!(expiring_within, Some(600)),
+            _ => panic!("Expected List command"),
+        }
+    }
+
+    #[test]
+    fn test_unblock_filter_parsing() {
+        let cli = Cli::try_parse_from(["traffic-cli", "unblock", "--all"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Unblock {
+                rule_id: None,
+                all: true,
+                matching: None
+            }
+        ));
+
+        let args = [
+            "traffic-cli",
+            "purge",
+            "--older-than",
+            "7d",
+            "--matching",
+            "203.0.113.0/24",
+        ];
+        let cli = Cli::try_parse_from(args).unwrap();
+        match cli.command {
+            Commands::Purge {
+                older_than,
+                matching,
+            } => {
+                assert_eq!(older_than, 604800);
+                assert_eq!(matching.as_deref(), Some("203.0.113.0/24"));
+            }
+            _ => panic!("Expected Purge command"),
+        }
+
+        assert!(Cli::try_parse_from(["traffic-cli", "unblock"]).is_err());
+        assert!(
+            Cli::try_parse_from(["traffic-cli", "unblock", "--matching", "not-a-net"]).is_err()
+        );
+    }
+
     #[test]
     fn test_ban_command_parsing() {
-        let args = vec!["traffic-cli", "ban", "10.0.0.1", "--seconds", "3600"];
+        let args = vec![
+            "traffic-cli",
+            "ban",
+            "10.0.0.1",
+            "--seconds",
+            "3600",
+            "--idempotency-key",
+            "incident-42-10.0.0.1",
+        ];
 
         let cli = Cli::try_parse_from(args).unwrap();
         match cli.command {
-            Commands::Ban { ip, seconds } => {
+            Commands::Ban {
+                ip,
+                ttl,
+                permanent,
+                idempotency_key,
+            } => {
                 assert_eq!(ip.to_string(), "10.0.0.1");
-                assert_eq!(seconds, Some(3600));
+                assert_eq!(ttl, 3600);
+                assert!(!permanent);
+                assert_eq!(idempotency_key.as_deref(), Some("incident-42-10.0.0.1"));
             }
             _ => panic!("Expected Ban command"),
         }
     }
+
+    #[test]
+    fn test_ban_ttl_defaults_to_finite() {
+        let cli = Cli::try_parse_from(["traffic-cli", "ban", "10.0.0.1"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Ban {
+                ttl: 3600,
+                permanent: false,
+                ..
+            }
+        ));
+
+        let cli = Cli::try_parse_from(["traffic-cli", "ban", "10.0.0.1", "--ttl", "7d"]).unwrap();
+        assert!(matches!(cli.command, Commands::Ban { ttl: 604800, .. }));
+
+        let cli = Cli::try_parse_from(["traffic-cli", "ban", "10.0.0.1", "--permanent"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Ban {
+                permanent: true,
+                ..
+            }
+        ));
+
+        assert!(Cli::try_parse_from([
+            "traffic-cli",
+            "ban",
+            "10.0.0.1",
+            "--ttl",
+            "1h",
+            "--permanent"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_generate_command_parsing() {
+        let args = vec![
+            "traffic-cli",
+            "generate",
+            "--target",
+            "10.0.0.1",
+            "--rate",
+            "50mbit",
+            "--pattern",
+            "burst",
+            "--concurrency",
+            "4",
+        ];
+
+        let cli = Cli::try_parse_from(args).unwrap();
+        match cli.command {
+            Commands::Generate(args) => {
+                assert_eq!(args.target.to_string(), "10.0.0.1");
+                assert_eq!(args.rate, 6_250_000);
+                assert_eq!(args.pattern, generate::Pattern::Burst);
+                assert_eq!(args.protocol, generate::Protocol::Udp);
+                assert_eq!(args.concurrency, 4);
+                assert_eq!(args.duration, 10);
+            }
+            _ => panic!("Expected Generate command"),
+        }
+    }
+
+    #[test]
+    fn test_port_histogram() {
+        let flow = |dport: Option<u16>, bytes: u64| FlowEntry {
+            src: "198.51.100.7".parse().unwrap(),
+            dst: "192.0.2.1".parse().unwrap(),
+            proto: "udp".to_string(),
+            dport,
+            bytes,
+            packets: 1,
+        };
+        let flows = [
+            flow(Some(53), 100),
+            flow(Some(443), 500),
+            flow(Some(53), 600),
+            flow(None, 10),
+        ];
+        assert_eq!(
+            port_histogram(&flows),
+            vec![
+                (Some(53), 2, 700, 2),
+                (Some(443), 1, 500, 1),
+                (None, 1, 10, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_exclude_parsing() {
+        let cli =
+            Cli::try_parse_from(["traffic-cli", "exclude", "198.51.100.7", "--for", "2h"]).unwrap();
+        match cli.command {
+            Commands::Exclude { ip, duration } => {
+                assert_eq!(ip, "198.51.100.7".parse::<IpAddr>().unwrap());
+                assert_eq!(duration, Some(7200));
+            }
+            _ => panic!("Expected Exclude command"),
+        }
+        let cli = Cli::try_parse_from(["traffic-cli", "exclude", "198.51.100.7"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Exclude { duration: None, .. }
+        ));
+    }
+
+    #[test]
+    fn test_trace_parsing() {
+        let cli = Cli::try_parse_from(["traffic-cli", "trace", "198.51.100.7"]).unwrap();
+        match cli.command {
+            Commands::Trace { ip, off } => {
+                assert_eq!(ip, Some("198.51.100.7".parse().unwrap()));
+                assert!(!off);
+            }
+            _ => panic!("Expected Trace command"),
+        }
+        let cli = Cli::try_parse_from(["traffic-cli", "trace", "--off"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Trace {
+                ip: None,
+                off: true
+            }
+        ));
+        assert!(Cli::try_parse_from(["traffic-cli", "trace"]).is_err());
+        assert!(Cli::try_parse_from(["traffic-cli", "trace", "198.51.100.7", "--off"]).is_err());
+    }
+
+    #[test]
+    fn test_pause_target_parsing() {
+        let cli = Cli::try_parse_from([
+            "traffic-cli",
+            "pause",
+            "--ip",
+            "198.51.100.7",
+            "--for",
+            "30m",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Pause { ip, rule, duration } => {
+                assert_eq!(
+                    pause_target(ip, rule),
+                    Some(PauseTarget::Ip("198.51.100.7".parse().unwrap()))
+                );
+                assert_eq!(duration, Some(1800));
+            }
+            _ => panic!("Expected Pause command"),
+        }
+
+        let cli = Cli::try_parse_from(["traffic-cli", "pause"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Pause {
+                ip: None,
+                rule: None,
+                duration: None
+            }
+        ));
+
+        assert!(Cli::try_parse_from(["traffic-cli", "pause", "--for", "30m"]).is_err());
+        assert!(Cli::try_parse_from([
+            "traffic-cli",
+            "resume",
+            "--ip",
+            "198.51.100.7",
+            "--rule",
+            "flood"
+        ])
+        .is_err());
+    }
 }
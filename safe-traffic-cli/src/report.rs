@@ -0,0 +1,477 @@
+use crate::client::TrafficClient;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use clap::{Args, ValueEnum};
+use safe_traffic_common::{
+    events::{Event, EventKind, Incident},
+    transport::SystemRule,
+    utils::{format_duration, network_of, FirewallRule},
+};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet},
+    net::IpAddr,
+    path::PathBuf,
+};
+
+/// 时间线最多列出的事件数
+const TIMELINE_LIMIT: usize = 200;
+/// 来源排行的条目数
+const TOP_SOURCES: usize = 20;
+
+/// 报告参数
+#[derive(Args, Debug)]
+pub struct ReportArgs {
+    /// Incident to report on
+    #[arg(long, conflicts_with_all = ["from", "to"], required_unless_present = "from")]
+    pub incident: Option<u64>,
+    /// Start of the period, RFC 3339 or "YYYY-MM-DD HH:MM:SS" in UTC
+    #[arg(long, value_parser = parse_time)]
+    pub from: Option<DateTime<Utc>>,
+    /// End of the period, defaults to now
+    #[arg(long, value_parser = parse_time, requires = "from")]
+    pub to: Option<DateTime<Utc>>,
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Markdown)]
+    pub format: Format,
+    /// Write the report to a file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+/// 报告格式
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Markdown,
+    Html,
+}
+
+/// 解析 RFC 3339 或 `YYYY-MM-DD HH:MM:SS`（UTC）时间
+pub fn parse_time(s: &str) -> Result<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    let time = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+        .with_context(|| format!("invalid time: {}", s))?;
+    Ok(time.and_utc())
+}
+
+/// 报告中的内容块
+#[derive(Debug)]
+enum Block {
+    Paragraph(String),
+    List(Vec<String>),
+    Table {
+        header: Vec<&'static str>,
+        rows: Vec<Vec<String>>,
+    },
+}
+
+#[derive(Debug)]
+struct Section {
+    heading: &'static str,
+    blocks: Vec<Block>,
+}
+
+/// 与格式无关的报告内容
+#[derive(Debug)]
+pub struct Report {
+    title: String,
+    sections: Vec<Section>,
+}
+
+impl Report {
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!("# {}\n", self.title);
+        for section in &self.sections {
+            out.push_str(&format!("\n## {}\n", section.heading));
+            for block in &section.blocks {
+                out.push('\n');
+                match block {
+                    Block::Paragraph(text) => out.push_str(&format!("{}\n", text)),
+                    Block::List(items) => {
+                        for item in items {
+                            out.push_str(&format!("- {}\n", item));
+                        }
+                    }
+                    Block::Table { header, rows } => {
+                        out.push_str(&format!("| {} |\n", header.join(" | ")));
+                        out.push_str(&format!("|{}\n", "---|".repeat(header.len())));
+                        for row in rows {
+                            let cells: Vec<String> =
+                                row.iter().map(|cell| cell.replace('|', "\\|")).collect();
+                            out.push_str(&format!("| {} |\n", cells.join(" | ")));
+                        }
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n<h1>{}</h1>\n",
+            escape_html(&self.title),
+            escape_html(&self.title)
+        );
+        for section in &self.sections {
+            out.push_str(&format!("<h2>{}</h2>\n", escape_html(section.heading)));
+            for block in &section.blocks {
+                match block {
+                    Block::Paragraph(text) => {
+                        out.push_str(&format!("<p>{}</p>\n", escape_html(text)))
+                    }
+                    Block::List(items) => {
+                        out.push_str("<ul>\n");
+                        for item in items {
+                            out.push_str(&format!("<li>{}</li>\n", escape_html(item)));
+                        }
+                        out.push_str("</ul>\n");
+                    }
+                    Block::Table { header, rows } => {
+                        out.push_str("<table>\n<tr>");
+                        for cell in header {
+                            out.push_str(&format!("<th>{}</th>", escape_html(cell)));
+                        }
+                        out.push_str("</tr>\n");
+                        for row in rows {
+                            out.push_str("<tr>");
+                            for cell in row {
+                                out.push_str(&format!("<td>{}</td>", escape_html(cell)));
+                            }
+                            out.push_str("</tr>\n");
+                        }
+                        out.push_str("</table>\n");
+                    }
+                }
+            }
+        }
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// 是否为对来源执行的动作
+fn is_action(kind: EventKind) -> bool {
+    matches!(kind, EventKind::Ban | EventKind::Limit | EventKind::Mirror)
+}
+
+/// 规则的匹配条件中是否出现该地址
+fn matches_ip(rule: &SystemRule, ips: &HashSet<String>) -> bool {
+    rule.matchers
+        .iter()
+        .flat_map(|matcher| matcher.split_whitespace())
+        .any(|token| ips.contains(token))
+}
+
+/// 根据时间范围内的事件与当前规则生成报告
+pub fn build(
+    title: String,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+    incident: Option<&Incident>,
+    events: &[Event],
+    system_rules: &[SystemRule],
+    active_rules: &[FirewallRule],
+) -> Report {
+    let actions: Vec<&Event> = events.iter().filter(|e| is_action(e.kind)).collect();
+    let mut per_ip: HashMap<IpAddr, u64> = HashMap::new();
+    for ip in actions.iter().filter_map(|event| event.ip) {
+        *per_ip.entry(ip).or_default() += 1;
+    }
+    let sources: HashSet<String> = per_ip.keys().map(IpAddr::to_string).collect();
+
+    // 概览
+    let mut summary = vec![
+        format!(
+            "Period: {} – {} UTC ({})",
+            since.format("%Y-%m-%d %H:%M:%S"),
+            until.format("%Y-%m-%d %H:%M:%S"),
+            format_duration((until - since).num_seconds().max(0) as u64)
+        ),
+        format!("Actions recorded: {}", actions.len()),
+        format!("Distinct sources: {}", per_ip.len()),
+    ];
+    if let Some(incident) = incident {
+        summary.push(format!(
+            "Incident totals: {} actions on {} IPs, peak {} bytes/s",
+            incident.actions, incident.total_ips, incident.peak_bps
+        ));
+        if incident.ended_at.is_none() {
+            summary.push("The incident is still ongoing.".to_string());
+        }
+    }
+    let mut summary_blocks = vec![Block::List(summary)];
+    if incident.is_some_and(|incident| incident.actions > actions.len() as u64) {
+        summary_blocks.push(Block::Paragraph(
+            "Only part of the incident's actions are still in the daemon's event buffer; per-event sections cover those that are.".to_string(),
+        ));
+    }
+
+    // 时间线
+    let mut timeline_rows: Vec<Vec<String>> = events
+        .iter()
+        .take(TIMELINE_LIMIT)
+        .map(|event| {
+            vec![
+                event.time.format("%Y-%m-%d %H:%M:%S").to_string(),
+                event.kind.to_string(),
+                event.ip.map(|ip| ip.to_string()).unwrap_or_default(),
+                event.message.clone(),
+            ]
+        })
+        .collect();
+    if events.len() > TIMELINE_LIMIT {
+        timeline_rows.push(vec![
+            String::new(),
+            String::new(),
+            String::new(),
+            format!("… {} more events", events.len() - TIMELINE_LIMIT),
+        ]);
+    }
+
+    // 来源排行
+    let mut top: Vec<(IpAddr, u64)> = per_ip.iter().map(|(ip, n)| (*ip, *n)).collect();
+    top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let top_rows = top
+        .iter()
+        .take(TOP_SOURCES)
+        .map(|(ip, n)| vec![ip.to_string(), network_of(*ip), n.to_string()])
+        .collect();
+
+    // 网段分布：incident 自带完整统计，否则按事件计算
+    let networks: Vec<(String, u64)> = match incident {
+        Some(incident) => incident.top_networks.clone(),
+        None => {
+            let mut networks: BTreeMap<String, u64> = BTreeMap::new();
+            for event in &actions {
+                if let Some(ip) = event.ip {
+                    *networks.entry(network_of(ip)).or_default() += 1;
+                }
+            }
+            let mut networks: Vec<_> = networks.into_iter().collect();
+            networks.sort_by_key(|(_, n)| Reverse(*n));
+            networks.truncate(TOP_SOURCES);
+            networks
+        }
+    };
+
+    // 动作统计
+    let mut kinds: BTreeMap<String, u64> = BTreeMap::new();
+    for event in events {
+        *kinds.entry(event.kind.to_string()).or_default() += 1;
+    }
+
+    // 效果：仍在生效的规则计数器
+    let matched: Vec<&SystemRule> = system_rules
+        .iter()
+        .filter(|rule| matches_ip(rule, &sources))
+        .collect();
+    let packets: u64 = matched.iter().filter_map(|rule| rule.packets).sum();
+    let bytes: u64 = matched.iter().filter_map(|rule| rule.bytes).sum();
+
+    // 残留状态
+    let residual: Vec<Vec<String>> = active_rules
+        .iter()
+        .filter(|rule| per_ip.contains_key(&rule.ip))
+        .map(|rule| {
+            vec![
+                rule.id.to_string(),
+                rule.ip.to_string(),
+                rule.rule_type.to_string(),
+                rule.remaining_secs
+                    .map(format_duration)
+                    .unwrap_or("permanent".to_string()),
+            ]
+        })
+        .collect();
+
+    let sections = vec![
+        Section {
+            heading: "Summary",
+            blocks: summary_blocks,
+        },
+        Section {
+            heading: "Timeline",
+            blocks: vec![Block::Table {
+                header: vec!["Time (UTC)", "Event", "IP", "Details"],
+                rows: timeline_rows,
+            }],
+        },
+        Section {
+            heading: "Top sources",
+            blocks: vec![Block::Table {
+                header: vec!["IP", "Network", "Actions"],
+                rows: top_rows,
+            }],
+        },
+        Section {
+            heading: "Source networks",
+            blocks: vec![
+                Block::Paragraph(
+                    "ASN and geo data are not available to the daemon; sources are grouped by /24 (IPv4) and /48 (IPv6) networks.".to_string(),
+                ),
+                Block::Table {
+                    header: vec!["Network", "Actions"],
+                    rows: networks
+                        .into_iter()
+                        .map(|(network, n)| vec![network, n.to_string()])
+                        .collect(),
+                },
+            ],
+        },
+        Section {
+            heading: "Actions taken",
+            blocks: vec![Block::Table {
+                header: vec!["Event", "Count"],
+                rows: kinds
+                    .into_iter()
+                    .map(|(kind, n)| vec![kind, n.to_string()])
+                    .collect(),
+            }],
+        },
+        Section {
+            heading: "Effectiveness",
+            blocks: vec![Block::Paragraph(format!(
+                "{} rules still installed for these sources dropped {} packets ({} bytes). Counters of rules that have already been removed are not included.",
+                matched.len(),
+                packets,
+                bytes
+            ))],
+        },
+        Section {
+            heading: "Residual state",
+            blocks: vec![if residual.is_empty() {
+                Block::Paragraph("No rules remain active for these sources.".to_string())
+            } else {
+                Block::Table {
+                    header: vec!["Rule ID", "IP", "Action", "Remaining"],
+                    rows: residual,
+                }
+            }],
+        },
+    ];
+
+    Report { title, sections }
+}
+
+/// 从守护进程收集数据并输出报告
+pub async fn run(client: &mut TrafficClient, args: ReportArgs) -> Result<()> {
+    let now = Utc::now();
+    let (title, since, until, incident) = match args.incident {
+        Some(id) => {
+            let incident = client
+                .get_incidents()
+                .await?
+                .into_iter()
+                .find(|incident| incident.id == id)
+                .with_context(|| {
+                    format!("incident {} not found, only recent incidents are kept", id)
+                })?;
+            (
+                format!("Incident #{} report", id),
+                incident.started_at,
+                incident.ended_at.unwrap_or(now),
+                Some(incident),
+            )
+        }
+        None => {
+            let since = args.from.context("--from or --incident is required")?;
+            let until = args.to.unwrap_or(now);
+            (
+                format!(
+                    "Traffic report {} – {}",
+                    since.format("%Y-%m-%d %H:%M"),
+                    until.format("%Y-%m-%d %H:%M")
+                ),
+                since,
+                until,
+                None,
+            )
+        }
+    };
+
+    let events = client.get_events(Some(since), Some(until)).await?;
+    let system_rules = client.get_system_rules().await?;
+    let active_rules = client.get_active_rules().await?.unwrap_or_default();
+    let report = build(
+        title,
+        since,
+        until,
+        incident.as_ref(),
+        &events,
+        &system_rules,
+        &active_rules,
+    );
+
+    let text = match args.format {
+        Format::Markdown => report.to_markdown(),
+        Format::Html => report.to_html(),
+    };
+    match args.output {
+        Some(path) => {
+            std::fs::write(&path, text)
+                .with_context(|| format!("failed to write {}", path.display()))?;
+            println!("Report written to {}", path.display());
+        }
+        None => print!("{}", text),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(kind: EventKind, ip: &str, message: &str) -> Event {
+        Event::new(kind, message).with_ip(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn test_build_report() {
+        let since = parse_time("2026-01-01 10:00:00").unwrap();
+        let until = parse_time("2026-01-01T10:05:00Z").unwrap();
+        let events = vec![
+            event(EventKind::Ban, "203.0.113.5", "ban <a|b>"),
+            event(EventKind::Ban, "203.0.113.5", "ban again"),
+            event(EventKind::Limit, "198.51.100.7", "limit"),
+        ];
+        let system_rules = vec![SystemRule {
+            handle: Some(4),
+            matchers: vec!["ip saddr == 203.0.113.5".to_string()],
+            packets: Some(10),
+            bytes: Some(1500),
+            statements: vec!["drop".to_string()],
+        }];
+
+        let report = build(
+            "Report".to_string(),
+            since,
+            until,
+            None,
+            &events,
+            &system_rules,
+            &[],
+        );
+
+        let markdown = report.to_markdown();
+        assert!(markdown.contains("Actions recorded: 3"));
+        assert!(markdown.contains("| 203.0.113.5 | 203.0.113.0/24 | 2 |"));
+        assert!(markdown.contains("ban <a\\|b>"));
+        assert!(markdown.contains("dropped 10 packets (1500 bytes)"));
+        assert!(markdown.contains("No rules remain active"));
+
+        let html = report.to_html();
+        assert!(html.contains("<td>ban &lt;a|b&gt;</td>"));
+        assert!(html.contains("<h2>Top sources</h2>"));
+    }
+}
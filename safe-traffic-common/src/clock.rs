@@ -0,0 +1,100 @@
+use chrono::{DateTime, Utc};
+use std::{
+    fmt::Debug,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+/// 时间来源
+///
+/// 窗口推进与规则过期只依赖单调时钟，NTP 校时或手动修改系统时间不会使封禁提前失效或窗口停滞；
+/// 墙上时间只用于展示与持久化。
+pub trait Clock: Send + Sync + Debug {
+    /// 自进程启动以来经过的单调时间
+    fn monotonic(&self) -> Duration;
+
+    /// 当前墙上时间
+    fn wall(&self) -> DateTime<Utc>;
+}
+
+/// 系统时钟，同一进程内所有实例共享单调时间起点
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+fn process_start() -> Instant {
+    static START: OnceLock<Instant> = OnceLock::new();
+    *START.get_or_init(Instant::now)
+}
+
+impl Clock for SystemClock {
+    fn monotonic(&self) -> Duration {
+        process_start().elapsed()
+    }
+
+    fn wall(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// 手动推进的时钟，用于测试与基准，可单独让墙上时间跳变
+#[derive(Debug)]
+pub struct ManualClock {
+    monotonic: Mutex<Duration>,
+    wall: Mutex<DateTime<Utc>>,
+}
+
+impl ManualClock {
+    pub fn new(wall: DateTime<Utc>) -> Self {
+        Self {
+            monotonic: Mutex::new(Duration::ZERO),
+            wall: Mutex::new(wall),
+        }
+    }
+
+    /// 正常流逝，单调时间与墙上时间同步前进
+    pub fn advance(&self, elapsed: Duration) {
+        *self.monotonic.lock().unwrap() += elapsed;
+        let mut wall = self.wall.lock().unwrap();
+        *wall += chrono::Duration::from_std(elapsed).unwrap_or(chrono::Duration::zero());
+    }
+
+    /// 模拟系统时间被校正，单调时间不受影响
+    pub fn set_wall(&self, wall: DateTime<Utc>) {
+        *self.wall.lock().unwrap() = wall;
+    }
+}
+
+impl Clock for ManualClock {
+    fn monotonic(&self) -> Duration {
+        *self.monotonic.lock().unwrap()
+    }
+
+    fn wall(&self) -> DateTime<Utc> {
+        *self.wall.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock_wall_jump_keeps_monotonic() {
+        let start = Utc::now();
+        let clock = ManualClock::new(start);
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(clock.monotonic(), Duration::from_secs(5));
+        assert_eq!(clock.wall(), start + chrono::Duration::seconds(5));
+
+        clock.set_wall(start - chrono::Duration::hours(1));
+        assert_eq!(clock.monotonic(), Duration::from_secs(5));
+        assert_eq!(clock.wall(), start - chrono::Duration::hours(1));
+    }
+
+    #[test]
+    fn test_system_clock_is_shared_and_monotonic() {
+        let first = SystemClock.monotonic();
+        let second = SystemClock.monotonic();
+        assert!(second >= first);
+    }
+}
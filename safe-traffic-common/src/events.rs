@@ -0,0 +1,290 @@
+use crate::{reason::Reason, rule_id::RuleId};
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{fmt, net::IpAddr, str::FromStr};
+
+/// 事件类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+pub enum EventKind {
+    Ban,
+    Limit,
+    Unblock,
+    Extend,
+    Mirror,
+    Inspect,
+    Police,
+    Warn,
+    Tarpit,
+    Exclude,
+    Unexclude,
+    Flush,
+    Incident,
+    Warning,
+    Capacity,
+    Spoof,
+    Conflict,
+    /// 外部集成降级或恢复
+    Health,
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s: &str = match self {
+            EventKind::Ban => "ban",
+            EventKind::Limit => "limit",
+            EventKind::Unblock => "unblock",
+            EventKind::Extend => "extend",
+            EventKind::Mirror => "mirror",
+            EventKind::Inspect => "inspect",
+            EventKind::Police => "police",
+            EventKind::Warn => "warn",
+            EventKind::Tarpit => "tarpit",
+            EventKind::Exclude => "exclude",
+            EventKind::Unexclude => "unexclude",
+            EventKind::Flush => "flush",
+            EventKind::Incident => "incident",
+            EventKind::Warning => "warning",
+            EventKind::Capacity => "capacity",
+            EventKind::Spoof => "spoof",
+            EventKind::Conflict => "conflict",
+            EventKind::Health => "health",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl EventKind {
+    /// 对某个地址执行或撤销的处置动作，计入该地址的处置历史
+    pub fn is_action(self) -> bool {
+        matches!(
+            self,
+            EventKind::Ban
+                | EventKind::Limit
+                | EventKind::Unblock
+                | EventKind::Extend
+                | EventKind::Mirror
+                | EventKind::Police
+                | EventKind::Warn
+                | EventKind::Tarpit
+                | EventKind::Exclude
+                | EventKind::Unexclude
+        )
+    }
+}
+
+/// 守护进程事件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    pub time: DateTime<Utc>,
+    pub kind: EventKind,
+    pub ip: Option<IpAddr>,
+    pub rule_id: Option<RuleId>,
+    pub message: String,
+    /// 事件发生时该 IP 在逐包检查中出现过的 TLS SNI
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sni: Vec<String>,
+    /// 由自动动作产生的事件所对应的触发原因
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<Reason>,
+}
+
+impl Event {
+    pub fn new(kind: EventKind, message: impl Into<String>) -> Self {
+        Self {
+            time: Utc::now(),
+            kind,
+            ip: None,
+            rule_id: None,
+            message: message.into(),
+            sni: Vec::new(),
+            reason: None,
+        }
+    }
+
+    pub fn with_ip(mut self, ip: IpAddr) -> Self {
+        self.ip = Some(ip);
+        self
+    }
+
+    pub fn with_rule(mut self, rule_id: &RuleId) -> Self {
+        self.rule_id = Some(rule_id.clone());
+        self
+    }
+
+    pub fn with_sni(mut self, sni: Vec<String>) -> Self {
+        self.sni = sni;
+        self
+    }
+
+    pub fn with_reason(mut self, reason: Reason) -> Self {
+        self.reason = Some(reason);
+        self
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} [{}] {}",
+            self.time.format("%H:%M:%S"),
+            self.kind,
+            self.message
+        )?;
+        if let Some(reason) = &self.reason {
+            write!(f, " [{}]", reason)?;
+        }
+        if !self.sni.is_empty() {
+            write!(f, " (sni: {})", self.sni.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for EventKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            EventKind::Ban,
+            EventKind::Limit,
+            EventKind::Unblock,
+            EventKind::Extend,
+            EventKind::Mirror,
+            EventKind::Inspect,
+            EventKind::Police,
+            EventKind::Warn,
+            EventKind::Tarpit,
+            EventKind::Exclude,
+            EventKind::Unexclude,
+            EventKind::Flush,
+            EventKind::Incident,
+            EventKind::Warning,
+            EventKind::Capacity,
+            EventKind::Spoof,
+            EventKind::Conflict,
+            EventKind::Health,
+        ]
+        .into_iter()
+        .find(|kind| kind.to_string() == s)
+        .ok_or_else(|| anyhow::anyhow!("unknown event kind: {}", s))
+    }
+}
+
+/// 一次决策，即某一时刻规则引擎或防火墙产生的事件，规则模拟与决策日志重放都以它输出结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Decision {
+    /// 自场景开始的秒数
+    pub at_secs: u64,
+    pub kind: EventKind,
+    pub ip: Option<IpAddr>,
+    pub rule_id: Option<RuleId>,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<Reason>,
+}
+
+impl Decision {
+    pub fn from_event(at_secs: u64, event: Event) -> Self {
+        Self {
+            at_secs,
+            kind: event.kind,
+            ip: event.ip,
+            rule_id: event.rule_id,
+            message: event.message,
+            reason: event.reason,
+        }
+    }
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>5}s {:<9} {}",
+            self.at_secs,
+            self.kind.to_string(),
+            self.message
+        )?;
+        if let Some(reason) = &self.reason {
+            write!(f, " [{}]", reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// 短时间内大量动作归并成的事件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Incident {
+    pub id: u64,
+    pub started_at: DateTime<Utc>,
+    pub last_action_at: DateTime<Utc>,
+    /// 结束时间，进行中为 None
+    pub ended_at: Option<DateTime<Utc>>,
+    /// 动作总数
+    pub actions: u64,
+    /// 涉及的来源 IP 数
+    pub total_ips: usize,
+    /// 触发动作时的最高流量，字节/秒
+    pub peak_bps: u64,
+    /// 动作最多的来源网段（IPv4 /24、IPv6 /48）及其动作数，按动作数降序
+    pub top_networks: Vec<(String, u64)>,
+}
+
+impl fmt::Display for Incident {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "incident #{}: {} actions on {} IPs, peak {} bytes/s",
+            self.id, self.actions, self.total_ips, self.peak_bps
+        )?;
+        if let Some((network, actions)) = self.top_networks.first() {
+            write!(f, ", top network {} ({} actions)", network, actions)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_event_and_decision_schema() {
+        let ip: IpAddr = "198.51.100.7".parse().unwrap();
+        let rule_id: RuleId = "ban_198.51.100.7_1".parse().unwrap();
+        let mut event = Event::new(EventKind::Ban, "banned 198.51.100.7")
+            .with_ip(ip)
+            .with_rule(&rule_id);
+        event.time = "2024-05-01T12:00:00Z".parse().unwrap();
+        // 字段名即对外格式，改名需递增 SCHEMA_VERSION
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            json!({
+                "time": "2024-05-01T12:00:00Z",
+                "kind": "Ban",
+                "ip": "198.51.100.7",
+                "rule_id": "ban_198.51.100.7_1",
+                "message": "banned 198.51.100.7",
+            })
+        );
+
+        let decision = Decision::from_event(30, event);
+        let value = serde_json::to_value(&decision).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "at_secs": 30,
+                "kind": "Ban",
+                "ip": "198.51.100.7",
+                "rule_id": "ban_198.51.100.7_1",
+                "message": "banned 198.51.100.7",
+            })
+        );
+        let parsed: Decision = serde_json::from_value(value).unwrap();
+        assert_eq!(parsed.rule_id, Some(rule_id));
+    }
+}
@@ -1,3 +1,12 @@
+pub mod clock;
 pub mod config;
+pub mod events;
+pub mod reason;
+pub mod rule_id;
+pub mod scenario;
 pub mod transport;
 pub mod utils;
+
+/// 共享类型（规则、事件、决策、流量统计等）序列化格式的版本。
+/// 字段只增加不改名，新增字段带默认值；改名、删除字段或改变字段含义时递增
+pub const SCHEMA_VERSION: u32 = 1;
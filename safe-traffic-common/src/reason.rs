@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// 自动动作的触发原因，随规则保存并出现在事件、控制接口与日志中
+///
+/// 字段均为机器可读的值，展示时可按需本地化；`Display` 输出英文摘要。
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Reason {
+    /// 触发的规则在配置中的序号
+    pub rule: usize,
+    /// 规则在配置中的名称
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule_name: Option<String>,
+    /// 比较的指标，如 `bps`、`new_bps`
+    pub metric: String,
+    /// 观测值，字节/秒
+    pub observed: u64,
+    /// 阈值，字节/秒
+    pub threshold: u64,
+    /// 计算观测值的窗口，秒
+    pub window_secs: u64,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.rule_name {
+            Some(name) => write!(f, "rule {} ({})", self.rule, name)?,
+            None => write!(f, "rule {}", self.rule)?,
+        }
+        write!(
+            f,
+            ": {} {} > {} over {}s",
+            self.metric, self.observed, self.threshold, self.window_secs
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reason_display_and_serde() {
+        let reason = Reason {
+            rule: 1,
+            rule_name: Some("syn-flood".to_string()),
+            metric: "new_bps".to_string(),
+            observed: 1200,
+            threshold: 500,
+            window_secs: 10,
+        };
+        assert_eq!(
+            reason.to_string(),
+            "rule 1 (syn-flood): new_bps 1200 > 500 over 10s"
+        );
+        let json = serde_json::to_string(&reason).unwrap();
+        assert_eq!(serde_json::from_str::<Reason>(&json).unwrap(), reason);
+    }
+}
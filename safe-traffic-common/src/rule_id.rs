@@ -0,0 +1,183 @@
+use chrono::Utc;
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use std::{
+    fmt,
+    net::IpAddr,
+    str::FromStr,
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+/// 规则类型，作为规则 ID 的命名空间
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum RuleKind {
+    Ban,
+    Limit,
+    Mirror,
+    Inspect,
+    Police,
+    Warn,
+}
+
+impl fmt::Display for RuleKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s: &str = match self {
+            RuleKind::Ban => "ban",
+            RuleKind::Limit => "limit",
+            RuleKind::Mirror => "mirror",
+            RuleKind::Inspect => "inspect",
+            RuleKind::Police => "police",
+            RuleKind::Warn => "warn",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for RuleKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ban" => Ok(RuleKind::Ban),
+            "limit" => Ok(RuleKind::Limit),
+            "mirror" => Ok(RuleKind::Mirror),
+            "inspect" => Ok(RuleKind::Inspect),
+            "police" => Ok(RuleKind::Police),
+            "warn" => Ok(RuleKind::Warn),
+            _ => anyhow::bail!("unknown rule kind: {}", s),
+        }
+    }
+}
+
+/// 规则标识，由类型、作用对象和唯一序号组成，序列化为 `kind_subject_nonce`
+///
+/// 序号在进程内单调递增，起点取自启动时的微秒时间戳，同一 IP 在同一秒内的多条规则也不会冲突。
+/// 作用对象在各副本间共享，规则引擎与控制器的多处索引不各自复制字符串。
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RuleId {
+    kind: RuleKind,
+    subject: Arc<str>,
+    nonce: u64,
+}
+
+fn next_nonce() -> u64 {
+    static NEXT: OnceLock<AtomicU64> = OnceLock::new();
+    NEXT.get_or_init(|| AtomicU64::new(Utc::now().timestamp_micros().max(0) as u64))
+        .fetch_add(1, Ordering::Relaxed)
+}
+
+impl RuleId {
+    /// 作用于 IP 的规则，可附带限定的源端口
+    pub fn for_ip(kind: RuleKind, ip: IpAddr, source_ports: Option<&[u16]>) -> Self {
+        let subject = match source_ports {
+            Some(ports) if !ports.is_empty() => {
+                let ports: Vec<String> = ports.iter().map(|port| port.to_string()).collect();
+                format!("{}_sport_{}", ip, ports.join("-"))
+            }
+            _ => ip.to_string(),
+        };
+        Self::new(kind, subject)
+    }
+
+    /// 作用于 MAC 地址的规则
+    pub fn for_mac(kind: RuleKind, mac: &str) -> Self {
+        Self::new(kind, format!("mac_{}", mac))
+    }
+
+    fn new(kind: RuleKind, subject: String) -> Self {
+        Self {
+            kind,
+            subject: subject.into(),
+            nonce: next_nonce(),
+        }
+    }
+
+    pub fn kind(&self) -> RuleKind {
+        self.kind
+    }
+
+    /// 规则作用对象，如 `10.0.0.1`、`10.0.0.1_sport_53` 或 `mac_aa:bb:cc:dd:ee:ff`
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+}
+
+impl fmt::Display for RuleId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}_{}", self.kind, self.subject, self.nonce)
+    }
+}
+
+impl FromStr for RuleId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || anyhow::anyhow!("invalid rule id: {}, expected kind_subject_nonce", s);
+        let (kind, rest) = s.split_once('_').ok_or_else(invalid)?;
+        let (subject, nonce) = rest.rsplit_once('_').ok_or_else(invalid)?;
+        if subject.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            kind: kind.parse()?,
+            subject: subject.into(),
+            nonce: nonce.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl Serialize for RuleId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for RuleId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rule_id_round_trip() {
+        let ip: IpAddr = "2001:db8::1".parse().unwrap();
+        let id = RuleId::for_ip(RuleKind::Limit, ip, Some(&[53, 123]));
+        let text = id.to_string();
+        assert!(text.starts_with("limit_2001:db8::1_sport_53-123_"));
+        assert_eq!(text.parse::<RuleId>().unwrap(), id);
+
+        let mac = RuleId::for_mac(RuleKind::Ban, "aa:bb:cc:dd:ee:ff");
+        let json = serde_json::to_string(&mac).unwrap();
+        assert_eq!(serde_json::from_str::<RuleId>(&json).unwrap(), mac);
+        assert_eq!(mac.subject(), "mac_aa:bb:cc:dd:ee:ff");
+    }
+
+    #[test]
+    fn test_rule_ids_do_not_collide() {
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let first = RuleId::for_ip(RuleKind::Ban, ip, None);
+        let second = RuleId::for_ip(RuleKind::Ban, ip, None);
+        assert_ne!(first, second);
+        assert!(second.nonce() > first.nonce());
+    }
+
+    #[test]
+    fn test_invalid_rule_ids() {
+        assert!("ban_10.0.0.1".parse::<RuleId>().is_err());
+        assert!("drop_10.0.0.1_1".parse::<RuleId>().is_err());
+        assert!("ban__1".parse::<RuleId>().is_err());
+        assert!("ban_10.0.0.1_x".parse::<RuleId>().is_err());
+    }
+}
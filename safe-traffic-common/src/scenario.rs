@@ -0,0 +1,117 @@
+use crate::events::{Decision, EventKind};
+
+use anyhow::{Context, Result, bail};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::{fmt, fs, net::IpAddr, path::Path};
+
+/// 合成流量场景
+#[derive(Deserialize, Debug, Clone, JsonSchema)]
+pub struct Scenario {
+    /// 模拟时长，秒
+    pub duration_secs: u64,
+    /// 流量来源
+    pub sources: Vec<Source>,
+    /// 对决策序列的断言
+    #[serde(default)]
+    pub expect: Vec<Expectation>,
+}
+
+/// 场景中的一个流量来源，在 [from_secs, until_secs) 内以恒定速率发送
+#[derive(Deserialize, Debug, Clone, JsonSchema)]
+pub struct Source {
+    pub ip: IpAddr,
+    /// 字节/秒
+    pub bps: u64,
+    /// 其中新建连接报文的字节/秒，默认 0
+    pub new_bps: Option<u64>,
+    /// 开始发送的时间，秒，默认 0
+    pub from_secs: Option<u64>,
+    /// 停止发送的时间，秒，默认持续到场景结束
+    pub until_secs: Option<u64>,
+}
+
+impl Source {
+    /// 该秒是否在发送
+    pub fn is_active(&self, at: u64) -> bool {
+        at >= self.from_secs.unwrap_or(0) && self.until_secs.is_none_or(|until| at < until)
+    }
+}
+
+/// 断言某类决策在限定时间内发生，或 never 为 true 时从不发生
+#[derive(Deserialize, Debug, Clone, JsonSchema)]
+pub struct Expectation {
+    /// 只看针对该 IP 的决策，默认任意 IP
+    pub ip: Option<IpAddr>,
+    /// 决策类型，如 Ban、Limit
+    pub action: EventKind,
+    /// 最晚发生的时间，秒，默认场景时长
+    pub within_secs: Option<u64>,
+    /// 断言该决策从不发生，默认 false
+    #[serde(default)]
+    pub never: bool,
+}
+
+impl Expectation {
+    /// 检查决策序列是否满足断言
+    pub fn check(&self, decisions: &[Decision]) -> Result<()> {
+        let first = decisions.iter().find(|decision| {
+            decision.kind == self.action && self.ip.is_none_or(|ip| decision.ip == Some(ip))
+        });
+        match (first, self.never) {
+            (Some(decision), true) => bail!("{} at {}s", self, decision.at_secs),
+            (None, false) => bail!("{}: never happened", self),
+            (Some(decision), false) => match self.within_secs {
+                Some(within) if decision.at_secs > within => {
+                    bail!("{}: first happened at {}s", self, decision.at_secs)
+                }
+                _ => Ok(()),
+            },
+            (None, true) => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Expectation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let target = self
+            .ip
+            .map(|ip| ip.to_string())
+            .unwrap_or_else(|| "any ip".to_string());
+        if self.never {
+            write!(f, "expected no {} of {}", self.action, target)
+        } else {
+            write!(f, "expected {} of {}", self.action, target)?;
+            match self.within_secs {
+                Some(within) => write!(f, " within {}s", within),
+                None => Ok(()),
+            }
+        }
+    }
+}
+
+impl Scenario {
+    pub fn parse(text: &str) -> Result<Self> {
+        let scenario: Scenario = toml::from_str(text)?;
+        if scenario.duration_secs == 0 {
+            bail!("duration_secs must be greater than 0");
+        }
+        Ok(scenario)
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read scenario {}", path.display()))?;
+        Self::parse(&text)
+    }
+
+    /// 检查全部断言，返回未满足的断言说明
+    pub fn failures(&self, decisions: &[Decision]) -> Vec<String> {
+        self.expect
+            .iter()
+            .filter_map(|expectation| expectation.check(decisions).err())
+            .map(|e| e.to_string())
+            .collect()
+    }
+}
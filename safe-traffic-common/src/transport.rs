@@ -1,7 +1,13 @@
-use crate::utils::FirewallRule;
+use crate::{
+    config::LogLevel,
+    events::{Event, EventKind, Incident},
+    rule_id::RuleId,
+    utils::{ExcludedTraffic, FirewallRule, RunState},
+};
 
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
-use std::net::IpAddr;
+use std::{fmt, net::IpAddr};
 
 /// 客户端请求类型
 #[derive(Debug, Deserialize, Serialize)]
@@ -13,15 +19,71 @@ pub enum Request {
         kbps: u64,
         burst: Option<u64>,
         seconds: Option<u64>,
+        /// 客户端生成的幂等键，超时重试时带上同一个键不会重复创建规则
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        idempotency_key: Option<String>,
     },
     /// 封禁IP指定时长
-    Ban { ip: IpAddr, seconds: Option<u64> },
+    Ban {
+        ip: IpAddr,
+        seconds: Option<u64>,
+        /// 客户端生成的幂等键，超时重试时带上同一个键不会重复创建规则
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        idempotency_key: Option<String>,
+    },
     /// 检查规则是否过期
-    IsExpiration { rule_id: String, seconds: u64 },
+    IsExpiration { rule_id: RuleId, seconds: u64 },
     /// 解封指定规则ID
-    Unblock { rule_id: String },
-    /// 白名单
-    Exclude { ip: IpAddr },
+    Unblock { rule_id: RuleId },
+    /// 延长规则生效时长
+    Extend { rule_id: RuleId, seconds: u64 },
+    /// 在一个事务中解除所有符合条件的规则
+    UnblockMatching { filter: RuleFilter },
+    /// 白名单，seconds 为空时永久生效，否则到期后自动移除
+    Exclude {
+        ip: IpAddr,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        seconds: Option<u64>,
+    },
+    /// 移出白名单
+    RemoveExclude { ip: IpAddr },
+    /// 获取白名单
+    GetExcludes,
+    /// 获取带有效期的白名单及其到期时间
+    GetTemporaryExcludes,
+    /// 获取白名单来源的流量统计
+    GetExcludedStats,
+    /// 获取最近的事件归并（incident）
+    GetIncidents,
+    /// 获取时间范围内仍保留在事件缓冲中的事件
+    GetEvents {
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    },
+    /// 按条件查询事件，配置了事件归档时包括已归档的事件
+    QueryEvents { query: EventQuery },
+    /// 分页获取某个 IP 的处置历史，按时间倒序，offset 为跳过的最新条数
+    GetHistory {
+        ip: IpAddr,
+        offset: usize,
+        limit: Option<usize>,
+    },
+    /// 获取 NFQUEUE 逐包检查中的 IP
+    GetInspections,
+    /// 汇总守护进程对某个 IP 的处置依据
+    Explain { ip: IpAddr },
+    /// 获取规则引擎中某个 IP 的滑动窗口原始数据与各规则窗口的平均值
+    GetWindow { ip: IpAddr },
+    /// 逐拍以 debug 级别记录规则引擎对某个 IP 的评估过程，ip 为空时停止
+    Trace { ip: Option<IpAddr> },
+    /// 获取连接跟踪中的流，ip 为空时返回全部
+    GetFlows { ip: Option<IpAddr> },
+    /// 获取统计规则的累计计数
+    GetAccounting,
+    /// 获取受管规则每天丢弃的流量
+    GetSavings,
+    /// 获取各外部集成的健康度
+    GetHealth,
 
     /// 获取所有活跃规则
     GetActiveRules,
@@ -43,6 +105,39 @@ pub enum Request {
     Pause,
     /// 恢复规则检查
     Resume,
+    /// 暂停对单个 IP 或单条规则的处置，seconds 为空时直到手动恢复
+    PauseTarget {
+        target: PauseTarget,
+        seconds: Option<u64>,
+    },
+    /// 恢复对单个 IP 或单条规则的处置
+    ResumeTarget { target: PauseTarget },
+    /// 获取生效中的定向暂停
+    GetPauses,
+    /// 获取仪表盘快照
+    Dashboard,
+    /// 获取面向告警的派生指标
+    GetAlerts,
+    /// 获取最近的守护进程日志，follow 时随后持续推送新日志直到客户端断开；
+    /// 首行为 Response，之后每行一条 LogLine
+    TailLogs { filter: LogFilter, follow: bool },
+    /// 订阅之后记录的事件，持续推送直到客户端断开；首行为 Response，之后每行一条 Event
+    SubscribeEvents,
+}
+
+impl Request {
+    /// 取出请求携带的幂等键，剩下的请求用于判断重复的键是否对应同一个请求
+    pub fn take_idempotency_key(&mut self) -> Option<String> {
+        match self {
+            Request::Ban {
+                idempotency_key, ..
+            }
+            | Request::Limit {
+                idempotency_key, ..
+            } => idempotency_key.take(),
+            _ => None,
+        }
+    }
 }
 
 /// 服务器响应类型
@@ -65,6 +160,537 @@ pub enum ResponseData {
     StringList(Vec<String>),
     /// 规则列表结果
     RuleList(Vec<FirewallRule>),
+    /// nftables 中实际存在的规则
+    SystemRules(Vec<SystemRule>),
+    /// 白名单流量统计结果
+    ExcludedStats(Vec<ExcludedTraffic>),
+    /// 最近的事件归并
+    Incidents(Vec<Incident>),
+    /// 事件列表
+    Events(Vec<Event>),
+    /// 逐包检查状态
+    Inspections(Vec<Inspection>),
+    /// 单个 IP 的处置依据
+    Explanation(Explanation),
+    /// 单个 IP 的滑动窗口数据
+    Window(WindowSnapshot),
+    /// 批量操作的逐项结果
+    BatchResult(Vec<Result<RuleId, BatchItemError>>),
+    /// 仪表盘快照
+    Dashboard(DashboardSnapshot),
+    /// 面向告警的派生指标
+    Alerts(AlertSignals),
+    /// 生效中的定向暂停
+    Pauses(Vec<TargetedPause>),
+    /// 连接跟踪中的流
+    Flows(Vec<FlowEntry>),
+    /// 统计规则的累计计数
+    Accounting(Vec<AccountingCounter>),
+    /// 带有效期的白名单
+    TemporaryExcludes(Vec<TemporaryExclude>),
+    /// 每天丢弃的流量
+    Savings(Vec<SavingsDay>),
+    /// 各外部集成的健康度
+    Health(Vec<IntegrationHealth>),
+    /// 某个 IP 的一页处置历史
+    History(ActionHistory),
     /// Ping响应
     Pong,
 }
+
+impl ResponseData {
+    /// 是否为空列表；untagged 反序列化时任何类型的空列表都会落到 StringList，按列表类型取结果前先判断
+    pub fn is_empty_list(&self) -> bool {
+        matches!(self, ResponseData::StringList(items) if items.is_empty())
+    }
+}
+
+/// nftables 链中实际存在的一条规则
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemRule {
+    pub handle: Option<u64>,
+    /// 匹配条件，如 `ip saddr == 10.0.0.1`
+    pub matchers: Vec<String>,
+    pub packets: Option<u64>,
+    pub bytes: Option<u64>,
+    /// 其余语句，如 `drop`、`limit rate 100 kbytes/second`
+    pub statements: Vec<String>,
+}
+
+impl fmt::Display for SystemRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.handle {
+            Some(handle) => write!(f, "[{}]", handle)?,
+            None => write!(f, "[-]")?,
+        }
+        for matcher in &self.matchers {
+            write!(f, " {}", matcher)?;
+        }
+        if let (Some(packets), Some(bytes)) = (self.packets, self.bytes) {
+            write!(f, " counter packets {} bytes {}", packets, bytes)?;
+        }
+        for statement in &self.statements {
+            write!(f, " {}", statement)?;
+        }
+        Ok(())
+    }
+}
+
+/// 批量解除规则的筛选条件，各条件同时满足才匹配；均为空时匹配全部规则
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RuleFilter {
+    /// 仅匹配触发 IP 落在该网段内的规则，如 `203.0.113.0/24`
+    pub network: Option<String>,
+    /// 仅匹配已生效超过该秒数的规则
+    pub older_than: Option<u64>,
+}
+
+impl fmt::Display for RuleFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.network, self.older_than) {
+            (None, None) => write!(f, "all rules"),
+            (Some(network), None) => write!(f, "rules in {}", network),
+            (None, Some(secs)) => write!(f, "rules older than {}s", secs),
+            (Some(network), Some(secs)) => {
+                write!(f, "rules in {} older than {}s", network, secs)
+            }
+        }
+    }
+}
+
+/// 事件查询条件，各条件同时满足才匹配；均为空时匹配全部事件
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EventQuery {
+    pub ip: Option<IpAddr>,
+    /// 规则 ID，或触发动作的规则在配置中的名称、序号
+    pub rule: Option<String>,
+    pub kind: Option<EventKind>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// 只返回最近的若干条
+    pub limit: Option<usize>,
+}
+
+impl EventQuery {
+    pub fn matches(&self, event: &Event) -> bool {
+        self.ip.is_none_or(|ip| event.ip == Some(ip))
+            && self.kind.is_none_or(|kind| event.kind == kind)
+            && self.since.is_none_or(|since| event.time >= since)
+            && self.until.is_none_or(|until| event.time <= until)
+            && self.rule.as_ref().is_none_or(|rule| {
+                event
+                    .rule_id
+                    .as_ref()
+                    .is_some_and(|rule_id| &rule_id.to_string() == rule)
+                    || event.reason.as_ref().is_some_and(|reason| {
+                        reason.rule_name.as_ref() == Some(rule) || &reason.rule.to_string() == rule
+                    })
+            })
+    }
+}
+
+/// 守护进程的一条日志，规则动作范围内的日志带有 IP、规则与动作
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogLine {
+    pub time: DateTime<Utc>,
+    pub level: LogLevel,
+    /// 产生日志的模块路径
+    pub target: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip: Option<IpAddr>,
+    /// 规则序号
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+}
+
+impl fmt::Display for LogLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:>5} {}: {}",
+            self.time.format("%Y-%m-%d %H:%M:%S%.3f"),
+            self.level.to_string().to_uppercase(),
+            self.target,
+            self.message
+        )?;
+        if let Some(ip) = self.ip {
+            write!(f, " ip={}", ip)?;
+        }
+        if let Some(rule) = self.rule {
+            write!(f, " rule={}", rule)?;
+        }
+        if let Some(action) = &self.action {
+            write!(f, " action={}", action)?;
+        }
+        Ok(())
+    }
+}
+
+/// 日志跟踪的筛选条件，各条件同时满足才匹配
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LogFilter {
+    /// 最详细的级别，默认 Info
+    pub level: Option<LogLevel>,
+    /// 动作作用于该 IP，或消息中出现该 IP
+    pub ip: Option<IpAddr>,
+    /// 规则名称或序号
+    pub rule: Option<String>,
+    /// 模块路径包含该字符串，如 `rules`
+    pub target: Option<String>,
+}
+
+impl LogFilter {
+    /// 允许的最详细级别
+    pub fn level(&self) -> LogLevel {
+        self.level.unwrap_or(LogLevel::Info)
+    }
+
+    pub fn matches(&self, line: &LogLine) -> bool {
+        line.level <= self.level()
+            && self.ip.is_none_or(|ip| {
+                line.ip == Some(ip)
+                    || line
+                        .message
+                        .split(|c: char| !(c.is_ascii_hexdigit() || c == '.' || c == ':'))
+                        .any(|word| word.trim_end_matches([':', '.']).parse() == Ok(ip))
+            })
+            && self.rule.as_ref().is_none_or(|rule| {
+                line.rule_name.as_ref() == Some(rule)
+                    || line.rule.is_some_and(|index| &index.to_string() == rule)
+            })
+            && self
+                .target
+                .as_ref()
+                .is_none_or(|target| line.target.contains(target.as_str()))
+    }
+}
+
+/// 批量操作中单个条目的失败原因
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchItemError {
+    pub ip: IpAddr,
+    pub reason: String,
+}
+
+impl fmt::Display for BatchItemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.ip, self.reason)
+    }
+}
+
+impl std::error::Error for BatchItemError {}
+
+/// 仪表盘所需的守护进程状态快照
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DashboardSnapshot {
+    /// 规则引擎运行状态
+    pub engine_state: RunState,
+    pub nft_available: bool,
+    /// 执行器池中空闲进程数
+    pub pool_size: usize,
+    /// 可用执行器许可数
+    pub available_executors: usize,
+    pub ban_rules: usize,
+    pub limit_rules: usize,
+    /// nft 不可用期间暂存、等待执行的动作数
+    pub deferred_actions: usize,
+    /// 每条配置规则的累计命中次数，按配置顺序排列
+    pub rule_hits: Vec<u64>,
+    /// 规则引擎正在跟踪的来源数
+    #[serde(default)]
+    pub tracked_sources: usize,
+    /// 每个跟踪来源的窗口与流量统计占用的内存，字节
+    #[serde(default)]
+    pub bytes_per_source: usize,
+    /// 最近事件
+    pub recent_events: Vec<Event>,
+}
+
+/// WebSocket 每次推送的内容
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PushUpdate {
+    pub snapshot: DashboardSnapshot,
+    /// 上次推送之后产生的事件，按时间先后排列
+    pub events: Vec<Event>,
+    /// 订阅者接收过慢而丢失的事件数
+    pub missed_events: u64,
+}
+
+/// 主节点经 WebSocket 回复备节点的状态，备节点每次请求即一次心跳
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StandbyState {
+    pub rules: Vec<FirewallRule>,
+    pub excludes: Vec<IpAddr>,
+    /// 带有效期的白名单，excludes 中不在此列的为永久白名单
+    pub temporary_excludes: Vec<TemporaryExclude>,
+}
+
+/// 一个 IP 的逐包检查统计
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Inspection {
+    pub ip: IpAddr,
+    /// 已判定的报文数
+    pub packets: u64,
+    /// 判定丢弃的报文数
+    pub dropped: u64,
+    /// 最近报文中最常见的负载长度及其占比
+    pub dominant_size: Option<(u16, f64)>,
+    /// 当前是否丢弃该长度的报文
+    pub dropping: bool,
+    /// 从 TLS ClientHello 中提取的 SNI
+    pub sni: Vec<String>,
+}
+
+/// 守护进程对单个 IP 的处置依据
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Explanation {
+    pub ip: IpAddr,
+    /// 是否在全局白名单中
+    pub excluded: bool,
+    /// 当前信誉分，越高表示近期违规越多
+    pub reputation: f64,
+    /// 作用于该 IP 的活跃规则
+    pub rules: Vec<FirewallRule>,
+    /// 逐包检查统计，包括观察到的 SNI
+    pub inspection: Option<Inspection>,
+    /// 与该 IP 相关的最近事件
+    pub events: Vec<Event>,
+    /// 与该 IP 相关的流，按字节数降序；未配置 [flows] 时为空
+    #[serde(default)]
+    pub flows: Vec<FlowEntry>,
+}
+
+/// 一条统计规则的累计计数，入站与出站分别统计
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountingCounter {
+    pub name: String,
+    pub rx_bytes: u64,
+    pub rx_packets: u64,
+    pub tx_bytes: u64,
+    pub tx_packets: u64,
+}
+
+/// 一天（UTC）中受管规则丢弃的流量，即为上游节省的带宽
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavingsDay {
+    pub date: NaiveDate,
+    pub bytes: u64,
+    pub packets: u64,
+}
+
+/// 某个 IP 的一页处置历史：封禁、限速、解除、延长等动作，按时间倒序
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActionHistory {
+    pub ip: IpAddr,
+    /// 该 IP 全部处置事件的条数
+    pub total: usize,
+    pub offset: usize,
+    /// 下一页的 offset，已是最后一页时为 None
+    pub next_offset: Option<usize>,
+    pub events: Vec<Event>,
+}
+
+/// 一个外部集成（webhook、云厂商元数据、上游封禁列表等）的健康度
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IntegrationHealth {
+    /// 集成名称，如 `upstream`、`notify:oncall`
+    pub integration: String,
+    /// 0-100，最近调用中成功的比例；周期性集成超过预期间隔没有成功时为 0
+    pub score: u8,
+    /// 计入健康分的最近调用次数
+    pub calls: usize,
+    pub failures: usize,
+    /// 最近调用的平均耗时，毫秒
+    pub latency_ms: Option<u64>,
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub degraded: bool,
+}
+
+/// 连接跟踪中的一条流，方向与计数取发起方向
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FlowEntry {
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    /// 传输层协议名，如 `tcp`、`udp`、`icmp`
+    pub proto: String,
+    /// 目的端口，无端口的协议为 None
+    pub dport: Option<u16>,
+    pub bytes: u64,
+    pub packets: u64,
+}
+
+impl fmt::Display for FlowEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} -> {}", self.proto, self.src, self.dst)?;
+        if let Some(dport) = self.dport {
+            write!(f, ":{}", dport)?;
+        }
+        write!(f, " {} bytes {} packets", self.bytes, self.packets)
+    }
+}
+
+/// 规则引擎眼中单个 IP 的流量，即规则检查时使用的数据
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WindowSnapshot {
+    pub ip: IpAddr,
+    /// 最近一次采样的每秒字节数，Input 钩子按 rx 统计，Output 钩子按 tx 统计
+    pub rx_delta: u64,
+    pub tx_delta: u64,
+    pub rx_new_delta: u64,
+    pub tx_new_delta: u64,
+    /// 已写入窗口的采样数
+    pub samples: u64,
+    /// 窗口中的原始采样，字节/秒，按时间从旧到新排列
+    pub slots: Vec<u64>,
+    /// 新建连接报文的原始采样
+    pub new_slots: Vec<u64>,
+    /// 已建立连接流量的原始采样
+    pub established_slots: Vec<u64>,
+    /// 每条规则按其窗口大小计算的当前平均值，按配置顺序排列
+    pub averages: Vec<WindowAverage>,
+}
+
+/// 一条规则窗口内的平均流量
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WindowAverage {
+    /// 规则在配置中的序号
+    pub rule: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule_name: Option<String>,
+    /// 比较的指标，如 `bps`、`new_bps`
+    pub metric: String,
+    pub window_secs: u64,
+    /// 平均值，字节/秒
+    pub average_bps: u64,
+    pub threshold_bps: u64,
+    /// 采样数是否已填满该窗口
+    pub warm: bool,
+}
+
+/// 面向告警设计的派生指标
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertSignals {
+    /// 是否有进行中的 incident
+    pub attack_in_progress: bool,
+    /// 最近的自动动作从触发到 nft 规则生效耗时的 p99，毫秒；尚无动作时为 None
+    pub enforcement_latency_p99_ms: Option<u64>,
+    /// 守护进程记录的规则中在 nftables 里已不存在的数量
+    pub divergent_rules: usize,
+    /// 启动以来管理接口 token 校验失败的次数
+    #[serde(default)]
+    pub management_auth_failures: u64,
+    /// 启动以来因反复校验失败被锁定的来源次数
+    #[serde(default)]
+    pub management_lockouts: u64,
+    /// 当前处于锁定中的来源数
+    #[serde(default)]
+    pub management_locked_out: usize,
+    /// 受管规则累计丢弃的字节数，跨重启累计
+    #[serde(default)]
+    pub dropped_bytes: u64,
+    /// 受管规则累计丢弃的报文数
+    #[serde(default)]
+    pub dropped_packets: u64,
+    /// 当天（UTC）丢弃的字节数
+    #[serde(default)]
+    pub dropped_bytes_today: u64,
+}
+
+impl AlertSignals {
+    /// 守护进程的规则记录与 nftables 是否不一致
+    pub fn divergence_detected(&self) -> bool {
+        self.divergent_rules > 0
+    }
+}
+
+/// 定向暂停的对象
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PauseTarget {
+    /// 不对该 IP 执行任何规则的动作
+    Ip(IpAddr),
+    /// 暂停该规则：配置中的名称或序号
+    Rule(String),
+}
+
+impl fmt::Display for PauseTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PauseTarget::Ip(ip) => write!(f, "ip {}", ip),
+            PauseTarget::Rule(rule) => write!(f, "rule {}", rule),
+        }
+    }
+}
+
+/// 一项带有效期的白名单
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TemporaryExclude {
+    pub ip: IpAddr,
+    /// 到期自动移除的时间
+    pub until: DateTime<Utc>,
+}
+
+impl fmt::Display for TemporaryExclude {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} excluded until {}", self.ip, self.until)
+    }
+}
+
+/// 一项生效中的定向暂停
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TargetedPause {
+    pub target: PauseTarget,
+    /// 自动恢复的时间，None 表示直到手动恢复
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl fmt::Display for TargetedPause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.until {
+            Some(until) => write!(f, "{} paused until {}", self.target, until),
+            None => write!(f, "{} paused until resumed", self.target),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(data: ResponseData) -> ResponseData {
+        serde_json::from_str(&serde_json::to_string(&data).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_empty_lists_decode_as_string_list() {
+        for empty in [
+            ResponseData::RuleList(Vec::new()),
+            ResponseData::Events(Vec::new()),
+            ResponseData::StringList(Vec::new()),
+        ] {
+            let decoded = round_trip(empty);
+            assert!(matches!(decoded, ResponseData::StringList(_)));
+            assert!(decoded.is_empty_list());
+        }
+        assert!(!round_trip(ResponseData::StringList(vec!["192.0.2.1".into()])).is_empty_list());
+        assert!(!round_trip(ResponseData::Pong).is_empty_list());
+    }
+
+    #[test]
+    fn test_temporary_excludes_response() {
+        let response = Response::Success(ResponseData::TemporaryExcludes(vec![TemporaryExclude {
+            ip: "198.51.100.7".parse().unwrap(),
+            until: Utc::now(),
+        }]));
+        let json = serde_json::to_string(&response).unwrap();
+        assert!(matches!(
+            serde_json::from_str(&json).unwrap(),
+            Response::Success(ResponseData::TemporaryExcludes(excludes)) if excludes.len() == 1
+        ));
+    }
+}
@@ -0,0 +1,135 @@
+//! 规则引擎热路径基准测试
+//!
+//! 运行 `cargo bench -p safe-traffic-daemon -- --save-baseline main` 保存基线，
+//! 修改后运行 `cargo bench -p safe-traffic-daemon -- --baseline main` 对比每个 tick 的耗时是否退化。
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use dashmap::DashMap;
+use safe_traffic_common::{config::Config, utils::TrafficStats};
+use safe_traffic_daemon::{controller::Firewall, nft, rules::RuleEngine, rules::Window};
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::runtime::Runtime;
+
+const IP_COUNT: u32 = 100_000;
+
+const CONFIG: &str = r#"
+    interface = "eth0"
+
+    [[rules]]
+    window_secs = 10
+    threshold_bps = 9223372036854775807
+    action = { Ban = { seconds = 60 } }
+
+    [[rules]]
+    window_secs = 60
+    threshold_bps = 9223372036854775807
+    action = { RateLimit = { kbps = 100, seconds = 60 } }
+"#;
+
+fn ip(i: u32) -> IpAddr {
+    IpAddr::V4(Ipv4Addr::from(0x0a00_0000 + i))
+}
+
+async fn mock_firewall(cfg: &Config) -> Arc<Firewall> {
+    let executor = Arc::new(nft::NftExecutor::new(1, 300, 100, true).await);
+    Arc::new(Firewall::new(cfg, executor).await.unwrap())
+}
+
+fn window_math(c: &mut Criterion) {
+    let start = Duration::ZERO;
+    let mut window = Window::new(start);
+    for i in 1..=60 {
+        window.advance(i * 1000, start + Duration::from_secs(i));
+    }
+
+    c.bench_function("window_advance", |b| {
+        b.iter_batched(
+            || window.clone(),
+            |mut w| w.advance(black_box(4096), start + Duration::from_secs(61)),
+            BatchSize::SmallInput,
+        )
+    });
+    c.bench_function("window_average_60s", |b| {
+        b.iter(|| window.average(black_box(60)))
+    });
+}
+
+fn rule_evaluation(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let cfg: Config = Config::parse(CONFIG).unwrap();
+    let fw = rt.block_on(mock_firewall(&cfg));
+
+    let stats = Arc::new(DashMap::new());
+    let engine = RuleEngine::new(cfg.rules.clone(), stats.clone());
+    // 窗口由监控器在采样时推进，这里预先写入两次采样
+    let windows = engine.windows();
+    for i in 0..IP_COUNT {
+        let sample = TrafficStats {
+            rx_delta: i as u64,
+            tx_delta: i as u64,
+            ..Default::default()
+        };
+        windows.record(ip(i), &sample, Duration::ZERO);
+        windows.record(ip(i), &sample, Duration::from_secs(1));
+        stats.insert(ip(i), sample);
+    }
+
+    let mut group = c.benchmark_group("rule_evaluation");
+    group.sample_size(10);
+    group.bench_function("check_and_apply_100k_ips", |b| {
+        b.to_async(&rt)
+            .iter(|| engine.check_and_apply(Arc::clone(&fw)))
+    });
+    group.finish();
+}
+
+fn command_generation(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let cfg: Config = Config::parse(CONFIG).unwrap();
+    let fw = rt.block_on(mock_firewall(&cfg));
+    let ports = [19, 53, 123, 389];
+
+    c.bench_function("ban_rule_command", |b| {
+        b.iter(|| fw.ban_rule_command(black_box(ip(42)), None))
+    });
+    c.bench_function("limit_rule_command_with_ports", |b| {
+        b.iter(|| fw.limit_rule_command(black_box(ip(42)), 100, 10, Some(&ports)))
+    });
+}
+
+fn parse_large_output(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let rules: Vec<String> = (0..10_000u32)
+        .map(|i| {
+            format!(
+                r#"{{"rule": {{"family": "inet", "table": "traffic_monitor", "chain": "input_stats", "handle": {}, "expr": [{{"match": {{"op": "==", "left": {{"payload": {{"protocol": "ip", "field": "saddr"}}}}, "right": "{}"}}}}, {{"counter": {{"packets": {}, "bytes": {}}}}}, {{"accept": null}}]}}}}"#,
+                i,
+                ip(i),
+                i,
+                i as u64 * 1500
+            )
+        })
+        .collect();
+    let output = format!(r#"{{"nftables": [{}]}}"#, rules.join(", "));
+
+    let mut group = c.benchmark_group("parse_output");
+    group.sample_size(20);
+    group.bench_function("parse_output_10k_rules", |b| {
+        b.to_async(&rt)
+            .iter(|| nft::parse_output(black_box(&output)))
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    window_math,
+    rule_evaluation,
+    command_generation,
+    parse_large_output
+);
+criterion_main!(benches);
@@ -0,0 +1,207 @@
+//! 流量统计：在独立的表中为每条统计规则安装只计数、不判决的规则，按服务汇总本机收发的流量，
+//! 不配置任何动作规则时守护进程即是一个轻量的流量统计代理
+
+use crate::nft::{parser::Expression, NftExecutor, NftObject};
+
+use anyhow::Result;
+use log::info;
+use safe_traffic_common::{
+    config::{AccountingProtocol, AccountingRule},
+    transport::AccountingCounter,
+};
+use std::sync::Arc;
+
+const TABLE: &str = "inet traffic_accounting";
+/// 晚于过滤链与扫描减速链，只统计被放行的流量
+const PRIORITY: i32 = 100;
+
+/// 统计规则及其计数的读取
+#[derive(Debug)]
+pub struct Accounting {
+    executor: Arc<NftExecutor>,
+    rules: Vec<AccountingRule>,
+}
+
+impl Accounting {
+    pub fn new(rules: &[AccountingRule], executor: Arc<NftExecutor>) -> Self {
+        Self {
+            executor,
+            rules: rules.to_vec(),
+        }
+    }
+
+    /// 匹配表达式，`port_field` 为入站的 `dport` 或出站的 `sport`
+    fn matcher(rule: &AccountingRule, port_field: &str) -> String {
+        let protocol = match rule.protocol {
+            None => return String::new(),
+            Some(AccountingProtocol::Tcp) => "tcp",
+            Some(AccountingProtocol::Udp) => "udp",
+            Some(AccountingProtocol::Icmp) => {
+                return "meta l4proto { icmp, ipv6-icmp } ".to_string()
+            }
+        };
+        match &rule.ports {
+            Some(ports) => {
+                let ports: Vec<String> = ports.iter().map(|port| port.to_string()).collect();
+                format!("{} {} {{ {} }} ", protocol, port_field, ports.join(", "))
+            }
+            None => format!("meta l4proto {} ", protocol),
+        }
+    }
+
+    /// 创建统计表与入站、出站两条链，每条统计规则在两条链中各有一条计数规则，以注释标识
+    pub fn setup_commands(&self) -> Vec<String> {
+        let mut commands = vec![format!("add table {}", TABLE)];
+        for (chain, hook) in [("input", "input"), ("output", "output")] {
+            commands.push(format!(
+                "add chain {} {} {{ type filter hook {} priority {}; policy accept; }}",
+                TABLE, chain, hook, PRIORITY
+            ));
+            commands.push(format!("flush chain {} {}", TABLE, chain));
+        }
+        for rule in &self.rules {
+            for (chain, port_field) in [("input", "dport"), ("output", "sport")] {
+                commands.push(format!(
+                    "add rule {} {} {}counter comment \"{}\"",
+                    TABLE,
+                    chain,
+                    Self::matcher(rule, port_field),
+                    rule.name
+                ));
+            }
+        }
+        commands
+    }
+
+    pub async fn install(&self) -> Result<()> {
+        self.executor.execute_batch(self.setup_commands()).await?;
+        info!("Accounting traffic of {} services", self.rules.len());
+        Ok(())
+    }
+
+    /// 读取全部统计规则的计数，按配置顺序排列
+    pub async fn counters(&self) -> Result<Vec<AccountingCounter>> {
+        let output = self
+            .executor
+            .execute(&format!("list table {}", TABLE))
+            .await?;
+        self.parse_counters(&output).await
+    }
+
+    /// 从 `list table` 的 JSON 输出中按注释取出计数，没有出现的规则计为 0
+    pub async fn parse_counters(&self, output: &str) -> Result<Vec<AccountingCounter>> {
+        let mut counters: Vec<AccountingCounter> = self
+            .rules
+            .iter()
+            .map(|rule| AccountingCounter {
+                name: rule.name.clone(),
+                rx_bytes: 0,
+                rx_packets: 0,
+                tx_bytes: 0,
+                tx_packets: 0,
+            })
+            .collect();
+        for obj in crate::nft::parse_output(output).await? {
+            let NftObject::Rule(rule_obj) = obj else {
+                continue;
+            };
+            let rule = &rule_obj.rule;
+            let Some(entry) = rule
+                .comment
+                .as_ref()
+                .and_then(|name| counters.iter_mut().find(|entry| &entry.name == name))
+            else {
+                continue;
+            };
+            for expr in rule.expr.iter().flatten() {
+                if let Expression::Counter(counter) = expr {
+                    let (bytes, packets) = match rule.chain.as_str() {
+                        "input" => (&mut entry.rx_bytes, &mut entry.rx_packets),
+                        _ => (&mut entry.tx_bytes, &mut entry.tx_packets),
+                    };
+                    *bytes += counter.counter.bytes;
+                    *packets += counter.counter.packets;
+                }
+            }
+        }
+        Ok(counters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use safe_traffic_common::config::Config;
+
+    const CONFIG: &str = r#"
+        interface = "eth0"
+        rules = []
+
+        [[accounting]]
+        name = "https"
+        protocol = "Tcp"
+        ports = [443, 8443]
+
+        [[accounting]]
+        name = "ssh"
+        protocol = "Tcp"
+        ports = [22]
+
+        [[accounting]]
+        name = "icmp"
+        protocol = "Icmp"
+    "#;
+
+    const LIST_OUTPUT: &str = r#"{"nftables": [
+        {"metainfo": {"version": "1.0.9", "json_schema_version": 1}},
+        {"table": {"family": "inet", "name": "traffic_accounting", "handle": 7}},
+        {"chain": {"family": "inet", "table": "traffic_accounting", "name": "input", "handle": 1, "type": "filter", "hook": "input", "prio": 100, "policy": "accept"}},
+        {"rule": {"family": "inet", "table": "traffic_accounting", "chain": "input", "handle": 3, "comment": "https",
+            "expr": [{"match": {"op": "==", "left": {"payload": {"protocol": "tcp", "field": "dport"}}, "right": {"set": [443, 8443]}}},
+                     {"counter": {"packets": 10, "bytes": 6000}}]}},
+        {"rule": {"family": "inet", "table": "traffic_accounting", "chain": "output", "handle": 4, "comment": "https",
+            "expr": [{"match": {"op": "==", "left": {"payload": {"protocol": "tcp", "field": "sport"}}, "right": {"set": [443, 8443]}}},
+                     {"counter": {"packets": 12, "bytes": 90000}}]}},
+        {"rule": {"family": "inet", "table": "traffic_accounting", "chain": "input", "handle": 5, "comment": "ssh",
+            "expr": [{"match": {"op": "==", "left": {"payload": {"protocol": "tcp", "field": "dport"}}, "right": 22}},
+                     {"counter": {"packets": 3, "bytes": 180}}]}}
+    ]}"#;
+
+    #[tokio::test]
+    async fn test_accounting_rules_and_counters() {
+        let cfg = Config::parse(CONFIG).unwrap();
+        let executor = Arc::new(NftExecutor::new(1, 300, 100, true).await);
+        let accounting = Accounting::new(cfg.accounting.as_deref().unwrap(), executor);
+
+        let commands = accounting.setup_commands();
+        assert!(commands.contains(
+            &"add chain inet traffic_accounting input { type filter hook input priority 100; policy accept; }"
+                .to_string()
+        ));
+        assert!(commands.contains(
+            &"add rule inet traffic_accounting input tcp dport { 443, 8443 } counter comment \"https\""
+                .to_string()
+        ));
+        assert!(commands.contains(
+            &"add rule inet traffic_accounting output tcp sport { 22 } counter comment \"ssh\""
+                .to_string()
+        ));
+        assert!(commands.contains(
+            &"add rule inet traffic_accounting input meta l4proto { icmp, ipv6-icmp } counter comment \"icmp\""
+                .to_string()
+        ));
+        // 统计规则不带判决
+        assert!(commands
+            .iter()
+            .filter(|command| command.starts_with("add rule"))
+            .all(|command| !command.contains("drop") && !command.contains("accept")));
+
+        let counters = accounting.parse_counters(LIST_OUTPUT).await.unwrap();
+        let names: Vec<&str> = counters.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["https", "ssh", "icmp"]);
+        assert_eq!((counters[0].rx_bytes, counters[0].tx_bytes), (6000, 90000));
+        assert_eq!((counters[0].rx_packets, counters[0].tx_packets), (10, 12));
+        assert_eq!((counters[1].rx_bytes, counters[1].tx_bytes), (180, 0));
+        assert_eq!(counters[2].rx_bytes, 0);
+    }
+}
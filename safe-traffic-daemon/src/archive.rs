@@ -0,0 +1,260 @@
+//! 事件归档：事件按时间分区以 JSON 行追加写入 `events-<分区开始时间>.jsonl`，
+//! 已结束的分区压缩为 `.jsonl.gz`，超出保留期限或总大小上限的分区从最旧的开始删除
+
+use crate::state::state_file;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use log::{info, warn};
+use safe_traffic_common::{
+    clock::Clock, config::EventArchiveConfig, events::Event, transport::EventQuery,
+};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time;
+
+const PREFIX: &str = "events-";
+const STAMP: &str = "%Y%m%dT%H%M%SZ";
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(300);
+
+/// 目录中的一个分区文件
+#[derive(Debug)]
+struct Partition {
+    start: i64,
+    path: PathBuf,
+    compressed: bool,
+    size: u64,
+}
+
+/// 一次维护中压缩与删除的分区文件数
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Maintenance {
+    pub compressed: usize,
+    pub deleted: usize,
+}
+
+/// 按时间分区的事件归档
+#[derive(Debug)]
+pub struct EventArchive {
+    dir: PathBuf,
+    partition_secs: i64,
+    retention_secs: i64,
+    max_bytes: Option<u64>,
+    /// 正在写入的分区及其文件，维护期间持有以免写入正在压缩的文件
+    writer: Mutex<Option<(i64, File)>>,
+}
+
+impl EventArchive {
+    /// 打开归档目录，不存在时创建
+    pub fn open(cfg: &EventArchiveConfig, state_dir: Option<&str>) -> Result<Self> {
+        let dir = cfg
+            .dir
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| state_file(state_dir, "events"));
+        fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+        Ok(Self {
+            dir,
+            partition_secs: cfg.partition_secs.unwrap_or(86400).max(1) as i64,
+            retention_secs: cfg.retention_days.unwrap_or(90) as i64 * 86400,
+            max_bytes: cfg.max_bytes,
+            writer: Mutex::new(None),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn partition_start(&self, time: DateTime<Utc>) -> i64 {
+        time.timestamp().div_euclid(self.partition_secs) * self.partition_secs
+    }
+
+    fn path(&self, start: i64, compressed: bool) -> PathBuf {
+        let stamp = DateTime::from_timestamp(start, 0)
+            .unwrap_or_default()
+            .format(STAMP);
+        let suffix = if compressed { ".jsonl.gz" } else { ".jsonl" };
+        self.dir.join(format!("{}{}{}", PREFIX, stamp, suffix))
+    }
+
+    /// 追加一条事件，每条事件一次写入，进程退出不会丢失已记录的事件
+    pub fn append(&self, event: &Event) -> Result<()> {
+        let start = self.partition_start(event.time);
+        let mut line = serde_json::to_vec(event)?;
+        line.push(b'\n');
+        let mut writer = self.writer.lock().unwrap();
+        if writer.as_ref().is_none_or(|(current, _)| *current != start) {
+            let path = self.path(start, false);
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .with_context(|| format!("failed to open {}", path.display()))?;
+            *writer = Some((start, file));
+        }
+        if let Some((_, file)) = writer.as_mut() {
+            file.write_all(&line)?;
+        }
+        Ok(())
+    }
+
+    /// 目录中的分区文件按开始时间排列，同一分区的压缩文件排在未压缩文件之前
+    fn partitions(&self) -> Result<Vec<Partition>> {
+        let mut partitions = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(rest) = name.to_str().and_then(|name| name.strip_prefix(PREFIX)) else {
+                continue;
+            };
+            let (stamp, compressed) = match rest.strip_suffix(".jsonl.gz") {
+                Some(stamp) => (stamp, true),
+                None => match rest.strip_suffix(".jsonl") {
+                    Some(stamp) => (stamp, false),
+                    None => continue,
+                },
+            };
+            let Ok(start) = NaiveDateTime::parse_from_str(stamp, STAMP) else {
+                continue;
+            };
+            partitions.push(Partition {
+                start: start.and_utc().timestamp(),
+                path: entry.path(),
+                compressed,
+                size: entry.metadata()?.len(),
+            });
+        }
+        partitions.sort_by_key(|partition| (partition.start, !partition.compressed));
+        Ok(partitions)
+    }
+
+    /// 压缩为 gzip 成员追加到该分区的压缩文件，晚到的事件再次压缩时成员依次相接
+    fn compress(&self, partition: &Partition) -> Result<Partition> {
+        let path = self.path(partition.start, true);
+        let mut input = File::open(&partition.path)?;
+        let output = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        let mut encoder = GzEncoder::new(output, Compression::default());
+        io::copy(&mut input, &mut encoder)?;
+        encoder.finish()?.sync_all()?;
+        fs::remove_file(&partition.path)?;
+        let size = fs::metadata(&path)?.len();
+        Ok(Partition {
+            start: partition.start,
+            path,
+            compressed: true,
+            size,
+        })
+    }
+
+    /// 压缩已结束的分区，删除超出保留期限的分区，总大小超出上限时再从最旧的分区开始删除；
+    /// `now` 所在的分区不会被压缩或删除
+    pub fn maintain(&self, now: DateTime<Utc>) -> Result<Maintenance> {
+        let current = self.partition_start(now);
+        let cutoff = now.timestamp() - self.retention_secs;
+        let mut writer = self.writer.lock().unwrap();
+        if writer.as_ref().is_some_and(|(start, _)| *start < current) {
+            *writer = None;
+        }
+
+        let mut result = Maintenance::default();
+        let mut kept: Vec<Partition> = Vec::new();
+        for partition in self.partitions()? {
+            if partition.start + self.partition_secs <= cutoff {
+                fs::remove_file(&partition.path)?;
+                result.deleted += 1;
+            } else if !partition.compressed && partition.start < current {
+                let compressed = self.compress(&partition)?;
+                result.compressed += 1;
+                // 已有的压缩文件被合并
+                if kept
+                    .last()
+                    .is_some_and(|last| last.start == compressed.start)
+                {
+                    kept.pop();
+                }
+                kept.push(compressed);
+            } else {
+                kept.push(partition);
+            }
+        }
+
+        if let Some(max_bytes) = self.max_bytes {
+            let mut total: u64 = kept.iter().map(|partition| partition.size).sum();
+            for partition in kept.iter().filter(|partition| partition.start < current) {
+                if total <= max_bytes {
+                    break;
+                }
+                fs::remove_file(&partition.path)?;
+                total -= partition.size;
+                result.deleted += 1;
+            }
+        }
+        Ok(result)
+    }
+
+    /// 查询时间范围所覆盖分区中匹配的事件，按时间先后排列，不截取条数；
+    /// 无法解析的行（如写入中途退出留下的半行）被跳过
+    pub fn query(&self, query: &EventQuery) -> Result<Vec<Event>> {
+        let since = query.since.map(|since| self.partition_start(since));
+        let until = query.until.map(|until| until.timestamp());
+        let mut events = Vec::new();
+        for partition in self.partitions()? {
+            if since.is_some_and(|since| partition.start < since)
+                || until.is_some_and(|until| partition.start > until)
+            {
+                continue;
+            }
+            let file = match File::open(&partition.path) {
+                Ok(file) => file,
+                // 查询期间被维护压缩或删除
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let reader: Box<dyn BufRead> = if partition.compressed {
+                Box::new(BufReader::new(MultiGzDecoder::new(file)))
+            } else {
+                Box::new(BufReader::new(file))
+            };
+            events.extend(
+                reader
+                    .lines()
+                    .map_while(Result::ok)
+                    .filter_map(|line| serde_json::from_str::<Event>(&line).ok())
+                    .filter(|event| query.matches(event)),
+            );
+        }
+        events.sort_by_key(|event| event.time);
+        Ok(events)
+    }
+
+    /// 定期维护，压缩与删除在阻塞线程中进行
+    pub async fn run(self: Arc<Self>, clock: Arc<dyn Clock>) {
+        info!("Archiving events to {}", self.dir.display());
+        let mut interval = time::interval(MAINTENANCE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let archive = Arc::clone(&self);
+            let now = clock.wall();
+            match tokio::task::spawn_blocking(move || archive.maintain(now)).await {
+                Ok(Ok(result)) if result != Maintenance::default() => info!(
+                    "Compressed {} and deleted {} event archive partitions",
+                    result.compressed, result.deleted
+                ),
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => warn!("Failed to maintain the event archive: {}", e),
+                Err(e) => warn!("Event archive maintenance panicked: {}", e),
+            }
+        }
+    }
+}
@@ -0,0 +1,286 @@
+//! 将长期、大流量的封禁发布到 BGP：RTBH 主机路由或 FlowSpec 丢弃规则，
+//! 让上游在流量到达本机之前丢弃；规则到期或被解除后撤回
+
+use crate::{controller::Firewall, outbound::ProxySettings};
+
+use anyhow::{anyhow, bail, Result};
+use log::{debug, info, warn};
+use safe_traffic_common::{
+    config::{Action, BgpBackend, BgpConfig, BgpMode, HookType},
+    utils::FirewallRule,
+};
+use std::{
+    collections::HashSet,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{process::Command, time};
+
+const DEFAULT_GOBGP_API: &str = "127.0.0.1:50051";
+/// RFC 7999 定义的 BLACKHOLE community
+const DEFAULT_COMMUNITY: &str = "65535:666";
+/// RFC 5737 / RFC 6666 中用于丢弃的地址
+const DEFAULT_NEXT_HOP_V4: Ipv4Addr = Ipv4Addr::new(192, 0, 2, 1);
+const DEFAULT_NEXT_HOP_V6: Ipv6Addr = Ipv6Addr::new(0x100, 0, 0, 0, 0, 0, 0, 1);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 定期与活跃规则核对，发布新的长期封禁并撤回已失效的
+pub struct BgpAnnouncer {
+    backend: BgpBackend,
+    api: String,
+    mode: BgpMode,
+    next_hop_v4: Ipv4Addr,
+    next_hop_v6: Ipv6Addr,
+    community: String,
+    min_ban_secs: u64,
+    min_bps: Option<u64>,
+    interval: Duration,
+    /// Input 钩子按来源地址匹配，Output 钩子按目的地址匹配
+    hook: HookType,
+    agent: ureq::Agent,
+    /// 已发布的地址
+    announced: HashSet<IpAddr>,
+}
+
+impl BgpAnnouncer {
+    pub fn new(cfg: &BgpConfig, hook: HookType) -> Self {
+        Self {
+            backend: cfg.backend,
+            api: cfg
+                .api
+                .clone()
+                .unwrap_or_else(|| DEFAULT_GOBGP_API.to_string()),
+            mode: cfg.mode.unwrap_or(BgpMode::Blackhole),
+            next_hop_v4: cfg.next_hop_v4.unwrap_or(DEFAULT_NEXT_HOP_V4),
+            next_hop_v6: cfg.next_hop_v6.unwrap_or(DEFAULT_NEXT_HOP_V6),
+            community: cfg
+                .community
+                .clone()
+                .unwrap_or_else(|| DEFAULT_COMMUNITY.to_string()),
+            min_ban_secs: cfg.min_ban_secs.unwrap_or(3600),
+            min_bps: cfg.min_bps,
+            interval: Duration::from_secs(cfg.interval.unwrap_or(10).max(1)),
+            hook,
+            agent: ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build(),
+            announced: HashSet::new(),
+        }
+    }
+
+    /// 按代理设置访问 API，本机的 API 地址仍然直连
+    pub fn with_proxy(mut self, proxy: &ProxySettings) -> Self {
+        self.agent = proxy.agent(&self.api, REQUEST_TIMEOUT);
+        self
+    }
+
+    /// 规则是否应发布：按 IP 的长期或永久封禁，设置了 min_bps 时还要求触发流量足够大
+    pub fn qualifies(&self, rule: &FirewallRule) -> bool {
+        let long_lived = match rule.rule_type {
+            Action::Ban { duration } => {
+                duration.is_none_or(|duration| duration.as_secs() >= self.min_ban_secs)
+            }
+            _ => return false,
+        };
+        let heavy = match self.min_bps {
+            Some(min) => rule
+                .reason
+                .as_ref()
+                .is_some_and(|reason| reason.observed >= min),
+            None => true,
+        };
+        // 限定源端口或按 MAC 的封禁无法用整条前缀表达
+        long_lived && heavy && rule.source_ports.is_none() && rule.mac.is_none()
+    }
+
+    fn prefix(ip: IpAddr) -> String {
+        match ip {
+            IpAddr::V4(_) => format!("{}/32", ip),
+            IpAddr::V6(_) => format!("{}/128", ip),
+        }
+    }
+
+    fn next_hop(&self, ip: IpAddr) -> IpAddr {
+        match ip {
+            IpAddr::V4(_) => IpAddr::V4(self.next_hop_v4),
+            IpAddr::V6(_) => IpAddr::V6(self.next_hop_v6),
+        }
+    }
+
+    fn flow_match(&self) -> &'static str {
+        match self.hook {
+            HookType::Input => "source",
+            HookType::Output => "destination",
+        }
+    }
+
+    /// ExaBGP 的文本命令
+    pub fn exabgp_command(&self, announce: bool, ip: IpAddr) -> String {
+        let verb = if announce { "announce" } else { "withdraw" };
+        let prefix = Self::prefix(ip);
+        match self.mode {
+            BgpMode::Blackhole if announce => format!(
+                "{} route {} next-hop {} community [{}]",
+                verb,
+                prefix,
+                self.next_hop(ip),
+                self.community
+            ),
+            BgpMode::Blackhole => {
+                format!("{} route {} next-hop {}", verb, prefix, self.next_hop(ip))
+            }
+            BgpMode::Flowspec => format!(
+                "{} flow route {{ match {{ {} {}; }} then {{ discard; }} }}",
+                verb,
+                self.flow_match(),
+                prefix
+            ),
+        }
+    }
+
+    /// gobgp 命令行参数，不含连接参数
+    pub fn gobgp_args(&self, announce: bool, ip: IpAddr) -> Vec<String> {
+        let verb = if announce { "add" } else { "del" };
+        let family = match ip {
+            IpAddr::V4(_) => "ipv4",
+            IpAddr::V6(_) => "ipv6",
+        };
+        let mut args: Vec<String> = ["global", "rib"].map(String::from).to_vec();
+        match self.mode {
+            BgpMode::Blackhole => {
+                args.extend([verb, "-a", family].map(String::from));
+                args.push(Self::prefix(ip));
+                if announce {
+                    args.extend([
+                        "nexthop".to_string(),
+                        self.next_hop(ip).to_string(),
+                        "community".to_string(),
+                        self.community.clone(),
+                    ]);
+                }
+            }
+            BgpMode::Flowspec => {
+                args.extend([
+                    "-a".to_string(),
+                    format!("{}-flowspec", family),
+                    verb.to_string(),
+                    "match".to_string(),
+                    self.flow_match().to_string(),
+                    Self::prefix(ip),
+                    "then".to_string(),
+                    "discard".to_string(),
+                ]);
+            }
+        }
+        args
+    }
+
+    /// 发布或撤回一个地址，结果计入 bgp 的健康度
+    async fn send_recorded(&self, announce: bool, ip: IpAddr, fw: &Firewall) -> Result<()> {
+        let started = Instant::now();
+        let result = self.send(announce, ip).await;
+        let outcome = result.as_ref().map(|_| ()).map_err(|e| e.to_string());
+        fw.health()
+            .record("bgp", fw.clock().wall(), started.elapsed(), outcome)
+            .await;
+        result
+    }
+
+    /// 发布或撤回一个地址
+    async fn send(&self, announce: bool, ip: IpAddr) -> Result<()> {
+        match self.backend {
+            BgpBackend::Exabgp => {
+                let agent = self.agent.clone();
+                let url = self.api.clone();
+                let command = self.exabgp_command(announce, ip);
+                tokio::task::spawn_blocking(move || {
+                    agent
+                        .post(&url)
+                        .send_form(&[("command", &command)])
+                        .map(|_| ())
+                        .map_err(|e| anyhow!("ExaBGP API {}: {}", url, e))
+                })
+                .await?
+            }
+            BgpBackend::Gobgp => {
+                let (host, port) = self
+                    .api
+                    .rsplit_once(':')
+                    .ok_or_else(|| anyhow!("invalid gobgp api address: {}", self.api))?;
+                let output = Command::new("gobgp")
+                    .args(["-u", host.trim_matches(['[', ']']), "-p", port])
+                    .args(self.gobgp_args(announce, ip))
+                    .output()
+                    .await?;
+                if !output.status.success() {
+                    bail!("gobgp: {}", String::from_utf8_lossy(&output.stderr).trim());
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// 按活跃规则核对一次，返回本轮发布与撤回的地址数
+    async fn reconcile(&mut self, rules: &[FirewallRule], fw: &Firewall) -> (usize, usize) {
+        let wanted: HashSet<IpAddr> = rules
+            .iter()
+            .filter(|rule| self.qualifies(rule))
+            .map(|rule| rule.ip)
+            .collect();
+        let to_withdraw: Vec<IpAddr> = self.announced.difference(&wanted).copied().collect();
+        let to_announce: Vec<IpAddr> = wanted.difference(&self.announced).copied().collect();
+
+        let mut withdrawn = 0;
+        for ip in to_withdraw {
+            match self.send_recorded(false, ip, fw).await {
+                Ok(()) => {
+                    info!("Withdrew BGP {:?} route for {}", self.mode, ip);
+                    self.announced.remove(&ip);
+                    withdrawn += 1;
+                }
+                // 失败的保留在已发布集合中，下一轮重试
+                Err(e) => warn!("Failed to withdraw BGP route for {}: {}", ip, e),
+            }
+        }
+        let mut announced = 0;
+        for ip in to_announce {
+            match self.send_recorded(true, ip, fw).await {
+                Ok(()) => {
+                    info!("Announced BGP {:?} route for {}", self.mode, ip);
+                    self.announced.insert(ip);
+                    announced += 1;
+                }
+                Err(e) => warn!("Failed to announce BGP route for {}: {}", ip, e),
+            }
+        }
+        (announced, withdrawn)
+    }
+
+    /// 周期性核对活跃规则
+    pub async fn run(mut self, fw: Arc<Firewall>) {
+        info!(
+            "Announcing bans of at least {}s via {:?} ({:?})",
+            self.min_ban_secs, self.backend, self.mode
+        );
+        fw.health().register("bgp", None, fw.clock().wall());
+        let mut interval = time::interval(self.interval);
+        loop {
+            interval.tick().await;
+            let rules = match fw.get_active_rules().await {
+                Ok(rules) => rules,
+                Err(e) => {
+                    warn!("Failed to get active rules for BGP announcements: {}", e);
+                    continue;
+                }
+            };
+            let (announced, withdrawn) = self.reconcile(&rules, &fw).await;
+            if announced > 0 || withdrawn > 0 {
+                debug!(
+                    "BGP announcements: +{} -{}, {} active",
+                    announced,
+                    withdrawn,
+                    self.announced.len()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//! 启动时的能力探测：nft 版本与内核接受的特性、内核版本、外部命令与 conntrack 后端，
+//! 并逐条列出因缺少能力而无法生效的已配置特性；缺少能力时静默降级会掩盖实际的防护缺口。
+
+use log::{info, warn};
+use safe_traffic_common::config::{BgpBackend, Config, FamilyType};
+use std::{fmt, fs, path::Path, process::Stdio};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+const PROC_CONNTRACK: &str = "/proc/net/nf_conntrack";
+const CONNTRACK_ACCT: &str = "/proc/sys/net/netfilter/nf_conntrack_acct";
+/// 特性探测使用的表名，只以 `nft -c` 检查，不会真正创建
+const PROBE_TABLE: &str = "safe_traffic_probe";
+
+/// 探测到的运行能力
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// nft 能执行且有权限修改规则集，否则以模拟模式运行
+    pub nft_usable: bool,
+    /// `nft --version` 报告的版本，如 `1.0.6`
+    pub nft_version: Option<String>,
+    /// 内核版本，取自 /proc/sys/kernel/osrelease
+    pub kernel: Option<String>,
+    /// 内核接受带 timeout 标志的集合
+    pub timeout_sets: bool,
+    /// 内核接受 flowtable
+    pub flowtables: bool,
+    /// 内核接受 netdev 族的 ingress 基础链
+    pub netdev: bool,
+    /// 可读取 /proc/net/nf_conntrack
+    pub conntrack_procfs: bool,
+    /// 可执行 conntrack 命令
+    pub conntrack_cli: bool,
+    /// 已开启 net.netfilter.nf_conntrack_acct
+    pub conntrack_acct: bool,
+    /// 可执行 systemd-run，失控保护需要
+    pub systemd_run: bool,
+    /// 可执行 gobgp 命令
+    pub gobgp: bool,
+}
+
+impl Capabilities {
+    /// 探测本机能力；nft_usable 为 false 时不探测 nft 特性，device 为 netdev 与 flowtable 探测挂载的网卡
+    pub async fn probe(nft_usable: bool, device: &str) -> Self {
+        let nft_version = command_output("nft", &["--version"])
+            .await
+            .and_then(|text| parse_nft_version(&text));
+        let (timeout_sets, flowtables, netdev) = if nft_usable {
+            (
+                nft_accepts(&format!(
+                    "add table inet {t}\nadd set inet {t} s {{ type ipv4_addr ; flags timeout ; }}",
+                    t = PROBE_TABLE
+                ))
+                .await,
+                nft_accepts(&format!(
+                    "add table inet {t}\nadd flowtable inet {t} f {{ hook ingress priority 0 ; devices = {{ \"{d}\" }} ; }}",
+                    t = PROBE_TABLE,
+                    d = device
+                ))
+                .await,
+                nft_accepts(&format!(
+                    "add table netdev {t}\nadd chain netdev {t} c {{ type filter hook ingress device \"{d}\" priority 0 ; }}",
+                    t = PROBE_TABLE,
+                    d = device
+                ))
+                .await,
+            )
+        } else {
+            (false, false, false)
+        };
+        Self {
+            nft_usable,
+            nft_version,
+            kernel: fs::read_to_string("/proc/sys/kernel/osrelease")
+                .ok()
+                .map(|text| text.trim().to_string()),
+            timeout_sets,
+            flowtables,
+            netdev,
+            conntrack_procfs: Path::new(PROC_CONNTRACK).exists(),
+            conntrack_cli: command_output("conntrack", &["--version"]).await.is_some(),
+            conntrack_acct: fs::read_to_string(CONNTRACK_ACCT).is_ok_and(|text| text.trim() == "1"),
+            systemd_run: command_output("systemd-run", &["--version"])
+                .await
+                .is_some(),
+            gobgp: command_output("gobgp", &["--version"]).await.is_some(),
+        }
+    }
+
+    /// 已配置但因缺少能力而无法生效或只能部分生效的特性，每条说明缺少的能力
+    pub fn gaps(&self, cfg: &Config) -> Vec<String> {
+        let mut gaps = Vec::new();
+        if !self.nft_usable {
+            gaps.push(
+                "nftables is unavailable: running in mock mode, no traffic is dropped or limited"
+                    .to_string(),
+            );
+        } else {
+            let netdev_family = matches!(cfg.family, Some(FamilyType::Netdev));
+            if netdev_family && !self.netdev {
+                gaps.push(
+                    "family = \"Netdev\": the kernel rejects netdev ingress chains".to_string(),
+                );
+            }
+            if cfg.early_drop.unwrap_or(false) {
+                if !self.netdev {
+                    gaps.push("early_drop: the kernel rejects netdev ingress chains".to_string());
+                }
+                if !self.timeout_sets {
+                    gaps.push("early_drop: the kernel rejects sets with timeouts".to_string());
+                }
+            }
+            if cfg.offload.is_some() && !self.flowtables {
+                gaps.push("offload: the kernel rejects flowtables".to_string());
+            }
+        }
+        let conntrack = self.conntrack_procfs || self.conntrack_cli;
+        for (feature, configured) in [
+            ("flows", cfg.flows.is_some()),
+            ("tarpit", cfg.tarpit.is_some()),
+            ("spoof_guard", cfg.spoof_guard.is_some()),
+        ] {
+            if configured && !conntrack {
+                gaps.push(format!(
+                    "{}: neither {} nor the conntrack command is available",
+                    feature, PROC_CONNTRACK
+                ));
+            }
+        }
+        if cfg.flows.is_some() && conntrack && !self.conntrack_acct {
+            gaps.push(
+                "flows: net.netfilter.nf_conntrack_acct is off, flow byte and packet counts stay 0"
+                    .to_string(),
+            );
+        }
+        if cfg.offload.is_some() && !self.conntrack_cli {
+            gaps.push(
+                "offload: the conntrack command is missing, flows of newly banned sources stay offloaded until they end"
+                    .to_string(),
+            );
+        }
+        if cfg.failsafe.is_some() && !self.systemd_run {
+            gaps.push("failsafe: systemd-run is missing, no dead-man's timer is armed".to_string());
+        }
+        if cfg
+            .bgp
+            .as_ref()
+            .is_some_and(|bgp| matches!(bgp.backend, BgpBackend::Gobgp))
+            && !self.gobgp
+        {
+            gaps.push("bgp: the gobgp command is missing, bans are not announced".to_string());
+        }
+        gaps
+    }
+
+    /// 输出启动横幅与能力报告，缺少能力的已配置特性逐条以 warn 输出
+    pub fn report(&self, cfg: &Config) {
+        info!("safe-traffic-daemon {} starting", env!("CARGO_PKG_VERSION"));
+        info!("Capabilities: {}", self);
+        for gap in self.gaps(cfg) {
+            warn!("Capability gap: {}", gap);
+        }
+    }
+}
+
+impl fmt::Display for Capabilities {
+    /// 以 key=value 形式输出，便于日志检索
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let yes_no = |supported: bool| if supported { "yes" } else { "no" };
+        let nft_feature = |supported: bool| {
+            if self.nft_usable {
+                yes_no(supported)
+            } else {
+                "unknown"
+            }
+        };
+        let conntrack = match (self.conntrack_procfs, self.conntrack_cli) {
+            (true, _) => "procfs",
+            (false, true) => "cli",
+            (false, false) => "none",
+        };
+        write!(
+            f,
+            "nft={} nft_usable={} kernel={} timeout_sets={} flowtables={} netdev={} \
+             conntrack={} conntrack_acct={} systemd_run={} gobgp={}",
+            self.nft_version.as_deref().unwrap_or("none"),
+            yes_no(self.nft_usable),
+            self.kernel.as_deref().unwrap_or("unknown"),
+            nft_feature(self.timeout_sets),
+            nft_feature(self.flowtables),
+            nft_feature(self.netdev),
+            conntrack,
+            yes_no(self.conntrack_acct),
+            yes_no(self.systemd_run),
+            yes_no(self.gobgp)
+        )
+    }
+}
+
+/// 从 `nftables v1.0.6 (Lester Gooch #5)` 中取出版本号
+pub fn parse_nft_version(text: &str) -> Option<String> {
+    text.split_whitespace()
+        .find_map(|word| word.strip_prefix('v'))
+        .filter(|version| version.starts_with(|c: char| c.is_ascii_digit()))
+        .map(str::to_string)
+}
+
+/// 执行命令，成功时返回 stdout，命令不存在或失败时返回 None
+async fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program)
+        .args(args)
+        .stderr(Stdio::null())
+        .output()
+        .await
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// 以 `nft -c` 检查脚本，内核接受时返回 true；不修改规则集
+async fn nft_accepts(script: &str) -> bool {
+    let child = Command::new("nft")
+        .args(["-c", "-f", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    let Ok(mut child) = child else {
+        return false;
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        if stdin.write_all(script.as_bytes()).await.is_err() {
+            return false;
+        }
+    }
+    child.wait().await.is_ok_and(|status| status.success())
+}
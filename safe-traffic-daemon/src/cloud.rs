@@ -0,0 +1,286 @@
+//! 从云厂商实例元数据中获取应当排除的地址：本机地址、VPC/子网网段与负载均衡健康检查来源
+
+use crate::controller::Firewall;
+
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use safe_traffic_common::config::{CloudExcludeConfig, CloudProvider, ExclusionTable};
+use std::{
+    collections::HashMap,
+    net::Ipv4Addr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// 链路本地的实例元数据服务地址（AWS、Azure、Hetzner）
+const LINK_LOCAL_METADATA: &str = "http://169.254.169.254";
+const GCP_METADATA: &str = "http://metadata.google.internal/computeMetadata/v1";
+/// 单次查询超时，不在该云上运行时尽快失败
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+/// 获取失败后的重试间隔，已获取的地址在此期间继续生效
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// GCP 负载均衡与健康检查的来源网段
+const GCP_HEALTH_CHECKS: &[&str] = &[
+    "35.191.0.0/16",
+    "130.211.0.0/22",
+    "209.85.152.0/22",
+    "209.85.204.0/22",
+];
+/// Azure 负载均衡健康探测使用的平台虚拟地址
+const AZURE_HEALTH_CHECKS: &[&str] = &["168.63.129.16"];
+
+/// 从元数据中读出的地址
+#[derive(Debug, Default)]
+struct Discovered {
+    /// 实例自身的私有与公网地址
+    addresses: Vec<String>,
+    /// 实例所在的 VPC/子网网段
+    networks: Vec<String>,
+}
+
+/// 定期刷新云厂商元数据并替换防火墙的云白名单
+pub struct CloudExclusions {
+    cfg: CloudExcludeConfig,
+    agent: ureq::Agent,
+}
+
+impl CloudExclusions {
+    pub fn new(cfg: CloudExcludeConfig) -> Self {
+        // 元数据服务只能从实例本身直连，不经代理
+        let agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build();
+        Self { cfg, agent }
+    }
+
+    /// 按配置组合出需要排除的条目
+    fn entries(&self, discovered: Discovered) -> Vec<String> {
+        let mut entries = discovered.addresses;
+        if self.cfg.vpc.unwrap_or(true) {
+            entries.extend(discovered.networks);
+        }
+        // AWS 的 ELB 与 Hetzner 的负载均衡从 VPC 内的私有地址发起健康检查，已由网段覆盖
+        if self.cfg.health_checks.unwrap_or(true) {
+            let ranges = match self.cfg.provider {
+                CloudProvider::Gcp => GCP_HEALTH_CHECKS,
+                CloudProvider::Azure => AZURE_HEALTH_CHECKS,
+                CloudProvider::Aws | CloudProvider::Hetzner => &[],
+            };
+            entries.extend(ranges.iter().map(|range| range.to_string()));
+        }
+        entries.sort();
+        entries.dedup();
+        entries
+    }
+
+    /// 查询元数据服务，返回需要排除的条目
+    fn fetch(&self) -> Result<Vec<String>> {
+        let discovered = match self.cfg.provider {
+            CloudProvider::Aws => fetch_aws(&self.agent)?,
+            CloudProvider::Gcp => fetch_gcp(&self.agent)?,
+            CloudProvider::Azure => fetch_azure(&self.agent)?,
+            CloudProvider::Hetzner => fetch_hetzner(&self.agent)?,
+        };
+        Ok(self.entries(discovered))
+    }
+
+    /// 立即获取一次，之后按 refresh_secs 刷新；获取失败时保留上一次的结果
+    pub async fn run(self, fw: Arc<Firewall>) {
+        let refresh = Duration::from_secs(self.cfg.refresh_secs.unwrap_or(3600).max(1));
+        let health = fw.health();
+        health.register("cloud", Some(refresh), fw.clock().wall());
+        let this = Arc::new(self);
+        loop {
+            let task = Arc::clone(&this);
+            let started = Instant::now();
+            let result = tokio::task::spawn_blocking(move || task.fetch())
+                .await
+                .map_err(|e| anyhow!("cloud metadata task failed: {}", e))
+                .and_then(|result| result)
+                .and_then(|entries| ExclusionTable::build(&entries, &HashMap::new()));
+            let outcome = result.as_ref().map(|_| ()).map_err(|e| e.to_string());
+            health
+                .record("cloud", fw.clock().wall(), started.elapsed(), outcome)
+                .await;
+
+            let wait = match result {
+                Ok(table) => {
+                    info!(
+                        "Excluding {} entries from {:?} metadata: {}",
+                        table.entries().len(),
+                        this.cfg.provider,
+                        table.entries().join(", ")
+                    );
+                    fw.set_cloud_excludes(table).await;
+                    refresh
+                }
+                Err(e) => {
+                    warn!(
+                        "Failed to read {:?} instance metadata, keeping the previous exclusions: {}",
+                        this.cfg.provider, e
+                    );
+                    RETRY_INTERVAL.min(refresh)
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// 不存在的元数据项（如未分配公网地址）返回 404，视为空
+fn optional(result: std::result::Result<ureq::Response, ureq::Error>) -> Result<Option<String>> {
+    match result {
+        Ok(response) => Ok(Some(response.into_string()?)),
+        Err(ureq::Error::Status(404, _)) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// 按行拆分的元数据值，忽略空行
+fn lines(value: Option<String>) -> Vec<String> {
+    value
+        .unwrap_or_default()
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// AWS IMDSv2：先获取会话令牌，再逐个网卡读取地址与 VPC 网段
+fn fetch_aws(agent: &ureq::Agent) -> Result<Discovered> {
+    let token = agent
+        .put(&format!("{}/latest/api/token", LINK_LOCAL_METADATA))
+        .set("X-aws-ec2-metadata-token-ttl-seconds", "60")
+        .call()?
+        .into_string()?;
+    let get = |path: &str| {
+        optional(
+            agent
+                .get(&format!(
+                    "{}/latest/meta-data/{}",
+                    LINK_LOCAL_METADATA, path
+                ))
+                .set("X-aws-ec2-metadata-token", &token)
+                .call(),
+        )
+    };
+
+    let mut discovered = Discovered::default();
+    for mac in lines(get("network/interfaces/macs/")?) {
+        let mac = mac.trim_end_matches('/');
+        let item = |name: &str| get(&format!("network/interfaces/macs/{}/{}", mac, name));
+        for name in ["local-ipv4s", "public-ipv4s", "ipv6s"] {
+            discovered.addresses.extend(lines(item(name)?));
+        }
+        for name in ["vpc-ipv4-cidr-blocks", "vpc-ipv6-cidr-blocks"] {
+            discovered.networks.extend(lines(item(name)?));
+        }
+    }
+    Ok(discovered)
+}
+
+/// GCP：网卡的内外网地址、别名网段，以及由子网掩码推出的子网
+fn fetch_gcp(agent: &ureq::Agent) -> Result<Discovered> {
+    let body = agent
+        .get(&format!("{}/instance/network-interfaces/", GCP_METADATA))
+        .set("Metadata-Flavor", "Google")
+        .query("recursive", "true")
+        .call()?
+        .into_string()?;
+    let interfaces: serde_json::Value = serde_json::from_str(&body)?;
+
+    let mut discovered = Discovered::default();
+    for interface in interfaces.as_array().into_iter().flatten() {
+        if let Some(ip) = interface["ip"].as_str() {
+            discovered.addresses.push(ip.to_string());
+            if let Some(mask) = interface["subnetmask"]
+                .as_str()
+                .and_then(|mask| mask.parse::<Ipv4Addr>().ok())
+            {
+                let prefix = u32::from(mask).count_ones();
+                discovered.networks.push(format!("{}/{}", ip, prefix));
+            }
+        }
+        for config in interface["accessConfigs"].as_array().into_iter().flatten() {
+            if let Some(ip) = config["externalIp"].as_str().filter(|ip| !ip.is_empty()) {
+                discovered.addresses.push(ip.to_string());
+            }
+        }
+        for ip in interface["ipv6s"].as_array().into_iter().flatten() {
+            if let Some(ip) = ip.as_str() {
+                discovered.addresses.push(ip.to_string());
+            }
+        }
+        for range in interface["ipAliases"].as_array().into_iter().flatten() {
+            if let Some(range) = range.as_str() {
+                discovered.networks.push(range.to_string());
+            }
+        }
+    }
+    Ok(discovered)
+}
+
+/// Azure IMDS：各网卡的私有/公网地址与子网
+fn fetch_azure(agent: &ureq::Agent) -> Result<Discovered> {
+    let body = agent
+        .get(&format!(
+            "{}/metadata/instance/network",
+            LINK_LOCAL_METADATA
+        ))
+        .set("Metadata", "true")
+        .query("api-version", "2021-02-01")
+        .call()?
+        .into_string()?;
+    let network: serde_json::Value = serde_json::from_str(&body)?;
+
+    let mut discovered = Discovered::default();
+    for interface in network["interface"].as_array().into_iter().flatten() {
+        for version in ["ipv4", "ipv6"] {
+            let addresses = interface[version]["ipAddress"].as_array();
+            for address in addresses.into_iter().flatten() {
+                for key in ["privateIpAddress", "publicIpAddress"] {
+                    if let Some(ip) = address[key].as_str().filter(|ip| !ip.is_empty()) {
+                        discovered.addresses.push(ip.to_string());
+                    }
+                }
+            }
+            for subnet in interface[version]["subnet"]
+                .as_array()
+                .into_iter()
+                .flatten()
+            {
+                if let (Some(address), Some(prefix)) =
+                    (subnet["address"].as_str(), subnet["prefix"].as_str())
+                {
+                    discovered.networks.push(format!("{}/{}", address, prefix));
+                }
+            }
+        }
+    }
+    Ok(discovered)
+}
+
+/// Hetzner Cloud：公网 IPv4 与私有网络（YAML 列表中的 ip 与 network 项）
+fn fetch_hetzner(agent: &ureq::Agent) -> Result<Discovered> {
+    let get = |path: &str| {
+        optional(
+            agent
+                .get(&format!(
+                    "{}/hetzner/v1/metadata/{}",
+                    LINK_LOCAL_METADATA, path
+                ))
+                .call(),
+        )
+    };
+
+    let mut discovered = Discovered::default();
+    discovered.addresses.extend(lines(get("public-ipv4")?));
+    for line in lines(get("private-networks")?) {
+        let line = line.trim_start_matches("- ");
+        match line.split_once(": ") {
+            Some(("ip", ip)) => discovered.addresses.push(ip.to_string()),
+            Some(("network", network)) => discovered.networks.push(network.to_string()),
+            _ => {}
+        }
+    }
+    Ok(discovered)
+}
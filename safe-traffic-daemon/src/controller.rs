@@ -1,16 +1,83 @@
-use crate::nft::{parse_output, NftError, NftExecutor, NftObject};
+use crate::accounting::Accounting;
+use crate::archive::EventArchive;
+use crate::events::EventStore;
+use crate::flows::{FlowTable, DEFAULT_MAX_FLOWS};
+use crate::health::Health;
+use crate::lockout::AuthGuard;
+use crate::logger;
+use crate::nfqueue::Inspector;
+use crate::nft::{parse_output, priority, sets::AddressSets, NftError, NftExecutor, NftObject};
+use crate::savings::Savings;
+use crate::state::{state_file, ExcludeOverrides};
 use anyhow::{anyhow, Result};
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
 use log::{debug, info, warn};
 use safe_traffic_common::{
-    config::{Action, Config, FamilyType, HookType, PolicyType},
-    utils::FirewallRule,
+    clock::{Clock, SystemClock},
+    config::{
+        is_link_local, parse_network, Action, ActionSource, Config, ExclusionTable, FamilyType,
+        HookType, PolicyType, ProtectedPort, SandboxMode, DEFAULT_PRECEDENCE,
+    },
+    events::{Event, EventKind},
+    rule_id::{RuleId, RuleKind},
+    transport::{BatchItemError, RuleFilter, SystemRule, TemporaryExclude},
+    utils::{format_duration, FirewallRule},
 };
 use std::collections::{HashMap, HashSet};
 use std::net::IpAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 
-use tokio::sync::RwLock;
+use tokio::process::Command;
+use tokio::sync::{Mutex, OwnedMutexGuard, RwLock};
+
+/// 镜像动作未指定时长时的默认持续时间，秒
+pub(crate) const DEFAULT_MIRROR_SECS: u64 = 300;
+/// 检查动作未指定时长时的默认持续时间，秒
+pub(crate) const DEFAULT_INSPECT_SECS: u64 = 300;
+/// 系统规则缓存的有效期
+const SYSTEM_RULES_TTL: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// 早期丢弃表中挂在 ingress 上的链
+const EARLY_DROP_CHAIN: &str = "ingress";
+/// 早期丢弃链的优先级，先于同一网卡上的其他 ingress 链
+const EARLY_DROP_PRIORITY: i64 = -500;
+/// 卸载转发连接的 flowtable
+const OFFLOAD_FLOWTABLE: &str = "offload";
+/// 向 flowtable 添加连接的 forward 链
+const OFFLOAD_CHAIN: &str = "offload_forward";
+
+/// flowtable 卸载的设置
+#[derive(Clone, Debug)]
+struct Offload {
+    hardware: bool,
+    devices: Vec<String>,
+    priority: i64,
+    /// 有规则生效、不再卸载的来源
+    exempt: AddressSets,
+}
+
+/// 系统规则缓存：读取时的单调时间及规则列表
+type SystemRulesCache = (std::time::Duration, Vec<SystemRule>);
+
+/// 按 (IP, 规则类型) 加的锁，已有规则的检查与新规则的创建在锁内完成
+type ApplyLocks = DashMap<(IpAddr, RuleKind), Arc<Mutex<()>>>;
+
+/// 持有期间同一 (IP, 规则类型) 的其他动作等待，释放时清理不再有人等待的锁
+struct ApplyGuard {
+    locks: Arc<ApplyLocks>,
+    key: (IpAddr, RuleKind),
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl Drop for ApplyGuard {
+    fn drop(&mut self) {
+        self.guard.take();
+        self.locks
+            .remove_if(&self.key, |_, lock| Arc::strong_count(lock) == 1);
+    }
+}
 
 /// 防火墙控制器（使用池化的 nft 执行器）
 #[derive(Clone, Debug)]
@@ -19,12 +86,60 @@ pub struct Firewall {
     table_name: String,
     chain_name: String,
     pub hook: HookType,
+    /// netdev 族的链挂载的网卡
+    devices: Vec<String>,
+    /// 每块网卡各挂一条基础链，跳转到共用的链
+    per_device_chains: bool,
+    /// 早期丢弃使用的 netdev 集合，未开启 early_drop 时为 None
+    early_drop: Option<AddressSets>,
+    /// flowtable 卸载，未配置 [offload] 时为 None
+    offload: Option<Offload>,
+    sandbox: Option<SandboxMode>,
+    /// 不挂载 hook 的沙盒表，Shadow 模式下即 table_name
+    sandbox_table: String,
     priority: i64,
     policy: PolicyType,
-    pub rules: Arc<RwLock<HashMap<String, FirewallRule>>>,
+    pub rules: Arc<RwLock<HashMap<RuleId, FirewallRule>>>,
     nft_available: bool,
     executor: Arc<NftExecutor>,
     global_exclude: Arc<RwLock<HashSet<IpAddr>>>,
+    /// 运行时对白名单的修改及其持久化文件，修改时先取 global_exclude 写锁
+    exclude_overrides: Arc<RwLock<ExcludeOverrides>>,
+    exclude_state: PathBuf,
+    /// 由云厂商元数据得出的白名单，每次刷新整体替换
+    cloud_exclude: Arc<RwLock<ExclusionTable>>,
+    /// Output 钩子下的本机网卡地址与浮动地址，每次刷新整体替换
+    host_exclude: Arc<RwLock<ExclusionTable>>,
+    pub events: Arc<EventStore>,
+    clock: Arc<dyn Clock>,
+    /// 最近一次读取的系统规则
+    system_rules: Arc<RwLock<Option<SystemRulesCache>>>,
+    /// NFQUEUE 逐包判定，未配置 [nfqueue] 时为 None
+    inspector: Option<Arc<Inspector>>,
+    /// 连接跟踪流表，未配置 [flows] 时为 None
+    flows: Option<Arc<FlowTable>>,
+    /// Warn 动作打上的 fwmark
+    warn_mark: Option<u32>,
+    /// 手动封禁的最长时长，秒
+    max_manual_ban: Option<u64>,
+    /// 按服务统计流量，未配置 [[accounting]] 时为 None
+    accounting: Option<Arc<Accounting>>,
+    /// 管理接口的认证失败锁定，未配置 [websocket] 时为 None
+    auth_guard: Option<Arc<AuthGuard>>,
+    /// 受管规则丢弃的流量
+    savings: Arc<Savings>,
+    /// 外部集成的健康度
+    health: Arc<Health>,
+    /// 并发的动作（如两条规则或相邻两个周期）对同一 IP 串行生效，避免重复创建规则
+    apply_locks: Arc<ApplyLocks>,
+    /// 动作冲突时各来源的优先级，从高到低
+    precedence: Vec<ActionSource>,
+    /// 已记录过自动动作让位冲突的规则，规则移除前不再重复记录
+    yielded: Arc<DashMap<RuleId, ()>>,
+    /// 自动动作不作用的管理端口
+    protected_ports: Vec<ProtectedPort>,
+    /// 已记录过放过受保护端口的来源，每个来源只记录一次
+    protected_noticed: Arc<DashMap<IpAddr, ()>>,
 }
 
 #[allow(dead_code)]
@@ -41,26 +156,125 @@ impl Firewall {
             .clone()
             .unwrap_or("traffic_input".to_string());
         let hook = cfg.hook.clone().unwrap_or(HookType::Input);
-        let priority = cfg.priority.unwrap_or(0);
+        let devices = cfg
+            .devices
+            .clone()
+            .unwrap_or_else(|| vec![cfg.interface.clone()]);
+        let sandbox = cfg.sandbox;
+        let sandbox_table = format!("{}_sandbox", table_name);
+        // Shadow 模式下规则只进入沙盒表，也不创建挂在流量路径上的早期丢弃与卸载
+        let shadow = sandbox == Some(SandboxMode::Shadow);
+        let table_name = if shadow {
+            sandbox_table.clone()
+        } else {
+            table_name
+        };
+        let early_drop = (cfg.early_drop.unwrap_or(false) && !shadow).then(|| {
+            AddressSets::new(
+                FamilyType::Netdev,
+                format!("{}_early", table_name),
+                "banned",
+            )
+        });
+        let offload = cfg
+            .offload
+            .as_ref()
+            .filter(|_| !shadow)
+            .map(|offload| Offload {
+                hardware: offload.hardware.unwrap_or(true),
+                devices: offload.devices.clone().unwrap_or_else(|| devices.clone()),
+                priority: offload.priority.unwrap_or(0),
+                exempt: AddressSets::new(&family, &table_name, "no_offload"),
+            });
+        let mut priority = cfg.priority.unwrap_or(0);
         let policy = cfg.policy.clone().unwrap_or(PolicyType::Accept);
-        let global_exclude = Arc::new(RwLock::new(
-            cfg.global_exclude.clone().unwrap_or_default(),
-        ));
+        // 配置中的白名单叠加上次运行时的增删
+        let exclude_state = state_file(cfg.state_dir.as_deref(), "excludes.json");
+        let mut exclude_overrides = ExcludeOverrides::load(&exclude_state).await?;
+        let mut global_exclude = cfg.global_exclude.clone().unwrap_or_default();
+        exclude_overrides.apply(&mut global_exclude);
+        let global_exclude = Arc::new(RwLock::new(global_exclude));
+        let inspector = cfg
+            .nfqueue
+            .as_ref()
+            .map(|nfqueue| Arc::new(Inspector::new(nfqueue, hook.clone())));
+        let accounting = cfg
+            .accounting
+            .as_ref()
+            .map(|rules| Arc::new(Accounting::new(rules, Arc::clone(&executor))));
+        let flows = cfg
+            .flows
+            .as_ref()
+            .map(|flows| Arc::new(FlowTable::new(flows.max_flows.unwrap_or(DEFAULT_MAX_FLOWS))));
+        let mut events = EventStore::default();
+        if let Some(inspector) = &inspector {
+            events = events.with_inspector(Arc::clone(inspector));
+        }
+        if let Some(archive) = &cfg.event_archive {
+            events = events.with_archive(Arc::new(EventArchive::open(
+                archive,
+                cfg.state_dir.as_deref(),
+            )?));
+        }
 
-        // 检查 nftables 是否可用
-        let nft_available = crate::nft::check_nftables_available().await?;
+        // 检查 nftables 是否可用，模拟执行器（如规则模拟）不触碰本机的 nft 与 conntrack
+        let nft_available = !executor.is_mock() && crate::nft::check_nftables_available().await?;
+
+        // 按配置的先后顺序与同一 hook 上已有的基础链协商优先级
+        if nft_available && !shadow && (cfg.run_before.is_some() || cfg.run_after.is_some()) {
+            let chains = priority::base_chains(&executor, &family, &hook, &table_name).await?;
+            priority = priority::negotiate(
+                priority,
+                &chains,
+                cfg.run_before.as_deref().unwrap_or_default(),
+                cfg.run_after.as_deref().unwrap_or_default(),
+            )?;
+        }
 
+        let events = Arc::new(events);
         let firewall = Firewall {
             family,
             table_name,
             chain_name,
             hook,
+            devices,
+            per_device_chains: cfg.per_device_chains.unwrap_or(false),
+            early_drop,
+            offload,
+            sandbox,
+            sandbox_table,
             priority,
             policy,
             rules: Arc::new(RwLock::new(HashMap::new())),
             nft_available,
             executor,
             global_exclude,
+            exclude_overrides: Arc::new(RwLock::new(exclude_overrides)),
+            exclude_state,
+            cloud_exclude: Arc::new(RwLock::new(ExclusionTable::default())),
+            host_exclude: Arc::new(RwLock::new(ExclusionTable::default())),
+            events: Arc::clone(&events),
+            clock: Arc::new(SystemClock),
+            system_rules: Arc::new(RwLock::new(None)),
+            inspector,
+            flows,
+            warn_mark: cfg.warn_page.as_ref().and_then(|warn_page| warn_page.mark),
+            max_manual_ban: cfg.max_manual_ban_secs(),
+            accounting,
+            auth_guard: cfg
+                .websocket
+                .as_ref()
+                .map(|websocket| Arc::new(AuthGuard::new(websocket))),
+            savings: Arc::new(Savings::new(cfg.state_dir.as_deref())),
+            health: Arc::new(Health::new(events)),
+            apply_locks: Arc::new(DashMap::new()),
+            precedence: cfg
+                .precedence
+                .clone()
+                .unwrap_or_else(|| DEFAULT_PRECEDENCE.to_vec()),
+            yielded: Arc::new(DashMap::new()),
+            protected_ports: cfg.protected_ports.clone().unwrap_or_default(),
+            protected_noticed: Arc::new(DashMap::new()),
         };
 
         if firewall.nft_available {
@@ -69,26 +283,195 @@ impl Firewall {
         } else {
             warn!("nftables is unavailable, using mock mode instead");
         }
+        if shadow {
+            warn!(
+                "sandbox mode Shadow: rules are installed into table {} without a hook and do not affect traffic",
+                firewall.table_name
+            );
+        }
 
         Ok(firewall)
     }
 
-    /// 检查 nftables 是否可用
+    /// 替换时间来源，规则过期按其单调时间判断
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// 当前使用的时间来源
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        Arc::clone(&self.clock)
+    }
+
+    /// 校验控制接口请求的手动封禁时长，配置了 max_manual_ban 时更长的与永久的封禁被拒绝
+    pub fn check_manual_ban(&self, seconds: Option<u64>) -> Result<()> {
+        match (self.max_manual_ban, seconds) {
+            (Some(max), None) => Err(anyhow!(
+                "permanent manual bans are not allowed, max_manual_ban is {}",
+                format_duration(max)
+            )),
+            (Some(max), Some(seconds)) if seconds > max => Err(anyhow!(
+                "manual ban of {} exceeds max_manual_ban of {}",
+                format_duration(seconds),
+                format_duration(max)
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// NFQUEUE 逐包判定，未配置时为 None
+    pub fn inspector(&self) -> Option<Arc<Inspector>> {
+        self.inspector.clone()
+    }
+
+    /// 连接跟踪流表，未配置时为 None
+    pub fn flows(&self) -> Option<Arc<FlowTable>> {
+        self.flows.clone()
+    }
+
+    /// 按服务统计流量，未配置时为 None
+    pub fn accounting(&self) -> Option<Arc<Accounting>> {
+        self.accounting.clone()
+    }
+
+    /// 管理接口的认证失败锁定，未配置时为 None
+    pub fn auth_guard(&self) -> Option<Arc<AuthGuard>> {
+        self.auth_guard.clone()
+    }
+
+    /// 受管规则丢弃的流量
+    pub fn savings(&self) -> Arc<Savings> {
+        Arc::clone(&self.savings)
+    }
+
+    /// 外部集成的健康度
+    pub fn health(&self) -> Arc<Health> {
+        Arc::clone(&self.health)
+    }
+
+    /// 初始化表和链的 nft 命令
+    pub fn init_commands(&self) -> Vec<String> {
+        // netdev 族的链需要指定挂载的网卡
+        let devices = match self.family {
+            FamilyType::Netdev => device_clause(&self.devices),
+            _ => String::new(),
+        };
+        let mut commands = match self.sandbox {
+            Some(SandboxMode::Shadow) => Vec::new(),
+            // 规则只装在不挂载 hook 的链中，各网卡的基础链跳转过来
+            _ if self.per_device_chains && matches!(self.family, FamilyType::Netdev) => {
+                let mut commands = vec![
+                    format!("add table {} {}", self.family, self.table_name),
+                    format!(
+                        "add chain {} {} {}",
+                        self.family, self.table_name, self.chain_name
+                    ),
+                ];
+                commands.extend(self.device_chain_commands(
+                    &self.family.to_string(),
+                    &self.table_name,
+                    &self.chain_name,
+                    self.family.hook_name(&self.hook),
+                    self.priority,
+                    &self.policy.to_string(),
+                ));
+                commands
+            }
+            _ => vec![
+                format!("add table {} {}", self.family, self.table_name),
+                format!(
+                    "add chain {} {} {} {{ type filter hook {}{} priority {}  ; policy {} ; }}",
+                    self.family,
+                    self.table_name,
+                    self.chain_name,
+                    self.family.hook_name(&self.hook),
+                    devices,
+                    self.priority,
+                    self.policy
+                ),
+            ],
+        };
+        if self.sandbox.is_some() {
+            // 沙盒表中的链不挂载 hook，规则能被解析并分配 handle，但不会处理任何报文
+            commands.extend([
+                format!("add table {} {}", self.family, self.sandbox_table),
+                format!(
+                    "add chain {} {} {}",
+                    self.family, self.sandbox_table, self.chain_name
+                ),
+            ]);
+        }
+        if let Some(sets) = &self.early_drop {
+            // 早期丢弃表完全由本进程管理，先删除上次运行遗留的内容
+            let table = sets.table();
+            commands.extend([
+                format!("add table netdev {}", table),
+                format!("delete table netdev {}", table),
+                format!("add table netdev {}", table),
+            ]);
+            if self.per_device_chains {
+                commands.push(format!("add chain netdev {} {}", table, EARLY_DROP_CHAIN));
+                commands.extend(self.device_chain_commands(
+                    "netdev",
+                    table,
+                    EARLY_DROP_CHAIN,
+                    "ingress",
+                    EARLY_DROP_PRIORITY,
+                    "accept",
+                ));
+            } else {
+                commands.push(format!(
+                    "add chain netdev {} {} {{ type filter hook ingress{} priority {} ; policy accept ; }}",
+                    table,
+                    EARLY_DROP_CHAIN,
+                    device_clause(&self.devices),
+                    EARLY_DROP_PRIORITY
+                ));
+            }
+            commands.extend(sets.create_commands());
+            commands.extend(sets.matchers("saddr").into_iter().map(|matcher| {
+                format!(
+                    "add rule netdev {} {} {} drop",
+                    table, EARLY_DROP_CHAIN, matcher
+                )
+            }));
+        }
+        if let Some(offload) = &self.offload {
+            commands.extend(self.offload_commands(offload));
+        }
+        commands
+    }
+
+    /// 每块网卡一条基础链，只含跳转到 chain 的规则；chain 中的规则与引用的集合由全部网卡共用，
+    /// 增删一次即同时作用于所有网卡
+    fn device_chain_commands(
+        &self,
+        family: &str,
+        table: &str,
+        chain: &str,
+        hook: &str,
+        priority: i64,
+        policy: &str,
+    ) -> Vec<String> {
+        self.devices
+            .iter()
+            .flat_map(|device| {
+                let base = device_chain_name(chain, device);
+                [
+                    format!(
+                        "add chain {} {} {} {{ type filter hook {} device \"{}\" priority {} ; policy {} ; }}",
+                        family, table, base, hook, device, priority, policy
+                    ),
+                    format!("add rule {} {} {} jump {}", family, table, base, chain),
+                ]
+            })
+            .collect()
+    }
+
     /// 初始化 nftables 表和链
     async fn init_table_and_chain(&self) -> Result<()> {
-        let commands = vec![
-            format!("add table {} {}", self.family, self.table_name),
-            format!(
-                "add chain {} {} {} {{ type filter hook {} priority {}  ; policy {} ; }}",
-                self.family,
-                self.table_name,
-                self.chain_name,
-                self.hook,
-                self.priority,
-                self.policy
-            ),
-        ];
-
+        let commands = self.init_commands();
         // self.executor.input(&commands[0]).await?;
         // self.executor.input(&commands[1]).await?;
         match self.executor.execute_batch(commands).await {
@@ -112,38 +495,61 @@ impl Firewall {
         Ok(())
     }
 
-    /// 对指定 IP 设置速率限制
-    pub async fn infinity_limit(
+    pub async fn limit(
         &self,
         ip: IpAddr,
         kbps: u64,
         burst: Option<u64>,
-    ) -> Result<String> {
-        let rule_id = format!("limit_{}_{}", ip, kbps);
-        let burst = if let Some(bur) = burst {
-            bur
-        } else {
-            kbps.min(1024) / 10
-        };
+        seconds: Option<u64>,
+    ) -> Result<RuleId> {
+        self.limit_on_ports(ip, kbps, burst, seconds, None).await
+    }
 
-        // 检查是否已存在相同规则
-        {
-            let rules = self.rules.read().await;
-            if let Some(existing_rule) = rules.get(&rule_id) {
-                if let Action::RateLimit {
-                    kbps: existing_kbps,
-                    ..
-                } = existing_rule.rule_type
+    /// 对指定 IP 设置速率限制，可限定源端口（如反射攻击常用的 123/19/53/389）；seconds 为 None 时永久
+    pub async fn limit_on_ports(
+        &self,
+        ip: IpAddr,
+        kbps: u64,
+        burst: Option<u64>,
+        seconds: Option<u64>,
+        source_ports: Option<&[u16]>,
+    ) -> Result<RuleId> {
+        self.arbitrate(ip, "limit").await?;
+        let _lock = self.lock_apply(ip, RuleKind::Limit).await;
+        let rule_id = RuleId::for_ip(RuleKind::Limit, ip, source_ports);
+        let burst = burst.unwrap_or(kbps.min(1024) / 10);
+
+        if let Some(existing) = self.find_limit_rule(ip, source_ports).await {
+            // 参数相同且仍在生效的规则 => 不再创建
+            if let Action::RateLimit {
+                kbps: existing_kbps,
+                ..
+            } = existing.rule_type
+            {
+                if existing_kbps == kbps
+                    && existing.rule_type.seconds() == seconds
+                    && !existing.is_expired(self.clock.as_ref())
                 {
-                    if existing_kbps == kbps {
-                        debug!("Rule {} already exists, skipping creation", rule_id);
-                        return Ok(rule_id);
-                    }
+                    debug!("Rule {} already exists, skipping creation", existing.id);
+                    return Ok(existing.id);
                 }
             }
+            // 同一来源更新参数时原地替换，不同来源按优先级仲裁
+            if Self::current_source() != Self::rule_source(&existing)
+                && !self.supersedes(&existing, "limit").await
+            {
+                return Ok(existing.id);
+            }
+
+            // 已有其他参数的限速规则 => 原地替换
+            return self
+                .replace_limit_rule(&existing, rule_id, kbps, burst, seconds, source_ports)
+                .await;
         }
 
-        let handle = self.create_limit_rule(ip, kbps, burst).await?;
+        let handle = self
+            .create_limit_rule(ip, kbps, burst, source_ports)
+            .await?;
 
         let rule = FirewallRule {
             id: rule_id.clone(),
@@ -151,69 +557,80 @@ impl Firewall {
             rule_type: Action::RateLimit {
                 kbps,
                 burst: Some(burst),
-                seconds: None,
+                duration: seconds.map(std::time::Duration::from_secs),
             },
-            created_at: Utc::now(),
+            created_at: self.clock.wall(),
+            created_mono: Some(self.clock.monotonic()),
             handle: Some(handle),
+            source_ports: source_ports.map(|ports| ports.to_vec()),
+            remaining_secs: None,
+            mac: None,
+            reason: logger::current_reason(),
         };
 
-        self.rules.write().await.insert(rule_id.clone(), rule);
+        self.insert_rule(rule).await;
         info!(
             "Set speed limit for {}: {} KB/s (burst: {} KB)",
             ip, kbps, burst
         );
+        self.events
+            .push(
+                Event::new(EventKind::Limit, format!("limit {} to {} KB/s", ip, kbps))
+                    .with_ip(ip)
+                    .with_rule(&rule_id),
+            )
+            .await;
 
         Ok(rule_id)
     }
 
-    pub async fn limit(
+    /// 查找同一 IP（及源端口）上已存在的限速规则
+    async fn find_limit_rule(
         &self,
         ip: IpAddr,
+        source_ports: Option<&[u16]>,
+    ) -> Option<FirewallRule> {
+        let rules = self.rules.read().await;
+        rules
+            .values()
+            .find(|rule| {
+                rule.ip == ip
+                    && rule.source_ports.as_deref() == source_ports
+                    && matches!(rule.rule_type, Action::RateLimit { .. })
+            })
+            .cloned()
+    }
+
+    /// 用 `replace rule` 原地替换已有的限速规则，并同步更新规则表
+    async fn replace_limit_rule(
+        &self,
+        existing: &FirewallRule,
+        rule_id: RuleId,
         kbps: u64,
-        burst: Option<u64>,
+        burst: u64,
         seconds: Option<u64>,
-    ) -> Result<String> {
-        if seconds.is_none() {
-            return self.infinity_limit(ip, kbps, burst).await;
-        };
-        let seconds = seconds.unwrap();
-
-        let duration = Duration::seconds(seconds as i64);
-        let now = Utc::now();
-        let until = now + duration;
-        let rule_id = format!("limit_{}_{}_{}", ip, kbps, until.timestamp());
-
-        let burst = if let Some(bur) = burst {
-            bur
-        } else {
-            kbps.min(1024) / 10
-        };
+        source_ports: Option<&[u16]>,
+    ) -> Result<RuleId> {
+        let old_handle = existing
+            .handle
+            .as_deref()
+            .ok_or_else(|| anyhow!("rule has no handle: {}", existing.id))?;
+        let ip = existing.ip;
+        if !self.rules.read().await.contains_key(&existing.id) {
+            return Err(anyhow!("rule was removed during update: {}", existing.id));
+        }
 
-        // 检查是否已存在相同规则
+        let rule_cmd = self.replace_limit_command(old_handle, ip, kbps, burst, source_ports);
+        // replace 依赖实际链中的 handle，沙盒中以等价的 add 命令试装
+        for trial in self
+            .trial_in_sandbox(&[self.limit_rule_command(ip, kbps, burst, source_ports)])
+            .await?
         {
-            let rules = self.rules.read().await;
-            for (_, rule) in rules.iter() {
-                if rule.ip == ip {
-                    if let Action::RateLimit {
-                        kbps: existing_kbps,
-                        seconds: sec,
-                        ..
-                    } = rule.rule_type
-                    {
-                        let existing_until = rule.created_at + duration;
-                        if existing_until > Utc::now() && existing_kbps == kbps && sec == Some(seconds) {
-                            debug!(
-                                "IP {} has already been banned until {}, skipping",
-                                ip, existing_until
-                            );
-                            return Ok(rule.id.clone());
-                        }
-                    }
-                }
-            }
+            trial?;
         }
-
-        let handle = self.create_limit_rule(ip, kbps, burst).await?;
+        // nft 往返期间不持有规则表的锁，状态查询与规则引擎不必等待
+        let output_with_handle = self.executor.execute(&rule_cmd).await?;
+        let handle = handle_from_output(&output_with_handle).await?;
 
         let rule = FirewallRule {
             id: rule_id.clone(),
@@ -221,45 +638,211 @@ impl Firewall {
             rule_type: Action::RateLimit {
                 kbps,
                 burst: Some(burst),
-                seconds: Some(seconds),
+                duration: seconds.map(std::time::Duration::from_secs),
             },
-            created_at: Utc::now(),
-            handle: Some(handle),
+            created_at: self.clock.wall(),
+            created_mono: Some(self.clock.monotonic()),
+            handle: Some(handle.clone()),
+            source_ports: source_ports.map(|ports| ports.to_vec()),
+            remaining_secs: None,
+            mac: None,
+            reason: logger::current_reason(),
+        };
+        // 只在交换条目时持有写锁；替换期间原规则已被移除时撤销刚装入的规则
+        let replaced = {
+            let mut rules = self.rules.write().await;
+            let replaced = rules.remove(&existing.id).is_some();
+            self.yielded.remove(&existing.id);
+            if replaced {
+                rules.insert(rule_id.clone(), rule);
+            }
+            replaced
+        };
+        self.invalidate_system_rules().await;
+        if !replaced {
+            if let Err(e) = self.remove_rule_by_handle(&handle).await {
+                warn!("failed to remove replacement rule handle {}: {}", handle, e);
+            }
+            return Err(anyhow!("rule was removed during update: {}", existing.id));
+        }
+        self.refresh_offload(&[ip]).await;
+
+        let old_kbps = match existing.rule_type {
+            Action::RateLimit { kbps, .. } => kbps,
+            Action::Ban { .. }
+            | Action::Mirror { .. }
+            | Action::Inspect { .. }
+            | Action::PoliceSources { .. }
+            | Action::Warn { .. } => 0,
         };
-
-        self.rules.write().await.insert(rule_id.clone(), rule);
         info!(
-            "Set speed limit for {}: {} KB/s (burst: {} KB)",
-            ip, kbps, burst
+            "Updated speed limit for {}: {} -> {} KB/s (burst: {} KB), rule {} replaced by {}",
+            ip, old_kbps, kbps, burst, existing.id, rule_id
         );
+        self.events
+            .push(
+                Event::new(
+                    EventKind::Limit,
+                    format!("update limit of {} from {} to {} KB/s", ip, old_kbps, kbps),
+                )
+                .with_ip(ip)
+                .with_rule(&rule_id),
+            )
+            .await;
 
         Ok(rule_id)
     }
 
-    async fn is_nft_available(&self) -> bool {
+    /// 以新的速率替换指定 handle 的限速规则
+    fn replace_limit_command(
+        &self,
+        handle: &str,
+        ip: IpAddr,
+        kbps: u64,
+        burst: u64,
+        source_ports: Option<&[u16]>,
+    ) -> String {
+        format!(
+            "replace rule {} {} {} handle {} {}",
+            self.family,
+            self.table_name,
+            self.chain_name,
+            handle,
+            self.limit_rule_expr(ip, kbps, burst, source_ports)
+        )
+    }
+
+    pub async fn is_nft_available(&self) -> bool {
         self.nft_available
     }
 
-    /// 创建速率限制规则
-    async fn create_limit_rule(&self, ip: IpAddr, kbps: u64, burst: u64) -> Result<String> {
+    /// 按钩子方向匹配规则对象地址的表达式；自动动作放过该地址的受保护端口
+    fn subject_match(&self, ip: IpAddr) -> String {
         let direction = match self.hook {
             HookType::Input => "saddr",
             HookType::Output => "daddr",
         };
+        format!(
+            "{}{}",
+            self.addr_match(ip, direction),
+            self.port_exemption(ip, Self::current_source())
+        )
+    }
+
+    /// 来自 source 的动作须放过的受保护端口，只有自动动作受限
+    fn exempt_ports(&self, ip: &IpAddr, source: ActionSource) -> Vec<&ProtectedPort> {
+        if source != ActionSource::Automatic {
+            return Vec::new();
+        }
+        self.protected_ports
+            .iter()
+            .filter(|protected| protected.protects(ip))
+            .collect()
+    }
+
+    /// 放过受保护端口的表达式，如 ` meta l4proto . th dport != { tcp . 22 }`；
+    /// 入站匹配本机的目的端口，出站匹配回复报文的源端口
+    fn port_exemption(&self, ip: IpAddr, source: ActionSource) -> String {
+        let ports = self.exempt_ports(&ip, source);
+        if ports.is_empty() {
+            return String::new();
+        }
+        let port = match self.hook {
+            HookType::Input => "dport",
+            HookType::Output => "sport",
+        };
+        let elements: Vec<String> = ports
+            .iter()
+            .map(|protected| protected.nft_element())
+            .collect();
+        format!(
+            " meta l4proto . th {} != {{ {} }}",
+            port,
+            elements.join(", ")
+        )
+    }
+
+    /// 封禁是否同步到早期丢弃集合；早期丢弃不区分端口，放过受保护端口的自动封禁不同步
+    fn early_drops(&self, rule: &FirewallRule) -> bool {
+        early_dropped(rule)
+            && self
+                .exempt_ports(&rule.ip, Self::rule_source(rule))
+                .is_empty()
+    }
 
+    /// 匹配地址的表达式，如 `ip saddr 10.0.0.1`；同一链路本地地址可能出现在每条链路上，
+    /// 限定为本机监控的网卡，不波及其他链路上的同名地址
+    fn addr_match(&self, ip: IpAddr, direction: &str) -> String {
         let ip_version = match ip {
             IpAddr::V4(_) => "ip",
             IpAddr::V6(_) => "ip6",
         };
+        if !is_link_local(&ip) {
+            return format!("{} {} {}", ip_version, direction, ip);
+        }
+        let meta = match self.hook {
+            HookType::Input => "iifname",
+            HookType::Output => "oifname",
+        };
+        let devices: Vec<String> = self
+            .devices
+            .iter()
+            .map(|device| format!("\"{}\"", device))
+            .collect();
+        let devices = match devices.as_slice() {
+            [device] => device.clone(),
+            _ => format!("{{ {} }}", devices.join(", ")),
+        };
+        format!("{} {} {} {} {}", meta, devices, ip_version, direction, ip)
+    }
 
-        let rule_cmd = format!(
-            "add rule {} {} {} {} {} {} limit rate {} kbytes/second burst {} kbytes drop",
-            self.family, self.table_name, self.chain_name, ip_version, direction, ip, kbps, burst,
-        );
+    /// 生成速率限制规则的 nft 命令
+    pub fn limit_rule_command(
+        &self,
+        ip: IpAddr,
+        kbps: u64,
+        burst: u64,
+        source_ports: Option<&[u16]>,
+    ) -> String {
+        format!(
+            "add rule {} {} {} {}",
+            self.family,
+            self.table_name,
+            self.chain_name,
+            self.limit_rule_expr(ip, kbps, burst, source_ports)
+        )
+    }
+
+    /// 速率限制规则的匹配表达式与动作
+    fn limit_rule_expr(
+        &self,
+        ip: IpAddr,
+        kbps: u64,
+        burst: u64,
+        source_ports: Option<&[u16]>,
+    ) -> String {
+        format!(
+            "{} {}limit rate {} kbytes/second burst {} kbytes counter drop",
+            self.subject_match(ip),
+            port_matcher(source_ports),
+            kbps,
+            burst,
+        )
+    }
+
+    /// 创建速率限制规则
+    async fn create_limit_rule(
+        &self,
+        ip: IpAddr,
+        kbps: u64,
+        burst: u64,
+        source_ports: Option<&[u16]>,
+    ) -> Result<String> {
+        let rule_cmd = self.limit_rule_command(ip, kbps, burst, source_ports);
 
         // self.executor.execute(&rule_cmd).await?;
         // let output_with_handle = self.create_ban_rule(ip).await?;
-        let output_with_handle = self.executor.execute(&rule_cmd).await?;
+        let output_with_handle = self.install_rule(&rule_cmd).await?;
         let nft_objs = parse_output(&output_with_handle).await?;
 
         let nft_obj = nft_objs.first()
@@ -282,162 +865,899 @@ impl Firewall {
         Ok(handle)
     }
 
-    /// 对指定 IP 封禁指定时长
-    pub async fn ban(&self, ip: IpAddr, seconds: Option<u64>) -> Result<String> {
-        if seconds.is_none() {
-            return self.infinity_ban(ip).await;
+    /// 按 MAC 地址封禁（二层网段），ip 为触发封禁的地址
+    pub async fn ban_mac(&self, ip: IpAddr, mac: &str, seconds: Option<u64>) -> Result<RuleId> {
+        let action = Action::Ban {
+            duration: seconds.map(std::time::Duration::from_secs),
         };
-        let seconds = seconds.unwrap();
-        let duration = Duration::seconds(seconds as i64);
-        let now = Utc::now();
-        let until = now + duration;
-        let rule_id = format!("ban_{}_{}", ip, until.timestamp());
+        self.apply_mac_rule(ip, mac, action).await
+    }
+
+    /// 按 MAC 地址限速（二层网段），ip 为触发限速的地址
+    pub async fn limit_mac(
+        &self,
+        ip: IpAddr,
+        mac: &str,
+        kbps: u64,
+        burst: Option<u64>,
+        seconds: Option<u64>,
+    ) -> Result<RuleId> {
+        let burst = burst.unwrap_or(kbps.min(1024) / 10);
+        self.apply_mac_rule(
+            ip,
+            mac,
+            Action::RateLimit {
+                kbps,
+                burst: Some(burst),
+                duration: seconds.map(std::time::Duration::from_secs),
+            },
+        )
+        .await
+    }
+
+    /// 创建匹配 `ether saddr` 的规则，换 IP 的二层主机依然会被拦截
+    async fn apply_mac_rule(&self, ip: IpAddr, mac: &str, action: Action) -> Result<RuleId> {
+        let now = self.clock.wall();
+        let (kind, rule_kind, verdict) = match action {
+            Action::Ban { .. } => (EventKind::Ban, RuleKind::Ban, "counter drop".to_string()),
+            Action::RateLimit { kbps, burst, .. } => (
+                EventKind::Limit,
+                RuleKind::Limit,
+                format!(
+                    "limit rate {} kbytes/second burst {} kbytes counter drop",
+                    kbps,
+                    burst.unwrap_or(0)
+                ),
+            ),
+            Action::Mirror { .. } => {
+                return Err(anyhow!("mirror is not supported for MAC rules"));
+            }
+            Action::Inspect { .. } => {
+                return Err(anyhow!("inspect is not supported for MAC rules"));
+            }
+            Action::PoliceSources { .. } => {
+                return Err(anyhow!("source policing is not supported for MAC rules"));
+            }
+            Action::Warn { .. } => {
+                return Err(anyhow!("warn is not supported for MAC rules"));
+            }
+        };
+        self.arbitrate(ip, &kind.to_string()).await?;
+        let rule_id = RuleId::for_mac(rule_kind, mac);
+        let _lock = self.lock_apply(ip, rule_kind).await;
 
-        // 检查是否已被封禁
+        // 同一 MAC 已有同类且未过期的规则 => 跳过
         {
             let rules = self.rules.read().await;
-            for (_, rule) in rules.iter() {
-                if rule.ip == ip {
-                    // if let Some(rule) = rules.get(&rule_id) {
-                    if let Action::Ban { seconds: _sec } = rule.rule_type {
-                        let existing_until = rule.created_at + duration;
-                        if existing_until > Utc::now() {
-                            debug!(
-                                "IP {} has already been banned until {}, skipping",
-                                ip, existing_until
-                            );
-                            return Ok(rule.id.clone());
-                        }
-                    }
-                }
+            if let Some(existing) = rules.values().find(|rule| {
+                rule.mac.as_deref() == Some(mac)
+                    && std::mem::discriminant(&rule.rule_type) == std::mem::discriminant(&action)
+                    && !rule.is_expired(self.clock.as_ref())
+            }) {
+                debug!("MAC {} already has rule {}, skipping", mac, existing.id);
+                return Ok(existing.id.clone());
             }
         }
 
-        let output_with_handle = self.create_ban_rule(ip).await?;
-        let nft_objs = parse_output(&output_with_handle).await?;
-
-        let nft_obj = nft_objs.first()
-            .ok_or_else(|| anyhow!("fail to  get output  after adding rule"))?;
-
-        let handle = match nft_obj {
-            NftObject::Add(obj) => obj
-                .get_handle()
-                .await
-                .ok_or_else(|| anyhow!("fail to get "))?
-                .to_string(),
-            NftObject::Other(other) => {
-                return Err(anyhow!("parse output error: {:?}", other));
-            }
-            _ => {
-                return Err(anyhow!("parse output error: {:?}", nft_obj));
-            }
-        };
+        let rule_cmd = format!(
+            "add rule {} {} {} ether saddr {} {}",
+            self.family, self.table_name, self.chain_name, mac, verdict
+        );
+        let output_with_handle = self.install_rule(&rule_cmd).await?;
+        let handle = handle_from_output(&output_with_handle).await?;
 
         let rule = FirewallRule {
             id: rule_id.clone(),
             ip,
-            rule_type: Action::Ban {
-                seconds: Some(seconds),
-            },
+            rule_type: action,
             created_at: now,
+            created_mono: Some(self.clock.monotonic()),
             handle: Some(handle),
+            source_ports: None,
+            remaining_secs: None,
+            mac: Some(mac.to_string()),
+            reason: logger::current_reason(),
         };
 
-        self.rules.write().await.insert(rule_id.clone(), rule);
-        info!("Banned {} until {} \n rule id : {}", ip, until, &rule_id);
+        self.insert_rule(rule).await;
+        info!("Applied {} on MAC {} (seen as {})", kind, mac, ip);
+        self.events
+            .push(
+                Event::new(kind, format!("{} {} by mac {}", kind, ip, mac))
+                    .with_ip(ip)
+                    .with_rule(&rule_id),
+            )
+            .await;
 
         Ok(rule_id)
     }
 
-    pub async fn infinity_ban(&self, ip: IpAddr) -> Result<String> {
-        let now = Utc::now();
-        let rule_id = format!("ban_{}", ip);
-
+    /// 将指定 IP 的流量镜像到取证主机，原流量不受影响；seconds 为空时持续 300 秒
+    pub async fn mirror(
+        &self,
+        ip: IpAddr,
+        target: IpAddr,
+        device: Option<&str>,
+        seconds: Option<u64>,
+    ) -> Result<RuleId> {
+        let seconds = seconds.unwrap_or(DEFAULT_MIRROR_SECS);
+        let now = self.clock.wall();
+        let until = now + Duration::seconds(seconds as i64);
+        let rule_id = RuleId::for_ip(RuleKind::Mirror, ip, None);
+        let _lock = self.lock_apply(ip, RuleKind::Mirror).await;
+
+        // 同一 IP 已在镜像中 => 跳过
         {
             let rules = self.rules.read().await;
-            if let Some(_existing_rule) = rules.get(&rule_id) {
-                debug!("Rule {} already exists, skipping creation", rule_id);
-                return Ok(rule_id);
+            if let Some(existing) = rules.values().find(|rule| {
+                rule.ip == ip
+                    && matches!(rule.rule_type, Action::Mirror { .. })
+                    && !rule.is_expired(self.clock.as_ref())
+            }) {
+                debug!("IP {} is already mirrored by {}, skipping", ip, existing.id);
+                return Ok(existing.id.clone());
             }
         }
 
-        let output_with_handle = self.create_ban_rule(ip).await?;
-        let nft_objs = parse_output(&output_with_handle).await?;
-
-        let nft_obj = nft_objs.first()
-            .ok_or_else(|| anyhow!("fail to  get output  after adding rule"))?;
-
-        let handle = match nft_obj {
-            NftObject::Add(obj) => obj
-                .get_handle()
-                .await
-                .ok_or_else(|| anyhow!("fail to get "))?
-                .to_string(),
-            NftObject::Other(other) => {
-                return Err(anyhow!("parse output error: {:?}", other));
-            }
-            _ => {
-                return Err(anyhow!("parse output error: {:?}", nft_obj));
-            }
-        };
+        let rule_cmd = self.mirror_rule_command(ip, target, device);
+        let output_with_handle = self.install_rule(&rule_cmd).await?;
+        let handle = handle_from_output(&output_with_handle).await?;
 
         let rule = FirewallRule {
             id: rule_id.clone(),
             ip,
-            rule_type: Action::Ban { seconds: None },
+            rule_type: Action::Mirror {
+                target,
+                device: device.map(|device| device.to_string()),
+                duration: Some(std::time::Duration::from_secs(seconds)),
+            },
             created_at: now,
+            created_mono: Some(self.clock.monotonic()),
             handle: Some(handle),
+            source_ports: None,
+            remaining_secs: None,
+            mac: None,
+            reason: logger::current_reason(),
         };
 
-        self.rules.write().await.insert(rule_id.clone(), rule);
-        info!("Banned {} infinity   \n rule id : {}", ip, &rule_id);
+        self.insert_rule(rule).await;
+        info!("Mirroring {} to {} until {}", ip, target, until);
+        self.events
+            .push(
+                Event::new(
+                    EventKind::Mirror,
+                    format!("mirror {} to {} for {}s", ip, target, seconds),
+                )
+                .with_ip(ip)
+                .with_rule(&rule_id),
+            )
+            .await;
 
         Ok(rule_id)
     }
 
-    /// 创建封禁规则
-    async fn create_ban_rule(&self, ip: IpAddr) -> Result<String> {
-        let direction = match self.hook {
-            HookType::Input => "saddr",
-            HookType::Output => "daddr",
-        };
-        let ip_version = match ip {
+    /// 生成镜像规则的 nft 命令，`dup` 不是终结动作，原流量继续匹配后续规则
+    pub fn mirror_rule_command(&self, ip: IpAddr, target: IpAddr, device: Option<&str>) -> String {
+        let target_version = match target {
             IpAddr::V4(_) => "ip",
             IpAddr::V6(_) => "ip6",
         };
+        let device = device
+            .map(|device| format!(" device \"{}\"", device))
+            .unwrap_or_default();
+
+        format!(
+            "add rule {} {} {} {} dup {} to {}{}",
+            self.family,
+            self.table_name,
+            self.chain_name,
+            self.subject_match(ip),
+            target_version,
+            target,
+            device
+        )
+    }
 
-        let rule_cmd = format!(
-            "add rule {} {} {} {} {} {} drop",
-            self.family, self.table_name, self.chain_name, ip_version, direction, ip
-        );
+    /// 将指定 IP 的报文送入 NFQUEUE 逐包判定；seconds 为空时持续 300 秒，同时检查的 IP 已达上限时返回 None
+    pub async fn inspect(&self, ip: IpAddr, seconds: Option<u64>) -> Result<Option<RuleId>> {
+        let inspector = self
+            .inspector
+            .as_ref()
+            .ok_or_else(|| anyhow!("the Inspect action requires an [nfqueue] section"))?;
+        let seconds = seconds.unwrap_or(DEFAULT_INSPECT_SECS);
+        let now = self.clock.wall();
+        let until = now + Duration::seconds(seconds as i64);
+        let rule_id = RuleId::for_ip(RuleKind::Inspect, ip, None);
+        let _lock = self.lock_apply(ip, RuleKind::Inspect).await;
+
+        // 同一 IP 已在检查中 => 跳过
+        let active: Vec<IpAddr> = {
+            let rules = self.rules.read().await;
+            let active: Vec<&FirewallRule> = rules
+                .values()
+                .filter(|rule| {
+                    matches!(rule.rule_type, Action::Inspect { .. })
+                        && !rule.is_expired(self.clock.as_ref())
+                })
+                .collect();
+            if let Some(existing) = active.iter().find(|rule| rule.ip == ip) {
+                debug!(
+                    "IP {} is already inspected by {}, skipping",
+                    ip, existing.id
+                );
+                return Ok(Some(existing.id.clone()));
+            }
+            if active.len() >= inspector.max_ips() {
+                warn!(
+                    "{} IPs are already under inspection, not inspecting {}",
+                    active.len(),
+                    ip
+                );
+                return Ok(None);
+            }
+            active.iter().map(|rule| rule.ip).collect()
+        };
 
-        let output_with_handle = self.executor.execute(&rule_cmd).await?;
+        let rule_cmd = self.inspect_rule_command(ip, inspector.queue_num());
+        let output_with_handle = self.install_rule(&rule_cmd).await?;
+        let handle = handle_from_output(&output_with_handle).await?;
+        inspector.watch(ip, &active);
 
-        Ok(output_with_handle)
-    }
+        let rule = FirewallRule {
+            id: rule_id.clone(),
+            ip,
+            rule_type: Action::Inspect {
+                duration: Some(std::time::Duration::from_secs(seconds)),
+            },
+            created_at: now,
+            created_mono: Some(self.clock.monotonic()),
+            handle: Some(handle),
+            source_ports: None,
+            remaining_secs: None,
+            mac: None,
+            reason: logger::current_reason(),
+        };
 
-    pub async fn is_expiration(&self, rule_id: &str, seconds: u64) -> bool {
-        let duration = Duration::seconds(seconds as i64);
-        let now = Utc::now();
-        let rules = self.rules.read().await;
-        if let Some(rule) = rules.get(rule_id) {
-            // for (_, rule) in rules.iter() {
-            // if rule.ip == ip {
-            let expiration = rule.created_at + duration;
+        self.insert_rule(rule).await;
+        info!("Inspecting packets of {} until {}", ip, until);
+        self.events
+            .push(
+                Event::new(
+                    EventKind::Inspect,
+                    format!("inspect {} for {}s", ip, seconds),
+                )
+                .with_ip(ip)
+                .with_rule(&rule_id),
+            )
+            .await;
+
+        Ok(Some(rule_id))
+    }
 
-            
-            now > expiration
-        } else {
-            false
-        }
-        // }
+    /// 生成检查规则的 nft 命令，`bypass` 使守护进程未监听队列时报文直接放行
+    pub fn inspect_rule_command(&self, ip: IpAddr, queue_num: u16) -> String {
+        format!(
+            "add rule {} {} {} {} queue num {} bypass",
+            self.family,
+            self.table_name,
+            self.chain_name,
+            self.subject_match(ip),
+            queue_num
+        )
     }
 
-    /// 解封指定IP
-    pub async fn unblock(&self, id: &str) -> Result<()> {
-        debug!("get RwLock to remove rule : {}", id);
+    /// 对发往本机地址 dest 的流量按来源分别限速，超过 kbps 的来源被丢弃，其他目的地址不受影响；
+    /// 规则以 dest 为对象，dest 上已有未过期的同类规则时直接返回
+    pub async fn police_sources(
+        &self,
+        dest: IpAddr,
+        kbps: u64,
+        burst: Option<u64>,
+        seconds: Option<u64>,
+    ) -> Result<RuleId> {
+        let burst = burst.unwrap_or(kbps.min(1024) / 10);
+        let rule_id = RuleId::for_ip(RuleKind::Police, dest, None);
+        let _lock = self.lock_apply(dest, RuleKind::Police).await;
 
-        let handle = {
+        {
+            let rules = self.rules.read().await;
+            if let Some(existing) = rules.values().find(|rule| {
+                rule.ip == dest
+                    && matches!(rule.rule_type, Action::PoliceSources { .. })
+                    && !rule.is_expired(self.clock.as_ref())
+            }) {
+                debug!(
+                    "sources of {} are already policed by {}, skipping",
+                    dest, existing.id
+                );
+                return Ok(existing.id.clone());
+            }
+        }
+
+        let rule_cmd = self.police_rule_command(dest, kbps, burst);
+        let output_with_handle = self.install_rule(&rule_cmd).await?;
+        let handle = handle_from_output(&output_with_handle).await?;
+
+        let rule = FirewallRule {
+            id: rule_id.clone(),
+            ip: dest,
+            rule_type: Action::PoliceSources {
+                kbps,
+                burst: Some(burst),
+                duration: seconds.map(std::time::Duration::from_secs),
+            },
+            created_at: self.clock.wall(),
+            created_mono: Some(self.clock.monotonic()),
+            handle: Some(handle),
+            source_ports: None,
+            remaining_secs: None,
+            mac: None,
+            reason: logger::current_reason(),
+        };
+
+        self.insert_rule(rule).await;
+        info!(
+            "Policing sources of {}: {} KB/s per source (burst: {} KB)",
+            dest, kbps, burst
+        );
+        self.events
+            .push(
+                Event::new(
+                    EventKind::Police,
+                    format!("police sources of {} to {} KB/s each", dest, kbps),
+                )
+                .with_ip(dest)
+                .with_rule(&rule_id),
+            )
+            .await;
+
+        Ok(rule_id)
+    }
+
+    /// 生成按来源限速规则的 nft 命令：匹配目的地址，再以来源地址为键的 meter 逐个限速
+    pub fn police_rule_command(&self, dest: IpAddr, kbps: u64, burst: u64) -> String {
+        let ip_version = match dest {
+            IpAddr::V4(_) => "ip",
+            IpAddr::V6(_) => "ip6",
+        };
+        // meter 名称只能包含字母、数字与下划线
+        let meter: String = dest
+            .to_string()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+
+        format!(
+            "add rule {} {} {} {} meter police_{} size 65535 {{ {} saddr limit rate over {} kbytes/second burst {} kbytes }} counter drop",
+            self.family,
+            self.table_name,
+            self.chain_name,
+            self.addr_match(dest, "daddr"),
+            meter,
+            ip_version,
+            kbps,
+            burst
+        )
+    }
+
+    /// 标记来源而不丢弃其报文：规则只计数并按配置打上 fwmark，来源由提示页导出供 Web 服务返回 429；
+    /// 同一 IP 已有未过期的同类规则时直接返回
+    pub async fn warn(&self, ip: IpAddr, seconds: Option<u64>) -> Result<RuleId> {
+        let rule_id = RuleId::for_ip(RuleKind::Warn, ip, None);
+        let _lock = self.lock_apply(ip, RuleKind::Warn).await;
+
+        {
+            let rules = self.rules.read().await;
+            if let Some(existing) = rules.values().find(|rule| {
+                rule.ip == ip
+                    && matches!(rule.rule_type, Action::Warn { .. })
+                    && !rule.is_expired(self.clock.as_ref())
+            }) {
+                debug!("IP {} is already warned by {}, skipping", ip, existing.id);
+                return Ok(existing.id.clone());
+            }
+        }
+
+        let rule_cmd = self.warn_rule_command(ip);
+        let output_with_handle = self.install_rule(&rule_cmd).await?;
+        let handle = handle_from_output(&output_with_handle).await?;
+
+        let rule = FirewallRule {
+            id: rule_id.clone(),
+            ip,
+            rule_type: Action::Warn {
+                duration: seconds.map(std::time::Duration::from_secs),
+            },
+            created_at: self.clock.wall(),
+            created_mono: Some(self.clock.monotonic()),
+            handle: Some(handle),
+            source_ports: None,
+            remaining_secs: None,
+            mac: None,
+            reason: logger::current_reason(),
+        };
+
+        self.insert_rule(rule).await;
+        let seconds = seconds
+            .map(|s| s.to_string())
+            .unwrap_or("infinity".to_string());
+        info!("Warning {} for {} seconds", ip, seconds);
+        self.events
+            .push(
+                Event::new(EventKind::Warn, format!("warn {} for {}s", ip, seconds))
+                    .with_ip(ip)
+                    .with_rule(&rule_id),
+            )
+            .await;
+
+        Ok(rule_id)
+    }
+
+    /// 生成提示规则的 nft 命令，没有判决语句，报文继续经过后续规则
+    pub fn warn_rule_command(&self, ip: IpAddr) -> String {
+        let mark = self
+            .warn_mark
+            .map(|mark| format!(" meta mark set {:#x}", mark))
+            .unwrap_or_default();
+
+        format!(
+            "add rule {} {} {} {} counter{}",
+            self.family,
+            self.table_name,
+            self.chain_name,
+            self.subject_match(ip),
+            mark
+        )
+    }
+
+    /// 对指定 IP 封禁指定时长
+    pub async fn ban(&self, ip: IpAddr, seconds: Option<u64>) -> Result<RuleId> {
+        self.ban_on_ports(ip, seconds, None).await
+    }
+
+    /// 对指定 IP 封禁指定时长，可限定源端口；seconds 为 None 时永久
+    pub async fn ban_on_ports(
+        &self,
+        ip: IpAddr,
+        seconds: Option<u64>,
+        source_ports: Option<&[u16]>,
+    ) -> Result<RuleId> {
+        self.arbitrate(ip, "ban").await?;
+        let _lock = self.lock_apply(ip, RuleKind::Ban).await;
+        let action = Action::Ban {
+            duration: seconds.map(std::time::Duration::from_secs),
+        };
+        let now = self.clock.wall();
+        let rule_id = RuleId::for_ip(RuleKind::Ban, ip, source_ports);
+
+        // 已有生效中的封禁 => 不再创建规则，优先级更高的来源替换之；永久封禁只以已有的永久封禁为准
+        if let Some(existing) = self
+            .find_active_ban(ip, source_ports, action.is_permanent())
+            .await
+        {
+            if !self.supersedes(&existing, "ban").await {
+                debug!("IP {} is already banned by {}, skipping", ip, existing.id);
+                return Ok(existing.id);
+            }
+            self.unblock(&existing.id).await?;
+        }
+
+        let output_with_handle = self.create_ban_rule(ip, source_ports).await?;
+        let nft_objs = parse_output(&output_with_handle).await?;
+
+        let nft_obj = nft_objs.first()
+            .ok_or_else(|| anyhow!("fail to  get output  after adding rule"))?;
+
+        let handle = match nft_obj {
+            NftObject::Add(obj) => obj
+                .get_handle()
+                .await
+                .ok_or_else(|| anyhow!("fail to get "))?
+                .to_string(),
+            NftObject::Other(other) => {
+                return Err(anyhow!("parse output error: {:?}", other));
+            }
+            _ => {
+                return Err(anyhow!("parse output error: {:?}", nft_obj));
+            }
+        };
+
+        let rule = FirewallRule {
+            id: rule_id.clone(),
+            ip,
+            rule_type: action,
+            created_at: now,
+            created_mono: Some(self.clock.monotonic()),
+            handle: Some(handle),
+            source_ports: source_ports.map(|ports| ports.to_vec()),
+            remaining_secs: None,
+            mac: None,
+            reason: logger::current_reason(),
+        };
+
+        let early = self.early_drops(&rule);
+        let until = rule.expires_at();
+        self.insert_rule(rule).await;
+        if early {
+            self.early_drop_add(&[(ip, seconds)]).await;
+        }
+        let message = match (seconds, until) {
+            (Some(seconds), Some(until)) => {
+                info!("Banned {} until {} \n rule id : {}", ip, until, &rule_id);
+                format!("ban {} for {}s", ip, seconds)
+            }
+            _ => {
+                info!("Banned {} infinity   \n rule id : {}", ip, &rule_id);
+                format!("ban {} infinity", ip)
+            }
+        };
+        self.events
+            .push(
+                Event::new(EventKind::Ban, message)
+                    .with_ip(ip)
+                    .with_rule(&rule_id),
+            )
+            .await;
+
+        Ok(rule_id)
+    }
+
+    /// 生成封禁规则的 nft 命令
+    pub fn ban_rule_command(&self, ip: IpAddr, source_ports: Option<&[u16]>) -> String {
+        format!(
+            "add rule {} {} {} {} {}counter drop",
+            self.family,
+            self.table_name,
+            self.chain_name,
+            self.subject_match(ip),
+            port_matcher(source_ports)
+        )
+    }
+
+    /// Promote 沙盒模式下先在沙盒表中试装规则：能被解析并分配 handle 即为通过，随后从沙盒中删除；
+    /// 其他模式下全部视为通过
+    async fn trial_in_sandbox(&self, commands: &[String]) -> Result<Vec<Result<()>>> {
+        if self.sandbox != Some(SandboxMode::Promote) || !self.nft_available {
+            return Ok(commands.iter().map(|_| Ok(())).collect());
+        }
+        let live = format!("{} {} {} ", self.family, self.table_name, self.chain_name);
+        let sandbox = format!(
+            "{} {} {} ",
+            self.family, self.sandbox_table, self.chain_name
+        );
+        let trials = commands
+            .iter()
+            .map(|command| command.replacen(&live, &sandbox, 1))
+            .collect();
+        let outputs = self.executor.execute_each(trials).await?;
+
+        let mut results = Vec::with_capacity(outputs.len());
+        let mut removals = Vec::new();
+        for output in outputs {
+            let handle = match output {
+                Ok(output) => handle_from_output(&output).await,
+                Err(e) => Err(e),
+            };
+            results.push(match handle {
+                Ok(handle) => {
+                    removals.push(format!("delete rule {}handle {}", sandbox, handle));
+                    Ok(())
+                }
+                Err(e) => {
+                    warn!(
+                        "rule rejected in sandbox table {}: {}",
+                        self.sandbox_table, e
+                    );
+                    Err(anyhow!("rule rejected in sandbox: {}", e))
+                }
+            });
+        }
+        if !removals.is_empty() {
+            self.executor.execute(&removals.join("; ")).await?;
+        }
+        Ok(results)
+    }
+
+    /// 添加一条规则，返回带 handle 的 nft 回显
+    async fn install_rule(&self, command: &str) -> Result<String> {
+        for trial in self.trial_in_sandbox(&[command.to_string()]).await? {
+            trial?;
+        }
+        self.executor.execute(command).await
+    }
+
+    /// 向集合写入元素，已有的元素先删除以更新超时；
+    /// 链中的规则仍然生效，写入失败时只记录警告
+    async fn add_elements(&self, sets: &AddressSets, items: &[(IpAddr, Option<u64>)]) {
+        let mut commands = Vec::new();
+        for (ip, seconds) in items {
+            commands.extend(sets.element_command("delete", *ip, None));
+            commands.extend(sets.element_command("add", *ip, *seconds));
+        }
+        if commands.is_empty() {
+            return;
+        }
+        match self.executor.execute_each(commands.clone()).await {
+            Ok(results) => {
+                for (command, result) in commands.iter().zip(results) {
+                    if let (true, Err(e)) = (command.starts_with("add"), result) {
+                        warn!("failed to update set: {}: {}", command, e);
+                    }
+                }
+            }
+            Err(e) => warn!("failed to update sets in table {}: {}", sets.table(), e),
+        }
+    }
+
+    /// 从集合中移除元素，元素可能已按超时自动过期，因此忽略删除失败
+    async fn remove_elements(&self, sets: &AddressSets, ips: &[IpAddr]) {
+        let commands: Vec<String> = ips
+            .iter()
+            .filter_map(|ip| sets.element_command("delete", *ip, None))
+            .collect();
+        if commands.is_empty() {
+            return;
+        }
+        if let Err(e) = self.executor.execute_each(commands).await {
+            debug!(
+                "failed to remove from sets in table {}: {}",
+                sets.table(),
+                e
+            );
+        }
+    }
+
+    /// 将封禁同步到早期丢弃集合
+    async fn early_drop_add(&self, bans: &[(IpAddr, Option<u64>)]) {
+        if let Some(sets) = &self.early_drop {
+            self.add_elements(sets, bans).await;
+        }
+    }
+
+    /// 从早期丢弃集合中移除
+    async fn early_drop_remove(&self, ips: &[IpAddr]) {
+        if let Some(sets) = &self.early_drop {
+            self.remove_elements(sets, ips).await;
+        }
+    }
+
+    /// 创建 flowtable 及向其添加连接的 forward 链；有规则生效的来源先行放过，不被卸载
+    fn offload_commands(&self, offload: &Offload) -> Vec<String> {
+        let flags = if offload.hardware {
+            " flags offload ;"
+        } else {
+            ""
+        };
+        let mut commands = vec![
+            format!(
+                "add flowtable {} {} {} {{ hook ingress priority {} ; devices = {{ {} }} ;{} }}",
+                self.family,
+                self.table_name,
+                OFFLOAD_FLOWTABLE,
+                offload.priority,
+                quoted_list(&offload.devices),
+                flags
+            ),
+            format!(
+                "add chain {} {} {} {{ type filter hook forward priority {} ; policy accept ; }}",
+                self.family, self.table_name, OFFLOAD_CHAIN, offload.priority
+            ),
+            format!(
+                "flush chain {} {} {}",
+                self.family, self.table_name, OFFLOAD_CHAIN
+            ),
+        ];
+        commands.extend(offload.exempt.create_commands());
+        for direction in ["saddr", "daddr"] {
+            commands.extend(
+                offload
+                    .exempt
+                    .matchers(direction)
+                    .into_iter()
+                    .map(|matcher| {
+                        format!(
+                            "add rule {} {} {} {} accept",
+                            self.family, self.table_name, OFFLOAD_CHAIN, matcher
+                        )
+                    }),
+            );
+        }
+        commands.push(format!(
+            "add rule {} {} {} meta l4proto {{ tcp, udp }} flow add @{}",
+            self.family, self.table_name, OFFLOAD_CHAIN, OFFLOAD_FLOWTABLE
+        ));
+        commands
+    }
+
+    /// 按来源上仍生效的规则更新卸载豁免：有规则时豁免到最晚的过期时间，没有规则时恢复卸载
+    async fn refresh_offload(&self, ips: &[IpAddr]) {
+        let Some(offload) = &self.offload else {
+            return;
+        };
+        let mut exempt = Vec::new();
+        let mut released = Vec::new();
+        {
+            let rules = self.rules.read().await;
+            for ip in ips {
+                let remaining: Vec<Option<u64>> = rules
+                    .values()
+                    .filter(|rule| rule.ip == *ip)
+                    .map(|rule| {
+                        rule.remaining(self.clock.as_ref())
+                            .map(|left| left.as_secs().max(1))
+                    })
+                    .collect();
+                if remaining.is_empty() {
+                    released.push(*ip);
+                } else {
+                    // 任一规则为永久规则时豁免不设超时
+                    let latest = remaining
+                        .into_iter()
+                        .try_fold(0, |latest, left| left.map(|left| left.max(latest)));
+                    exempt.push((*ip, latest));
+                }
+            }
+        }
+        self.add_elements(&offload.exempt, &exempt).await;
+        self.remove_elements(&offload.exempt, &released).await;
+    }
+
+    /// 新建规则后使其来源不再被卸载，并删除该来源的 conntrack 记录，
+    /// 已卸载的连接随之撤下，后续报文回到软件路径经过规则
+    async fn exempt_from_offload(&self, ips: &[IpAddr]) {
+        if self.offload.is_none() {
+            return;
+        }
+        self.refresh_offload(ips).await;
+        if !self.nft_available {
+            return;
+        }
+        for ip in ips {
+            for direction in ["-s", "-d"] {
+                // 没有匹配的连接时 conntrack 以非零状态退出，只关心能否执行
+                if let Err(e) = Command::new("conntrack")
+                    .args(["-D", direction, &ip.to_string()])
+                    .output()
+                    .await
+                {
+                    warn!("failed to flush offloaded flows of {}: {}", ip, e);
+                }
+            }
+        }
+    }
+
+    /// 记录新规则
+    async fn insert_rule(&self, rule: FirewallRule) {
+        let ip = rule.ip;
+        if let Some(reason) = &rule.reason {
+            info!("rule {} for {} triggered by {}", rule.id, ip, reason);
+        }
+        self.rules.write().await.insert(rule.id.clone(), rule);
+        self.invalidate_system_rules().await;
+        self.exempt_from_offload(&[ip]).await;
+    }
+
+    /// 创建封禁规则
+    /// 获取 (IP, 规则类型) 的锁，持有期间同一 IP 的同类动作等待
+    async fn lock_apply(&self, ip: IpAddr, kind: RuleKind) -> ApplyGuard {
+        let lock = Arc::clone(&*self.apply_locks.entry((ip, kind)).or_default());
+        ApplyGuard {
+            locks: Arc::clone(&self.apply_locks),
+            key: (ip, kind),
+            guard: Some(lock.lock_owned().await),
+        }
+    }
+
+    /// 查找同一 IP（及源端口）上生效中的封禁规则，不含按 MAC 的封禁；permanent_only 时只查找永久封禁
+    async fn find_active_ban(
+        &self,
+        ip: IpAddr,
+        source_ports: Option<&[u16]>,
+        permanent_only: bool,
+    ) -> Option<FirewallRule> {
+        let rules = self.rules.read().await;
+        rules
+            .values()
+            .find(|rule| {
+                rule.ip == ip
+                    && rule.mac.is_none()
+                    && rule.source_ports.as_deref() == source_ports
+                    && matches!(rule.rule_type, Action::Ban { .. })
+                    && (!permanent_only || rule.rule_type.is_permanent())
+                    && !rule.is_expired(self.clock.as_ref())
+            })
+            .cloned()
+    }
+
+    async fn create_ban_rule(&self, ip: IpAddr, source_ports: Option<&[u16]>) -> Result<String> {
+        let rule_cmd = self.ban_rule_command(ip, source_ports);
+
+        let output_with_handle = self.install_rule(&rule_cmd).await?;
+
+        Ok(output_with_handle)
+    }
+
+    pub async fn is_expiration(&self, rule_id: &RuleId, seconds: u64) -> bool {
+        let rules = self.rules.read().await;
+        if let Some(rule) = rules.get(rule_id) {
+            // for (_, rule) in rules.iter() {
+            // if rule.ip == ip {
+            rule.elapsed(self.clock.as_ref()) > std::time::Duration::from_secs(seconds)
+        } else {
+            false
+        }
+        // }
+    }
+
+    /// 按规则自身记录的时长判断是否已过期，规则不存在或为永久规则时返回 false
+    pub async fn is_expired(&self, rule_id: &RuleId) -> bool {
+        let rules = self.rules.read().await;
+        rules
+            .get(rule_id)
+            .is_some_and(|rule| rule.is_expired(self.clock.as_ref()))
+    }
+
+    pub async fn has_rule(&self, rule_id: &RuleId) -> bool {
+        self.rules.read().await.contains_key(rule_id)
+    }
+
+    /// 延长规则的生效时长，不删除重建 nft 规则，返回新的过期时间
+    pub async fn extend(&self, rule_id: &RuleId, seconds: u64) -> Result<DateTime<Utc>> {
+        let (ip, until, early_dropped) = {
+            let mut rules = self.rules.write().await;
+            let rule = rules
+                .get_mut(rule_id)
+                .ok_or_else(|| anyhow!("fail to get rule by id: {}", rule_id))?;
+            // 手动封禁延长后的剩余时长同样受 max_manual_ban 限制
+            if matches!(rule.rule_type, Action::Ban { .. })
+                && Self::rule_source(rule) == ActionSource::Manual
+            {
+                if let Some(remaining) = rule.remaining(self.clock.as_ref()) {
+                    self.check_manual_ban(Some(remaining.as_secs().saturating_add(seconds)))?;
+                }
+            }
+            if rule
+                .rule_type
+                .extend(std::time::Duration::from_secs(seconds))
+                .is_none()
+            {
+                return Err(anyhow!("rule {} is permanent, nothing to extend", rule_id));
+            }
+            let remaining = rule
+                .remaining(self.clock.as_ref())
+                .ok_or_else(|| anyhow!("rule {} has no expiration", rule_id))?;
+            let until = self.clock.wall() + Duration::from_std(remaining)?;
+            (rule.ip, until, self.early_drops(rule).then_some(remaining))
+        };
+        if let Some(remaining) = early_dropped {
+            self.early_drop_add(&[(ip, Some(remaining.as_secs().max(1)))])
+                .await;
+        }
+        self.refresh_offload(&[ip]).await;
+
+        info!(
+            "Extended rule {} by {}s, now until {}",
+            rule_id, seconds, until
+        );
+        self.events
+            .push(
+                Event::new(
+                    EventKind::Extend,
+                    format!("extend {} by {}s", rule_id, seconds),
+                )
+                .with_ip(ip)
+                .with_rule(rule_id),
+            )
+            .await;
+
+        Ok(until)
+    }
+
+    /// 解封指定IP
+    pub async fn unblock(&self, id: &RuleId) -> Result<()> {
+        debug!("get RwLock to remove rule : {}", id);
+
+        let handle = {
             let rules = self.rules.read().await;
             let rule = rules
                 .get(id)
@@ -454,8 +1774,20 @@ impl Firewall {
             rules.remove(id)
         };
 
-        if removed.is_some() {
+        self.yielded.remove(id);
+        if let Some(rule) = removed {
+            if self.early_drops(&rule) {
+                self.early_drop_remove(&[rule.ip]).await;
+            }
+            self.refresh_offload(&[rule.ip]).await;
             info!("Unblocked successful,\n remove rule: {}", id);
+            self.events
+                .push(
+                    Event::new(EventKind::Unblock, format!("remove rule {}", id))
+                        .with_ip(rule.ip)
+                        .with_rule(id),
+                )
+                .await;
         } else {
             warn!("fail to remove rule, maybe not exist: {}", id);
             return Err(anyhow!("fail to remove rule, maybe not exist: {}", id));
@@ -464,6 +1796,72 @@ impl Firewall {
         Ok(())
     }
 
+    /// 解除所有符合条件的规则，删除命令在同一 nft 事务中提交，要么全部生效要么全部失败
+    pub async fn unblock_matching(&self, filter: &RuleFilter) -> Result<Vec<RuleId>> {
+        let network = filter.network.as_deref().map(parse_network).transpose()?;
+        let older_than = filter.older_than.map(std::time::Duration::from_secs);
+
+        // 持有写锁直到规则表更新完成，避免与新增规则交错
+        let mut rules = self.rules.write().await;
+        let matched: Vec<(RuleId, Option<String>)> = rules
+            .values()
+            .filter(|rule| {
+                network
+                    .as_ref()
+                    .is_none_or(|network| network.contains(rule.ip))
+            })
+            .filter(|rule| older_than.is_none_or(|age| rule.elapsed(self.clock.as_ref()) >= age))
+            .map(|rule| (rule.id.clone(), rule.handle.clone()))
+            .collect();
+
+        if matched.is_empty() {
+            debug!("No rules match {}", filter);
+            return Ok(Vec::new());
+        }
+
+        let commands: Vec<String> = matched
+            .iter()
+            .filter_map(|(_, handle)| handle.as_ref())
+            .map(|handle| {
+                format!(
+                    "delete rule {} {} {} handle {}",
+                    self.family, self.table_name, self.chain_name, handle
+                )
+            })
+            .collect();
+        if !commands.is_empty() {
+            // 同一行提交的多条命令由 nft 作为一个事务执行
+            self.executor.execute(&commands.join("; ")).await?;
+        }
+
+        let ids: Vec<RuleId> = matched.into_iter().map(|(id, _)| id).collect();
+        let removed: Vec<FirewallRule> = ids.iter().filter_map(|id| rules.remove(id)).collect();
+        drop(rules);
+        for id in &ids {
+            self.yielded.remove(id);
+        }
+        self.invalidate_system_rules().await;
+        let early: Vec<IpAddr> = removed
+            .iter()
+            .filter(|rule| self.early_drops(rule))
+            .map(|rule| rule.ip)
+            .collect();
+        self.early_drop_remove(&early).await;
+        let sources: HashSet<IpAddr> = removed.iter().map(|rule| rule.ip).collect();
+        self.refresh_offload(&sources.into_iter().collect::<Vec<_>>())
+            .await;
+
+        info!("Unblocked {} {}", ids.len(), filter);
+        self.events
+            .push(Event::new(
+                EventKind::Unblock,
+                format!("remove {} {}", ids.len(), filter),
+            ))
+            .await;
+
+        Ok(ids)
+    }
+
     /// 根据句柄移除规则
     async fn remove_rule_by_handle(&self, handle: &str) -> Result<()> {
         debug!("Removing rule by handle: {}", handle);
@@ -474,29 +1872,88 @@ impl Firewall {
         );
 
         self.executor.input(&remove_command).await?;
+        self.invalidate_system_rules().await;
 
         debug!("execute command to delete nft rule: {}", &remove_command);
 
         Ok(())
     }
 
-    /// 获取所有活跃规则
+    /// 获取所有活跃规则，并附带剩余生效时间
     pub async fn get_active_rules(&self) -> Result<Vec<FirewallRule>> {
         let rules = self.rules.read().await;
-        Ok(rules.values().cloned().collect())
+        Ok(rules
+            .values()
+            .map(|rule| FirewallRule {
+                remaining_secs: rule
+                    .remaining(self.clock.as_ref())
+                    .map(|left| left.as_secs()),
+                ..rule.clone()
+            })
+            .collect())
     }
 
-    /// 获取当前 nftables 规则（从系统读取）
-    pub async fn get_system_rules(&self) -> Result<String> {
+    /// 获取当前 nftables 规则，短时间内的重复调用直接返回缓存
+    pub async fn get_system_rules(&self) -> Result<Vec<SystemRule>> {
+        if let Some((read_at, rules)) = self.system_rules.read().await.as_ref() {
+            if self.clock.monotonic().saturating_sub(*read_at) < SYSTEM_RULES_TTL {
+                return Ok(rules.clone());
+            }
+        }
+        self.refresh_system_rules().await
+    }
+
+    /// 规则增删后丢弃缓存，下次读取时重新列出
+    async fn invalidate_system_rules(&self) {
+        *self.system_rules.write().await = None;
+    }
+
+    /// 绕过缓存从系统读取当前链中的规则
+    pub async fn refresh_system_rules(&self) -> Result<Vec<SystemRule>> {
         if !self.is_nft_available().await {
-            return Ok("nftables not available".to_string());
+            return Ok(Vec::new());
         }
 
         let list_cmd = format!(
             "list chain {} {} {}",
             self.family, self.table_name, self.chain_name
         );
-        self.executor.execute(&list_cmd).await
+        let output = self.executor.execute(&list_cmd).await?;
+        let rules: Vec<SystemRule> = parse_output(&output)
+            .await?
+            .iter()
+            .filter_map(|obj| match obj {
+                NftObject::Rule(rule) => Some(rule.rule.to_system_rule()),
+                _ => None,
+            })
+            .collect();
+
+        *self.system_rules.write().await = Some((self.clock.monotonic(), rules.clone()));
+        Ok(rules)
+    }
+
+    /// 守护进程记录的规则中，句柄在 nftables 链里已不存在的数量；nft 不可用时为 0
+    pub async fn divergent_rules(&self) -> Result<usize> {
+        if !self.is_nft_available().await {
+            return Ok(0);
+        }
+        let handles: HashSet<String> = self
+            .refresh_system_rules()
+            .await?
+            .iter()
+            .filter_map(|rule| rule.handle.map(|handle| handle.to_string()))
+            .collect();
+        Ok(self
+            .rules
+            .read()
+            .await
+            .values()
+            .filter(|rule| {
+                rule.handle
+                    .as_ref()
+                    .is_some_and(|handle| !handles.contains(handle))
+            })
+            .count())
     }
 
     /// 清理所有自管理规则
@@ -517,14 +1974,29 @@ impl Firewall {
             self.family, self.table_name, self.chain_name
         );
         self.executor.input(&flush_cmd).await?;
+        for sets in self
+            .early_drop
+            .iter()
+            .chain(self.offload.iter().map(|offload| &offload.exempt))
+        {
+            self.executor.input(&sets.flush_command()).await?;
+        }
 
         // 清空内存中的规则记录
         self.rules.write().await.clear();
+        self.yielded.clear();
+        self.invalidate_system_rules().await;
 
         info!(
             "Cleaned up all rules in chain {} (count: {})",
             self.chain_name, rule_count
         );
+        self.events
+            .push(Event::new(
+                EventKind::Flush,
+                format!("flush {} rules", rule_count),
+            ))
+            .await;
         Ok(rule_count)
     }
 
@@ -539,12 +2011,17 @@ impl Firewall {
         // return Ok(());
         // }
 
-        let delete_cmd = format!("delete table {} {}", self.family, self.table_name);
-        self.executor.input(&delete_cmd).await?;
+        for table in self.owned_tables() {
+            self.executor
+                .input(&format!("delete table {}", table))
+                .await?;
+        }
         let _ = self.executor.execute("list tables").await?;
 
         // 清空内存中的规则记录
         self.rules.write().await.clear();
+        self.yielded.clear();
+        self.invalidate_system_rules().await;
 
         info!(
             "Cleaned up all rules in chain {} (count: {})",
@@ -553,25 +2030,25 @@ impl Firewall {
         Ok(())
     }
 
+    /// 守护进程创建并在退出时删除的 nft 表，如 `inet traffic_filter`
+    pub fn owned_tables(&self) -> Vec<String> {
+        let mut tables = vec![format!("{} {}", self.family, self.table_name)];
+        if let Some(sets) = &self.early_drop {
+            tables.push(format!("netdev {}", sets.table()));
+        }
+        if self.sandbox == Some(SandboxMode::Promote) {
+            tables.push(format!("{} {}", self.family, self.sandbox_table));
+        }
+        tables
+    }
+
     /// 检查防火墙状态
     pub async fn status(&self) -> Result<String> {
         let rules = self.rules.read().await;
         let active_count = rules.len();
         let expired_count = rules
             .values()
-            .filter(|rule| {
-                if let Action::Ban { seconds } = rule.rule_type {
-                    let duration = if let Some(seconds) = seconds {
-                        Duration::seconds(seconds as i64)
-                    } else {
-                        Duration::seconds(0)
-                    };
-                    let until = Utc::now() + duration;
-                    until <= Utc::now()
-                } else {
-                    false
-                }
-            })
+            .filter(|rule| rule.is_expired(self.clock.as_ref()))
             .count();
 
         let (pool_size, available_permits) = self.executor.get_pool_stats().await;
@@ -582,64 +2059,515 @@ impl Firewall {
         ))
     }
 
-    /// 批量添加规则（更高效）
-    pub async fn batch_ban(&self, ips: Vec<IpAddr>, seconds: u64) -> Result<Vec<String>> {
-        let mut commands = Vec::new();
-        let mut rule_ids = Vec::new();
+    /// 统计活跃的封禁规则与限速规则数量
+    pub async fn rule_counts(&self) -> (usize, usize) {
+        let rules = self.rules.read().await;
+        let bans = rules
+            .values()
+            .filter(|rule| matches!(rule.rule_type, Action::Ban { .. }))
+            .count();
+        let limits = rules
+            .values()
+            .filter(|rule| matches!(rule.rule_type, Action::RateLimit { .. }))
+            .count();
+        (bans, limits)
+    }
 
+    /// 获取执行器池状态 (池大小, 可用许可)
+    pub async fn pool_stats(&self) -> (usize, usize) {
+        self.executor.get_pool_stats().await
+    }
+
+    /// 批量添加规则（更高效）
+    /// 批量封禁，逐个返回结果；只有执行成功的条目会被记录到规则表
+    pub async fn batch_ban(
+        &self,
+        ips: Vec<IpAddr>,
+        seconds: u64,
+    ) -> Result<Vec<Result<RuleId, BatchItemError>>> {
         let duration = Duration::seconds(seconds as i64);
-        let until = Utc::now() + duration;
+        let until = self.clock.wall() + duration;
+
+        // 按排序后的顺序加锁，与其他批量操作交错时不会互相等待
+        let mut keys = ips.clone();
+        keys.sort();
+        keys.dedup();
+        let mut _locks = Vec::with_capacity(keys.len());
+        for ip in keys {
+            _locks.push(self.lock_apply(ip, RuleKind::Ban).await);
+        }
 
-        for ip in ips.clone() {
-            let rule_id = format!("ban_{}_{}", ip, until.timestamp());
-            let ip_version = match ip {
-                IpAddr::V4(_) => "ip saddr",
-                IpAddr::V6(_) => "ip6 saddr",
+        // 已被封禁的 IP 直接返回现有规则，不再重复创建；仲裁拒绝的 IP 记为失败
+        let mut existing = Vec::with_capacity(ips.len());
+        for ip in &ips {
+            let outcome = match self.arbitrate(*ip, "ban").await {
+                Err(e) => Some(Err(BatchItemError {
+                    ip: *ip,
+                    reason: e.to_string(),
+                })),
+                Ok(()) => match self.find_active_ban(*ip, None, false).await {
+                    Some(rule) if !self.supersedes(&rule, "ban").await => Some(Ok(rule.id)),
+                    Some(rule) => match self.unblock(&rule.id).await {
+                        Ok(()) => None,
+                        Err(e) => Some(Err(BatchItemError {
+                            ip: *ip,
+                            reason: e.to_string(),
+                        })),
+                    },
+                    None => None,
+                },
             };
-
-            let rule_cmd = format!(
-                "add rule {} {} {} {} {} drop",
-                self.family, self.table_name, self.chain_name, ip_version, ip
-            );
-
-            commands.push(rule_cmd);
-            rule_ids.push(rule_id);
+            existing.push(outcome);
         }
+        let pending: Vec<IpAddr> = ips
+            .iter()
+            .zip(&existing)
+            .filter(|(_, existing)| existing.is_none())
+            .map(|(ip, _)| *ip)
+            .collect();
+
+        let commands: Vec<String> = pending
+            .iter()
+            .map(|ip| self.ban_rule_command(*ip, None))
+            .collect();
+
+        // 批量执行命令，单条失败不影响其余命令；沙盒中试装未通过的命令不再提交
+        let trials = self.trial_in_sandbox(&commands).await?;
+        let passed = commands
+            .into_iter()
+            .zip(&trials)
+            .filter(|(_, trial)| trial.is_ok())
+            .map(|(command, _)| command)
+            .collect();
+        let mut executed = self.executor.execute_each(passed).await?.into_iter();
+        let outputs: Vec<Result<String>> = trials
+            .into_iter()
+            .map(|trial| {
+                trial.and_then(|_| {
+                    executed
+                        .next()
+                        .unwrap_or_else(|| Err(anyhow!("missing output for batch command")))
+                })
+            })
+            .collect();
 
-        // 批量执行命令
-        self.executor.execute_batch(commands).await?;
-
-        // 批量更新内存中的规则
+        let mut fresh = Vec::with_capacity(pending.len());
+        let mut early = Vec::new();
+        let mut created = Vec::new();
         {
             let mut rules = self.rules.write().await;
-            for (i, ip) in ips.into_iter().enumerate() {
-                let rule = FirewallRule {
-                    id: rule_ids[i].clone(),
-                    ip,
-                    rule_type: Action::Ban {
-                        seconds: Some(seconds),
-                    },
-                    created_at: Utc::now(),
-                    handle: Some(format!("ban_{}_{}", ip, Utc::now().timestamp())),
+            for (ip, output) in pending.into_iter().zip(outputs) {
+                let handle = match output {
+                    Ok(output) => handle_from_output(&output).await,
+                    Err(e) => Err(e),
                 };
-                rules.insert(rule_ids[i].clone(), rule);
+
+                match handle {
+                    Ok(handle) => {
+                        let rule_id = RuleId::for_ip(RuleKind::Ban, ip, None);
+                        let rule = FirewallRule {
+                            id: rule_id.clone(),
+                            ip,
+                            rule_type: Action::Ban {
+                                duration: Some(std::time::Duration::from_secs(seconds)),
+                            },
+                            created_at: self.clock.wall(),
+                            created_mono: Some(self.clock.monotonic()),
+                            handle: Some(handle),
+                            source_ports: None,
+                            remaining_secs: None,
+                            mac: None,
+                            reason: logger::current_reason(),
+                        };
+                        if self.early_drops(&rule) {
+                            early.push((ip, Some(seconds)));
+                        }
+                        rules.insert(rule_id.clone(), rule);
+                        created.push(ip);
+                        fresh.push(Ok(rule_id));
+                    }
+                    Err(e) => {
+                        warn!("Batch ban of {} failed: {}", ip, e);
+                        fresh.push(Err(BatchItemError {
+                            ip,
+                            reason: e.to_string(),
+                        }));
+                    }
+                }
             }
         }
+        if !created.is_empty() {
+            self.invalidate_system_rules().await;
+        }
+        let mut fresh = fresh.into_iter();
+        let results: Vec<Result<RuleId, BatchItemError>> = existing
+            .into_iter()
+            .filter_map(|existing| existing.or_else(|| fresh.next()))
+            .collect();
+        let failed: Vec<String> = results
+            .iter()
+            .filter_map(|result| result.as_ref().err())
+            .map(|e| e.ip.to_string())
+            .collect();
+
+        self.early_drop_add(&early).await;
+        self.exempt_from_offload(&created).await;
+
+        let banned = results.len() - failed.len();
+        info!(
+            "Batch banned {} IPs until {}, {} failed",
+            banned,
+            until,
+            failed.len()
+        );
+        let message = if failed.is_empty() {
+            format!("batch ban {} IPs for {}s", banned, seconds)
+        } else {
+            format!(
+                "batch ban {} IPs for {}s, {} failed: {}",
+                banned,
+                seconds,
+                failed.len(),
+                failed.join(", ")
+            )
+        };
+        self.events.push(Event::new(EventKind::Ban, message)).await;
+        Ok(results)
+    }
+
+    /// 当前动作的来源：在触发原因范围内执行的为自动动作
+    fn current_source() -> ActionSource {
+        match logger::current_reason() {
+            Some(_) => ActionSource::Automatic,
+            None => ActionSource::Manual,
+        }
+    }
+
+    /// 已有规则的来源，手动创建的规则没有触发原因
+    fn rule_source(rule: &FirewallRule) -> ActionSource {
+        match rule.reason {
+            Some(_) => ActionSource::Automatic,
+            None => ActionSource::Manual,
+        }
+    }
+
+    /// 按配置的优先级，来源 a 是否高于 b
+    pub fn outranks(&self, a: ActionSource, b: ActionSource) -> bool {
+        let rank = |source| self.precedence.iter().position(|s| *s == source);
+        rank(a) < rank(b)
+    }
+
+    /// 白名单是否压过来自 source 的动作：IP 在白名单中且白名单优先级更高
+    pub async fn exclusion_prevails(&self, ip: &IpAddr, source: ActionSource) -> bool {
+        self.outranks(ActionSource::Exclude, source) && self.is_excluded(ip).await
+    }
+
+    /// 记录一次冲突及其裁决结果
+    pub async fn report_conflict(&self, ip: IpAddr, message: String) {
+        warn!("{}", message);
+        self.events
+            .push(Event::new(EventKind::Conflict, message).with_ip(ip))
+            .await;
+    }
+
+    /// 仲裁作用于白名单中 IP 的动作：白名单优先时拒绝，否则照常执行；两种情况都记录冲突。
+    /// 自动动作放过受保护的管理端口，每个来源记录一次
+    async fn arbitrate(&self, ip: IpAddr, action: &str) -> Result<()> {
+        let source = Self::current_source();
+        let ports = self.exempt_ports(&ip, source);
+        if !ports.is_empty() && self.protected_noticed.insert(ip, ()).is_none() {
+            let ports: Vec<String> = ports
+                .iter()
+                .map(|protected| protected.to_string())
+                .collect();
+            let message = format!(
+                "{} {} of {} leaves {} open: protected management ports",
+                source,
+                action,
+                ip,
+                ports.join(", ")
+            );
+            self.report_conflict(ip, message).await;
+        }
+        if !self.is_excluded(&ip).await {
+            return Ok(());
+        }
+        if self.outranks(ActionSource::Exclude, source) {
+            let message = format!(
+                "refused {} {} of excluded {}: exclusions take precedence",
+                source, action, ip
+            );
+            self.report_conflict(ip, message.clone()).await;
+            return Err(anyhow!(message));
+        }
+        self.report_conflict(
+            ip,
+            format!(
+                "{} {} of excluded {} overrides the exclusion",
+                source, action, ip
+            ),
+        )
+        .await;
+        Ok(())
+    }
 
-        info!("Batch banned {} IPs until {}", rule_ids.len(), until);
-        Ok(rule_ids)
+    /// 仲裁同一 IP 上已有的同类规则：当前来源优先级更高时返回 true，由调用方替换已有规则
+    async fn supersedes(&self, existing: &FirewallRule, action: &str) -> bool {
+        let source = Self::current_source();
+        let holder = Self::rule_source(existing);
+        if source == holder {
+            return false;
+        }
+        let replace = self.outranks(source, holder);
+        // 规则引擎每个周期都可能重试同一动作，让位只记录一次
+        if !replace
+            && source == ActionSource::Automatic
+            && self.yielded.insert(existing.id.clone(), ()).is_some()
+        {
+            return false;
+        }
+        let message = if replace {
+            format!(
+                "{} {} of {} replaces {} rule {}",
+                source, action, existing.ip, holder, existing.id
+            )
+        } else {
+            format!(
+                "{} {} of {} yields to {} rule {}",
+                source, action, existing.ip, holder, existing.id
+            )
+        };
+        self.report_conflict(existing.ip, message).await;
+        replace
     }
 
     pub async fn is_excluded(&self, ip: &IpAddr) -> bool {
         self.global_exclude.read().await.contains(ip)
+            || self.cloud_exclude.read().await.matches(ip).is_some()
+            || self.host_exclude.read().await.matches(ip).is_some()
     }
 
-    pub async fn add_exclude(&self, ip: &IpAddr) -> Result<()> {
-        if self.global_exclude.write().await.insert(*ip) {
-            Ok(())
-        } else {
-            Err(anyhow!("fail to add {} to global exclude", ip))
+    /// 说明 IP 被哪一条白名单排除：配置或控制接口加入的全局白名单、临时白名单、云厂商或本机地址白名单
+    pub async fn exclusion_of(&self, ip: &IpAddr) -> Option<String> {
+        if self.global_exclude.read().await.contains(ip) {
+            let overrides = self.exclude_overrides.read().await;
+            let exclusion = match overrides.expires.get(ip) {
+                Some(until) => format!(
+                    "temporary exclude {} until {}",
+                    ip,
+                    until.format("%Y-%m-%d %H:%M:%S UTC")
+                ),
+                None if overrides.added.contains(ip) => format!("runtime exclude {}", ip),
+                None => format!("global_exclude entry {}", ip),
+            };
+            return Some(exclusion);
+        }
+        if let Some(entry) = self.cloud_exclude.read().await.matches(ip) {
+            return Some(format!("cloud_exclude entry {}", entry));
+        }
+        self.host_exclude
+            .read()
+            .await
+            .matches(ip)
+            .map(|entry| format!("host_exclude entry {}", entry))
+    }
+
+    /// 替换由云厂商元数据得出的白名单
+    pub async fn set_cloud_excludes(&self, table: ExclusionTable) {
+        *self.cloud_exclude.write().await = table;
+    }
+
+    /// 替换本机地址白名单
+    pub async fn set_host_excludes(&self, table: ExclusionTable) {
+        *self.host_exclude.write().await = table;
+    }
+
+    /// 加入全局白名单并持久化，seconds 为空时永久生效，否则到期后由 expire_excludes 移除。
+    /// 已永久存在时直接返回 false；已有的临时白名单按新的有效期替换
+    pub async fn add_exclude(&self, ip: &IpAddr, seconds: Option<u64>) -> Result<bool> {
+        let mut excludes = self.global_exclude.write().await;
+        let mut overrides = self.exclude_overrides.write().await;
+        let renewed = overrides.expires.contains_key(ip);
+        if excludes.contains(ip) && !renewed {
+            return Ok(false);
         }
+
+        let until = seconds.map(|seconds| self.clock.wall() + Duration::seconds(seconds as i64));
+        overrides.add(*ip, until);
+        self.persist_excludes(&overrides).await;
+        excludes.insert(*ip);
+        drop(overrides);
+        drop(excludes);
+
+        let message = match until {
+            Some(until) => format!("exclude {} until {}", ip, until),
+            None => format!("exclude {}", ip),
+        };
+        info!("Global exclude: {}", message);
+        self.events
+            .push(Event::new(EventKind::Exclude, message).with_ip(*ip))
+            .await;
+        // 续期时已有的规则在首次加入时已经处理
+        if renewed {
+            return Ok(true);
+        }
+
+        // 已有的规则按优先级解除或保留
+        let existing: Vec<FirewallRule> = self
+            .rules
+            .read()
+            .await
+            .values()
+            .filter(|rule| rule.ip == *ip)
+            .cloned()
+            .collect();
+        for rule in existing {
+            let holder = Self::rule_source(&rule);
+            if self.outranks(ActionSource::Exclude, holder) {
+                self.report_conflict(
+                    *ip,
+                    format!("exclusion of {} lifts {} rule {}", ip, holder, rule.id),
+                )
+                .await;
+                if let Err(e) = self.unblock(&rule.id).await {
+                    warn!("Failed to lift rule {} of excluded {}: {}", rule.id, ip, e);
+                }
+            } else {
+                self.report_conflict(
+                    *ip,
+                    format!(
+                        "{} rule {} stays in place, it takes precedence over the exclusion of {}",
+                        holder, rule.id, ip
+                    ),
+                )
+                .await;
+            }
+        }
+        Ok(true)
+    }
+
+    /// 从全局白名单移除并持久化，不存在时直接返回 false
+    pub async fn remove_exclude(&self, ip: &IpAddr) -> Result<bool> {
+        let mut excludes = self.global_exclude.write().await;
+        if !excludes.contains(ip) {
+            return Ok(false);
+        }
+
+        let mut overrides = self.exclude_overrides.write().await;
+        overrides.remove(*ip);
+        self.persist_excludes(&overrides).await;
+        excludes.remove(ip);
+        drop(overrides);
+        drop(excludes);
+
+        info!("Removed {} from global exclude", ip);
+        self.events
+            .push(Event::new(EventKind::Unexclude, format!("unexclude {}", ip)).with_ip(*ip))
+            .await;
+        Ok(true)
+    }
+
+    /// 保存运行时对白名单的修改；写入失败时修改照常生效，只是重启后不再保留
+    async fn persist_excludes(&self, overrides: &ExcludeOverrides) {
+        if let Err(e) = overrides.save(&self.exclude_state).await {
+            warn!("failed to persist global exclude changes: {:#}", e);
+        }
+    }
+
+    /// 获取全局白名单
+    pub async fn get_excludes(&self) -> Vec<IpAddr> {
+        self.global_exclude.read().await.iter().copied().collect()
+    }
+
+    /// 带有效期的白名单，按到期时间排列
+    pub async fn temporary_excludes(&self) -> Vec<TemporaryExclude> {
+        let mut excludes: Vec<TemporaryExclude> = self
+            .exclude_overrides
+            .read()
+            .await
+            .expires
+            .iter()
+            .map(|(ip, until)| TemporaryExclude {
+                ip: *ip,
+                until: *until,
+            })
+            .collect();
+        excludes.sort_by_key(|exclude| exclude.until);
+        excludes
+    }
+
+    /// 移除到期的临时白名单并持久化
+    pub async fn expire_excludes(&self) -> Result<()> {
+        let mut excludes = self.global_exclude.write().await;
+        let mut overrides = self.exclude_overrides.write().await;
+        let expired = overrides.expired(self.clock.wall());
+        if expired.is_empty() {
+            return Ok(());
+        }
+        for ip in &expired {
+            overrides.expire(*ip);
+        }
+        self.persist_excludes(&overrides).await;
+        for ip in &expired {
+            excludes.remove(ip);
+        }
+        drop(overrides);
+        drop(excludes);
+
+        for ip in expired {
+            info!("Exclusion of {} expired", ip);
+            self.events
+                .push(
+                    Event::new(EventKind::Unexclude, format!("exclusion of {} expired", ip))
+                        .with_ip(ip),
+                )
+                .await;
+        }
+        Ok(())
+    }
+}
+
+/// netdev 链挂载网卡的声明，例如 ` device "eth0"`
+fn device_clause(devices: &[String]) -> String {
+    match devices {
+        [device] => format!(" device \"{}\"", device),
+        devices => format!(" devices = {{ {} }}", quoted_list(devices)),
+    }
+}
+
+/// 网卡上基础链的名称，如 `ingress_eth0`；网卡名中链名不允许的字符替换为 `_`
+fn device_chain_name(chain: &str, device: &str) -> String {
+    let device: String = device
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("{}_{}", chain, device)
+}
+
+/// 网卡名列表，例如 `"eth0", "eth1"`
+fn quoted_list(devices: &[String]) -> String {
+    devices
+        .iter()
+        .map(|device| format!("\"{}\"", device))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// 是否为同步到早期丢弃集合的封禁：按 IP 整体封禁，未限定源端口或 MAC
+fn early_dropped(rule: &FirewallRule) -> bool {
+    matches!(rule.rule_type, Action::Ban { .. })
+        && rule.source_ports.is_none()
+        && rule.mac.is_none()
+}
+
+/// 生成源端口匹配表达式，例如 `udp sport { 53, 123 } `
+fn port_matcher(source_ports: Option<&[u16]>) -> String {
+    match source_ports {
+        Some(ports) if !ports.is_empty() => {
+            let ports: Vec<String> = ports.iter().map(|port| port.to_string()).collect();
+            format!("udp sport {{ {} }} ", ports.join(", "))
+        }
+        _ => String::new(),
     }
 }
 
@@ -655,3 +2583,479 @@ impl Drop for Firewall {
     }
 }
 */
+
+/// 从 nft 添加规则的回显中解析规则 handle
+async fn handle_from_output(output: &str) -> Result<String> {
+    let nft_objs = parse_output(output).await?;
+    let nft_obj = nft_objs
+        .first()
+        .ok_or_else(|| anyhow!("fail to  get output  after adding rule"))?;
+
+    match nft_obj {
+        NftObject::Add(obj) => Ok(obj
+            .get_handle()
+            .await
+            .ok_or_else(|| anyhow!("fail to get "))?
+            .to_string()),
+        _ => Err(anyhow!("parse output error: {:?}", nft_obj)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::TempStateDir;
+    use safe_traffic_common::{clock::ManualClock, config::parse_ip, reason::Reason};
+
+    /// 状态目录在 dir 下的防火墙，nft 命令只记录不执行
+    async fn firewall_in(dir: &TempStateDir, extra: &str) -> Firewall {
+        let cfg = Config::parse(&format!(
+            "interface = \"eth0\"\nstate_dir = \"{}\"\nrules = []\n{}",
+            dir.path().display(),
+            extra
+        ))
+        .unwrap();
+        let executor = Arc::new(NftExecutor::new(1, 300, 100, true).await);
+        Firewall::new(&cfg, executor).await.unwrap()
+    }
+
+    /// 使用独立临时状态目录的防火墙，目录随返回值一起释放
+    async fn firewall(extra: &str) -> (Firewall, TempStateDir) {
+        let dir = TempStateDir::new("controller");
+        (firewall_in(&dir, extra).await, dir)
+    }
+
+    fn reason() -> Reason {
+        Reason {
+            rule: 0,
+            rule_name: Some("flood".to_string()),
+            metric: "bps".to_string(),
+            observed: 2000,
+            threshold: 1000,
+            window_secs: 10,
+        }
+    }
+
+    async fn conflicts(fw: &Firewall) -> Vec<String> {
+        fw.events
+            .recent(100)
+            .await
+            .into_iter()
+            .filter(|event| event.kind == EventKind::Conflict)
+            .map(|event| event.message)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_limit_replaced_in_place() {
+        let (fw, _dir) = firewall("").await;
+        let ip: IpAddr = "198.51.100.7".parse().unwrap();
+        let first = fw.limit(ip, 100, None, Some(600)).await.unwrap();
+        let before = fw.get_active_rules().await.unwrap();
+        let old_handle = before[0].handle.clone().unwrap();
+        assert_eq!(
+            fw.replace_limit_command(&old_handle, ip, 200, 20, None),
+            format!(
+                "replace rule inet traffic_filter traffic_input handle {} \
+                 ip saddr 198.51.100.7 limit rate 200 kbytes/second burst 20 kbytes counter drop",
+                old_handle
+            )
+        );
+
+        let second = fw.limit(ip, 200, None, Some(600)).await.unwrap();
+        assert_ne!(second, first);
+        let after = fw.get_active_rules().await.unwrap();
+        assert_eq!(after.len(), 1);
+        assert_eq!(after[0].id, second);
+        assert_ne!(after[0].handle.as_deref(), Some(old_handle.as_str()));
+        assert!(matches!(
+            after[0].rule_type,
+            Action::RateLimit { kbps: 200, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rule_changes_invalidate_system_rules() {
+        let (fw, _dir) = firewall("").await;
+        let ip: IpAddr = "198.51.100.8".parse().unwrap();
+        let cached = || async {
+            *fw.system_rules.write().await = Some((fw.clock.monotonic(), Vec::new()));
+        };
+
+        cached().await;
+        let id = fw.ban(ip, Some(600)).await.unwrap();
+        assert!(fw.system_rules.read().await.is_none());
+
+        cached().await;
+        fw.unblock(&id).await.unwrap();
+        assert!(fw.system_rules.read().await.is_none());
+
+        fw.ban(ip, Some(600)).await.unwrap();
+        cached().await;
+        fw.flush().await.unwrap();
+        assert!(fw.system_rules.read().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_link_local_rules_are_scoped_to_monitored_devices() {
+        let (fw, _dir) = firewall("global_exclude = [\"fe80::2%eth0\"]").await;
+        let neighbor = parse_ip("fe80::1%eth0").unwrap();
+        assert_eq!(
+            fw.ban_rule_command(neighbor, None),
+            "add rule inet traffic_filter traffic_input iifname \"eth0\" ip6 saddr fe80::1 counter drop"
+        );
+        assert_eq!(
+            fw.limit_rule_command(neighbor, 100, 10, Some(&[443])),
+            "add rule inet traffic_filter traffic_input iifname \"eth0\" ip6 saddr fe80::1 udp sport { 443 } limit rate 100 kbytes/second burst 10 kbytes counter drop"
+        );
+        // 全局地址不受影响
+        let global: IpAddr = "2001:db8::1".parse().unwrap();
+        assert_eq!(
+            fw.ban_rule_command(global, None),
+            "add rule inet traffic_filter traffic_input ip6 saddr 2001:db8::1 counter drop"
+        );
+        assert!(fw.is_excluded(&"fe80::2".parse().unwrap()).await);
+
+        let (fw, _dir) = firewall("hook = \"Output\"\ndevices = [\"eth0\", \"eth1\"]").await;
+        assert_eq!(
+            fw.ban_rule_command(neighbor, None),
+            "add rule inet traffic_filter traffic_input oifname { \"eth0\", \"eth1\" } ip6 daddr fe80::1 counter drop"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_manual_bans_are_capped() {
+        let (fw, _dir) = firewall("max_manual_ban = \"7d\"").await;
+        assert!(fw.check_manual_ban(Some(3600)).is_ok());
+        assert!(fw.check_manual_ban(Some(7 * 86400)).is_ok());
+        let err = fw.check_manual_ban(Some(8 * 86400)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "manual ban of 8d exceeds max_manual_ban of 7d"
+        );
+        assert!(fw.check_manual_ban(None).is_err());
+
+        let (fw, _dir) = firewall("").await;
+        assert!(fw.check_manual_ban(None).is_ok());
+        assert!(fw.check_manual_ban(Some(365 * 86400)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_manual_ban_extension_is_capped() {
+        let (fw, _dir) = firewall("max_manual_ban = \"7d\"").await;
+        let manual = fw
+            .ban("198.51.100.30".parse().unwrap(), Some(6 * 86400))
+            .await
+            .unwrap();
+        assert!(fw.extend(&manual, 3600).await.is_ok());
+        let err = fw.extend(&manual, 2 * 86400).await.unwrap_err();
+        assert!(err.to_string().contains("exceeds max_manual_ban of 7d"));
+
+        // 自动封禁不受限制
+        let automatic = logger::with_reason(
+            reason(),
+            fw.ban("198.51.100.31".parse().unwrap(), Some(6 * 86400)),
+        )
+        .await
+        .unwrap();
+        assert!(fw.extend(&automatic, 30 * 86400).await.is_ok());
+    }
+
+    const DEVICES: &str = "devices = [\"eth0\", \"bond0.100\"]\nper_device_chains = true";
+
+    #[tokio::test]
+    async fn test_netdev_chain_per_device() {
+        let (fw, _dir) =
+            firewall(&format!("{}\nfamily = \"Netdev\"\npriority = -10", DEVICES)).await;
+        assert_eq!(
+            fw.init_commands(),
+            [
+                "add table netdev traffic_filter",
+                "add chain netdev traffic_filter traffic_input",
+                "add chain netdev traffic_filter traffic_input_eth0 { type filter hook ingress device \"eth0\" priority -10 ; policy accept ; }",
+                "add rule netdev traffic_filter traffic_input_eth0 jump traffic_input",
+                "add chain netdev traffic_filter traffic_input_bond0_100 { type filter hook ingress device \"bond0.100\" priority -10 ; policy accept ; }",
+                "add rule netdev traffic_filter traffic_input_bond0_100 jump traffic_input",
+            ]
+        );
+        // 规则只装一次，全部网卡共用
+        assert_eq!(
+            fw.ban_rule_command("198.51.100.7".parse().unwrap(), None),
+            "add rule netdev traffic_filter traffic_input ip saddr 198.51.100.7 counter drop"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_early_drop_sets_shared_across_devices() {
+        let (fw, _dir) = firewall(&format!("{}\nearly_drop = true", DEVICES)).await;
+        let commands = fw.init_commands();
+        let early: Vec<&str> = commands
+            .iter()
+            .map(String::as_str)
+            .filter(|command| command.contains("traffic_filter_early"))
+            .collect();
+        assert_eq!(
+            early[3..],
+            [
+                "add chain netdev traffic_filter_early ingress",
+                "add chain netdev traffic_filter_early ingress_eth0 { type filter hook ingress device \"eth0\" priority -500 ; policy accept ; }",
+                "add rule netdev traffic_filter_early ingress_eth0 jump ingress",
+                "add chain netdev traffic_filter_early ingress_bond0_100 { type filter hook ingress device \"bond0.100\" priority -500 ; policy accept ; }",
+                "add rule netdev traffic_filter_early ingress_bond0_100 jump ingress",
+                "add set netdev traffic_filter_early banned4 { type ipv4_addr ; flags timeout ; }",
+                "add set netdev traffic_filter_early banned6 { type ipv6_addr ; flags timeout ; }",
+                "add rule netdev traffic_filter_early ingress ip saddr @banned4 drop",
+                "add rule netdev traffic_filter_early ingress ip6 saddr @banned6 drop",
+            ]
+        );
+        // 主表仍是一条挂载在 input 上的基础链
+        assert!(commands[1]
+            .starts_with("add chain inet traffic_filter traffic_input { type filter hook input"));
+    }
+
+    const PROTECTED_PORTS: &str = r#"
+        [exclude_groups]
+        peers = ["198.51.100.1"]
+
+        [[protected_ports]]
+        port = 22
+        sources = ["203.0.113.0/24"]
+
+        [[protected_ports]]
+        port = 179
+        sources = ["@peers", "203.0.113.7"]
+    "#;
+
+    #[tokio::test]
+    async fn test_automatic_actions_spare_protected_ports() {
+        let (fw, _dir) = firewall(PROTECTED_PORTS).await;
+        let office: IpAddr = "203.0.113.7".parse().unwrap();
+        let stranger: IpAddr = "192.0.2.9".parse().unwrap();
+
+        let automatic = logger::with_reason(reason(), async {
+            (
+                fw.ban_rule_command(office, None),
+                fw.limit_rule_command(office, 100, 10, None),
+                fw.ban_rule_command(stranger, None),
+            )
+        })
+        .await;
+        assert_eq!(
+            automatic.0,
+            "add rule inet traffic_filter traffic_input ip saddr 203.0.113.7 \
+             meta l4proto . th dport != { tcp . 22, tcp . 179 } counter drop"
+        );
+        assert!(automatic
+            .1
+            .contains("ip saddr 203.0.113.7 meta l4proto . th dport != { tcp . 22, tcp . 179 } "));
+        assert_eq!(
+            automatic.2,
+            "add rule inet traffic_filter traffic_input ip saddr 192.0.2.9 counter drop"
+        );
+
+        // 手动动作不放过
+        assert_eq!(
+            fw.ban_rule_command(office, None),
+            "add rule inet traffic_filter traffic_input ip saddr 203.0.113.7 counter drop"
+        );
+
+        // 每个来源只记录一次
+        for _ in 0..2 {
+            logger::with_reason(reason(), fw.ban(office, Some(60)))
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            conflicts(&fw).await,
+            ["automatic ban of 203.0.113.7 leaves tcp/22, tcp/179 open: protected management ports"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_output_hook_matches_reply_port() {
+        let (fw, _dir) = firewall(&format!("hook = \"Output\"\n{}", PROTECTED_PORTS)).await;
+        let command = logger::with_reason(reason(), async {
+            fw.ban_rule_command("198.51.100.1".parse().unwrap(), None)
+        })
+        .await;
+        assert!(command.ends_with(
+            "ip daddr 198.51.100.1 meta l4proto . th sport != { tcp . 179 } counter drop"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_default_precedence() {
+        let (fw, _dir) = firewall("").await;
+        let ip: IpAddr = "198.51.100.7".parse().unwrap();
+
+        // 白名单优先于已有的手动封禁：加入白名单时解除
+        fw.ban(ip, Some(60)).await.unwrap();
+        assert!(fw.add_exclude(&ip, None).await.unwrap());
+        assert!(fw.get_active_rules().await.unwrap().is_empty());
+        let err = fw.ban(ip, Some(60)).await.unwrap_err();
+        assert!(err.to_string().contains("exclusions take precedence"));
+        let messages = conflicts(&fw).await;
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].starts_with("exclusion of 198.51.100.7 lifts manual rule ban_"));
+        assert_eq!(
+            messages[1],
+            "refused manual ban of excluded 198.51.100.7: exclusions take precedence"
+        );
+
+        // 手动封禁替换自动封禁，自动封禁不覆盖手动封禁
+        let other: IpAddr = "198.51.100.8".parse().unwrap();
+        logger::with_reason(reason(), fw.ban(other, Some(60)))
+            .await
+            .unwrap();
+        fw.ban(other, Some(3600)).await.unwrap();
+        logger::with_reason(reason(), fw.ban(other, Some(60)))
+            .await
+            .unwrap();
+        logger::with_reason(reason(), fw.ban(other, Some(60)))
+            .await
+            .unwrap();
+        let rules = fw.get_active_rules().await.unwrap();
+        assert_eq!(rules.len(), 1);
+        assert!(rules[0].reason.is_none());
+        // 重试的自动封禁只记录一次让位
+        let messages = conflicts(&fw).await;
+        assert_eq!(messages.len(), 4);
+        assert!(messages[2].starts_with("manual ban of 198.51.100.8 replaces automatic rule ban_"));
+        assert!(messages[3].starts_with("automatic ban of 198.51.100.8 yields to manual rule ban_"));
+    }
+
+    #[tokio::test]
+    async fn test_yielded_entries_follow_rules() {
+        let (fw, _dir) = firewall("").await;
+        let ip: IpAddr = "198.51.100.10".parse().unwrap();
+
+        // 手动限速原地更新后，原规则的让位记录随之移除
+        fw.limit(ip, 100, None, Some(60)).await.unwrap();
+        logger::with_reason(reason(), fw.limit(ip, 50, None, Some(60)))
+            .await
+            .unwrap();
+        assert_eq!(fw.yielded.len(), 1);
+        fw.limit(ip, 200, None, Some(60)).await.unwrap();
+        assert!(fw.yielded.is_empty());
+
+        // 按条件批量解除的规则同样移除
+        logger::with_reason(reason(), fw.limit(ip, 50, None, Some(60)))
+            .await
+            .unwrap();
+        assert_eq!(fw.yielded.len(), 1);
+        fw.unblock_matching(&RuleFilter::default()).await.unwrap();
+        assert!(fw.yielded.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_manual_over_exclusions() {
+        let (fw, _dir) = firewall("precedence = [\"Manual\", \"Exclude\", \"Automatic\"]").await;
+        let ip: IpAddr = "198.51.100.9".parse().unwrap();
+
+        // 手动封禁优先于白名单：加入白名单时保留，之后仍可手动限速，自动动作被拒绝
+        fw.ban(ip, Some(60)).await.unwrap();
+        fw.add_exclude(&ip, None).await.unwrap();
+        assert!(!fw.exclusion_prevails(&ip, ActionSource::Manual).await);
+        assert!(fw.exclusion_prevails(&ip, ActionSource::Automatic).await);
+        assert_eq!(fw.get_active_rules().await.unwrap().len(), 1);
+        fw.limit(ip, 100, None, Some(60)).await.unwrap();
+        assert!(
+            logger::with_reason(reason(), fw.limit(ip, 50, None, Some(60)))
+                .await
+                .is_err()
+        );
+        assert_eq!(fw.get_active_rules().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_bans_create_one_rule() {
+        let (fw, _dir) = firewall("").await;
+        let fw = Arc::new(fw);
+        let ip: IpAddr = "198.51.100.9".parse().unwrap();
+
+        let tasks: Vec<_> = (0..16)
+            .map(|_| {
+                let fw = Arc::clone(&fw);
+                tokio::spawn(async move { fw.ban(ip, Some(60)).await.unwrap() })
+            })
+            .collect();
+        let mut ids = Vec::new();
+        for task in tasks {
+            ids.push(task.await.unwrap());
+        }
+        ids.dedup();
+        assert_eq!(ids.len(), 1);
+
+        let batch = fw.batch_ban(vec![ip, ip], 60).await.unwrap();
+        assert!(batch.iter().all(|id| id.as_ref().ok() == Some(&ids[0])));
+        assert_eq!(fw.get_active_rules().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_temporary_exclusions_expire() {
+        let dir = TempStateDir::new("controller-exclusions");
+        let clock = Arc::new(ManualClock::new(Utc::now()));
+        let restart = || async {
+            firewall_in(&dir, "global_exclude = [\"192.0.2.1\"]")
+                .await
+                .with_clock(clock.clone())
+        };
+        let fw = restart().await;
+        let ip: IpAddr = "198.51.100.7".parse().unwrap();
+        let configured: IpAddr = "192.0.2.1".parse().unwrap();
+
+        assert!(fw.add_exclude(&ip, Some(7200)).await.unwrap());
+        assert!(fw.is_excluded(&ip).await);
+        // 续期替换原来的有效期；配置中的白名单已永久生效
+        assert!(fw.add_exclude(&ip, Some(3600)).await.unwrap());
+        assert!(!fw.add_exclude(&configured, Some(60)).await.unwrap());
+        let until = clock.wall() + Duration::seconds(3600);
+        assert_eq!(
+            fw.temporary_excludes().await,
+            [TemporaryExclude { ip, until }]
+        );
+
+        // 重启后有效期仍在
+        clock.advance(std::time::Duration::from_secs(1800));
+        let fw = restart().await;
+        fw.expire_excludes().await.unwrap();
+        assert!(fw.is_excluded(&ip).await);
+        assert_eq!(fw.temporary_excludes().await.len(), 1);
+
+        clock.advance(std::time::Duration::from_secs(1800));
+        fw.expire_excludes().await.unwrap();
+        assert!(!fw.is_excluded(&ip).await);
+        assert!(fw.is_excluded(&configured).await);
+        assert!(fw.temporary_excludes().await.is_empty());
+        let events = fw.events.recent(10).await;
+        let last = events.last().unwrap();
+        assert_eq!(last.kind, EventKind::Unexclude);
+        assert_eq!(last.message, "exclusion of 198.51.100.7 expired");
+
+        assert!(!restart().await.is_excluded(&ip).await);
+    }
+
+    #[tokio::test]
+    async fn test_exclusion_of_names_the_entry() {
+        let (fw, _dir) = firewall("global_exclude = [\"198.51.100.30\"]").await;
+        let runtime: IpAddr = "203.0.113.5".parse().unwrap();
+        let temporary: IpAddr = "203.0.113.6".parse().unwrap();
+
+        assert!(fw.add_exclude(&runtime, None).await.unwrap());
+        assert!(fw.add_exclude(&temporary, Some(3600)).await.unwrap());
+        assert_eq!(
+            fw.exclusion_of(&"198.51.100.30".parse().unwrap()).await,
+            Some("global_exclude entry 198.51.100.30".to_string())
+        );
+        assert_eq!(
+            fw.exclusion_of(&runtime).await,
+            Some("runtime exclude 203.0.113.5".to_string())
+        );
+        assert!(fw
+            .exclusion_of(&temporary)
+            .await
+            .unwrap()
+            .starts_with("temporary exclude 203.0.113.6 until "));
+        assert_eq!(fw.exclusion_of(&"192.0.2.1".parse().unwrap()).await, None);
+    }
+}
@@ -1,12 +1,46 @@
-use crate::{controller::Firewall, rules::RuleEngine};
+use crate::{controller::Firewall, idempotency::IdempotencyStore, logger, rules::RuleEngine};
 
 use anyhow::{Context, Result};
-use log::{debug, error, info};
-use safe_traffic_common::transport::{Request, Response, ResponseData};
+use chrono::Utc;
+use log::{debug, error, info, warn};
+use safe_traffic_common::{
+    config::{LogLevel, DEFAULT_IDEMPOTENCY_RETENTION_SECS},
+    transport::{
+        AlertSignals, DashboardSnapshot, Explanation, LogFilter, LogLine, Request, Response,
+        ResponseData,
+    },
+    utils::format_duration,
+    SCHEMA_VERSION,
+};
+use serde::Serialize;
 use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::broadcast::error::RecvError;
+
+/// explain 返回的最近事件数
+const EXPLAIN_EVENTS: usize = 20;
+
+/// 仪表盘快照，控制套接字与 WebSocket 推送共用
+pub async fn dashboard_snapshot(firewall: &Firewall, engine: &RuleEngine) -> DashboardSnapshot {
+    let (pool_size, available_executors) = firewall.pool_stats().await;
+    let (ban_rules, limit_rules) = firewall.rule_counts().await;
+    DashboardSnapshot {
+        engine_state: engine.get_state().await,
+        nft_available: firewall.is_nft_available().await,
+        pool_size,
+        available_executors,
+        ban_rules,
+        limit_rules,
+        deferred_actions: engine.deferred_actions(),
+        rule_hits: engine.rule_hits(),
+        tracked_sources: engine.windows().tracked_sources(),
+        bytes_per_source: engine.windows().bytes_per_source(),
+        recent_events: firewall.events.recent(20).await,
+    }
+}
 
 /// 流量监控服务器
 pub struct TrafficDaemon {
@@ -14,6 +48,8 @@ pub struct TrafficDaemon {
     engine: Arc<RuleEngine>,
     socket_path: String,
     max_connections: usize,
+    /// 封禁与限速请求的幂等键
+    idempotency: Arc<IdempotencyStore>,
 }
 
 #[allow(dead_code)]
@@ -25,6 +61,9 @@ impl TrafficDaemon {
             engine,
             socket_path: "/run/traffic.sock".to_string(),
             max_connections: 100,
+            idempotency: Arc::new(IdempotencyStore::new(Duration::from_secs(
+                DEFAULT_IDEMPOTENCY_RETENTION_SECS,
+            ))),
         }
     }
 
@@ -40,6 +79,12 @@ impl TrafficDaemon {
         self
     }
 
+    /// 设置幂等键的保留时长，秒
+    pub fn with_idempotency_retention(mut self, secs: u64) -> Self {
+        self.idempotency = Arc::new(IdempotencyStore::new(Duration::from_secs(secs)));
+        self
+    }
+
     /// 启动服务器
     pub async fn start(&self) -> Result<()> {
         self.cleanup_socket().await?;
@@ -57,11 +102,14 @@ impl TrafficDaemon {
                 Ok((stream, _)) => {
                     let firewall = Arc::clone(&self.firewall);
                     let engine = Arc::clone(&self.engine);
+                    let idempotency = Arc::clone(&self.idempotency);
                     let semaphore = Arc::clone(&semaphore);
 
                     tokio::spawn(async move {
                         let _permit = semaphore.acquire().await.unwrap();
-                        if let Err(e) = Self::handle_connection(stream, firewall, engine).await {
+                        if let Err(e) =
+                            Self::handle_connection(stream, firewall, engine, idempotency).await
+                        {
                             error!("Error handling connection: {}", e);
                         }
                     });
@@ -78,6 +126,7 @@ impl TrafficDaemon {
         mut stream: UnixStream,
         firewall: Arc<Firewall>,
         engine: Arc<RuleEngine>,
+        idempotency: Arc<IdempotencyStore>,
     ) -> Result<()> {
         let mut buffer = vec![0u8; 8192]; // 增大缓冲区以处理更大的请求
 
@@ -90,7 +139,20 @@ impl TrafficDaemon {
                 Ok(bytes_read) => {
                     let request_data = &buffer[..bytes_read];
 
-                    match Self::process_request(request_data, &firewall, &engine).await {
+                    // 跟踪日志占用整个连接，不再一问一答
+                    if let Ok(Request::TailLogs { filter, follow }) =
+                        serde_json::from_slice(request_data)
+                    {
+                        return Self::tail_logs(stream, filter, follow).await;
+                    }
+                    // 订阅事件同样占用整个连接
+                    if let Ok(Request::SubscribeEvents) = serde_json::from_slice(request_data) {
+                        return Self::subscribe_events(stream, &firewall).await;
+                    }
+
+                    match Self::process_request(request_data, &firewall, &engine, &idempotency)
+                        .await
+                    {
                         Ok(response) => {
                             let response_json = serde_json::to_vec(&response)
                                 .context("Failed to serialize response")?;
@@ -127,27 +189,122 @@ impl TrafficDaemon {
         Ok(())
     }
 
+    /// 发送最近的日志，follow 时持续推送新日志直到客户端断开；
+    /// 这里不输出日志，以免跟踪的客户端收到由自己引起的日志
+    async fn tail_logs(stream: UnixStream, filter: LogFilter, follow: bool) -> Result<()> {
+        // 先订阅再取最近的日志，两者之间产生的日志不会丢失，但可能重复
+        let mut subscription = follow.then(|| logger::subscribe(filter.level()));
+        let (mut reader, mut writer) = stream.into_split();
+        let header = Response::Success(ResponseData::Message(format!(
+            "logs at {} and above",
+            filter.level()
+        )));
+        write_line(&mut writer, &header).await?;
+        for line in logger::recent() {
+            if filter.matches(&line) {
+                write_line(&mut writer, &line).await?;
+            }
+        }
+        let Some(subscription) = subscription.as_mut() else {
+            return Ok(());
+        };
+        let mut buffer = [0u8; 64];
+        loop {
+            tokio::select! {
+                line = subscription.recv() => match line {
+                    Ok(line) => {
+                        if filter.matches(&line) {
+                            write_line(&mut writer, &line).await?;
+                        }
+                    }
+                    Err(RecvError::Lagged(missed)) => {
+                        let notice = LogLine {
+                            time: Utc::now(),
+                            level: LogLevel::Warn,
+                            target: module_path!().to_string(),
+                            message: format!("{} log lines dropped, the client is too slow", missed),
+                            ip: None,
+                            rule: None,
+                            rule_name: None,
+                            action: None,
+                        };
+                        write_line(&mut writer, &notice).await?;
+                    }
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+                // 客户端关闭连接或发来任何数据都结束跟踪
+                _ = reader.read(&mut buffer) => return Ok(()),
+            }
+        }
+    }
+
+    /// 推送订阅之后记录的每条事件，直到客户端断开
+    async fn subscribe_events(stream: UnixStream, firewall: &Firewall) -> Result<()> {
+        let mut subscription = firewall.events.subscribe();
+        let (mut reader, mut writer) = stream.into_split();
+        let header = Response::Success(ResponseData::Message("subscribed to events".to_string()));
+        write_line(&mut writer, &header).await?;
+        let mut buffer = [0u8; 64];
+        loop {
+            tokio::select! {
+                event = subscription.recv() => match event {
+                    Ok(event) => write_line(&mut writer, &event).await?,
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!("Event subscriber is too slow, {} events dropped", missed);
+                    }
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+                // 客户端关闭连接或发来任何数据都结束订阅
+                _ = reader.read(&mut buffer) => return Ok(()),
+            }
+        }
+    }
+
     /// 处理客户端请求，为每个 Firewall 公开方法提供对应的 handler
     async fn process_request(
         data: &[u8],
         firewall: &Arc<Firewall>,
         engine: &Arc<RuleEngine>,
+        idempotency: &IdempotencyStore,
     ) -> Result<Response> {
-        let request: Request =
+        let mut request: Request =
             serde_json::from_slice(data).context("Failed to parse request JSON")?;
 
         debug!("Processing request: {:?}", request);
 
+        // 带幂等键的请求：保留期内重复的键返回首次的结果
+        if let Some(key) = request.take_idempotency_key() {
+            let fingerprint = serde_json::to_string(&request)?;
+            let now = firewall.clock().monotonic();
+            return idempotency
+                .execute(
+                    &key,
+                    &fingerprint,
+                    now,
+                    Self::handle_request(request, firewall, engine),
+                )
+                .await;
+        }
+        Self::handle_request(request, firewall, engine).await
+    }
+
+    /// 执行已解析的请求
+    async fn handle_request(
+        request: Request,
+        firewall: &Arc<Firewall>,
+        engine: &Arc<RuleEngine>,
+    ) -> Result<Response> {
         let response_data = match request {
             Request::Limit {
                 ip,
                 kbps,
                 burst,
                 seconds,
+                ..
             } => match firewall.limit(ip, kbps, burst, seconds).await {
                 Ok(rule_id) => {
                     info!("Successfully set limit for {}: {} kbps", ip, kbps);
-                    ResponseData::Message(rule_id)
+                    ResponseData::Message(rule_id.to_string())
                 }
                 Err(e) => {
                     error!("Failed to set limit for {}: {}", ip, e);
@@ -157,21 +314,29 @@ impl TrafficDaemon {
                 }
             },
 
-            Request::Ban { ip, seconds } => match firewall.ban(ip, seconds).await {
-                Ok(rule_id) => {
-                    let seconds: String = seconds
-                        .map(|s| s.to_string())
-                        .unwrap_or("infinity".to_string());
-                    info!("Successfully banned {} for {} seconds", ip, seconds);
-                    ResponseData::Message(rule_id)
-                }
-                Err(e) => {
-                    error!("Failed to ban {}: {}", ip, e);
+            Request::Ban { ip, seconds, .. } => {
+                if let Err(e) = firewall.check_manual_ban(seconds) {
+                    error!("Refused to ban {}: {}", ip, e);
                     return Ok(Response::Error {
                         message: e.to_string(),
                     });
                 }
-            },
+                match firewall.ban(ip, seconds).await {
+                    Ok(rule_id) => {
+                        let seconds: String = seconds
+                            .map(|s| s.to_string())
+                            .unwrap_or("infinity".to_string());
+                        info!("Successfully banned {} for {} seconds", ip, seconds);
+                        ResponseData::Message(rule_id.to_string())
+                    }
+                    Err(e) => {
+                        error!("Failed to ban {}: {}", ip, e);
+                        return Ok(Response::Error {
+                            message: e.to_string(),
+                        });
+                    }
+                }
+            }
 
             Request::IsExpiration { rule_id, seconds } => {
                 let is_expired = firewall.is_expiration(&rule_id, seconds).await;
@@ -192,10 +357,49 @@ impl TrafficDaemon {
                 }
             },
 
-            Request::Exclude { ip } => match firewall.add_exclude(&ip).await {
-                Ok(_) => {
+            Request::Extend { rule_id, seconds } => {
+                match firewall.extend(&rule_id, seconds).await {
+                    Ok(until) => {
+                        info!("Successfully extended rule {} by {}s", rule_id, seconds);
+                        ResponseData::Message(format!("Rule {} extended until {}", rule_id, until))
+                    }
+                    Err(e) => {
+                        error!("Failed to extend rule {}: {}", rule_id, e);
+                        return Ok(Response::Error {
+                            message: e.to_string(),
+                        });
+                    }
+                }
+            }
+
+            Request::UnblockMatching { filter } => match firewall.unblock_matching(&filter).await {
+                Ok(ids) => {
+                    info!("Successfully unbanned {} {}", ids.len(), filter);
+                    ResponseData::StringList(ids.iter().map(|id| id.to_string()).collect())
+                }
+                Err(e) => {
+                    error!("Failed to unban {}: {}", filter, e);
+                    return Ok(Response::Error {
+                        message: e.to_string(),
+                    });
+                }
+            },
+
+            Request::Exclude { ip, seconds } => match firewall.add_exclude(&ip, seconds).await {
+                Ok(true) => {
                     info!("Successfully exclude ip: {}", ip);
-                    ResponseData::Message(format!("Successfully excludeip: {}", ip))
+                    ResponseData::Message(match seconds {
+                        Some(seconds) => format!(
+                            "Successfully excluded ip: {} for {}",
+                            ip,
+                            format_duration(seconds)
+                        ),
+                        None => format!("Successfully excludeip: {}", ip),
+                    })
+                }
+                Ok(false) => {
+                    debug!("ip {} is already excluded", ip);
+                    ResponseData::Message(format!("{} is already excluded", ip))
                 }
                 Err(e) => {
                     error!("Failed to exclude ip {}: {}", ip, e);
@@ -205,6 +409,195 @@ impl TrafficDaemon {
                 }
             },
 
+            Request::RemoveExclude { ip } => match firewall.remove_exclude(&ip).await {
+                Ok(true) => {
+                    info!("Successfully removed ip {} from exclude", ip);
+                    ResponseData::Message(format!("Successfully removed {} from exclude", ip))
+                }
+                Ok(false) => {
+                    debug!("ip {} is not excluded", ip);
+                    ResponseData::Message(format!("{} is not excluded", ip))
+                }
+                Err(e) => {
+                    error!("Failed to remove ip {} from exclude: {}", ip, e);
+                    return Ok(Response::Error {
+                        message: e.to_string(),
+                    });
+                }
+            },
+
+            Request::GetExcludes => {
+                let ips = firewall.get_excludes().await;
+                debug!("Retrieved {} excluded ips", ips.len());
+                ResponseData::StringList(ips.iter().map(|ip| ip.to_string()).collect())
+            }
+
+            Request::GetTemporaryExcludes => {
+                let excludes = firewall.temporary_excludes().await;
+                debug!("Retrieved {} temporary exclusions", excludes.len());
+                ResponseData::TemporaryExcludes(excludes)
+            }
+
+            Request::GetExcludedStats => {
+                let stats = engine.excluded_stats();
+                debug!("Retrieved traffic stats of {} excluded ips", stats.len());
+                ResponseData::ExcludedStats(stats)
+            }
+
+            Request::GetIncidents => {
+                let incidents = engine.incidents();
+                debug!("Retrieved {} incidents", incidents.len());
+                ResponseData::Incidents(incidents)
+            }
+
+            Request::GetInspections => match firewall.inspector() {
+                Some(inspector) => {
+                    let inspections = inspector.inspections();
+                    debug!("Retrieved {} inspections", inspections.len());
+                    ResponseData::Inspections(inspections)
+                }
+                None => {
+                    return Ok(Response::Error {
+                        message: "NFQUEUE inspection is not configured".to_string(),
+                    })
+                }
+            },
+
+            Request::Explain { ip } => {
+                let rules = match firewall.get_active_rules().await {
+                    Ok(rules) => rules.into_iter().filter(|rule| rule.ip == ip).collect(),
+                    Err(e) => {
+                        error!("Failed to get active rules: {}", e);
+                        return Ok(Response::Error {
+                            message: e.to_string(),
+                        });
+                    }
+                };
+                debug!("Explaining {}", ip);
+                ResponseData::Explanation(Explanation {
+                    ip,
+                    excluded: firewall.is_excluded(&ip).await,
+                    reputation: engine.reputation(&ip),
+                    rules,
+                    inspection: firewall
+                        .inspector()
+                        .and_then(|inspector| inspector.inspection(&ip)),
+                    events: firewall.events.for_ip(ip, EXPLAIN_EVENTS).await,
+                    flows: firewall
+                        .flows()
+                        .map(|flows| flows.for_ip(&ip))
+                        .unwrap_or_default(),
+                })
+            }
+
+            Request::GetWindow { ip } => match engine.window(&ip) {
+                Some(window) => {
+                    debug!("Retrieved window of {}", ip);
+                    ResponseData::Window(window)
+                }
+                None => {
+                    return Ok(Response::Error {
+                        message: format!("no traffic has been observed from {}", ip),
+                    })
+                }
+            },
+
+            Request::Trace { ip } => {
+                let previous = engine.trace(ip);
+                ResponseData::Message(match (ip, previous) {
+                    (Some(ip), _) => format!("tracing rule evaluation of {} at debug level", ip),
+                    (None, Some(previous)) => format!("stopped tracing {}", previous),
+                    (None, None) => "no IP was being traced".to_string(),
+                })
+            }
+
+            Request::GetFlows { ip } => match firewall.flows() {
+                Some(flows) => {
+                    let flows = match ip {
+                        Some(ip) => flows.for_ip(&ip),
+                        None => flows.all(),
+                    };
+                    debug!("Retrieved {} flows", flows.len());
+                    ResponseData::Flows(flows)
+                }
+                None => {
+                    return Ok(Response::Error {
+                        message: "the flow table is not configured".to_string(),
+                    })
+                }
+            },
+
+            Request::GetAccounting => match firewall.accounting() {
+                Some(accounting) => match accounting.counters().await {
+                    Ok(counters) => {
+                        debug!("Retrieved {} accounting counters", counters.len());
+                        ResponseData::Accounting(counters)
+                    }
+                    Err(e) => {
+                        error!("Failed to read accounting counters: {}", e);
+                        return Ok(Response::Error {
+                            message: e.to_string(),
+                        });
+                    }
+                },
+                None => {
+                    return Ok(Response::Error {
+                        message: "accounting is not configured".to_string(),
+                    })
+                }
+            },
+
+            Request::GetSavings => {
+                let days = firewall.savings().days();
+                debug!("Retrieved dropped traffic of {} days", days.len());
+                ResponseData::Savings(days)
+            }
+
+            Request::GetHealth => {
+                let health = firewall.health().snapshot(firewall.clock().wall());
+                debug!("Retrieved health of {} integrations", health.len());
+                ResponseData::Health(health)
+            }
+
+            Request::GetEvents { since, until } => {
+                let events = firewall.events.between(since, until).await;
+                debug!("Retrieved {} events", events.len());
+                ResponseData::Events(events)
+            }
+
+            Request::QueryEvents { query } => match firewall.events.query(query).await {
+                Ok(events) => {
+                    debug!("Queried {} events", events.len());
+                    ResponseData::Events(events)
+                }
+                Err(e) => {
+                    error!("Failed to query events: {}", e);
+                    return Ok(Response::Error {
+                        message: e.to_string(),
+                    });
+                }
+            },
+
+            Request::GetHistory { ip, offset, limit } => {
+                match firewall.events.history(ip, offset, limit).await {
+                    Ok(history) => {
+                        debug!(
+                            "Retrieved {} of {} history events for {}",
+                            history.events.len(),
+                            history.total,
+                            ip
+                        );
+                        ResponseData::History(history)
+                    }
+                    Err(e) => {
+                        error!("Failed to read history of {}: {}", ip, e);
+                        return Ok(Response::Error {
+                            message: e.to_string(),
+                        });
+                    }
+                }
+            }
+
             Request::GetActiveRules => match firewall.get_active_rules().await {
                 Ok(rules) => {
                     debug!("Retrieved {} active rules", rules.len());
@@ -219,9 +612,9 @@ impl TrafficDaemon {
             },
 
             Request::GetSystemRules => match firewall.get_system_rules().await {
-                Ok(rules_output) => {
-                    debug!("Retrieved system rules");
-                    ResponseData::Message(rules_output)
+                Ok(rules) => {
+                    debug!("Retrieved {} system rules", rules.len());
+                    ResponseData::SystemRules(rules)
                 }
                 Err(e) => {
                     error!("Failed to get system rules: {}", e);
@@ -299,9 +692,87 @@ impl TrafficDaemon {
                 }
             },
 
+            Request::PauseTarget { target, seconds } => {
+                match engine.pause_target(target, seconds) {
+                    Ok(pause) => ResponseData::Message(pause.to_string()),
+                    Err(e) => {
+                        error!("Failed to pause: {}", e);
+                        return Ok(Response::Error {
+                            message: e.to_string(),
+                        });
+                    }
+                }
+            }
+
+            Request::ResumeTarget { target } => match engine.resume_target(target.clone()) {
+                Ok(true) => ResponseData::Message(format!("{} resumed", target)),
+                Ok(false) => ResponseData::Message(format!("{} was not paused", target)),
+                Err(e) => {
+                    error!("Failed to resume: {}", e);
+                    return Ok(Response::Error {
+                        message: e.to_string(),
+                    });
+                }
+            },
+
+            Request::GetPauses => {
+                let pauses = engine.pauses();
+                debug!("Retrieved {} targeted pauses", pauses.len());
+                ResponseData::Pauses(pauses)
+            }
+
             Request::Status => match firewall.status().await {
-                Ok(status_info) => {
+                Ok(mut status_info) => {
                     debug!("Retrieved firewall status");
+                    let today = firewall.savings().day(firewall.clock().wall().date_naive());
+                    let (dropped_bytes, dropped_packets) = firewall.savings().totals();
+                    status_info.push_str(&format!(
+                        "\n- 今日丢弃: {} 字节 / {} 报文\n- 累计丢弃: {} 字节 / {} 报文",
+                        today.bytes, today.packets, dropped_bytes, dropped_packets
+                    ));
+                    status_info.push_str(&format!("\n- 数据格式版本: {}", SCHEMA_VERSION));
+                    let windows = engine.windows();
+                    status_info.push_str(&format!(
+                        "\n- 跟踪来源: {} 个，每个约 {} 字节{}",
+                        windows.tracked_sources(),
+                        windows.bytes_per_source(),
+                        if windows.is_low_memory() {
+                            "（低内存模式）"
+                        } else {
+                            ""
+                        }
+                    ));
+                    let health = firewall.health().snapshot(firewall.clock().wall());
+                    if !health.is_empty() {
+                        let degraded: Vec<&str> = health
+                            .iter()
+                            .filter(|integration| integration.degraded)
+                            .map(|integration| integration.integration.as_str())
+                            .collect();
+                        status_info.push_str(&format!(
+                            "\n- 外部集成: {} 个，降级: {}",
+                            health.len(),
+                            if degraded.is_empty() {
+                                "无".to_string()
+                            } else {
+                                degraded.join(", ")
+                            }
+                        ));
+                    }
+                    let temporary = firewall.temporary_excludes().await;
+                    if !temporary.is_empty() {
+                        status_info.push_str(&format!("\n- 临时白名单: {}", temporary.len()));
+                        for exclude in temporary {
+                            status_info.push_str(&format!("\n  - {}", exclude));
+                        }
+                    }
+                    let pauses = engine.pauses();
+                    if !pauses.is_empty() {
+                        status_info.push_str(&format!("\n- 定向暂停: {}", pauses.len()));
+                        for pause in pauses {
+                            status_info.push_str(&format!("\n  - {}", pause));
+                        }
+                    }
                     ResponseData::Message(status_info)
                 }
                 Err(e) => {
@@ -313,14 +784,22 @@ impl TrafficDaemon {
             },
 
             Request::BatchBan { ips, seconds } => {
+                if let Err(e) = firewall.check_manual_ban(Some(seconds)) {
+                    error!("Refused to batch ban {} IPs: {}", ips.len(), e);
+                    return Ok(Response::Error {
+                        message: e.to_string(),
+                    });
+                }
                 match firewall.batch_ban(ips.clone(), seconds).await {
-                    Ok(rule_ids) => {
+                    Ok(results) => {
+                        let failed = results.iter().filter(|result| result.is_err()).count();
                         info!(
-                            "Successfully batch banned {} IPs for {} seconds",
+                            "Batch banned {} of {} IPs for {} seconds",
+                            results.len() - failed,
                             ips.len(),
                             seconds
                         );
-                        ResponseData::StringList(rule_ids)
+                        ResponseData::BatchResult(results)
                     }
                     Err(e) => {
                         error!("Failed to batch ban IPs: {}", e);
@@ -331,10 +810,61 @@ impl TrafficDaemon {
                 }
             }
 
+            Request::Dashboard => {
+                ResponseData::Dashboard(dashboard_snapshot(firewall, engine).await)
+            }
+
+            Request::GetAlerts => match firewall.divergent_rules().await {
+                Ok(divergent_rules) => {
+                    debug!("Retrieved alert signals");
+                    let guard = firewall.auth_guard();
+                    let now = firewall.clock().monotonic();
+                    let (dropped_bytes, dropped_packets) = firewall.savings().totals();
+                    ResponseData::Alerts(AlertSignals {
+                        attack_in_progress: engine.attack_in_progress(),
+                        enforcement_latency_p99_ms: engine.enforcement_latency_p99(),
+                        divergent_rules,
+                        management_auth_failures: guard
+                            .as_ref()
+                            .map_or(0, |guard| guard.failures_total()),
+                        management_lockouts: guard
+                            .as_ref()
+                            .map_or(0, |guard| guard.lockouts_total()),
+                        management_locked_out: guard
+                            .as_ref()
+                            .map_or(0, |guard| guard.locked_out(now)),
+                        dropped_bytes,
+                        dropped_packets,
+                        dropped_bytes_today: firewall
+                            .savings()
+                            .day(firewall.clock().wall().date_naive())
+                            .bytes,
+                    })
+                }
+                Err(e) => {
+                    error!("Failed to compare rules with nftables: {}", e);
+                    return Ok(Response::Error {
+                        message: e.to_string(),
+                    });
+                }
+            },
+
             Request::Ping => {
                 debug!("Ping request received");
                 ResponseData::Pong
             }
+
+            Request::TailLogs { .. } => {
+                return Ok(Response::Error {
+                    message: "TailLogs must be sent on its own".to_string(),
+                });
+            }
+
+            Request::SubscribeEvents => {
+                return Ok(Response::Error {
+                    message: "SubscribeEvents must be sent on its own".to_string(),
+                });
+            }
         };
 
         Ok(Response::Success(response_data))
@@ -419,6 +949,17 @@ impl ServerBuilder {
     }
 }
 
+/// 写入一行 JSON
+async fn write_line<T: Serialize>(
+    writer: &mut tokio::net::unix::OwnedWriteHalf,
+    value: &T,
+) -> Result<()> {
+    let mut line = serde_json::to_vec(value)?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+    Ok(())
+}
+
 /// 便利宏，用于快速创建服务器
 #[macro_export]
 macro_rules! create_server {
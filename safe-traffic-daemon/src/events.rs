@@ -0,0 +1,191 @@
+use crate::archive::EventArchive;
+use crate::logger;
+use crate::nfqueue::Inspector;
+use safe_traffic_common::{
+    events::Event,
+    transport::{ActionHistory, EventQuery},
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+use log::{debug, warn};
+use std::{collections::VecDeque, net::IpAddr, sync::Arc};
+use tokio::sync::{broadcast, RwLock};
+
+const DEFAULT_CAPACITY: usize = 256;
+/// 处置历史未指定条数时的每页条数
+const HISTORY_PAGE: usize = 50;
+/// 处置历史每页的最大条数
+const MAX_HISTORY_PAGE: usize = 1000;
+
+/// 最近事件的环形缓冲
+#[derive(Debug)]
+pub struct EventStore {
+    events: RwLock<VecDeque<Event>>,
+    capacity: usize,
+    /// 新事件的订阅通道，供 WebSocket 推送使用
+    subscribers: broadcast::Sender<Event>,
+    /// 用于在事件中附上来源请求过的 SNI
+    inspector: Option<Arc<Inspector>>,
+    /// 事件同时写入磁盘归档，未配置时只保留在缓冲中
+    archive: Option<Arc<EventArchive>>,
+}
+
+impl Default for EventStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl EventStore {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            events: RwLock::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            subscribers: broadcast::channel(capacity.max(1)).0,
+            inspector: None,
+            archive: None,
+        }
+    }
+
+    /// 记录事件时附上逐包检查中观察到的 SNI
+    pub fn with_inspector(mut self, inspector: Arc<Inspector>) -> Self {
+        self.inspector = Some(inspector);
+        self
+    }
+
+    /// 记录事件时同时写入归档
+    pub fn with_archive(mut self, archive: Arc<EventArchive>) -> Self {
+        self.archive = Some(archive);
+        self
+    }
+
+    pub fn archive(&self) -> Option<Arc<EventArchive>> {
+        self.archive.clone()
+    }
+
+    /// 订阅之后记录的事件，接收过慢时丢弃最旧的事件
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.subscribers.subscribe()
+    }
+
+    /// 记录事件，超出容量时丢弃最旧的事件
+    pub async fn push(&self, mut event: Event) {
+        if event.sni.is_empty() {
+            if let (Some(inspector), Some(ip)) = (&self.inspector, event.ip) {
+                event.sni = inspector.sni(&ip);
+            }
+        }
+        if event.reason.is_none() {
+            event.reason = logger::current_reason();
+        }
+        debug!("event: {}", event);
+        if let Some(archive) = &self.archive {
+            if let Err(e) = archive.append(&event) {
+                warn!("Failed to archive event: {}", e);
+            }
+        }
+        // 没有订阅者时发送失败，忽略即可
+        let _ = self.subscribers.send(event.clone());
+        let mut events = self.events.write().await;
+        if events.len() >= self.capacity {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+
+    /// 取出全部事件并清空缓冲，按时间先后排列
+    pub async fn take_all(&self) -> Vec<Event> {
+        self.events.write().await.drain(..).collect()
+    }
+
+    /// 获取最近的 n 条事件，按时间先后排列
+    pub async fn recent(&self, n: usize) -> Vec<Event> {
+        let events = self.events.read().await;
+        events
+            .iter()
+            .skip(events.len().saturating_sub(n))
+            .cloned()
+            .collect()
+    }
+
+    /// 获取与该 IP 相关的最近 n 条事件，按时间先后排列
+    pub async fn for_ip(&self, ip: IpAddr, n: usize) -> Vec<Event> {
+        let events = self.events.read().await;
+        let matched: Vec<&Event> = events.iter().filter(|event| event.ip == Some(ip)).collect();
+        matched[matched.len().saturating_sub(n)..]
+            .iter()
+            .map(|event| (*event).clone())
+            .collect()
+    }
+
+    /// 获取时间范围内的事件，按时间先后排列
+    pub async fn between(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Vec<Event> {
+        let events = self.events.read().await;
+        events
+            .iter()
+            .filter(|event| since.is_none_or(|since| event.time >= since))
+            .filter(|event| until.is_none_or(|until| event.time <= until))
+            .cloned()
+            .collect()
+    }
+
+    /// 按条件查询事件，按时间先后排列；配置了归档时查询归档，否则只查询缓冲
+    pub async fn query(&self, query: EventQuery) -> Result<Vec<Event>> {
+        let mut events = match &self.archive {
+            Some(archive) => {
+                let archive = Arc::clone(archive);
+                let query = query.clone();
+                tokio::task::spawn_blocking(move || archive.query(&query)).await??
+            }
+            None => self
+                .events
+                .read()
+                .await
+                .iter()
+                .filter(|event| query.matches(event))
+                .cloned()
+                .collect(),
+        };
+        if let Some(limit) = query.limit {
+            events.drain(..events.len().saturating_sub(limit));
+        }
+        Ok(events)
+    }
+
+    /// 某个 IP 的处置历史，按时间倒序分页；配置了归档时包括已归档的事件
+    pub async fn history(
+        &self,
+        ip: IpAddr,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> Result<ActionHistory> {
+        let query = EventQuery {
+            ip: Some(ip),
+            ..Default::default()
+        };
+        let mut events: Vec<Event> = self
+            .query(query)
+            .await?
+            .into_iter()
+            .filter(|event| event.kind.is_action())
+            .collect();
+        events.reverse();
+        let total = events.len();
+        let limit = limit.unwrap_or(HISTORY_PAGE).clamp(1, MAX_HISTORY_PAGE);
+        let events: Vec<Event> = events.into_iter().skip(offset).take(limit).collect();
+        let next_offset = Some(offset + events.len()).filter(|next| *next < total);
+        Ok(ActionHistory {
+            ip,
+            total,
+            offset,
+            next_offset,
+            events,
+        })
+    }
+}
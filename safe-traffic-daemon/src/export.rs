@@ -0,0 +1,317 @@
+use crate::controller::Firewall;
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use log::{debug, info, warn};
+use safe_traffic_common::{
+    config::{FlowExportConfig, HookType},
+    utils::TrafficStats,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{net::UdpSocket, time};
+
+const IPFIX_VERSION: u16 = 10;
+const TEMPLATE_SET_ID: u16 = 2;
+const TEMPLATE_V4: u16 = 256;
+const TEMPLATE_V6: u16 = 257;
+/// UDP 传输下模板需要周期性重发，每隔多少个报文附带一次
+const TEMPLATE_REFRESH: u32 = 20;
+/// 单个报文最多携带的记录数，避免超过常见 MTU
+const MAX_RECORDS_PER_MESSAGE: usize = 32;
+
+// IPFIX 信息元素编号 (RFC 7012)
+const IE_OCTET_DELTA_COUNT: u16 = 1;
+const IE_SOURCE_IPV4: u16 = 8;
+const IE_DESTINATION_IPV4: u16 = 12;
+const IE_SOURCE_IPV6: u16 = 27;
+const IE_DESTINATION_IPV6: u16 = 28;
+const IE_FLOW_START_SECONDS: u16 = 150;
+const IE_FLOW_END_SECONDS: u16 = 151;
+
+/// 单个违规 IP 在一个导出周期内的流量记录
+#[derive(Debug, Clone)]
+pub struct FlowRecord {
+    pub ip: IpAddr,
+    pub octets: u64,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+/// 将被封禁/限速 IP 的流量以 IPFIX 格式导出到采集器
+pub struct FlowExporter {
+    collector: SocketAddr,
+    interval: Duration,
+    domain_id: u32,
+    /// 入站规则导出源地址，出站规则导出目的地址
+    hook: HookType,
+    sequence: u32,
+    messages_sent: u32,
+    /// 上次导出时每个 IP 的累计字节数与时间
+    last: HashMap<IpAddr, (u64, DateTime<Utc>)>,
+}
+
+impl FlowExporter {
+    pub fn new(cfg: &FlowExportConfig, hook: HookType) -> Self {
+        Self {
+            collector: cfg.collector,
+            interval: Duration::from_secs(cfg.interval.unwrap_or(10).max(1)),
+            domain_id: cfg.observation_domain.unwrap_or(0),
+            hook,
+            sequence: 0,
+            messages_sent: 0,
+            last: HashMap::new(),
+        }
+    }
+
+    /// 周期性导出当前被处置 IP 的流量
+    pub async fn run(
+        mut self,
+        fw: Arc<Firewall>,
+        stats: Arc<DashMap<IpAddr, TrafficStats>>,
+    ) -> anyhow::Result<()> {
+        let bind_addr = match self.collector {
+            SocketAddr::V4(_) => "0.0.0.0:0",
+            SocketAddr::V6(_) => "[::]:0",
+        };
+        let socket = UdpSocket::bind(bind_addr).await?;
+        socket.connect(self.collector).await?;
+        info!(
+            "Exporting offender flows to IPFIX collector {}",
+            self.collector
+        );
+
+        let mut interval = time::interval(self.interval);
+        loop {
+            interval.tick().await;
+
+            let offenders: HashSet<IpAddr> = match fw.get_active_rules().await {
+                Ok(rules) => rules.into_iter().map(|rule| rule.ip).collect(),
+                Err(e) => {
+                    warn!("Failed to get active rules for flow export: {}", e);
+                    continue;
+                }
+            };
+            let records = self.collect(&offenders, &stats, Utc::now());
+            if records.is_empty() {
+                continue;
+            }
+
+            for chunk in records.chunks(MAX_RECORDS_PER_MESSAGE) {
+                let with_templates = self.messages_sent.is_multiple_of(TEMPLATE_REFRESH);
+                let message = self.encode(chunk, Utc::now(), with_templates);
+                if let Err(e) = socket.send(&message).await {
+                    warn!("Failed to send IPFIX message to {}: {}", self.collector, e);
+                    break;
+                }
+                self.messages_sent = self.messages_sent.wrapping_add(1);
+                self.sequence = self.sequence.wrapping_add(chunk.len() as u32);
+            }
+            debug!("Exported {} offender flow records", records.len());
+        }
+    }
+
+    /// 计算每个违规 IP 自上次导出以来的字节增量
+    fn collect(
+        &mut self,
+        offenders: &HashSet<IpAddr>,
+        stats: &DashMap<IpAddr, TrafficStats>,
+        now: DateTime<Utc>,
+    ) -> Vec<FlowRecord> {
+        // 已解除处置的 IP 不再跟踪
+        self.last.retain(|ip, _| offenders.contains(ip));
+
+        let mut records = Vec::new();
+        for ip in offenders {
+            let Some(entry) = stats.get(ip) else {
+                continue;
+            };
+            let total = match self.hook {
+                HookType::Input => entry.rx_bytes,
+                HookType::Output => entry.tx_bytes,
+            };
+
+            match self.last.insert(*ip, (total, now)) {
+                Some((previous, start)) if total > previous => records.push(FlowRecord {
+                    ip: *ip,
+                    octets: total - previous,
+                    start,
+                    end: now,
+                }),
+                // 首次出现只记录基线
+                _ => {}
+            }
+        }
+        records
+    }
+
+    /// 编码一个 IPFIX 报文 (RFC 7011)
+    pub fn encode(
+        &self,
+        records: &[FlowRecord],
+        export_time: DateTime<Utc>,
+        with_templates: bool,
+    ) -> Vec<u8> {
+        let mut body = Vec::new();
+
+        if with_templates {
+            let (v4_ie, v6_ie) = match self.hook {
+                HookType::Input => (IE_SOURCE_IPV4, IE_SOURCE_IPV6),
+                HookType::Output => (IE_DESTINATION_IPV4, IE_DESTINATION_IPV6),
+            };
+            let mut set = Vec::new();
+            let templates = [(TEMPLATE_V4, v4_ie, 4), (TEMPLATE_V6, v6_ie, 16)];
+            for (template_id, ip_ie, ip_len) in templates {
+                set.extend_from_slice(&template_id.to_be_bytes());
+                set.extend_from_slice(&4u16.to_be_bytes());
+                for (ie, len) in [
+                    (ip_ie, ip_len),
+                    (IE_OCTET_DELTA_COUNT, 8u16),
+                    (IE_FLOW_START_SECONDS, 4),
+                    (IE_FLOW_END_SECONDS, 4),
+                ] {
+                    set.extend_from_slice(&ie.to_be_bytes());
+                    set.extend_from_slice(&len.to_be_bytes());
+                }
+            }
+            push_set(&mut body, TEMPLATE_SET_ID, &set);
+        }
+
+        for (template_id, is_v4) in [(TEMPLATE_V4, true), (TEMPLATE_V6, false)] {
+            let mut set = Vec::new();
+            for record in records.iter().filter(|r| r.ip.is_ipv4() == is_v4) {
+                match record.ip {
+                    IpAddr::V4(ip) => set.extend_from_slice(&ip.octets()),
+                    IpAddr::V6(ip) => set.extend_from_slice(&ip.octets()),
+                }
+                set.extend_from_slice(&record.octets.to_be_bytes());
+                set.extend_from_slice(&(record.start.timestamp() as u32).to_be_bytes());
+                set.extend_from_slice(&(record.end.timestamp() as u32).to_be_bytes());
+            }
+            if !set.is_empty() {
+                push_set(&mut body, template_id, &set);
+            }
+        }
+
+        let mut message = Vec::with_capacity(16 + body.len());
+        message.extend_from_slice(&IPFIX_VERSION.to_be_bytes());
+        message.extend_from_slice(&((16 + body.len()) as u16).to_be_bytes());
+        message.extend_from_slice(&(export_time.timestamp() as u32).to_be_bytes());
+        message.extend_from_slice(&self.sequence.to_be_bytes());
+        message.extend_from_slice(&self.domain_id.to_be_bytes());
+        message.extend_from_slice(&body);
+        message
+    }
+}
+
+fn push_set(body: &mut Vec<u8>, set_id: u16, content: &[u8]) {
+    body.extend_from_slice(&set_id.to_be_bytes());
+    body.extend_from_slice(&((4 + content.len()) as u16).to_be_bytes());
+    body.extend_from_slice(content);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_be_bytes([bytes[offset], bytes[offset + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_encode_layout() {
+        let cfg = FlowExportConfig {
+            collector: "192.0.2.100:4739".parse().unwrap(),
+            interval: None,
+            observation_domain: Some(7),
+        };
+        let exporter = FlowExporter::new(&cfg, HookType::Input);
+        let start = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let end = Utc.timestamp_opt(1_700_000_010, 0).unwrap();
+        let v4: IpAddr = "198.51.100.7".parse().unwrap();
+        let v6: IpAddr = "2001:db8::7".parse().unwrap();
+        // 故意把 IPv6 记录放在前面，编码时仍按模板分组
+        let records = [
+            FlowRecord {
+                ip: v6,
+                octets: 2048,
+                start,
+                end,
+            },
+            FlowRecord {
+                ip: v4,
+                octets: 1024,
+                start,
+                end,
+            },
+        ];
+        let message = exporter.encode(&records, end, true);
+
+        // 报文头：版本、总长度、导出时间、序号、观测域
+        assert_eq!(u16_at(&message, 0), 10);
+        assert_eq!(u16_at(&message, 2) as usize, message.len());
+        assert_eq!(message.len(), 16 + 44 + 24 + 36);
+        assert_eq!(u32_at(&message, 4), 1_700_000_010);
+        assert_eq!(u32_at(&message, 8), 0);
+        assert_eq!(u32_at(&message, 12), 7);
+
+        // 模板集：两个模板，各 4 个字段，入站导出源地址
+        let templates = &message[16..60];
+        assert_eq!(u16_at(templates, 0), TEMPLATE_SET_ID);
+        assert_eq!(u16_at(templates, 2), 44);
+        let expected = [
+            (TEMPLATE_V4, IE_SOURCE_IPV4, 4),
+            (TEMPLATE_V6, IE_SOURCE_IPV6, 16),
+        ];
+        for (i, (template_id, ip_ie, ip_len)) in expected.into_iter().enumerate() {
+            let template = &templates[4 + i * 20..4 + (i + 1) * 20];
+            assert_eq!(u16_at(template, 0), template_id);
+            assert_eq!(u16_at(template, 2), 4);
+            let fields: Vec<(u16, u16)> = (0..4)
+                .map(|f| (u16_at(template, 4 + f * 4), u16_at(template, 6 + f * 4)))
+                .collect();
+            assert_eq!(
+                fields,
+                [
+                    (ip_ie, ip_len),
+                    (IE_OCTET_DELTA_COUNT, 8),
+                    (IE_FLOW_START_SECONDS, 4),
+                    (IE_FLOW_END_SECONDS, 4),
+                ]
+            );
+        }
+
+        // IPv4 数据集：地址、字节数、起止时间
+        let data = &message[60..84];
+        assert_eq!(u16_at(data, 0), TEMPLATE_V4);
+        assert_eq!(u16_at(data, 2), 24);
+        assert_eq!(&data[4..8], &[198, 51, 100, 7]);
+        assert_eq!(u64::from_be_bytes(data[8..16].try_into().unwrap()), 1024);
+        assert_eq!(u32_at(data, 16), 1_700_000_000);
+        assert_eq!(u32_at(data, 20), 1_700_000_010);
+
+        // IPv6 数据集
+        let data = &message[84..];
+        assert_eq!(u16_at(data, 0), TEMPLATE_V6);
+        assert_eq!(u16_at(data, 2), 36);
+        let IpAddr::V6(v6) = v6 else { unreachable!() };
+        assert_eq!(&data[4..20], &v6.octets());
+        assert_eq!(u64::from_be_bytes(data[20..28].try_into().unwrap()), 2048);
+        assert_eq!(u32_at(data, 28), 1_700_000_000);
+        assert_eq!(u32_at(data, 32), 1_700_000_010);
+
+        // 不附带模板时只剩数据集
+        let message = exporter.encode(&records[1..], end, false);
+        assert_eq!(message.len(), 16 + 24);
+        assert_eq!(u16_at(&message, 16), TEMPLATE_V4);
+    }
+}
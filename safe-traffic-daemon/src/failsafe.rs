@@ -0,0 +1,123 @@
+//! 失控保护（dead-man's switch）：用 systemd-run 挂一个一次性的定时器，到点删除守护进程的全部 nft 表；
+//! 守护进程每隔 refresh_secs 重新挂一次，把到点时间往后推。守护进程崩溃、被 SIGKILL 或卡死而没有
+//! 清理时，定时器不再被推迟，timeout_secs 后封禁与限速自行撤销，不会把流量永久挡在外面。
+//!
+//! 正常退出时先撤下定时器，再由 cleanup 删除表。
+
+use crate::controller::Firewall;
+
+use anyhow::{bail, Result};
+use log::{debug, info, warn};
+use safe_traffic_common::config::FailsafeConfig;
+use std::{sync::Arc, time::Duration};
+use tokio::{process::Command, time};
+
+/// 失控保护定时器
+#[derive(Debug, Clone)]
+pub struct Failsafe {
+    /// transient 单元名，按表名区分，同一主机上的多个实例互不影响
+    unit: String,
+    timeout: Duration,
+    refresh: Duration,
+    /// 到点时删除的表，如 `inet traffic_filter`
+    tables: Vec<String>,
+}
+
+impl Failsafe {
+    pub fn new(cfg: &FailsafeConfig, fw: &Firewall) -> Self {
+        let tables = fw.owned_tables();
+        let table = tables[0].rsplit(' ').next().unwrap_or_default();
+        Self {
+            unit: format!("safe-traffic-failsafe-{}", table),
+            timeout: Duration::from_secs(cfg.timeout_secs()),
+            refresh: Duration::from_secs(cfg.refresh_secs()),
+            tables,
+        }
+    }
+
+    pub fn unit(&self) -> &str {
+        &self.unit
+    }
+
+    /// 到点时执行的脚本，逐个删除表，某个表已不存在时不影响其余的
+    pub fn teardown_script(&self) -> String {
+        self.tables
+            .iter()
+            .map(|table| format!("nft delete table {}", table))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    /// 挂定时器的 systemd-run 参数
+    pub fn arm_command(&self) -> Vec<String> {
+        [
+            "systemd-run",
+            "--unit",
+            &self.unit,
+            "--collect",
+            "--description",
+            "safe-traffic failsafe: remove enforcement if the daemon stops refreshing",
+            "--on-active",
+            &format!("{}s", self.timeout.as_secs()),
+            "--timer-property=AccuracySec=1s",
+            "/bin/sh",
+            "-c",
+            &self.teardown_script(),
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect()
+    }
+
+    /// 撤下定时器；定时器不存在（尚未挂上或已触发）时不算失败
+    pub async fn disarm(&self) {
+        let timer = format!("{}.timer", self.unit);
+        let service = format!("{}.service", self.unit);
+        match Command::new("systemctl")
+            .args(["stop", &timer, &service])
+            .output()
+            .await
+        {
+            Ok(output) if !output.status.success() => debug!(
+                "systemctl stop {}: {}",
+                timer,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+            Ok(_) => {}
+            Err(e) => debug!("Failed to run systemctl: {}", e),
+        }
+    }
+
+    /// 重新挂定时器，到点时间从现在起算 timeout_secs。
+    /// 先撤下旧的定时器，启动时也会清掉上次崩溃留下的
+    pub async fn arm(&self) -> Result<()> {
+        self.disarm().await;
+        let command = self.arm_command();
+        let output = Command::new(&command[0])
+            .args(&command[1..])
+            .output()
+            .await?;
+        if !output.status.success() {
+            bail!(
+                "systemd-run: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    }
+
+    pub async fn run(self: Arc<Self>) {
+        info!(
+            "Failsafe armed: enforcement is removed {}s after the daemon stops refreshing unit {}",
+            self.timeout.as_secs(),
+            self.unit
+        );
+        let mut interval = time::interval(self.refresh);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.arm().await {
+                warn!("Failed to refresh the failsafe timer: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! 流表：从连接跟踪读取每条流的五元组与计数，按字节数保留前 max_flows 条
+//!
+//! 计数需要开启 `net.netfilter.nf_conntrack_acct`，未开启时字节数与报文数均为 0。
+
+use anyhow::{bail, Result};
+use safe_traffic_common::transport::FlowEntry;
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    net::IpAddr,
+    sync::RwLock,
+};
+use tokio::process::Command;
+
+pub const DEFAULT_MAX_FLOWS: usize = 4096;
+const PROC_CONNTRACK: &str = "/proc/net/nf_conntrack";
+
+/// 最近一次读取的流，按字节数降序排列
+#[derive(Debug)]
+pub struct FlowTable {
+    max_flows: usize,
+    flows: RwLock<Vec<FlowEntry>>,
+    /// 每个来源访问的目的端口，按截断前的全部流统计，大量小流的扫描者不会被截掉
+    source_dports: RwLock<HashMap<IpAddr, HashSet<u16>>>,
+    /// 每个来源发起的流的报文数之和，同样按截断前的全部流统计
+    source_packets: RwLock<HashMap<IpAddr, u64>>,
+}
+
+impl Default for FlowTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_FLOWS)
+    }
+}
+
+impl FlowTable {
+    pub fn new(max_flows: usize) -> Self {
+        Self {
+            max_flows: max_flows.max(1),
+            flows: RwLock::new(Vec::new()),
+            source_dports: RwLock::new(HashMap::new()),
+            source_packets: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// 整体替换流表，超过上限时丢弃字节数最少的流
+    pub fn replace(&self, mut flows: Vec<FlowEntry>) {
+        let mut source_dports: HashMap<IpAddr, HashSet<u16>> = HashMap::new();
+        let mut source_packets: HashMap<IpAddr, u64> = HashMap::new();
+        for flow in &flows {
+            if let Some(dport) = flow.dport {
+                source_dports.entry(flow.src).or_default().insert(dport);
+            }
+            *source_packets.entry(flow.src).or_default() += flow.packets;
+        }
+        *self.source_dports.write().unwrap() = source_dports;
+        *self.source_packets.write().unwrap() = source_packets;
+        flows.sort_by_key(|flow| Reverse(flow.bytes));
+        flows.truncate(self.max_flows);
+        *self.flows.write().unwrap() = flows;
+    }
+
+    pub fn all(&self) -> Vec<FlowEntry> {
+        self.flows.read().unwrap().clone()
+    }
+
+    /// 源或目的为该 IP 的流
+    pub fn for_ip(&self, ip: &IpAddr) -> Vec<FlowEntry> {
+        self.flows
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|flow| flow.src == *ip || flow.dst == *ip)
+            .cloned()
+            .collect()
+    }
+
+    /// 与该 IP 相关的流访问过的目的端口
+    pub fn dports(&self, ip: &IpAddr) -> Vec<u16> {
+        let mut ports: Vec<u16> = self
+            .flows
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|flow| flow.src == *ip || flow.dst == *ip)
+            .filter_map(|flow| flow.dport)
+            .collect();
+        ports.sort_unstable();
+        ports.dedup();
+        ports
+    }
+
+    /// 最近一次读取中每个来源发起的流访问过的目的端口
+    pub fn source_dports(&self) -> HashMap<IpAddr, HashSet<u16>> {
+        self.source_dports.read().unwrap().clone()
+    }
+
+    /// 报文数在 1 至 max_packets 之间的来源数；未开启计数时报文数为 0，不计入
+    pub fn thin_sources(&self, max_packets: u64) -> usize {
+        self.source_packets
+            .read()
+            .unwrap()
+            .values()
+            .filter(|packets| (1..=max_packets).contains(*packets))
+            .count()
+    }
+
+    /// 重新读取连接跟踪，返回读取到的流数
+    pub async fn refresh(&self) -> Result<usize> {
+        self.refresh_observed(|_| {}).await
+    }
+
+    /// 重新读取连接跟踪，截断前的全部流先交给 observe
+    pub async fn refresh_observed(&self, observe: impl FnOnce(&[FlowEntry])) -> Result<usize> {
+        let text = read_conntrack().await?;
+        let flows = parse_conntrack(&text);
+        let count = flows.len();
+        observe(&flows);
+        self.replace(flows);
+        Ok(count)
+    }
+}
+
+/// 优先读取 procfs，内核未提供时调用 conntrack 命令
+async fn read_conntrack() -> Result<String> {
+    if let Ok(text) = tokio::fs::read_to_string(PROC_CONNTRACK).await {
+        return Ok(text);
+    }
+    let output = Command::new("conntrack")
+        .args(["-L", "-o", "extended"])
+        .output()
+        .await?;
+    if !output.status.success() {
+        bail!(
+            "conntrack -L failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// 解析 /proc/net/nf_conntrack 或 `conntrack -L -o extended` 的输出，只取发起方向的元组与计数
+pub fn parse_conntrack(text: &str) -> Vec<FlowEntry> {
+    text.lines().filter_map(parse_line).collect()
+}
+
+fn parse_line(line: &str) -> Option<FlowEntry> {
+    let mut proto = None;
+    let (mut src, mut dst, mut dport, mut bytes, mut packets) = (None, None, None, None, None);
+    for token in line.split_whitespace() {
+        match token.split_once('=') {
+            // 每个键第一次出现时属于发起方向
+            Some(("src", value)) if src.is_none() => src = value.parse().ok(),
+            Some(("dst", value)) if dst.is_none() => dst = value.parse().ok(),
+            Some(("dport", value)) if dport.is_none() => dport = value.parse().ok(),
+            Some(("bytes", value)) if bytes.is_none() => bytes = value.parse().ok(),
+            Some(("packets", value)) if packets.is_none() => packets = value.parse().ok(),
+            Some(_) => {}
+            // 行首依次为三层协议名、编号、四层协议名、编号，跳过三层协议名
+            None if proto.is_none()
+                && token != "ipv4"
+                && token != "ipv6"
+                && token.parse::<u64>().is_err() =>
+            {
+                proto = Some(token.to_string())
+            }
+            None => {}
+        }
+    }
+    Some(FlowEntry {
+        src: src?,
+        dst: dst?,
+        proto: proto?,
+        dport,
+        bytes: bytes.unwrap_or(0),
+        packets: packets.unwrap_or(0),
+    })
+}
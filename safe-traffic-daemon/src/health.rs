@@ -0,0 +1,219 @@
+//! 外部集成的健康度：按集成（webhook、云厂商元数据、上游封禁列表、BGP、热备主节点）记录最近调用的
+//! 结果与耗时，得出 0-100 的健康分。健康分低于 DEGRADED_BELOW，或周期性集成超过预期间隔数倍没有成功
+//! 调用时记录一次 health 事件，恢复后再记录一次；过期的 API 密钥这类静默失败不必等到攻击时才被发现。
+
+use crate::events::EventStore;
+
+use chrono::{DateTime, Utc};
+use log::warn;
+use safe_traffic_common::{
+    clock::Clock,
+    events::{Event, EventKind},
+    transport::IntegrationHealth,
+};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time;
+
+/// 计入健康分的最近调用次数
+const WINDOW: usize = 20;
+/// 调用次数达到该值后才按成功比例判定降级，避免启动时的一次失败就告警
+const MIN_CALLS: usize = 3;
+/// 健康分低于该值视为降级
+pub const DEGRADED_BELOW: u8 = 50;
+/// 周期性集成超过预期间隔的该倍数没有成功调用视为停滞
+const STALE_FACTOR: u32 = 3;
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+struct Integration {
+    /// 最近调用的（是否成功, 耗时）
+    outcomes: VecDeque<(bool, Duration)>,
+    /// 周期性集成的预期成功间隔，事件驱动的集成为 None
+    expected: Option<Duration>,
+    /// 登记时间，从未成功的周期性集成从此时起算
+    since: DateTime<Utc>,
+    last_success: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+    degraded: bool,
+}
+
+impl Integration {
+    fn new(expected: Option<Duration>, now: DateTime<Utc>) -> Self {
+        Self {
+            outcomes: VecDeque::with_capacity(WINDOW),
+            expected,
+            since: now,
+            last_success: None,
+            last_error: None,
+            degraded: false,
+        }
+    }
+
+    fn failures(&self) -> usize {
+        self.outcomes.iter().filter(|(ok, _)| !ok).count()
+    }
+
+    /// 周期性集成超过预期间隔的 STALE_FACTOR 倍没有成功调用
+    fn stale(&self, now: DateTime<Utc>) -> bool {
+        self.expected.is_some_and(|expected| {
+            (now - self.last_success.unwrap_or(self.since))
+                .to_std()
+                .is_ok_and(|elapsed| elapsed > expected * STALE_FACTOR)
+        })
+    }
+
+    fn score(&self, now: DateTime<Utc>) -> u8 {
+        if self.stale(now) {
+            return 0;
+        }
+        if self.outcomes.is_empty() {
+            return 100;
+        }
+        let succeeded = self.outcomes.len() - self.failures();
+        (succeeded * 100 / self.outcomes.len()) as u8
+    }
+
+    fn is_degraded(&self, now: DateTime<Utc>) -> bool {
+        self.stale(now) || (self.outcomes.len() >= MIN_CALLS && self.score(now) < DEGRADED_BELOW)
+    }
+
+    /// 降级状态变化时返回事件内容
+    fn transition(&mut self, name: &str, now: DateTime<Utc>) -> Option<String> {
+        let degraded = self.is_degraded(now);
+        if degraded == self.degraded {
+            return None;
+        }
+        self.degraded = degraded;
+        let message = if !degraded {
+            format!("integration {} recovered, health {}", name, self.score(now))
+        } else if self.stale(now) {
+            let last = match self.last_success {
+                Some(last) => format!("since {}", last.format("%Y-%m-%d %H:%M:%S UTC")),
+                None => "yet".to_string(),
+            };
+            format!("integration {} degraded: no successful call {}", name, last)
+        } else {
+            format!(
+                "integration {} degraded: {} of the last {} calls failed, last error: {}",
+                name,
+                self.failures(),
+                self.outcomes.len(),
+                self.last_error.as_deref().unwrap_or("unknown")
+            )
+        };
+        Some(message)
+    }
+
+    fn snapshot(&self, name: &str, now: DateTime<Utc>) -> IntegrationHealth {
+        let latency_ms = (!self.outcomes.is_empty()).then(|| {
+            let total: Duration = self.outcomes.iter().map(|(_, latency)| *latency).sum();
+            (total / self.outcomes.len() as u32).as_millis() as u64
+        });
+        IntegrationHealth {
+            integration: name.to_string(),
+            score: self.score(now),
+            calls: self.outcomes.len(),
+            failures: self.failures(),
+            latency_ms,
+            last_success: self.last_success,
+            last_error: self.last_error.clone(),
+            degraded: self.degraded,
+        }
+    }
+}
+
+/// 各外部集成的健康度
+#[derive(Debug)]
+pub struct Health {
+    integrations: Mutex<BTreeMap<String, Integration>>,
+    events: Arc<EventStore>,
+}
+
+impl Health {
+    pub fn new(events: Arc<EventStore>) -> Self {
+        Self {
+            integrations: Mutex::new(BTreeMap::new()),
+            events,
+        }
+    }
+
+    /// 登记集成，尚未调用时也出现在健康度列表中；expected 为周期性集成预期的成功间隔
+    pub fn register(&self, name: &str, expected: Option<Duration>, now: DateTime<Utc>) {
+        self.integrations
+            .lock()
+            .unwrap()
+            .entry(name.to_string())
+            .or_insert_with(|| Integration::new(expected, now));
+    }
+
+    /// 记录一次调用的结果与耗时，未登记的集成按事件驱动登记
+    pub async fn record(
+        &self,
+        name: &str,
+        now: DateTime<Utc>,
+        latency: Duration,
+        result: Result<(), String>,
+    ) {
+        let message = {
+            let mut integrations = self.integrations.lock().unwrap();
+            let integration = integrations
+                .entry(name.to_string())
+                .or_insert_with(|| Integration::new(None, now));
+            if integration.outcomes.len() == WINDOW {
+                integration.outcomes.pop_front();
+            }
+            integration.outcomes.push_back((result.is_ok(), latency));
+            match result {
+                Ok(()) => integration.last_success = Some(now),
+                Err(e) => integration.last_error = Some(e),
+            }
+            integration.transition(name, now)
+        };
+        if let Some(message) = message {
+            self.report(message).await;
+        }
+    }
+
+    /// 检查周期性集成是否停滞
+    pub async fn check(&self, now: DateTime<Utc>) {
+        let messages: Vec<String> = self
+            .integrations
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .filter_map(|(name, integration)| integration.transition(name, now))
+            .collect();
+        for message in messages {
+            self.report(message).await;
+        }
+    }
+
+    async fn report(&self, message: String) {
+        warn!("{}", message);
+        self.events
+            .push(Event::new(EventKind::Health, message))
+            .await;
+    }
+
+    /// 各集成的健康度，按名称排序
+    pub fn snapshot(&self, now: DateTime<Utc>) -> Vec<IntegrationHealth> {
+        self.integrations
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, integration)| integration.snapshot(name, now))
+            .collect()
+    }
+
+    pub async fn run(self: Arc<Self>, clock: Arc<dyn Clock>) {
+        let mut interval = time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            self.check(clock.wall()).await;
+        }
+    }
+}
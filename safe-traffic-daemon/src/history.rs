@@ -0,0 +1,98 @@
+//! 逐来源的降采样流量历史：每 5 分钟一个桶累计字节数，只保留最近 24 小时，
+//! 供按滚动一天总量触发的规则使用；内存随来源数与有流量的桶数增长，每个来源至多 288 个桶
+
+use dashmap::DashMap;
+use std::{collections::VecDeque, net::IpAddr, time::Duration};
+
+/// 历史覆盖的时长，秒
+pub const DAY_SECS: u64 = 86400;
+const BUCKET_SECS: u64 = 300;
+const BUCKETS: u64 = DAY_SECS / BUCKET_SECS;
+
+/// 单个来源的历史
+#[derive(Debug)]
+struct SourceHistory {
+    /// 上次写入的单调时间，按整秒推进
+    last_ts: Duration,
+    /// (桶序号, 字节数)，按序号递增排列，只保存有流量的桶
+    buckets: VecDeque<(u64, u64)>,
+}
+
+impl SourceHistory {
+    /// 丢弃 now 所在桶往前一天以外的桶
+    fn expire(&mut self, now: Duration) {
+        let oldest = (now.as_secs() / BUCKET_SECS).saturating_sub(BUCKETS - 1);
+        while self.buckets.front().is_some_and(|(id, _)| *id < oldest) {
+            self.buckets.pop_front();
+        }
+    }
+}
+
+/// 所有来源的降采样历史，由流量监控器随采样写入
+#[derive(Debug, Default)]
+pub struct ByteHistory {
+    sources: DashMap<IpAddr, SourceHistory>,
+}
+
+impl ByteHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 写入来源在 at 时刻的速率采样，距上次写入的整秒数乘以速率计入 at 所在的桶；
+    /// 首次出现时只记录时间，该采样不计入
+    pub fn record(&self, ip: IpAddr, bps: u64, at: Duration) {
+        let mut source = self.sources.entry(ip).or_insert_with(|| SourceHistory {
+            last_ts: at,
+            buckets: VecDeque::new(),
+        });
+        let secs = at.saturating_sub(source.last_ts).as_secs();
+        if secs == 0 {
+            return;
+        }
+        source.last_ts += Duration::from_secs(secs);
+        let bytes = bps.saturating_mul(secs);
+        if bytes == 0 {
+            return;
+        }
+        let id = at.as_secs() / BUCKET_SECS;
+        match source.buckets.back_mut() {
+            Some((last, total)) if *last == id => *total = total.saturating_add(bytes),
+            _ => source.buckets.push_back((id, bytes)),
+        }
+        source.expire(at);
+    }
+
+    /// 来源在 now 之前 24 小时内的总字节数，精度为一个桶
+    pub fn total(&self, ip: &IpAddr, now: Duration) -> u64 {
+        let oldest = (now.as_secs() / BUCKET_SECS).saturating_sub(BUCKETS - 1);
+        self.sources
+            .get(ip)
+            .map(|source| {
+                source
+                    .buckets
+                    .iter()
+                    .filter(|(id, _)| *id >= oldest)
+                    .map(|(_, bytes)| bytes)
+                    .sum()
+            })
+            .unwrap_or(0)
+    }
+
+    /// 丢弃一天以外的桶，并移除一天内没有流量的来源
+    pub fn prune(&self, now: Duration) {
+        self.sources.retain(|_, source| {
+            source.expire(now);
+            !source.buckets.is_empty() || now.saturating_sub(source.last_ts).as_secs() < DAY_SECS
+        });
+    }
+
+    /// 保存历史的来源数
+    pub fn len(&self) -> usize {
+        self.sources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+}
@@ -0,0 +1,106 @@
+//! 本机地址排除：hook 为 Output 时统计的是本机发出的流量，
+//! 本机网卡地址与浮动地址不能成为动作的对象，否则会限制本机发往正常客户端的回程流量
+
+use crate::controller::Firewall;
+
+use anyhow::{anyhow, Result};
+use futures::stream::TryStreamExt;
+use log::{info, warn};
+use netlink_packet_route::address::AddressAttribute;
+use rtnetlink::Handle;
+use safe_traffic_common::config::{ExclusionTable, HostExcludeConfig};
+use std::{collections::HashMap, net::IpAddr, sync::Arc, time::Duration};
+
+/// 定期读取监控网卡上的地址，连同配置的浮动地址替换防火墙的本机白名单
+pub struct HostExclusions {
+    handle: Handle,
+    interfaces: Vec<String>,
+    vips: Vec<String>,
+    include_interfaces: bool,
+    refresh: Duration,
+}
+
+impl HostExclusions {
+    pub fn new(handle: Handle, interfaces: Vec<String>, cfg: HostExcludeConfig) -> Self {
+        Self {
+            handle,
+            interfaces,
+            vips: cfg.vips.unwrap_or_default(),
+            include_interfaces: cfg.enabled.unwrap_or(true),
+            refresh: Duration::from_secs(cfg.refresh_secs.unwrap_or(60).max(1)),
+        }
+    }
+
+    /// 需要排除的条目：网卡地址与浮动地址
+    async fn entries(&self) -> Result<Vec<String>> {
+        let mut entries = if self.include_interfaces {
+            interface_addresses(&self.handle, &self.interfaces)
+                .await?
+                .iter()
+                .map(IpAddr::to_string)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        entries.extend(self.vips.iter().cloned());
+        entries.sort();
+        entries.dedup();
+        Ok(entries)
+    }
+
+    /// 立即读取一次，之后按 refresh_secs 刷新，地址变化时记录；读取失败时保留上一次的结果
+    pub async fn run(self, fw: Arc<Firewall>) {
+        let mut current: Option<Vec<String>> = None;
+        loop {
+            let result = self.entries().await.and_then(|entries| {
+                ExclusionTable::build(&entries, &HashMap::new()).map(|table| (entries, table))
+            });
+            match result {
+                Ok((entries, table)) => {
+                    if current.as_ref() != Some(&entries) {
+                        info!("Excluding host addresses: {}", entries.join(", "));
+                        fw.set_host_excludes(table).await;
+                        current = Some(entries);
+                    }
+                }
+                Err(e) => warn!(
+                    "Failed to read host addresses, keeping the previous exclusions: {}",
+                    e
+                ),
+            }
+            tokio::time::sleep(self.refresh).await;
+        }
+    }
+}
+
+/// 网卡上配置的全部地址，不存在的网卡（如尚未创建的虚拟网卡）跳过
+pub async fn interface_addresses(handle: &Handle, interfaces: &[String]) -> Result<Vec<IpAddr>> {
+    let mut addresses = Vec::new();
+    for name in interfaces {
+        let mut links = handle.link().get().match_name(name.clone()).execute();
+        let index = match links.try_next().await {
+            Ok(Some(link)) => link.header.index,
+            Ok(None) | Err(_) => {
+                warn!("Interface {} not found, no host addresses read", name);
+                continue;
+            }
+        };
+        let mut messages = handle
+            .address()
+            .get()
+            .set_link_index_filter(index)
+            .execute();
+        while let Some(msg) = messages
+            .try_next()
+            .await
+            .map_err(|e| anyhow!("failed to read addresses of {}: {}", name, e))?
+        {
+            for attr in &msg.attributes {
+                if let AddressAttribute::Address(ip) = attr {
+                    addresses.push(ip.to_canonical());
+                }
+            }
+        }
+    }
+    Ok(addresses)
+}
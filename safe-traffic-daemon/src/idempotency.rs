@@ -0,0 +1,106 @@
+//! 控制接口的幂等键：客户端超时后带同一个键重试封禁或限速时，保留期内返回首次的结果，不再重复创建规则
+
+use anyhow::{bail, Result};
+use log::debug;
+use safe_traffic_common::transport::Response;
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// 幂等键的最大长度
+const MAX_KEY_LEN: usize = 128;
+
+/// 某个键首次成功执行的结果
+#[derive(Debug)]
+struct Record {
+    /// 去掉幂等键后的请求，同一个键用于不同的请求时拒绝
+    fingerprint: String,
+    /// 序列化后的响应
+    response: String,
+    /// 执行时的单调时间
+    at: Duration,
+}
+
+/// 同一个键的请求在槽位上串行执行，重试即使与首次请求并发也会等待首次的结果
+type Slot = Arc<tokio::sync::Mutex<Option<Record>>>;
+
+pub struct IdempotencyStore {
+    retention: Duration,
+    slots: Mutex<HashMap<String, Slot>>,
+}
+
+impl IdempotencyStore {
+    pub fn new(retention: Duration) -> Self {
+        Self {
+            retention,
+            slots: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 以 key 执行请求：保留期内已有成功的结果时直接返回，同一个键用于不同的请求时报错。
+    /// 失败的结果不保留，重试会再次执行
+    pub async fn execute<F>(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        now: Duration,
+        request: F,
+    ) -> Result<Response>
+    where
+        F: Future<Output = Result<Response>>,
+    {
+        if key.is_empty() || key.len() > MAX_KEY_LEN {
+            bail!("idempotency key must be 1 to {} characters", MAX_KEY_LEN);
+        }
+        let slot = self.slot(key, now);
+        let mut record = slot.lock().await;
+        if let Some(existing) = record.as_ref().filter(|record| self.fresh(record, now)) {
+            if existing.fingerprint != fingerprint {
+                bail!(
+                    "idempotency key {} was already used for a different request",
+                    key
+                );
+            }
+            debug!("Replaying the result of idempotency key {}", key);
+            return Ok(serde_json::from_str(&existing.response)?);
+        }
+
+        let response = request.await?;
+        if matches!(response, Response::Success(_)) {
+            *record = Some(Record {
+                fingerprint: fingerprint.to_string(),
+                response: serde_json::to_string(&response)?,
+                at: now,
+            });
+        }
+        Ok(response)
+    }
+
+    fn fresh(&self, record: &Record, now: Duration) -> bool {
+        now.saturating_sub(record.at) < self.retention
+    }
+
+    /// 取得 key 的槽位，同时清理没有请求在使用且已过期的槽位
+    fn slot(&self, key: &str, now: Duration) -> Slot {
+        let mut slots = self.slots.lock().unwrap();
+        slots.retain(|_, slot| {
+            Arc::strong_count(slot) > 1
+                || slot
+                    .try_lock()
+                    .is_ok_and(|record| record.as_ref().is_some_and(|r| self.fresh(r, now)))
+        });
+        Arc::clone(slots.entry(key.to_string()).or_default())
+    }
+
+    /// 保留中的键数
+    pub fn len(&self) -> usize {
+        self.slots.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,171 @@
+use safe_traffic_common::{
+    events::{Event, EventKind, Incident},
+    utils::network_of,
+};
+
+use chrono::{DateTime, Duration, Utc};
+use log::{info, warn};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    net::IpAddr,
+    sync::Mutex,
+};
+
+pub const DEFAULT_THRESHOLD: u64 = 50;
+pub const DEFAULT_WINDOW_SECS: u64 = 60;
+/// 保留的已结束 incident 数量
+const HISTORY: usize = 64;
+/// incident 中列出的来源网段数量
+const TOP_NETWORKS: usize = 10;
+
+/// 进行中的 incident 及其完整的来源集合
+struct OpenIncident {
+    incident: Incident,
+    ips: HashSet<IpAddr>,
+    networks: HashMap<String, u64>,
+}
+
+impl OpenIncident {
+    fn record(&mut self, ip: IpAddr, bps: u64, time: DateTime<Utc>) {
+        let incident = &mut self.incident;
+        incident.actions += 1;
+        incident.last_action_at = time;
+        incident.peak_bps = incident.peak_bps.max(bps);
+        self.ips.insert(ip);
+        *self.networks.entry(network_of(ip)).or_default() += 1;
+    }
+
+    /// 生成当前状态的快照
+    fn snapshot(&self) -> Incident {
+        let mut networks: Vec<_> = self
+            .networks
+            .iter()
+            .map(|(network, actions)| (network.clone(), *actions))
+            .collect();
+        networks.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        networks.truncate(TOP_NETWORKS);
+
+        Incident {
+            total_ips: self.ips.len(),
+            top_networks: networks,
+            ..self.incident.clone()
+        }
+    }
+}
+
+#[derive(Default)]
+struct TrackerState {
+    /// 统计窗口内的动作（时间、IP、流量）
+    recent: VecDeque<(DateTime<Utc>, IpAddr, u64)>,
+    open: Option<OpenIncident>,
+    closed: VecDeque<Incident>,
+    next_id: u64,
+}
+
+/// 将短时间内的大量动作归并为 incident，开始与结束时各产生一条汇总事件
+pub struct IncidentTracker {
+    threshold: u64,
+    window: Duration,
+    state: Mutex<TrackerState>,
+}
+
+impl IncidentTracker {
+    pub fn new(threshold: u64, window_secs: u64) -> Self {
+        IncidentTracker {
+            threshold: threshold.max(1),
+            window: Duration::seconds(window_secs as i64),
+            state: Mutex::new(TrackerState {
+                next_id: 1,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// 记录一次动作，开启新的 incident 时返回对应事件
+    pub fn record(&self, ip: IpAddr, bps: u64, time: DateTime<Utc>) -> Option<Event> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(open) = state.open.as_mut() {
+            open.record(ip, bps, time);
+            return None;
+        }
+
+        state.recent.push_back((time, ip, bps));
+        while let Some((first, _, _)) = state.recent.front() {
+            if time - *first <= self.window {
+                break;
+            }
+            state.recent.pop_front();
+        }
+        if (state.recent.len() as u64) <= self.threshold {
+            return None;
+        }
+
+        // 超过阈值，窗口内的动作全部归入新的 incident
+        let id = state.next_id;
+        state.next_id += 1;
+        let started_at = state.recent.front().map(|(time, _, _)| *time)?;
+        let mut open = OpenIncident {
+            incident: Incident {
+                id,
+                started_at,
+                last_action_at: started_at,
+                ended_at: None,
+                actions: 0,
+                total_ips: 0,
+                peak_bps: 0,
+                top_networks: Vec::new(),
+            },
+            ips: HashSet::new(),
+            networks: HashMap::new(),
+        };
+        for (time, ip, bps) in state.recent.drain(..) {
+            open.record(ip, bps, time);
+        }
+        let message = format!(
+            "incident #{} opened: {} actions within {}s",
+            id,
+            open.incident.actions,
+            self.window.num_seconds()
+        );
+        warn!("{}", message);
+        state.open = Some(open);
+        Some(Event::new(EventKind::Incident, message))
+    }
+
+    /// 结束已安静超过统计窗口的 incident，返回汇总事件
+    pub fn close_idle(&self, now: DateTime<Utc>) -> Option<Event> {
+        let mut state = self.state.lock().unwrap();
+        let idle = state
+            .open
+            .as_ref()
+            .is_some_and(|open| now - open.incident.last_action_at > self.window);
+        if !idle {
+            return None;
+        }
+
+        let mut incident = state.open.take()?.snapshot();
+        incident.ended_at = Some(now);
+        let message = format!(
+            "{} closed after {}s",
+            incident,
+            (incident.last_action_at - incident.started_at).num_seconds()
+        );
+        info!("{}", message);
+        if state.closed.len() >= HISTORY {
+            state.closed.pop_front();
+        }
+        state.closed.push_back(incident);
+        Some(Event::new(EventKind::Incident, message))
+    }
+
+    /// 最近的 incident，进行中的排在最后
+    pub fn incidents(&self) -> Vec<Incident> {
+        let state = self.state.lock().unwrap();
+        state
+            .closed
+            .iter()
+            .cloned()
+            .chain(state.open.as_ref().map(OpenIncident::snapshot))
+            .collect()
+    }
+}
@@ -0,0 +1,195 @@
+//! 决策日志：规则引擎每一拍对每个来源的判定，以定长二进制记录追加写入
+//!
+//! 文件以 `STJ1` 开头，之后每条记录 44 字节（小端）：时间（微秒）、地址族、地址（16 字节）、
+//! 流量与新建连接流量（字节/秒）、触发的规则序号（无为 0xFFFF）、判定结果。
+//! 被抽样的来源每一拍都会记录，重放时可以重建完整的滑动窗口。
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs::{File, OpenOptions},
+    hash::{Hash, Hasher},
+    io::{BufWriter, Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    path::Path,
+    sync::Mutex,
+};
+
+const MAGIC: &[u8; 4] = b"STJ1";
+pub const RECORD_LEN: usize = 44;
+const NO_RULE: u16 = u16::MAX;
+
+/// 一拍中对一个来源的判定结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// 未超过任何规则的阈值
+    NoAction,
+    /// 执行了动作
+    Applied,
+    /// 在白名单中，只计量
+    Excluded,
+    /// 来源被定向暂停
+    Paused,
+    /// 超过阈值但处于预热期
+    Warmup,
+    /// nft 不可用，动作已暂存
+    Deferred,
+    /// 超过阈值但处于伪造源洪泛的整体防护中，不创建逐 IP 规则
+    Spoofed,
+}
+
+impl Outcome {
+    fn code(self) -> u8 {
+        match self {
+            Outcome::NoAction => 0,
+            Outcome::Applied => 1,
+            Outcome::Excluded => 2,
+            Outcome::Paused => 3,
+            Outcome::Warmup => 4,
+            Outcome::Deferred => 5,
+            Outcome::Spoofed => 6,
+        }
+    }
+
+    fn from_code(code: u8) -> Result<Self> {
+        Ok(match code {
+            0 => Outcome::NoAction,
+            1 => Outcome::Applied,
+            2 => Outcome::Excluded,
+            3 => Outcome::Paused,
+            4 => Outcome::Warmup,
+            5 => Outcome::Deferred,
+            6 => Outcome::Spoofed,
+            _ => bail!("unknown journal outcome: {}", code),
+        })
+    }
+}
+
+/// 一条决策记录
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalRecord {
+    /// 所在拍的墙上时间，同一拍的记录相同
+    pub time: DateTime<Utc>,
+    pub ip: IpAddr,
+    /// 规则比较使用的流量，字节/秒
+    pub bps: u64,
+    pub new_bps: u64,
+    /// 触发的规则序号
+    pub rule: Option<usize>,
+    pub outcome: Outcome,
+}
+
+impl JournalRecord {
+    pub fn encode(&self) -> [u8; RECORD_LEN] {
+        let mut buf = [0u8; RECORD_LEN];
+        buf[0..8].copy_from_slice(&self.time.timestamp_micros().to_le_bytes());
+        match self.ip {
+            IpAddr::V4(ip) => {
+                buf[8] = 4;
+                buf[9..13].copy_from_slice(&ip.octets());
+            }
+            IpAddr::V6(ip) => {
+                buf[8] = 6;
+                buf[9..25].copy_from_slice(&ip.octets());
+            }
+        }
+        buf[25..33].copy_from_slice(&self.bps.to_le_bytes());
+        buf[33..41].copy_from_slice(&self.new_bps.to_le_bytes());
+        let rule = self
+            .rule
+            .and_then(|rule| u16::try_from(rule).ok())
+            .unwrap_or(NO_RULE);
+        buf[41..43].copy_from_slice(&rule.to_le_bytes());
+        buf[43] = self.outcome.code();
+        buf
+    }
+
+    pub fn decode(buf: &[u8; RECORD_LEN]) -> Result<Self> {
+        let u64_at = |at: usize| u64::from_le_bytes(buf[at..at + 8].try_into().unwrap());
+        let micros = i64::from_le_bytes(buf[0..8].try_into().unwrap());
+        let time = DateTime::from_timestamp_micros(micros)
+            .ok_or_else(|| anyhow!("invalid journal timestamp: {}", micros))?;
+        let ip = match buf[8] {
+            4 => IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(&buf[9..13]).unwrap())),
+            6 => IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(&buf[9..25]).unwrap())),
+            family => bail!("invalid journal address family: {}", family),
+        };
+        let rule = u16::from_le_bytes([buf[41], buf[42]]);
+        Ok(Self {
+            time,
+            ip,
+            bps: u64_at(25),
+            new_bps: u64_at(33),
+            rule: (rule != NO_RULE).then_some(rule as usize),
+            outcome: Outcome::from_code(buf[43])?,
+        })
+    }
+}
+
+/// 追加写入的决策日志
+#[derive(Debug)]
+pub struct DecisionJournal {
+    writer: Mutex<BufWriter<File>>,
+    /// 按来源抽样的阈值，哈希值低于该值的来源被记录
+    cutoff: u64,
+}
+
+impl DecisionJournal {
+    /// 打开或创建日志文件，已有文件的格式不符时返回错误
+    pub fn open(path: &Path, sample_rate: f64) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)
+            .with_context(|| format!("failed to open journal {}", path.display()))?;
+        if file.metadata()?.len() == 0 {
+            file.write_all(MAGIC)?;
+        } else {
+            let mut magic = [0u8; 4];
+            file.read_exact(&mut magic)?;
+            if &magic != MAGIC {
+                bail!("{} is not a decision journal", path.display());
+            }
+        }
+        Ok(Self {
+            writer: Mutex::new(BufWriter::new(file)),
+            cutoff: (sample_rate.clamp(0.0, 1.0) * u32::MAX as f64) as u64,
+        })
+    }
+
+    /// 来源是否被抽中，同一来源的结果始终相同
+    pub fn sampled(&self, ip: &IpAddr) -> bool {
+        let mut hasher = DefaultHasher::new();
+        ip.hash(&mut hasher);
+        hasher.finish() & u32::MAX as u64 <= self.cutoff
+    }
+
+    pub fn record(&self, record: &JournalRecord) -> Result<()> {
+        self.writer.lock().unwrap().write_all(&record.encode())?;
+        Ok(())
+    }
+
+    /// 每拍结束时写入磁盘
+    pub fn flush(&self) -> Result<()> {
+        self.writer.lock().unwrap().flush()?;
+        Ok(())
+    }
+}
+
+/// 读取整个日志文件，末尾不完整的记录（写入中途退出）被忽略
+pub fn read_journal(path: &Path) -> Result<Vec<JournalRecord>> {
+    let data = std::fs::read(path)
+        .with_context(|| format!("failed to read journal {}", path.display()))?;
+    let Some(body) = data.strip_prefix(MAGIC.as_slice()) else {
+        bail!("{} is not a decision journal", path.display());
+    };
+    body.chunks_exact(RECORD_LEN)
+        .map(|chunk| JournalRecord::decode(chunk.try_into().unwrap()))
+        .collect()
+}
@@ -0,0 +1,42 @@
+pub mod accounting; // 按服务统计流量
+pub mod archive; // 事件归档
+pub mod bgp; // BGP 黑洞路由与 FlowSpec 发布
+pub mod capabilities; // 启动能力探测
+pub mod cloud; // 云厂商元数据白名单
+pub mod controller; // nftables 控制
+pub mod daemon;
+pub mod error;
+pub mod events; // 事件记录
+pub mod export; // IPFIX 流量导出
+pub mod failsafe; // 失控保护
+pub mod flows; // 连接跟踪流表
+pub mod health; // 外部集成健康度
+pub mod history; // 降采样流量历史
+pub mod host; // 本机地址白名单
+pub mod idempotency; // 控制接口幂等键
+pub mod incidents; // 动作归并
+pub mod journal; // 决策日志
+pub mod lockout; // 管理接口认证失败锁定
+pub mod logger;
+pub mod monitor; // 流量监控
+pub mod neighbors; // 邻居表（IP 到 MAC）
+pub mod nfqueue; // NFQUEUE 逐包判定
+pub mod nft;
+pub mod notify; // 事件通知
+pub mod outbound; // 出站请求代理
+pub mod push; // WebSocket 推送
+pub mod rearm; // 重启后恢复封禁
+pub mod recorder; // 流量快照记录
+pub mod reputation; // 来源信誉分
+pub mod rules; // 规则引擎
+pub mod savings; // 丢弃流量统计
+pub mod setup; // 配置生成与 Schema
+pub mod simulate; // 规则模拟
+pub mod sketch; // 不同来源数估计
+pub mod spoof; // 伪造源洪泛防护
+pub mod standby; // 热备
+pub mod state; // 运行时状态持久化
+pub mod tarpit; // 端口扫描减速
+pub mod tasks;
+pub mod upstream; // 上游封禁列表
+pub mod warnpage; // 限速提示页
@@ -0,0 +1,125 @@
+//! 管理接口的自我保护：同一来源在窗口内反复 token 校验失败时被锁定，
+//! 锁定期间拒绝其连接，并用守护进程自己的封禁动作在 nft 中短时封禁该来源
+
+use crate::controller::Firewall;
+use dashmap::DashMap;
+use log::warn;
+use safe_traffic_common::{
+    config::WebSocketConfig,
+    events::{Event, EventKind},
+};
+use std::{
+    collections::VecDeque,
+    net::IpAddr,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// 管理接口的认证失败计数与锁定
+#[derive(Debug)]
+pub struct AuthGuard {
+    /// 窗口内失败达到该次数时锁定，0 为不锁定
+    max_failures: u32,
+    window: Duration,
+    lockout: Duration,
+    /// 各来源窗口内每次失败的单调时间
+    failures: DashMap<IpAddr, VecDeque<Duration>>,
+    /// 锁定中的来源及解除锁定的单调时间
+    locked: DashMap<IpAddr, Duration>,
+    failures_total: AtomicU64,
+    lockouts_total: AtomicU64,
+}
+
+impl AuthGuard {
+    pub fn new(cfg: &WebSocketConfig) -> Self {
+        Self {
+            max_failures: cfg.max_auth_failures.unwrap_or(5),
+            window: Duration::from_secs(cfg.auth_window_secs.unwrap_or(300)),
+            lockout: Duration::from_secs(cfg.lockout_secs.unwrap_or(900)),
+            failures: DashMap::new(),
+            locked: DashMap::new(),
+            failures_total: AtomicU64::new(0),
+            lockouts_total: AtomicU64::new(0),
+        }
+    }
+
+    /// 来源是否处于锁定中
+    pub fn is_locked(&self, ip: &IpAddr, now: Duration) -> bool {
+        self.locked.get(ip).is_some_and(|until| *until > now)
+    }
+
+    /// 记录一次校验失败；窗口内失败达到上限时锁定该来源并返回 true
+    pub fn record_failure(&self, ip: IpAddr, now: Duration) -> bool {
+        self.failures_total.fetch_add(1, Ordering::Relaxed);
+        let window_start = now.saturating_sub(self.window);
+        // 清理窗口外的失败与已解除的锁定，来源再多也不会无限增长
+        self.failures.retain(|_, times| {
+            while times.front().is_some_and(|at| *at <= window_start) {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+        self.locked.retain(|_, until| *until > now);
+        if self.max_failures == 0 {
+            return false;
+        }
+
+        let mut times = self.failures.entry(ip).or_default();
+        times.push_back(now);
+        if times.len() < self.max_failures as usize {
+            return false;
+        }
+        drop(times);
+        self.failures.remove(&ip);
+        self.locked.insert(ip, now + self.lockout);
+        self.lockouts_total.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    /// 校验通过后清除该来源的失败记录
+    pub fn record_success(&self, ip: &IpAddr) {
+        self.failures.remove(ip);
+    }
+
+    /// 锁定刚达到上限的来源：记录事件并封禁 lockout_secs 秒。
+    /// 回环地址只拒绝连接不封禁，白名单中的来源按 precedence 仲裁
+    pub async fn enforce(&self, fw: &Firewall, ip: IpAddr) {
+        let message = format!(
+            "management API locked out {} for {}s after {} failed authentications",
+            ip,
+            self.lockout.as_secs(),
+            self.max_failures
+        );
+        warn!("{}", message);
+        fw.events
+            .push(Event::new(EventKind::Warning, message).with_ip(ip))
+            .await;
+        if ip.is_loopback() {
+            return;
+        }
+        if let Err(e) = fw.ban(ip, Some(self.lockout.as_secs())).await {
+            warn!(
+                "Failed to ban {} after repeated authentication failures: {}",
+                ip, e
+            );
+        }
+    }
+
+    /// 启动以来的校验失败次数
+    pub fn failures_total(&self) -> u64 {
+        self.failures_total.load(Ordering::Relaxed)
+    }
+
+    /// 启动以来的锁定次数
+    pub fn lockouts_total(&self) -> u64 {
+        self.lockouts_total.load(Ordering::Relaxed)
+    }
+
+    /// 当前锁定中的来源数
+    pub fn locked_out(&self, now: Duration) -> usize {
+        self.locked
+            .iter()
+            .filter(|entry| *entry.value() > now)
+            .count()
+    }
+}
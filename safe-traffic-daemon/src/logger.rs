@@ -1,2 +1,360 @@
 // src/logger.rs
-// 这里可以接入文件或数据库日志，这里示例如仅使用标准日志输出
+//! 日志输出：在 env_logger 之上按规则的日志级别与采样率过滤规则动作产生的日志，
+//! 可选以 systemd-journald 原生协议写入带结构化字段的日志；
+//! 同时保留最近的日志，并推送给通过控制接口跟踪日志的客户端
+
+use chrono::Utc;
+use env_logger::Env;
+use log::{info, Level, LevelFilter, Log, Metadata, Record};
+use safe_traffic_common::{
+    config::{LogLevel, Rule},
+    reason::Reason,
+    transport::LogLine,
+};
+use std::{
+    collections::VecDeque,
+    future::Future,
+    io,
+    net::IpAddr,
+    os::unix::net::UnixDatagram,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex, OnceLock,
+    },
+};
+use tokio::sync::broadcast;
+
+/// journald 原生协议的接收 socket
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+/// 保留的最近日志条数
+const RECENT_LOGS: usize = 200;
+/// 跟踪日志的客户端来不及读取时缓冲的条数
+const TAP_CAPACITY: usize = 1024;
+/// 只向跟踪的客户端推送本项目模块的日志，依赖库的调试日志不推送
+const TAP_TARGET_PREFIX: &str = "safe_traffic";
+
+tokio::task_local! {
+    /// 当前正在执行的规则动作
+    static RULE_SCOPE: RuleScope;
+    /// 当前动作的触发原因
+    static REASON: Reason;
+}
+
+/// 启用后日志写入 journald，不再输出到 stderr
+static JOURNAL: OnceLock<Journal> = OnceLock::new();
+/// 最近的日志与跟踪日志的客户端
+static TAP: OnceLock<LogTap> = OnceLock::new();
+
+/// 正在执行的规则动作：允许输出的日志级别，以及写入 journald 的结构化字段
+#[derive(Debug, Clone)]
+pub struct RuleScope {
+    pub level: LevelFilter,
+    pub ip: IpAddr,
+    /// 规则序号
+    pub rule: usize,
+    pub action: &'static str,
+    /// 触发时的流量，字节/秒
+    pub bps: u64,
+}
+
+/// 包装 env_logger，规则动作范围内的日志按该规则的级别过滤
+struct RuleAwareLogger {
+    inner: env_logger::Logger,
+}
+
+impl Log for RuleAwareLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata) || tap().wants(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        let scope = RULE_SCOPE.try_with(|scope| scope.clone()).ok();
+        // 跟踪的客户端可能要求比输出更详细的级别，规则的日志级别与采样只作用于输出
+        let output = self.inner.matches(record)
+            && scope
+                .as_ref()
+                .is_none_or(|scope| record.level() <= scope.level);
+        tap().publish(record, scope.as_ref(), output);
+        if !output {
+            return;
+        }
+        if let Some(journal) = JOURNAL.get() {
+            // journald 不可用时退回 stderr
+            if journal.send(record, scope.as_ref()).is_ok() {
+                return;
+            }
+        }
+        self.inner.log(record);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// 初始化全局日志（可通过环境变量 RUST_LOG 调节级别）
+pub fn init() {
+    let inner = env_logger::Builder::from_env(Env::default().default_filter_or("info")).build();
+    log::set_max_level(inner.filter());
+    let _ = TAP.set(LogTap::new(inner.filter()));
+    log::set_boxed_logger(Box::new(RuleAwareLogger { inner })).expect("logger already initialized");
+}
+
+/// 之后的日志以原生协议写入 journald，规则动作的日志带有 IP、RULE、ACTION、BPS 字段
+pub fn enable_journald() -> io::Result<()> {
+    let journal = Journal::connect()?;
+    let _ = JOURNAL.set(journal);
+    Ok(())
+}
+
+/// 在规则动作的范围内执行，期间的日志按该规则的级别过滤并带上结构化字段
+pub async fn scope<F: Future>(scope: RuleScope, f: F) -> F::Output {
+    RULE_SCOPE.scope(scope, f).await
+}
+
+/// 在触发原因的范围内执行，期间创建的规则、事件和 journald 日志都带上该原因
+pub async fn with_reason<F: Future>(reason: Reason, f: F) -> F::Output {
+    REASON.scope(reason, f).await
+}
+
+/// 当前动作的触发原因，不在 with_reason 范围内时为 None
+pub fn current_reason() -> Option<Reason> {
+    REASON.try_with(|reason| reason.clone()).ok()
+}
+
+/// 最近输出的日志，供 logs 命令查看
+pub fn recent() -> Vec<LogLine> {
+    tap().recent.lock().unwrap().iter().cloned().collect()
+}
+
+/// 跟踪之后产生的日志，level 可以比输出的级别更详细，订阅期间全局日志级别相应提高
+pub fn subscribe(level: LogLevel) -> LogSubscription {
+    let tap = tap();
+    let level = LevelFilter::from(level);
+    let receiver = tap.sender.subscribe();
+    tap.levels.lock().unwrap().push(level);
+    tap.refresh();
+    LogSubscription { receiver, level }
+}
+
+/// 日志跟踪的订阅，丢弃时恢复原来的日志级别
+pub struct LogSubscription {
+    receiver: broadcast::Receiver<LogLine>,
+    level: LevelFilter,
+}
+
+impl LogSubscription {
+    pub async fn recv(&mut self) -> Result<LogLine, broadcast::error::RecvError> {
+        self.receiver.recv().await
+    }
+}
+
+impl Drop for LogSubscription {
+    fn drop(&mut self) {
+        let tap = tap();
+        let mut levels = tap.levels.lock().unwrap();
+        if let Some(index) = levels.iter().position(|level| *level == self.level) {
+            levels.swap_remove(index);
+        }
+        drop(levels);
+        tap.refresh();
+    }
+}
+
+fn tap() -> &'static LogTap {
+    TAP.get_or_init(|| LogTap::new(log::max_level()))
+}
+
+/// 最近的日志与日志跟踪的订阅者
+struct LogTap {
+    sender: broadcast::Sender<LogLine>,
+    recent: Mutex<VecDeque<LogLine>>,
+    /// 各订阅者要求的级别
+    levels: Mutex<Vec<LevelFilter>>,
+    /// 订阅者中最详细的级别，没有订阅者时为 Off
+    level: AtomicUsize,
+    /// 未跟踪时的全局日志级别
+    base: LevelFilter,
+}
+
+impl LogTap {
+    fn new(base: LevelFilter) -> Self {
+        Self {
+            sender: broadcast::channel(TAP_CAPACITY).0,
+            recent: Mutex::new(VecDeque::with_capacity(RECENT_LOGS)),
+            levels: Mutex::new(Vec::new()),
+            level: AtomicUsize::new(LevelFilter::Off as usize),
+            base,
+        }
+    }
+
+    /// 按订阅者要求的最详细级别调整全局日志级别
+    fn refresh(&self) {
+        let level = self
+            .levels
+            .lock()
+            .unwrap()
+            .iter()
+            .copied()
+            .max()
+            .unwrap_or(LevelFilter::Off);
+        self.level.store(level as usize, Ordering::Relaxed);
+        log::set_max_level(self.base.max(level));
+    }
+
+    fn wants(&self, metadata: &Metadata) -> bool {
+        metadata.level() as usize <= self.level.load(Ordering::Relaxed)
+            && metadata.target().starts_with(TAP_TARGET_PREFIX)
+    }
+
+    /// output 表示该日志已输出，计入最近的日志
+    fn publish(&self, record: &Record, scope: Option<&RuleScope>, output: bool) {
+        let follow = self.wants(record.metadata());
+        if !output && !follow {
+            return;
+        }
+        let line = LogLine {
+            time: Utc::now(),
+            level: match record.level() {
+                Level::Error => LogLevel::Error,
+                Level::Warn => LogLevel::Warn,
+                Level::Info => LogLevel::Info,
+                Level::Debug => LogLevel::Debug,
+                Level::Trace => LogLevel::Trace,
+            },
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+            ip: scope.map(|scope| scope.ip),
+            rule: scope.map(|scope| scope.rule),
+            rule_name: current_reason().and_then(|reason| reason.rule_name),
+            action: scope.map(|scope| scope.action.to_string()),
+        };
+        if follow {
+            let _ = self.sender.send(line.clone());
+        }
+        if output {
+            let mut recent = self.recent.lock().unwrap();
+            if recent.len() == RECENT_LOGS {
+                recent.pop_front();
+            }
+            recent.push_back(line);
+        }
+    }
+}
+
+/// systemd-journald 原生协议的写入端
+struct Journal {
+    socket: UnixDatagram,
+}
+
+impl Journal {
+    fn connect() -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(JOURNAL_SOCKET)?;
+        Ok(Journal { socket })
+    }
+
+    fn send(&self, record: &Record, scope: Option<&RuleScope>) -> io::Result<()> {
+        let mut buf = Vec::new();
+        let priority = match record.level() {
+            Level::Error => "3",
+            Level::Warn => "4",
+            Level::Info => "6",
+            Level::Debug | Level::Trace => "7",
+        };
+        journal_field(&mut buf, "PRIORITY", priority);
+        journal_field(&mut buf, "MESSAGE", &record.args().to_string());
+        journal_field(&mut buf, "SYSLOG_IDENTIFIER", "safe-traffic-daemon");
+        journal_field(&mut buf, "TARGET", record.target());
+        if let Some(scope) = scope {
+            journal_field(&mut buf, "IP", &scope.ip.to_string());
+            journal_field(&mut buf, "RULE", &scope.rule.to_string());
+            journal_field(&mut buf, "ACTION", scope.action);
+            journal_field(&mut buf, "BPS", &scope.bps.to_string());
+        }
+        if let Some(reason) = current_reason() {
+            if let Some(name) = &reason.rule_name {
+                journal_field(&mut buf, "RULE_NAME", name);
+            }
+            journal_field(&mut buf, "METRIC", &reason.metric);
+            journal_field(&mut buf, "OBSERVED", &reason.observed.to_string());
+            journal_field(&mut buf, "THRESHOLD", &reason.threshold.to_string());
+            journal_field(&mut buf, "WINDOW_SECS", &reason.window_secs.to_string());
+        }
+        self.socket.send(&buf)?;
+        Ok(())
+    }
+}
+
+/// 写入一个字段，值含换行时使用带长度前缀的二进制格式
+fn journal_field(buf: &mut Vec<u8>, name: &str, value: &str) {
+    buf.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        buf.push(b'=');
+    }
+    buf.extend_from_slice(value.as_bytes());
+    buf.push(b'\n');
+}
+
+/// 单条规则的日志策略与本周期计数
+struct RuleLog {
+    level: LevelFilter,
+    sample: u64,
+    fired: AtomicU64,
+    logged: AtomicU64,
+}
+
+/// 规则触发日志的级别与采样
+pub struct RuleLogger {
+    rules: Vec<RuleLog>,
+}
+
+impl RuleLogger {
+    pub fn new(rules: &[Rule]) -> Self {
+        let rules = rules
+            .iter()
+            .map(|rule| RuleLog {
+                level: rule.log_level.map_or(LevelFilter::Info, Into::into),
+                sample: rule.log_sample.unwrap_or(1).max(1),
+                fired: AtomicU64::new(0),
+                logged: AtomicU64::new(0),
+            })
+            .collect();
+        RuleLogger { rules }
+    }
+
+    /// 记录规则触发一次，返回本次执行动作时允许的日志级别；未被采样时只保留错误日志
+    pub fn admit(&self, rule: usize) -> LevelFilter {
+        let log = &self.rules[rule];
+        if log
+            .fired
+            .fetch_add(1, Ordering::Relaxed)
+            .is_multiple_of(log.sample)
+        {
+            log.logged.fetch_add(1, Ordering::Relaxed);
+            log.level
+        } else {
+            log.level.min(LevelFilter::Error)
+        }
+    }
+
+    /// 输出本周期被采样省略的触发次数，并清零计数
+    pub fn summary(&self) {
+        for (index, log) in self.rules.iter().enumerate() {
+            let fired = log.fired.swap(0, Ordering::Relaxed);
+            let logged = log.logged.swap(0, Ordering::Relaxed);
+            if fired > logged {
+                info!(
+                    "rule {} triggered {} times, {} logged, {} sampled out",
+                    index,
+                    fired,
+                    logged,
+                    fired - logged
+                );
+            }
+        }
+    }
+}
@@ -1,19 +1,17 @@
-mod controller; // nftables 控制
-mod daemon;
-mod error;
-mod logger;
-mod monitor; // 流量监控
-mod nft;
-mod rules; // 规则引擎 // 日志记录
-mod tasks;
-
 use safe_traffic_common::config;
+use safe_traffic_daemon::{
+    capabilities::Capabilities, controller, host, journal, logger, nft, setup, simulate, standby,
+    tasks,
+};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use config::Config;
-use env_logger::Env;
-use log::info;
-use std::sync::Arc;
+use log::{info, warn};
+use std::{
+    future,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 #[derive(Parser)]
 #[command(author, version, about = "Safe Server Traffic 自动限流与封禁工具")]
@@ -21,23 +19,136 @@ struct Args {
     /// 配置文件路径
     #[arg(short, long, default_value = "/etc/safe-server-traffic/default.toml")]
     config: String,
+    /// 配置检查发现危险的组合时拒绝启动，而不只是输出警告
+    #[arg(long)]
+    strict: bool,
+    /// 不启动守护进程，只检查配置并执行其中的 [[rule_tests]]，有测试失败时返回错误
+    #[arg(long)]
+    check_config: bool,
+    /// 不启动守护进程，用配置中的规则离线重放记录的流量快照（record_stats 写入的 JSON 行），输出决策报告
+    #[arg(long, value_name = "STATS")]
+    replay: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// 配置文件辅助工具
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// 用合成流量场景模拟配置中的规则，输出决策序列并检查场景中的断言
+    Simulate {
+        /// 场景文件（TOML）
+        scenario: PathBuf,
+        /// 以 JSON 输出决策序列
+        #[arg(long)]
+        json: bool,
+    },
+    /// 用配置文件中的规则重放决策日志，列出记录中与重放中是否执行动作不一致的来源
+    Replay {
+        /// 决策日志文件
+        journal: PathBuf,
+        /// 以 JSON 输出重放结果
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// 输出描述 TOML 配置的 JSON Schema
+    Schema,
+    /// 根据网卡速率生成初始配置
+    Generate(setup::GenerateArgs),
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // 初始化日志（可通过环境变量 RUST_LOG 调节级别）
-    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    logger::init();
     // 解析命令行参数
     let args = Args::parse();
+    if let Some(Command::Config { command }) = args.command {
+        match command {
+            ConfigCommand::Schema => println!("{}", setup::schema()?),
+            ConfigCommand::Generate(generate) => setup::generate(generate)?,
+        }
+        return Ok(());
+    }
     info!("Loading configuration file: {}", &args.config);
     // 读取并验证配置
     let cfg = Config::from_file(&args.config)?;
-    let nft_available = crate::nft::check_nftables_available().await?;
+    lint_config(&cfg, args.strict).await?;
+    if args.check_config {
+        if args.command.is_some() || args.replay.is_some() {
+            anyhow::bail!("--check-config cannot be combined with --replay or a subcommand");
+        }
+        return check_config(&cfg).await;
+    }
+    if let Some(path) = &args.replay {
+        if args.command.is_some() {
+            anyhow::bail!("--replay cannot be combined with a subcommand");
+        }
+        return run_stats_replay(&cfg, path).await;
+    }
+    match args.command {
+        Some(Command::Simulate { scenario, json }) => {
+            return run_simulation(&cfg, &scenario, json).await
+        }
+        Some(Command::Replay { journal, json }) => return run_replay(&cfg, &journal, json).await,
+        _ => {}
+    }
+    if cfg.log_target.unwrap_or_default() == config::LogTarget::Journald {
+        match logger::enable_journald() {
+            Ok(()) => info!("Logging to the systemd journal"),
+            Err(e) => warn!("journald is unavailable, logging to stderr: {}", e),
+        }
+    }
+    let nft_available = nft::check_nftables_available().await?;
+    // 缺少能力的已配置特性逐条告警，而不是静默降级
+    let device = cfg
+        .devices
+        .as_ref()
+        .and_then(|devices| devices.first())
+        .unwrap_or(&cfg.interface);
+    Capabilities::probe(nft_available, device)
+        .await
+        .report(&cfg);
 
-    // 创建执行器池
-    let max_pool_size = cfg.executor_pool_size.unwrap_or(5);
-    let max_process_age = cfg.executor_max_age_secs.unwrap_or(300);
-    let max_commands_per_process = cfg.executor_max_commands.unwrap_or(100);
+    // 创建执行器池，未配置的参数根据运行环境自动推算
+    let tuning = if cfg.executor_pool_size.is_none()
+        || cfg.executor_max_age_secs.is_none()
+        || cfg.executor_max_commands.is_none()
+    {
+        let env = nft::tune::Environment::probe(nft_available).await;
+        let tuning = nft::PoolTuning::for_environment(&env);
+        info!(
+            "Probed environment: {} cpus, load {:.2}, nft latency {}",
+            env.cpus,
+            env.load,
+            env.nft_latency_ms
+                .map(|ms| format!("{}ms", ms))
+                .unwrap_or("unknown".to_string())
+        );
+        tuning
+    } else {
+        nft::PoolTuning::default()
+    };
+    let max_pool_size = cfg.executor_pool_size.unwrap_or(tuning.pool_size);
+    let max_process_age = cfg.executor_max_age_secs.unwrap_or(tuning.max_age_secs);
+    let max_commands_per_process = cfg.executor_max_commands.unwrap_or(tuning.max_commands);
+    info!(
+        "Executor pool: size {}{}, max age {}s{}, max commands {}{}",
+        max_pool_size,
+        auto_marker(cfg.executor_pool_size),
+        max_process_age,
+        auto_marker(cfg.executor_max_age_secs),
+        max_commands_per_process,
+        auto_marker(cfg.executor_max_commands)
+    );
 
     let executor = Arc::new(
         nft::NftExecutor::new(
@@ -49,17 +160,150 @@ async fn main() -> anyhow::Result<()> {
         .await,
     );
 
-    // 启动防火墙控制器
-    let fw = Arc::new(controller::Firewall::new(&cfg, Arc::clone(&executor)).await?);
-    // 启动流量监控与规则引擎
-    tasks::run(cfg, fw.clone(), executor.clone()).await?;
+    if cfg.standby.is_some() {
+        // 备节点接管时才创建表，退出前自行清理
+        standby::run(cfg, executor.clone()).await?;
+    } else {
+        // 启动防火墙控制器
+        let fw = Arc::new(controller::Firewall::new(&cfg, Arc::clone(&executor)).await?);
+        // 启动流量监控与规则引擎
+        tasks::run(&cfg, fw.clone(), executor.clone(), future::pending()).await?;
 
-    fw.cleanup().await?;
+        fw.cleanup().await?;
 
-    executor.input("delete table inet traffic_monitor").await?;
+        executor.input("delete table inet traffic_monitor").await?;
+    }
     // tokio::time::sleep(std::time::Duration::from_secs(2)).await;
     executor.cleanup().await?;
     drop(executor);
 
     Ok(())
 }
+
+/// 检查配置中危险的组合，逐条输出警告；strict 时有警告即返回错误
+async fn lint_config(cfg: &Config, strict: bool) -> anyhow::Result<()> {
+    let interfaces = cfg
+        .devices
+        .clone()
+        .unwrap_or_else(|| vec![cfg.interface.clone()]);
+    // 读不到网卡地址时（如在 CI 中）跳过与网卡地址相关的检查
+    let local_addrs = match rtnetlink::new_connection() {
+        Ok((connection, handle, _messages)) => {
+            tokio::spawn(connection);
+            host::interface_addresses(&handle, &interfaces)
+                .await
+                .unwrap_or_else(|e| {
+                    warn!("Failed to read interface addresses for config lint: {}", e);
+                    Vec::new()
+                })
+        }
+        Err(e) => {
+            warn!("Failed to open netlink for config lint: {}", e);
+            Vec::new()
+        }
+    };
+    let warnings = cfg.lint(&local_addrs);
+    for warning in &warnings {
+        warn!("Config lint: {}", warning);
+    }
+    if strict && !warnings.is_empty() {
+        anyhow::bail!("{} config lint warnings in --strict mode", warnings.len());
+    }
+    Ok(())
+}
+
+/// 执行配置中的规则测试，逐个输出结果，有测试失败时返回错误
+async fn check_config(cfg: &Config) -> anyhow::Result<()> {
+    let results = simulate::run_rule_tests(cfg).await?;
+    for result in &results {
+        if result.passed() {
+            println!("rule test {}: ok", result.name);
+        } else {
+            println!("rule test {}: FAILED", result.name);
+            for failure in &result.failures {
+                println!("  - {}", failure);
+            }
+        }
+    }
+    let failed = results.iter().filter(|result| !result.passed()).count();
+    if failed > 0 {
+        anyhow::bail!("{} of {} rule tests failed", failed, results.len());
+    }
+    eprintln!("configuration ok, {} rule tests passed", results.len());
+    Ok(())
+}
+
+/// 输出模拟的决策序列，有断言未满足时返回错误
+async fn run_simulation(cfg: &Config, path: &Path, json: bool) -> anyhow::Result<()> {
+    let scenario = simulate::Scenario::from_file(path)?;
+    let decisions = simulate::simulate(cfg, &scenario).await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&decisions)?);
+    } else {
+        for decision in &decisions {
+            println!("{}", decision);
+        }
+    }
+
+    let failures = scenario.failures(&decisions);
+    for failure in &failures {
+        eprintln!("FAILED: {}", failure);
+    }
+    if !failures.is_empty() {
+        anyhow::bail!(
+            "{} of {} expectations failed",
+            failures.len(),
+            scenario.expect.len()
+        );
+    }
+    if !scenario.expect.is_empty() {
+        eprintln!("all {} expectations passed", scenario.expect.len());
+    }
+    Ok(())
+}
+
+/// 输出重放的决策序列与不一致的来源
+async fn run_replay(cfg: &Config, path: &Path, json: bool) -> anyhow::Result<()> {
+    let records = journal::read_journal(path)?;
+    let replay = simulate::replay(cfg, &records).await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&replay)?);
+        return Ok(());
+    }
+    for decision in &replay.decisions {
+        println!("{}", decision);
+    }
+    if replay.changes.is_empty() {
+        eprintln!(
+            "replayed {} records, no source changed between acted and not acted",
+            records.len()
+        );
+    } else {
+        eprintln!("{} sources changed:", replay.changes.len());
+        for change in &replay.changes {
+            eprintln!("  {}", change);
+        }
+    }
+    Ok(())
+}
+
+/// 输出流量快照重放的决策序列与汇总
+async fn run_stats_replay(cfg: &Config, path: &Path) -> anyhow::Result<()> {
+    let snapshots = simulate::read_stats(path)?;
+    let started = std::time::Instant::now();
+    let replay = simulate::replay_stats(cfg, &snapshots).await?;
+    for decision in &replay.decisions {
+        println!("{}", decision);
+    }
+    eprintln!("{} in {:.1?}", replay, started.elapsed());
+    Ok(())
+}
+
+/// 未在配置中指定的参数在日志中标注为自动推算
+fn auto_marker<T>(configured: Option<T>) -> &'static str {
+    if configured.is_some() {
+        ""
+    } else {
+        " (auto)"
+    }
+}
@@ -1,13 +1,27 @@
-use crate::nft::{parser::*, NftError, NftExecutor};
+use crate::{
+    events::EventStore,
+    flows::FlowTable,
+    neighbors::NeighborTable,
+    nft::{parser::*, NftError, NftExecutor},
+    rules::WindowStore,
+    sketch::SourceSketches,
+};
 use dashmap::DashMap;
 use futures::stream::TryStreamExt;
-use log::{debug, error, warn};
+use log::{debug, error, info, warn};
 use rtnetlink::Handle;
-use safe_traffic_common::utils::TrafficStats;
+use safe_traffic_common::{
+    clock::{Clock, SystemClock},
+    events::{Event, EventKind},
+    utils::TrafficStats,
+};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
     time::{Duration, Instant},
 };
 use tokio::time;
@@ -21,6 +35,9 @@ pub struct IpTrafficStats {
     pub tx_bytes: u64,
     pub rx_packets: u64,
     pub tx_packets: u64,
+    /// 新建连接报文的字节数
+    pub rx_new_bytes: u64,
+    pub tx_new_bytes: u64,
     #[allow(dead_code)]
     pub last_updated: Instant,
 }
@@ -33,6 +50,26 @@ pub struct TrafficMonitor {
     stats: Arc<DashMap<IpAddr, TrafficStats>>,
     update_interval: Duration,
     executor: Arc<NftExecutor>,
+    /// 需要按 MAC 执行动作时维护的邻居表
+    neighbors: Option<Arc<NeighborTable>>,
+    /// 配置了 [flows] 时每个周期刷新的流表
+    flows: Option<Arc<FlowTable>>,
+    /// 按目的地址计数的本机地址及其流量统计
+    destinations: Vec<IpAddr>,
+    dest_stats: Option<Arc<DashMap<IpAddr, TrafficStats>>>,
+    /// 同时跟踪的 IP 数上限
+    max_tracked_ips: Option<usize>,
+    /// 达到跟踪上限时记录事件
+    events: Option<Arc<EventStore>>,
+    /// 本周期新建计数规则的 IP，尚无完整采样，不参与丢弃比较
+    probation: Mutex<HashSet<IpAddr>>,
+    /// 是否处于跟踪上限，只在状态变化时记录
+    capped: AtomicBool,
+    /// 规则引擎读取的滑动窗口，每次采样后按采样时间推进
+    windows: Option<Arc<WindowStore>>,
+    /// 按来源数触发的规则所需的草图，每次读取流表后写入
+    sketches: Option<Arc<SourceSketches>>,
+    clock: Arc<dyn Clock>,
 }
 
 impl TrafficMonitor {
@@ -49,12 +86,67 @@ impl TrafficMonitor {
             stats,
             update_interval,
             executor,
+            neighbors: None,
+            flows: None,
+            destinations: Vec::new(),
+            dest_stats: None,
+            max_tracked_ips: None,
+            events: None,
+            probation: Mutex::new(HashSet::new()),
+            capped: AtomicBool::new(false),
+            windows: None,
+            sketches: None,
+            clock: Arc::new(SystemClock),
         }
     }
 
+    /// 采样后推进规则引擎的窗口，采样时间取自与引擎相同的时钟
+    pub fn with_windows(mut self, windows: Arc<WindowStore>, clock: Arc<dyn Clock>) -> Self {
+        self.windows = Some(windows);
+        self.clock = clock;
+        self
+    }
+
+    /// 限制同时跟踪的 IP 数，超过后丢弃流量最小的来源，并在事件中记录
+    pub fn with_max_tracked_ips(mut self, max: usize, events: Arc<EventStore>) -> Self {
+        self.max_tracked_ips = Some(max.max(1));
+        self.events = Some(events);
+        self
+    }
+
+    /// 每个周期同步内核邻居表，将 IP 关联到 MAC 地址
+    pub fn with_neighbors(mut self, neighbors: Arc<NeighborTable>) -> Self {
+        self.neighbors = Some(neighbors);
+        self
+    }
+
+    /// 每个周期从连接跟踪刷新流表
+    pub fn with_flows(mut self, flows: Arc<FlowTable>) -> Self {
+        self.flows = Some(flows);
+        self
+    }
+
+    /// 读取流表时记录各目的地址的不同来源数，需要同时设置 with_flows
+    pub fn with_source_sketches(mut self, sketches: Arc<SourceSketches>) -> Self {
+        self.sketches = Some(sketches);
+        self
+    }
+
+    /// 统计发往这些本机地址的入站流量，供按目的地址触发的规则使用
+    pub fn with_destinations(
+        mut self,
+        destinations: Vec<IpAddr>,
+        dest_stats: Arc<DashMap<IpAddr, TrafficStats>>,
+    ) -> Self {
+        self.destinations = destinations;
+        self.dest_stats = Some(dest_stats);
+        self
+    }
+
     /// 启动流量监控
     pub async fn start(&self) -> anyhow::Result<()> {
         self.setup_nft_table_structure().await?;
+        self.setup_destination_counters().await?;
         let mut interval = time::interval(self.update_interval);
 
         loop {
@@ -65,6 +157,30 @@ impl TrafficMonitor {
                 continue;
             }
 
+            if let Err(e) = self.update_destination_stats().await {
+                warn!("更新目的地址流量统计失败: {}", e);
+            }
+
+            if let Some(neighbors) = &self.neighbors {
+                if let Err(e) = neighbors.refresh(&self.handle).await {
+                    warn!("刷新邻居表失败: {}", e);
+                }
+            }
+
+            if let Some(flows) = &self.flows {
+                let at = self.clock.monotonic();
+                let refreshed = flows
+                    .refresh_observed(|all| {
+                        if let Some(sketches) = &self.sketches {
+                            sketches.observe(all, at);
+                        }
+                    })
+                    .await;
+                if let Err(e) = refreshed {
+                    warn!("刷新流表失败: {}", e);
+                }
+            }
+
             // 清理过期的流量统计
             self.cleanup_expired_stats().await;
         }
@@ -74,9 +190,114 @@ impl TrafficMonitor {
     async fn update_traffic_stats_per_ip(&self) -> anyhow::Result<()> {
         let ip_stats = self.get_traffic_via_nftables_json().await?;
         self.update_stats_from_ip_data(ip_stats).await?;
+        self.shed_lightest().await?;
+        Ok(())
+    }
+
+    /// 跟踪的 IP 超过上限时，丢弃流量最小的来源并删除其计数规则，再次出现时从零开始计数
+    async fn shed_lightest(&self) -> anyhow::Result<()> {
+        let Some(max) = self.max_tracked_ips else {
+            return Ok(());
+        };
+        let probation = std::mem::take(&mut *self.probation.lock().unwrap());
+        let over = self.stats.len().saturating_sub(max);
+        if over == 0 {
+            if self.capped.swap(false, Ordering::Relaxed) {
+                info!(
+                    "tracking {} IPs, below max_tracked_ips ({}) again",
+                    self.stats.len(),
+                    max
+                );
+            }
+            return Ok(());
+        }
+
+        // 按流量升序、地址升序排列，结果与遍历顺序无关
+        let mut candidates: Vec<(u64, IpAddr)> = self
+            .stats
+            .iter()
+            .filter(|entry| !probation.contains(entry.key()))
+            .map(|entry| {
+                (
+                    entry.value().rx_delta + entry.value().tx_delta,
+                    *entry.key(),
+                )
+            })
+            .collect();
+        candidates.sort();
+        let shed: HashSet<IpAddr> = candidates
+            .into_iter()
+            .take(over)
+            .map(|(_, ip)| ip)
+            .collect();
+        for ip in &shed {
+            self.stats.remove(ip);
+        }
+        self.remove_counter_rules(&shed).await?;
+
+        debug!("shed {} lightest IPs from traffic monitor", shed.len());
+        if !self.capped.swap(true, Ordering::Relaxed) {
+            let message = format!(
+                "traffic monitor reached max_tracked_ips ({}), shedding the lightest sources",
+                max
+            );
+            warn!("{}", message);
+            if let Some(events) = &self.events {
+                events.push(Event::new(EventKind::Capacity, message)).await;
+            }
+        }
         Ok(())
     }
 
+    /// 删除指定 IP 的计数规则
+    async fn remove_counter_rules(&self, ips: &HashSet<IpAddr>) -> anyhow::Result<()> {
+        let mut commands = Vec::new();
+        for (chain, direction) in [("input_stats", "input"), ("output_stats", "output")] {
+            let output = self
+                .executor
+                .execute(&format!("list chain inet traffic_monitor {}", chain))
+                .await?;
+            let nft_data: NftJsonOutput = serde_json::from_str(&output)
+                .map_err(|e| anyhow::anyhow!("解析 NFT JSON 失败: {}", e))?;
+            for obj in nft_data.nftables {
+                let NftObject::Rule(rule_obj) = obj else {
+                    continue;
+                };
+                let ip = rule_obj
+                    .rule
+                    .expr
+                    .iter()
+                    .flatten()
+                    .find_map(|expr| match expr {
+                        Expression::Match(match_expr) => {
+                            self.extract_ip_from_match(match_expr, direction)
+                        }
+                        _ => None,
+                    });
+                if let (Some(ip), Some(handle)) = (ip, rule_obj.rule.get_handle().await) {
+                    if ips.contains(&ip) {
+                        commands.push(format!(
+                            "delete rule inet traffic_monitor {} handle {}",
+                            chain, handle
+                        ));
+                    }
+                }
+            }
+        }
+        if !commands.is_empty() {
+            self.executor.execute_batch(commands).await?;
+        }
+        Ok(())
+    }
+
+    /// 本周期可以新建计数规则的 IP 数；达到上限后仍保留少量名额，让新的大流量来源替换较小的来源
+    fn new_ip_budget(&self) -> usize {
+        match self.max_tracked_ips {
+            Some(max) => max.saturating_sub(self.stats.len()).max((max / 10).max(1)),
+            None => usize::MAX,
+        }
+    }
+
     /// 通过 nftables JSON 格式获取流量统计
     async fn get_traffic_via_nftables_json(
         &self,
@@ -117,11 +338,18 @@ impl TrafficMonitor {
                     // 查找匹配的IP地址和对应的计数器
                     let mut ip_addr: Option<IpAddr> = None;
                     let mut counter_info: Option<(u64, u64)> = None;
+                    let mut new_flow = false;
 
                     for expr in expr_list {
                         match expr {
                             Expression::Match(match_expr) => {
-                                ip_addr = self.extract_ip_from_match(match_expr, direction);
+                                if is_ct_new_match(match_expr) {
+                                    new_flow = true;
+                                } else if let Some(ip) =
+                                    self.extract_ip_from_match(match_expr, direction)
+                                {
+                                    ip_addr = Some(ip);
+                                }
                             }
                             Expression::Counter(counter_expr) => {
                                 counter_info = Some((
@@ -141,10 +369,19 @@ impl TrafficMonitor {
                             tx_bytes: 0,
                             rx_packets: 0,
                             tx_packets: 0,
+                            rx_new_bytes: 0,
+                            tx_new_bytes: 0,
                             last_updated: Instant::now(),
                         });
 
-                        if direction == "input" {
+                        // 新建连接计数规则不终止匹配，其流量同时计入总量规则
+                        if new_flow {
+                            if direction == "input" {
+                                entry.rx_new_bytes += bytes;
+                            } else {
+                                entry.tx_new_bytes += bytes;
+                            }
+                        } else if direction == "input" {
                             entry.rx_bytes += bytes;
                             entry.rx_packets += packets;
                         } else {
@@ -199,9 +436,19 @@ impl TrafficMonitor {
 
         let active_ips = self.get_active_ips().await?;
 
+        let mut budget = self.new_ip_budget();
+        let mut probation = HashSet::new();
         for ip in active_ips {
+            if !self.stats.contains_key(&ip) {
+                if budget == 0 {
+                    continue;
+                }
+                budget -= 1;
+                probation.insert(ip);
+            }
             self.ensure_ip_counter_rules(&ip.to_string()).await?;
         }
+        *self.probation.lock().unwrap() = probation;
 
         Ok(())
     }
@@ -227,6 +474,57 @@ impl TrafficMonitor {
         Ok(())
     }
 
+    /// 在独立的 dest_stats 链中为每个目的地址添加计数规则，不影响按来源计数的链
+    async fn setup_destination_counters(&self) -> anyhow::Result<()> {
+        if self.destinations.is_empty() {
+            return Ok(());
+        }
+        let mut commands = vec![
+            "add chain inet traffic_monitor dest_stats { type filter hook input priority -101; policy accept; }".to_string(),
+            "flush chain inet traffic_monitor dest_stats".to_string(),
+        ];
+        for dest in &self.destinations {
+            let ip_family = match dest {
+                IpAddr::V4(_) => "ip",
+                IpAddr::V6(_) => "ip6",
+            };
+            commands.push(format!(
+                "add rule inet traffic_monitor dest_stats {} daddr {} counter",
+                ip_family, dest
+            ));
+        }
+        self.executor.execute_batch(commands).await?;
+        info!("统计发往 {} 个目的地址的流量", self.destinations.len());
+        Ok(())
+    }
+
+    /// 读取目的地址计数，换算为每秒的入站流量
+    async fn update_destination_stats(&self) -> anyhow::Result<()> {
+        let Some(dest_stats) = &self.dest_stats else {
+            return Ok(());
+        };
+        let output = self
+            .executor
+            .execute("list chain inet traffic_monitor dest_stats")
+            .await?;
+        // 按 daddr 解析，计数落在 tx 字段中
+        let mut counters = HashMap::new();
+        self.parse_nft_json_output(&output, &mut counters, "output")
+            .await?;
+        let at = self.clock.monotonic();
+        for (dest, counter) in counters {
+            let mut stats = dest_stats.entry(dest).or_default();
+            let rx_delta = counter.tx_bytes.saturating_sub(stats.rx_bytes);
+            stats.rx_bytes = counter.tx_bytes;
+            stats.rx_delta = rx_delta / self.update_interval.as_secs();
+            stats.last_updated = Instant::now();
+            if let Some(windows) = &self.windows {
+                windows.record_destination(dest, stats.rx_delta, at);
+            }
+        }
+        Ok(())
+    }
+
     /// 为特定IP确保计数器规则存在
     async fn ensure_ip_counter_rules(&self, ip: &str) -> anyhow::Result<()> {
         let ip_family = identify_ip(ip).await?;
@@ -235,6 +533,18 @@ impl TrafficMonitor {
         let existing_rules = self.executor.execute(check_cmd).await.unwrap_or_default();
 
         if !existing_rules.contains(&format!("\"{}\"", ip)) {
+            // 新建连接计数规则需位于终止的总量规则之前
+            let input_new_rule = format!(
+                "add rule inet traffic_monitor input_stats {} saddr {} ct state new counter",
+                ip_family, ip
+            );
+            let _ = self.executor.execute(&input_new_rule).await;
+            let output_new_rule = format!(
+                "add rule inet traffic_monitor output_stats {} daddr {} ct state new counter",
+                ip_family, ip
+            );
+            let _ = self.executor.execute(&output_new_rule).await;
+
             // 添加输入流量计数规则
             let input_rule = format!(
                 "add rule inet traffic_monitor input_stats {} saddr {} counter accept",
@@ -410,6 +720,8 @@ impl TrafficMonitor {
         &self,
         ip_stats: HashMap<IpAddr, IpTrafficStats>,
     ) -> anyhow::Result<()> {
+        // 同一次读取的采样共用一个时间
+        let at = self.clock.monotonic();
         for (ip, new_stats) in ip_stats {
             let mut stats = self.stats.entry(ip).or_default();
 
@@ -417,13 +729,22 @@ impl TrafficMonitor {
             let rx_delta = new_stats.rx_bytes.saturating_sub(stats.rx_bytes);
 
             let tx_delta = new_stats.tx_bytes.saturating_sub(stats.tx_bytes);
+            let rx_new_delta = new_stats.rx_new_bytes.saturating_sub(stats.rx_new_bytes);
+            let tx_new_delta = new_stats.tx_new_bytes.saturating_sub(stats.tx_new_bytes);
 
             // 更新统计
             stats.rx_bytes = new_stats.rx_bytes;
             stats.tx_bytes = new_stats.tx_bytes;
             stats.rx_delta = rx_delta / self.update_interval.as_secs();
             stats.tx_delta = tx_delta / self.update_interval.as_secs();
+            stats.rx_new_bytes = new_stats.rx_new_bytes;
+            stats.tx_new_bytes = new_stats.tx_new_bytes;
+            stats.rx_new_delta = rx_new_delta / self.update_interval.as_secs();
+            stats.tx_new_delta = tx_new_delta / self.update_interval.as_secs();
             stats.last_updated = Instant::now();
+            if let Some(windows) = &self.windows {
+                windows.record(ip, &stats, at);
+            }
 
             if rx_delta > 0 || tx_delta > 0 {
                 debug!(
@@ -443,6 +764,17 @@ impl TrafficMonitor {
 
         self.stats
             .retain(|_ip, stats| now.duration_since(stats.last_updated) < expire_duration);
+        if let Some(dest_stats) = &self.dest_stats {
+            dest_stats
+                .retain(|_dest, stats| now.duration_since(stats.last_updated) < expire_duration);
+        }
+        if let Some(windows) = &self.windows {
+            windows.retain(|ip| self.stats.contains_key(ip));
+            if let Some(dest_stats) = &self.dest_stats {
+                windows.retain_destinations(|dest| dest_stats.contains_key(dest));
+            }
+            windows.prune_history(self.clock.monotonic());
+        }
     }
 
     /// 清理 nftables 规则
@@ -460,6 +792,23 @@ impl TrafficMonitor {
     }
 }
 
+/// 是否为 `ct state new` 匹配
+fn is_ct_new_match(match_expr: &MatchExpr) -> bool {
+    let match_obj = &match_expr.r#match;
+    let is_ct_state = match_obj
+        .left
+        .get("ct")
+        .and_then(|ct| ct.get("key"))
+        .and_then(|key| key.as_str())
+        == Some("state");
+    let is_new = match &match_obj.right {
+        serde_json::Value::String(state) => state == "new",
+        serde_json::Value::Array(states) => states.iter().any(|state| state == "new"),
+        _ => false,
+    };
+    is_ct_state && is_new
+}
+
 async fn identify_ip(ip_str: &str) -> anyhow::Result<&str> {
     match ip_str.parse::<IpAddr>() {
         Ok(IpAddr::V4(_)) => Ok("ip"),
@@ -0,0 +1,79 @@
+use dashmap::DashMap;
+use futures::stream::TryStreamExt;
+use log::debug;
+use netlink_packet_route::neighbour::{NeighbourAddress, NeighbourAttribute};
+use rtnetlink::Handle;
+use std::{collections::HashMap, net::IpAddr};
+
+/// IP 到 MAC 地址的映射，来自内核邻居表（ARP / NDP）
+#[derive(Debug, Default)]
+pub struct NeighborTable {
+    entries: DashMap<IpAddr, String>,
+}
+
+impl NeighborTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 查询 IP 当前对应的 MAC 地址
+    pub fn lookup(&self, ip: &IpAddr) -> Option<String> {
+        self.entries.get(ip).map(|mac| mac.clone())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 从内核邻居表重新加载映射，已从邻居表中消失的条目一并移除
+    pub async fn refresh(&self, handle: &Handle) -> anyhow::Result<()> {
+        let mut fresh = HashMap::new();
+        let mut neighbours = handle.neighbours().get().execute();
+
+        while let Some(msg) = neighbours.try_next().await? {
+            let mut ip = None;
+            let mut mac = None;
+            for attr in &msg.attributes {
+                match attr {
+                    NeighbourAttribute::Destination(NeighbourAddress::Inet(v4)) => {
+                        ip = Some(IpAddr::V4(*v4));
+                    }
+                    NeighbourAttribute::Destination(NeighbourAddress::Inet6(v6)) => {
+                        ip = Some(IpAddr::V6(*v6));
+                    }
+                    NeighbourAttribute::LinkLocalAddress(bytes) if bytes.len() == 6 => {
+                        mac = Some(format_mac(bytes));
+                    }
+                    _ => {}
+                }
+            }
+
+            if let (Some(ip), Some(mac)) = (ip, mac) {
+                fresh.insert(ip, mac);
+            }
+        }
+
+        self.entries.retain(|ip, _| fresh.contains_key(ip));
+        for (ip, mac) in fresh {
+            if let Some(old) = self.entries.insert(ip, mac.clone()) {
+                if old != mac {
+                    debug!("neighbor {} moved from {} to {}", ip, old, mac);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn format_mac(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
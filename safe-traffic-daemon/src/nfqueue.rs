@@ -0,0 +1,500 @@
+use anyhow::{Context, Result};
+use dashmap::DashMap;
+use log::{info, warn};
+use netlink_sys::{protocols::NETLINK_NETFILTER, Socket, SocketAddr};
+use safe_traffic_common::{
+    config::{HookType, NfqueueConfig},
+    transport::Inspection,
+};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, HashMap, VecDeque},
+    io::ErrorKind,
+    net::IpAddr,
+};
+
+pub const DEFAULT_QUEUE_NUM: u16 = 0;
+const DEFAULT_MAX_LEN: u32 = 1024;
+const DEFAULT_MAX_IPS: usize = 16;
+const DEFAULT_SAMPLE_SIZE: usize = 200;
+const DEFAULT_UNIFORM_RATIO: f64 = 0.9;
+/// 复制到用户态的报文长度，足以覆盖常见的 TLS ClientHello
+const COPY_RANGE: u32 = 4096;
+/// 每个 IP 保留的 SNI 数量上限
+const MAX_SNI: usize = 32;
+const RECV_BUFFER: usize = 64 * 1024;
+
+// linux/netfilter/nfnetlink_queue.h
+const NFNL_SUBSYS_QUEUE: u16 = 3;
+const NFQNL_MSG_PACKET: u16 = 0;
+const NFQNL_MSG_VERDICT: u16 = 1;
+const NFQNL_MSG_CONFIG: u16 = 2;
+const NFQA_PACKET_HDR: u16 = 1;
+const NFQA_VERDICT_HDR: u16 = 2;
+const NFQA_PAYLOAD: u16 = 10;
+const NFQA_CFG_CMD: u16 = 1;
+const NFQA_CFG_PARAMS: u16 = 2;
+const NFQA_CFG_QUEUE_MAXLEN: u16 = 3;
+const NFQA_CFG_MASK: u16 = 4;
+const NFQA_CFG_FLAGS: u16 = 5;
+const NFQNL_CFG_CMD_BIND: u8 = 1;
+const NFQNL_COPY_PACKET: u8 = 2;
+const NFQA_CFG_F_FAIL_OPEN: u32 = 1;
+const NF_DROP: u32 = 0;
+const NF_ACCEPT: u32 = 1;
+// linux/netlink.h
+const NLMSG_HDRLEN: usize = 16;
+const NLMSG_ERROR: u16 = 2;
+const NLM_F_REQUEST: u16 = 1;
+const NLM_F_ACK: u16 = 4;
+const NLA_TYPE_MASK: u16 = 0x3fff;
+
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+
+/// 单个 IP 的报文画像
+#[derive(Debug, Default)]
+struct Profile {
+    /// 最近报文的负载长度
+    sizes: VecDeque<u16>,
+    counts: HashMap<u16, usize>,
+    packets: u64,
+    dropped: u64,
+    dropping: bool,
+    sni: BTreeSet<String>,
+}
+
+impl Profile {
+    /// 记录一个报文的负载长度，只保留最近 sample_size 个
+    fn observe(&mut self, size: u16, sample_size: usize) {
+        self.sizes.push_back(size);
+        *self.counts.entry(size).or_default() += 1;
+        if self.sizes.len() > sample_size {
+            if let Some(old) = self.sizes.pop_front() {
+                if let Some(count) = self.counts.get_mut(&old) {
+                    *count -= 1;
+                    if *count == 0 {
+                        self.counts.remove(&old);
+                    }
+                }
+            }
+        }
+    }
+
+    /// 最常见的负载长度及其占比
+    fn dominant(&self) -> Option<(u16, f64)> {
+        let (size, count) = self
+            .counts
+            .iter()
+            .max_by_key(|(size, count)| (**count, Reverse(**size)))?;
+        Some((*size, *count as f64 / self.sizes.len() as f64))
+    }
+}
+
+/// NFQUEUE 逐包判定：被 Inspect 动作送入队列的报文按负载长度分布判定，并记录 TLS SNI
+///
+/// 队列规则带 `bypass`，守护进程未监听时报文直接放行；队列积压超过 max_len 时内核按 fail-open 放行。
+#[derive(Debug)]
+pub struct Inspector {
+    queue_num: u16,
+    max_len: u32,
+    max_ips: usize,
+    sample_size: usize,
+    uniform_ratio: f64,
+    extract_sni: bool,
+    hook: HookType,
+    profiles: DashMap<IpAddr, Profile>,
+}
+
+impl Inspector {
+    pub fn new(cfg: &NfqueueConfig, hook: HookType) -> Self {
+        Inspector {
+            queue_num: cfg.queue_num.unwrap_or(DEFAULT_QUEUE_NUM),
+            max_len: cfg.max_len.unwrap_or(DEFAULT_MAX_LEN),
+            max_ips: cfg.max_ips.unwrap_or(DEFAULT_MAX_IPS),
+            sample_size: cfg.sample_size.unwrap_or(DEFAULT_SAMPLE_SIZE).max(1),
+            uniform_ratio: cfg.uniform_ratio.unwrap_or(DEFAULT_UNIFORM_RATIO),
+            extract_sni: cfg.extract_sni.unwrap_or(true),
+            hook,
+            profiles: DashMap::new(),
+        }
+    }
+
+    pub fn queue_num(&self) -> u16 {
+        self.queue_num
+    }
+
+    /// 同时检查的 IP 数上限
+    pub fn max_ips(&self) -> usize {
+        self.max_ips
+    }
+
+    /// 开始检查 IP，同时丢弃不在 active 中的 IP 的画像
+    pub fn watch(&self, ip: IpAddr, active: &[IpAddr]) {
+        self.profiles.retain(|ip, _| active.contains(ip));
+        self.profiles.insert(ip, Profile::default());
+    }
+
+    /// 该 IP 在检查中请求过的 TLS SNI
+    pub fn sni(&self, ip: &IpAddr) -> Vec<String> {
+        self.profiles
+            .get(ip)
+            .map(|profile| profile.sni.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// 单个 IP 的检查统计，未在检查中时返回 None
+    pub fn inspection(&self, ip: &IpAddr) -> Option<Inspection> {
+        self.profiles
+            .get(ip)
+            .map(|profile| summarize(*ip, &profile))
+    }
+
+    /// 各 IP 的检查统计
+    pub fn inspections(&self) -> Vec<Inspection> {
+        let mut inspections: Vec<Inspection> = self
+            .profiles
+            .iter()
+            .map(|entry| summarize(*entry.key(), entry.value()))
+            .collect();
+        inspections.sort_by_key(|inspection| Reverse(inspection.packets));
+        inspections
+    }
+
+    /// 绑定队列并持续判定报文，阻塞运行，应放在独立线程中
+    pub fn run(&self) -> Result<()> {
+        let mut socket =
+            Socket::new(NETLINK_NETFILTER).context("failed to open netfilter netlink socket")?;
+        socket.bind_auto()?;
+        // 接收缓冲溢出时不报错，积压的报文由内核按 fail-open 放行
+        socket.set_no_enobufs(true)?;
+        let kernel = SocketAddr::new(0, 0);
+
+        let mut seq = 0u32;
+        let mut cmd = vec![NFQNL_CFG_CMD_BIND, 0];
+        cmd.extend_from_slice(&0u16.to_be_bytes());
+        let mut params = COPY_RANGE.to_be_bytes().to_vec();
+        params.push(NFQNL_COPY_PACKET);
+        let config = Message::new(NFQNL_MSG_CONFIG, NLM_F_ACK, seq, self.queue_num)
+            .attr(NFQA_CFG_CMD, &cmd)
+            .attr(NFQA_CFG_PARAMS, &params)
+            .attr(NFQA_CFG_QUEUE_MAXLEN, &self.max_len.to_be_bytes())
+            .attr(NFQA_CFG_MASK, &NFQA_CFG_F_FAIL_OPEN.to_be_bytes())
+            .attr(NFQA_CFG_FLAGS, &NFQA_CFG_F_FAIL_OPEN.to_be_bytes())
+            .finish();
+        socket.send_to(&config, &kernel, 0)?;
+        info!("Inspecting packets queued to NFQUEUE {}", self.queue_num);
+
+        let mut buf = Vec::with_capacity(RECV_BUFFER);
+        loop {
+            buf.clear();
+            match socket.recv(&mut buf, 0) {
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e).context("failed to receive queued packets"),
+            }
+
+            for (kind, payload) in messages(&buf) {
+                if kind == NLMSG_ERROR {
+                    // 应答中的错误码为负的 errno，0 表示确认
+                    let code = payload
+                        .get(..4)
+                        .map(|code| i32::from_ne_bytes([code[0], code[1], code[2], code[3]]))
+                        .unwrap_or(0);
+                    if code != 0 {
+                        return Err(std::io::Error::from_raw_os_error(-code))
+                            .with_context(|| format!("failed to bind NFQUEUE {}", self.queue_num));
+                    }
+                    continue;
+                }
+                if kind != (NFNL_SUBSYS_QUEUE << 8) | NFQNL_MSG_PACKET {
+                    continue;
+                }
+
+                let Some((id, packet)) = queued_packet(payload) else {
+                    continue;
+                };
+                let verdict = if self.verdict(packet) {
+                    NF_ACCEPT
+                } else {
+                    NF_DROP
+                };
+                seq = seq.wrapping_add(1);
+                let mut header = verdict.to_be_bytes().to_vec();
+                header.extend_from_slice(&id.to_be_bytes());
+                let message = Message::new(NFQNL_MSG_VERDICT, 0, seq, self.queue_num)
+                    .attr(NFQA_VERDICT_HDR, &header)
+                    .finish();
+                socket.send_to(&message, &kernel, 0)?;
+            }
+        }
+    }
+
+    /// 判定一个报文，返回是否放行
+    fn verdict(&self, data: &[u8]) -> bool {
+        let Some(packet) = parse_packet(data) else {
+            return true;
+        };
+        let ip = match self.hook {
+            HookType::Input => packet.src,
+            HookType::Output => packet.dst,
+        };
+        // 检查已结束但规则尚未删除的 IP 直接放行
+        let Some(mut profile) = self.profiles.get_mut(&ip) else {
+            return true;
+        };
+        profile.packets += 1;
+
+        if self.extract_sni && packet.protocol == IPPROTO_TCP && profile.sni.len() < MAX_SNI {
+            if let Some(sni) = client_hello_sni(packet.payload) {
+                if profile.sni.insert(sni.clone()) {
+                    info!("{} requested TLS server name {}", ip, sni);
+                }
+            }
+        }
+
+        // 纯 ACK 等无负载的报文不参与判断
+        if packet.payload_len == 0 {
+            return true;
+        }
+        let size = packet.payload_len.min(u16::MAX as usize) as u16;
+        profile.observe(size, self.sample_size);
+
+        let dominant = profile.dominant();
+        let dropping = profile.sizes.len() >= self.sample_size
+            && dominant.is_some_and(|(_, share)| share >= self.uniform_ratio);
+        if dropping != profile.dropping {
+            profile.dropping = dropping;
+            match dominant {
+                Some((size, share)) if dropping => warn!(
+                    "{}: {:.0}% of recent packets carry {} byte payloads, dropping them",
+                    ip,
+                    share * 100.0,
+                    size
+                ),
+                _ => info!("{}: payload sizes vary again, accepting all packets", ip),
+            }
+        }
+
+        if dropping && dominant.is_some_and(|(dominant, _)| dominant == size) {
+            profile.dropped += 1;
+            return false;
+        }
+        true
+    }
+}
+
+fn summarize(ip: IpAddr, profile: &Profile) -> Inspection {
+    Inspection {
+        ip,
+        packets: profile.packets,
+        dropped: profile.dropped,
+        dominant_size: profile.dominant(),
+        dropping: profile.dropping,
+        sni: profile.sni.iter().cloned().collect(),
+    }
+}
+
+/// nfnetlink 请求消息
+struct Message {
+    buf: Vec<u8>,
+}
+
+impl Message {
+    fn new(msg: u16, flags: u16, seq: u32, queue_num: u16) -> Self {
+        let mut buf = Vec::with_capacity(64);
+        // nlmsghdr，长度在 finish 时回填
+        buf.extend_from_slice(&0u32.to_ne_bytes());
+        buf.extend_from_slice(&((NFNL_SUBSYS_QUEUE << 8) | msg).to_ne_bytes());
+        buf.extend_from_slice(&(NLM_F_REQUEST | flags).to_ne_bytes());
+        buf.extend_from_slice(&seq.to_ne_bytes());
+        buf.extend_from_slice(&0u32.to_ne_bytes());
+        // nfgenmsg：AF_UNSPEC、NFNETLINK_V0、队列号
+        buf.extend_from_slice(&[0, 0]);
+        buf.extend_from_slice(&queue_num.to_be_bytes());
+        Message { buf }
+    }
+
+    fn attr(mut self, kind: u16, data: &[u8]) -> Self {
+        self.buf
+            .extend_from_slice(&((4 + data.len()) as u16).to_ne_bytes());
+        self.buf.extend_from_slice(&kind.to_ne_bytes());
+        self.buf.extend_from_slice(data);
+        self.buf.resize(align(self.buf.len()), 0);
+        self
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let len = (self.buf.len() as u32).to_ne_bytes();
+        self.buf[..4].copy_from_slice(&len);
+        self.buf
+    }
+}
+
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// 拆分接收缓冲中的 netlink 消息，返回消息类型及消息头之后的内容
+fn messages(mut buf: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut messages = Vec::new();
+    while buf.len() >= NLMSG_HDRLEN {
+        let len = u32::from_ne_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+        let kind = u16::from_ne_bytes([buf[4], buf[5]]);
+        if len < NLMSG_HDRLEN || len > buf.len() {
+            break;
+        }
+        messages.push((kind, &buf[NLMSG_HDRLEN..len]));
+        buf = &buf[align(len).min(buf.len())..];
+    }
+    messages
+}
+
+/// 拆分 netlink 属性，返回属性类型及内容
+fn attributes(mut data: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut attributes = Vec::new();
+    while data.len() >= 4 {
+        let len = u16::from_ne_bytes([data[0], data[1]]) as usize;
+        let kind = u16::from_ne_bytes([data[2], data[3]]) & NLA_TYPE_MASK;
+        if len < 4 || len > data.len() {
+            break;
+        }
+        attributes.push((kind, &data[4..len]));
+        data = &data[align(len).min(data.len())..];
+    }
+    attributes
+}
+
+/// 解析队列消息，返回报文 ID 与 IP 报文
+fn queued_packet(payload: &[u8]) -> Option<(u32, &[u8])> {
+    let mut id = None;
+    let mut packet: &[u8] = &[];
+    // 跳过 nfgenmsg
+    for (kind, data) in attributes(payload.get(4..)?) {
+        match kind {
+            NFQA_PACKET_HDR => {
+                let bytes = data.get(..4)?;
+                id = Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+            }
+            NFQA_PAYLOAD => packet = data,
+            _ => {}
+        }
+    }
+    id.map(|id| (id, packet))
+}
+
+/// 解析出的 IP 报文
+struct Packet<'a> {
+    src: IpAddr,
+    dst: IpAddr,
+    protocol: u8,
+    /// 传输层负载的实际长度，复制到用户态的报文可能被截断
+    payload_len: usize,
+    /// 已复制的传输层负载
+    payload: &'a [u8],
+}
+
+fn parse_packet(data: &[u8]) -> Option<Packet<'_>> {
+    let (src, dst, protocol, header_len, total_len) = match data.first()? >> 4 {
+        4 => {
+            let src: [u8; 4] = data.get(12..16)?.try_into().ok()?;
+            let dst: [u8; 4] = data.get(16..20)?.try_into().ok()?;
+            let total_len = u16::from_be_bytes([*data.get(2)?, *data.get(3)?]) as usize;
+            let header_len = (data[0] & 0x0f) as usize * 4;
+            (src.into(), dst.into(), *data.get(9)?, header_len, total_len)
+        }
+        // 不解析扩展头，带扩展头的报文按原始负载统计
+        6 => {
+            let src: [u8; 16] = data.get(8..24)?.try_into().ok()?;
+            let dst: [u8; 16] = data.get(24..40)?.try_into().ok()?;
+            let payload_len = u16::from_be_bytes([*data.get(4)?, *data.get(5)?]) as usize;
+            (src.into(), dst.into(), *data.get(6)?, 40, 40 + payload_len)
+        }
+        _ => return None,
+    };
+
+    let segment = data.get(header_len..)?;
+    let transport_header = match protocol {
+        IPPROTO_TCP => (*segment.get(12)? >> 4) as usize * 4,
+        IPPROTO_UDP => 8,
+        _ => 0,
+    };
+    Some(Packet {
+        src,
+        dst,
+        protocol,
+        payload_len: total_len
+            .saturating_sub(header_len)
+            .saturating_sub(transport_header),
+        payload: segment.get(transport_header..).unwrap_or_default(),
+    })
+}
+
+/// 从 TLS ClientHello 中提取 SNI，不是完整的 ClientHello 时返回 None
+fn client_hello_sni(payload: &[u8]) -> Option<String> {
+    // 记录头：类型 22（handshake）、版本、长度；握手头：类型 1（ClientHello）、长度
+    if *payload.first()? != 22 || *payload.get(5)? != 1 {
+        return None;
+    }
+    let mut reader = Reader {
+        data: payload,
+        pos: 9,
+    };
+    // client_version、random
+    reader.skip(2 + 32)?;
+    let session_id = reader.u8()? as usize;
+    reader.skip(session_id)?;
+    let cipher_suites = reader.u16()? as usize;
+    reader.skip(cipher_suites)?;
+    let compression_methods = reader.u8()? as usize;
+    reader.skip(compression_methods)?;
+
+    let end = reader.u16()? as usize + reader.pos;
+    while reader.pos + 4 <= end {
+        let kind = reader.u16()?;
+        let len = reader.u16()? as usize;
+        if kind != 0 {
+            reader.skip(len)?;
+            continue;
+        }
+        // server_name 扩展：列表长度、名称类型（0 为主机名）、名称长度
+        reader.skip(2)?;
+        if reader.u8()? != 0 {
+            return None;
+        }
+        let len = reader.u16()? as usize;
+        let name = reader.take(len)?;
+        if name.is_empty() || !name.iter().all(u8::is_ascii_graphic) {
+            return None;
+        }
+        return Some(String::from_utf8_lossy(name).to_ascii_lowercase());
+    }
+    None
+}
+
+/// 按大端序读取 TLS 字段
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let slice = self.data.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(slice)
+    }
+
+    fn skip(&mut self, n: usize) -> Option<()> {
+        self.take(n).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+}
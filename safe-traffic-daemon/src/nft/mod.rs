@@ -1,14 +1,26 @@
 pub mod parser;
+pub mod priority;
+pub mod sets;
+pub mod tune;
 use crate::error::FirewallError;
 use anyhow::{Context, Result};
 use chrono::{DateTime, Duration, Utc};
 use log::{debug, error, info, warn};
 pub use parser::{parse_output, NftObject};
-use std::{collections::VecDeque, fs::OpenOptions, process::Stdio, sync::Arc};
+use std::{
+    collections::VecDeque,
+    fs::OpenOptions,
+    process::Stdio,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::process::{Child, Command};
 use tokio::sync::{Mutex, Semaphore};
 use tokio::time::{timeout, Duration as TokioDuration};
+pub use tune::PoolTuning;
 
 const TIMEOUT_SEC: u64 = 5;
 
@@ -55,7 +67,7 @@ impl NftProcess {
             .stderr(Stdio::from(err_file))
             .kill_on_drop(true)
             .spawn()
-            .context("Failed to spawn nft process")?;
+            .map_err(|e| NftError::ProcessNotAvailable(format!("failed to spawn nft: {}", e)))?;
 
         let stdin = child.stdin.take();
         let stdout = child.stdout.take().map(BufReader::new);
@@ -97,9 +109,10 @@ impl NftProcess {
                 error!("NFT process initialization failed: {}", e);
                 Err(e)
             }
+            // 进程未能就绪属于 nft 不可用，而不是某条命令被拒绝
             Err(e) => {
                 error!("NFT process initialization timeout: {}", e);
-                Err(NftError::Timeout.into())
+                Err(NftError::ProcessNotAvailable("nft did not become ready".to_string()).into())
             }
         }
     }
@@ -199,14 +212,14 @@ impl NftProcess {
             .ok_or_else(|| NftError::ProcessNotAvailable("stdin not available".to_string()))?
             .write(full_command.as_bytes())
             .await
-            .context("Failed to write command to nft process")?;
+            .map_err(|e| NftError::CommunicationError(format!("failed to write command: {}", e)))?;
 
         self.stdin
             .as_mut()
             .ok_or_else(|| NftError::ProcessNotAvailable("stdin not available".to_string()))?
             .flush()
             .await
-            .context("Failed to flush stdin")?;
+            .map_err(|e| NftError::CommunicationError(format!("failed to flush stdin: {}", e)))?;
 
         let mut out_content = String::new();
         let _r = self
@@ -215,7 +228,7 @@ impl NftProcess {
             .ok_or_else(|| NftError::ProcessNotAvailable("stdout not available".to_string()))?
             .read_line(&mut out_content)
             .await
-            .context("Failed to read line")?;
+            .map_err(|e| NftError::CommunicationError(format!("failed to read output: {}", e)))?;
 
         Ok(out_content)
     }
@@ -230,14 +243,14 @@ impl NftProcess {
             .ok_or_else(|| NftError::ProcessNotAvailable("stdin not available".to_string()))?
             .write(full_command.as_bytes())
             .await
-            .context("Failed to write command to nft process")?;
+            .map_err(|e| NftError::CommunicationError(format!("failed to write command: {}", e)))?;
 
         self.stdin
             .as_mut()
             .ok_or_else(|| NftError::ProcessNotAvailable("stdin not available".to_string()))?
             .flush()
             .await
-            .context("Failed to flush stdin")?;
+            .map_err(|e| NftError::CommunicationError(format!("failed to flush stdin: {}", e)))?;
 
         Ok(())
     }
@@ -353,6 +366,15 @@ struct ProcessStats {
     is_busy: bool,
 }
 
+/// 模拟模式下注入的故障，用于测试 nft 不可用与命令被拒绝时的处理
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockFault {
+    /// nft 进程无法启动
+    Unavailable,
+    /// nft 拒绝命令；stderr 写入日志文件，调用方只会等到超时
+    Rejected,
+}
+
 // NFT 执行器池
 #[derive(Debug)]
 pub struct NftExecutor {
@@ -362,6 +384,10 @@ pub struct NftExecutor {
     max_process_age: Duration,
     max_commands_per_process: usize,
     mock_mode: bool,
+    /// 模拟模式下分配给新增规则的 handle
+    mock_handle: AtomicU64,
+    /// 模拟模式下注入的故障：包含该片段的命令按故障失败
+    mock_faults: std::sync::Mutex<Vec<(String, MockFault)>>,
 }
 
 impl NftExecutor {
@@ -381,14 +407,64 @@ impl NftExecutor {
             max_process_age,
             max_commands_per_process,
             mock_mode,
+            mock_handle: AtomicU64::new(1),
+            mock_faults: std::sync::Mutex::new(Vec::new()),
+        }
+    }
+
+    /// 模拟模式下让包含 matching 的命令（空串为全部命令）按 fault 失败，fault 为 None 时撤销
+    pub fn inject_fault(&self, matching: &str, fault: Option<MockFault>) {
+        let mut faults = self.mock_faults.lock().unwrap();
+        faults.retain(|(pattern, _)| pattern != matching);
+        if let Some(fault) = fault {
+            faults.push((matching.to_string(), fault));
+        }
+    }
+
+    /// 模拟执行：命中注入的故障时返回对应的错误
+    fn mock_result(&self, command: &str) -> Result<String> {
+        let fault = self
+            .mock_faults
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(pattern, _)| command.contains(pattern.as_str()))
+            .map(|(_, fault)| *fault);
+        match fault {
+            Some(MockFault::Unavailable) => Err(NftError::ProcessNotAvailable(
+                "nft is not available (mocked)".to_string(),
+            )
+            .into()),
+            Some(MockFault::Rejected) => Err(NftError::Timeout.into()),
+            None => Ok(self.mock_output(command)),
+        }
+    }
+
+    /// 模拟模式下的输出：新增规则时按 `--echo --handle --json` 的格式回显带 handle 的规则
+    fn mock_output(&self, command: &str) -> String {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        match words.as_slice() {
+            ["add" | "insert" | "replace", "rule", family, table, chain, ..] => {
+                let handle = self.mock_handle.fetch_add(1, Ordering::Relaxed);
+                format!(
+                    r#"{{"nftables": [{{"add": {{"rule": {{"family": "{}", "table": "{}", "chain": "{}", "handle": {}}}}}}}]}}"#,
+                    family, table, chain, handle
+                )
+            }
+            _ => "success (mocked)".to_string(),
         }
     }
 
+    /// 是否只记录命令而不执行
+    pub fn is_mock(&self) -> bool {
+        self.mock_mode
+    }
+
     /// 执行 nft 命令
     pub async fn execute(&self, command: &str) -> Result<String> {
         if self.mock_mode {
             debug!("Mocking nft command execution: {}", command);
-            return Ok("success (mocked)".to_string());
+            return self.mock_result(command);
         }
 
         // 获取信号量许可
@@ -414,7 +490,7 @@ impl NftExecutor {
     pub async fn input(&self, command: &str) -> Result<()> {
         if self.mock_mode {
             debug!("Mocking nft command execution: {}", command);
-            return Ok(());
+            return self.mock_result(command).map(|_| ());
         }
 
         // 获取信号量许可
@@ -524,8 +600,8 @@ impl NftExecutor {
         (pool_size, available_permits)
     }
 
-    /// 执行批量命令（更高效）
-    pub async fn execute_batch(&self, commands: Vec<String>) -> Result<Vec<String>> {
+    /// 逐条执行批量命令，返回每条命令各自的结果，单条失败不会中断其余命令
+    pub async fn execute_each(&self, commands: Vec<String>) -> Result<Vec<Result<String>>> {
         if self.mock_mode {
             debug!(
                 "Mocking batch nft command execution: {} commands",
@@ -533,10 +609,46 @@ impl NftExecutor {
             );
             return Ok(commands
                 .iter()
-                .map(|_| "success (mocked)".to_string())
+                .map(|command| self.mock_result(command))
                 .collect());
         }
 
+        let _permit = self
+            .semaphore
+            .acquire()
+            .await
+            .map_err(|_| FirewallError::ExecutorPoolExhausted)?;
+
+        let mut process = self.get_or_create_process().await?;
+        let mut results = Vec::with_capacity(commands.len());
+
+        for command in commands {
+            let result = process.execute_command(&command).await;
+            if result.is_err() && !process.is_alive() {
+                // 进程已退出，换一个进程继续执行剩余命令
+                self.return_or_destroy_process(process).await;
+                process = self.get_or_create_process().await?;
+            }
+            results.push(result);
+        }
+
+        self.return_or_destroy_process(process).await;
+        Ok(results)
+    }
+
+    /// 执行批量命令（更高效）
+    pub async fn execute_batch(&self, commands: Vec<String>) -> Result<Vec<String>> {
+        if self.mock_mode {
+            debug!(
+                "Mocking batch nft command execution: {} commands",
+                commands.len()
+            );
+            return commands
+                .iter()
+                .map(|command| self.mock_result(command))
+                .collect();
+        }
+
         let _permit = self
             .semaphore
             .acquire()
@@ -584,3 +696,17 @@ pub async fn check_nftables_available() -> Result<bool> {
         Err(_) => Ok(false),
     }
 }
+
+/// 错误是否由 nft 执行器暂时不可用引起（进程无法启动、管道断开、执行器池耗尽）。
+/// nft 的 stderr 写入日志文件，被拒绝的命令只表现为超时，超时因此不算暂时不可用，重试也不会成功
+pub fn is_unavailable(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref::<NftError>(),
+            Some(NftError::ProcessNotAvailable(_) | NftError::CommunicationError(_))
+        ) || matches!(
+            cause.downcast_ref::<FirewallError>(),
+            Some(FirewallError::ExecutorPoolExhausted)
+        )
+    })
+}
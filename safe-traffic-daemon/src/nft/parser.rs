@@ -1,4 +1,6 @@
+use safe_traffic_common::transport::SystemRule;
 use serde::Deserialize;
+use serde_json::Value;
 
 /// NFT JSON 输出结构体
 #[derive(Debug, Deserialize)]
@@ -17,17 +19,19 @@ pub enum NftObject {
 }
 
 #[derive(Debug, Deserialize)]
-#[allow(dead_code)]
 pub struct ChainObject {
-    chain: Chain,
+    pub chain: Chain,
 }
 
 #[derive(Debug, Deserialize)]
-#[allow(dead_code)]
 pub struct Chain {
-    family: String,
-    table: String,
-    name: String,
+    pub family: String,
+    pub table: String,
+    pub name: String,
+    /// 基础链挂载的 hook，普通链为 None
+    pub hook: Option<String>,
+    /// 基础链优先级
+    pub prio: Option<i64>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -46,8 +50,9 @@ impl RuleObject {
 pub struct Rule {
     family: String,
     table: String,
-    chain: String,
+    pub chain: String,
     handle: Option<u64>,
+    pub comment: Option<String>,
     pub expr: Option<Vec<Expression>>,
 }
 
@@ -55,6 +60,34 @@ impl Rule {
     pub async fn get_handle(&self) -> Option<u64> {
         self.handle
     }
+
+    /// 转换为对外暴露的结构化规则
+    pub fn to_system_rule(&self) -> SystemRule {
+        let mut rule = SystemRule {
+            handle: self.handle,
+            matchers: Vec::new(),
+            packets: None,
+            bytes: None,
+            statements: Vec::new(),
+        };
+        for expr in self.expr.iter().flatten() {
+            match expr {
+                Expression::Match(expr) => rule.matchers.push(format!(
+                    "{} {} {}",
+                    describe(&expr.r#match.left),
+                    expr.r#match.op,
+                    describe(&expr.r#match.right)
+                )),
+                Expression::Counter(expr) => {
+                    rule.packets = Some(expr.counter.packets);
+                    rule.bytes = Some(expr.counter.bytes);
+                }
+                Expression::Accept(_) => rule.statements.push("accept".to_string()),
+                Expression::Other(value) => rule.statements.push(describe(value)),
+            }
+        }
+        rule
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -76,9 +109,7 @@ impl AddObject {
 pub enum Expression {
     Match(MatchExpr),
     Counter(CounterExpr),
-    #[allow(dead_code)]
     Accept(AcceptExpr),
-    #[allow(dead_code)]
     Other(serde_json::Value),
 }
 
@@ -89,7 +120,6 @@ pub struct MatchExpr {
 
 #[derive(Debug, Deserialize)]
 pub struct Match {
-    #[allow(dead_code)]
     op: String,
     pub left: serde_json::Value,
     pub right: serde_json::Value,
@@ -108,8 +138,9 @@ pub struct Counter {
 
 #[derive(Debug, Deserialize)]
 pub struct AcceptExpr {
+    // 必须存在该字段，否则 drop 等其他语句也会被当作 accept
     #[allow(dead_code)]
-    accept: Option<serde_json::Value>,
+    accept: serde_json::Value,
 }
 
 pub async fn parse_output(json_output: &str) -> anyhow::Result<Vec<NftObject>> {
@@ -118,3 +149,107 @@ pub async fn parse_output(json_output: &str) -> anyhow::Result<Vec<NftObject>> {
 
     Ok(nft_data.nftables)
 }
+
+/// 把 nft JSON 表达式还原为接近 nft 语法的文本
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(describe).collect();
+            format!("{{ {} }}", items.join(", "))
+        }
+        Value::Object(map) if map.len() == 1 => {
+            let (key, inner) = map.iter().next().unwrap();
+            describe_keyed(key, inner)
+        }
+        Value::Object(map) => map
+            .iter()
+            .map(|(key, inner)| format!("{} {}", key, describe(inner)))
+            .collect::<Vec<_>>()
+            .join(" "),
+        other => other.to_string(),
+    }
+}
+
+fn describe_keyed(key: &str, inner: &Value) -> String {
+    let field = |name: &str| inner.get(name).map(describe).unwrap_or_default();
+    match (key, inner) {
+        ("payload", Value::Object(_)) => format!("{} {}", field("protocol"), field("field")),
+        ("meta", Value::Object(_)) => format!("meta {}", field("key")),
+        ("prefix", Value::Object(_)) => format!("{}/{}", field("addr"), field("len")),
+        ("range", Value::Array(bounds)) if bounds.len() == 2 => {
+            format!("{}-{}", describe(&bounds[0]), describe(&bounds[1]))
+        }
+        ("set", _) => describe(inner),
+        (_, Value::Null) => key.to_string(),
+        _ => format!("{} {}", key, describe(inner)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `nft -j list chain inet traffic_filter traffic_input` 的输出片段
+    const LIST_CHAIN: &str = r#"{"nftables": [
+        {"metainfo": {"version": "1.0.6", "release_name": "Lester Gooch #5", "json_schema_version": 1}},
+        {"chain": {"family": "inet", "table": "traffic_filter", "name": "traffic_input", "handle": 1, "type": "filter", "hook": "input", "prio": 0, "policy": "accept"}},
+        {"rule": {"family": "inet", "table": "traffic_filter", "chain": "traffic_input", "handle": 4, "expr": [
+            {"match": {"op": "==", "left": {"payload": {"protocol": "ip", "field": "saddr"}}, "right": "198.51.100.7"}},
+            {"counter": {"packets": 12, "bytes": 3400}},
+            {"drop": null}
+        ]}},
+        {"rule": {"family": "inet", "table": "traffic_filter", "chain": "traffic_input", "handle": 5, "expr": [
+            {"match": {"op": "==", "left": {"payload": {"protocol": "ip", "field": "saddr"}}, "right": {"prefix": {"addr": "203.0.113.0", "len": 24}}}},
+            {"match": {"op": "==", "left": {"payload": {"protocol": "tcp", "field": "dport"}}, "right": {"set": [22, {"range": [8000, 8080]}]}}},
+            {"limit": {"rate": 100, "burst": 10, "per": "second", "rate_unit": "kbytes", "burst_unit": "kbytes", "inv": true}},
+            {"counter": {"packets": 0, "bytes": 0}},
+            {"drop": null}
+        ]}},
+        {"rule": {"family": "inet", "table": "traffic_filter", "chain": "traffic_input", "handle": 6, "expr": [
+            {"match": {"op": "!=", "left": {"meta": {"key": "l4proto"}}, "right": "udp"}},
+            {"accept": null}
+        ]}}
+    ]}"#;
+
+    #[tokio::test]
+    async fn test_list_chain_to_system_rules() {
+        let rules: Vec<SystemRule> = parse_output(LIST_CHAIN)
+            .await
+            .unwrap()
+            .iter()
+            .filter_map(|obj| match obj {
+                NftObject::Rule(rule) => Some(rule.rule.to_system_rule()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(rules.len(), 3);
+
+        assert_eq!(rules[0].handle, Some(4));
+        assert_eq!(rules[0].matchers, ["ip saddr == 198.51.100.7"]);
+        assert_eq!((rules[0].packets, rules[0].bytes), (Some(12), Some(3400)));
+        assert_eq!(rules[0].statements, ["drop"]);
+
+        assert_eq!(rules[1].handle, Some(5));
+        assert_eq!(
+            rules[1].matchers,
+            [
+                "ip saddr == 203.0.113.0/24",
+                "tcp dport == { 22, 8000-8080 }"
+            ]
+        );
+        assert_eq!((rules[1].packets, rules[1].bytes), (Some(0), Some(0)));
+        assert_eq!(
+            rules[1].statements,
+            [
+                "limit burst 10 burst_unit kbytes inv true per second rate 100 rate_unit kbytes",
+                "drop"
+            ]
+        );
+
+        assert_eq!(rules[2].matchers, ["meta l4proto != udp"]);
+        assert_eq!((rules[2].packets, rules[2].bytes), (None, None));
+        assert_eq!(rules[2].statements, ["accept"]);
+    }
+}
@@ -0,0 +1,138 @@
+//! 与同一 hook 上已有的基础链协商优先级，使本链按配置排在它们之前或之后
+
+use super::{parse_output, NftExecutor, NftObject};
+use anyhow::{bail, Result};
+use log::{info, warn};
+use safe_traffic_common::config::{FamilyType, HookType};
+use std::fmt;
+
+/// 同一 hook 上已有的基础链
+#[derive(Debug, Clone)]
+pub struct BaseChain {
+    pub family: String,
+    pub table: String,
+    pub name: String,
+    pub priority: i64,
+}
+
+impl BaseChain {
+    /// 配置条目为表名（如 `firewalld`）或 `表名/链名`
+    fn matches(&self, entry: &str) -> bool {
+        match entry.split_once('/') {
+            Some((table, chain)) => self.table == table && self.name == chain,
+            None => self.table == entry,
+        }
+    }
+}
+
+impl fmt::Display for BaseChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}/{} (priority {})",
+            self.family, self.table, self.name, self.priority
+        )
+    }
+}
+
+/// 与本链处于同一 hook、按优先级共同排序的协议族
+fn sharing_families(family: &FamilyType) -> &'static [&'static str] {
+    match family {
+        FamilyType::Ip4 => &["ip", "inet"],
+        FamilyType::Ip6 => &["ip6", "inet"],
+        FamilyType::Inet => &["ip", "ip6", "inet"],
+        FamilyType::Bridge => &["bridge"],
+        FamilyType::Netdev => &["netdev"],
+    }
+}
+
+/// 列出与本链挂在同一 hook 上的其他基础链
+pub async fn base_chains(
+    executor: &NftExecutor,
+    family: &FamilyType,
+    hook: &HookType,
+    own_table: &str,
+) -> Result<Vec<BaseChain>> {
+    let output = executor.execute("list chains").await?;
+    let families = sharing_families(family);
+    let hook = family.hook_name(hook);
+    Ok(parse_output(&output)
+        .await?
+        .into_iter()
+        .filter_map(|obj| match obj {
+            NftObject::Chain(obj) => Some(obj.chain),
+            _ => None,
+        })
+        .filter(|chain| {
+            chain.hook.as_deref() == Some(hook)
+                && families.contains(&chain.family.as_str())
+                && chain.table != own_table
+        })
+        .filter_map(|chain| {
+            Some(BaseChain {
+                priority: chain.prio?,
+                family: chain.family,
+                table: chain.table,
+                name: chain.name,
+            })
+        })
+        .collect())
+}
+
+/// 在配置的优先级基础上选择最接近的值，使本链先于 run_before、晚于 run_after 中的链执行；
+/// 同优先级的链执行顺序不确定，因此要求严格小于或大于；无法同时满足时返回错误
+pub fn negotiate(
+    configured: i64,
+    chains: &[BaseChain],
+    run_before: &[String],
+    run_after: &[String],
+) -> Result<i64> {
+    let matching = |entries: &[String]| -> Vec<&BaseChain> {
+        entries
+            .iter()
+            .flat_map(|entry| {
+                let found: Vec<&BaseChain> =
+                    chains.iter().filter(|chain| chain.matches(entry)).collect();
+                if found.is_empty() {
+                    warn!(
+                        "no base chain on this hook matches \"{}\", ignoring it when choosing the priority",
+                        entry
+                    );
+                }
+                found
+            })
+            .collect()
+    };
+    let first_before = matching(run_before)
+        .into_iter()
+        .min_by_key(|chain| chain.priority);
+    let last_after = matching(run_after)
+        .into_iter()
+        .max_by_key(|chain| chain.priority);
+
+    if let (Some(before), Some(after)) = (first_before, last_after) {
+        if after.priority.saturating_add(1) > before.priority.saturating_sub(1) {
+            bail!(
+                "cannot order the chain before {} and after {}: no priority lies between them",
+                before,
+                after
+            );
+        }
+    }
+
+    let mut priority = configured;
+    if let Some(chain) = first_before {
+        priority = priority.min(chain.priority.saturating_sub(1));
+    }
+    if let Some(chain) = last_after {
+        priority = priority.max(chain.priority.saturating_add(1));
+    }
+
+    if priority != configured {
+        info!(
+            "chain priority adjusted from {} to {} to satisfy run_before/run_after",
+            configured, priority
+        );
+    }
+    Ok(priority)
+}
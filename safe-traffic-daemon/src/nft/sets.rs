@@ -0,0 +1,86 @@
+//! 按 IP 版本拆分的带超时地址集合，如 `banned4` / `banned6`
+
+use std::net::IpAddr;
+
+/// 同一前缀下的 IPv4 与 IPv6 地址集合，协议族只能容纳一种版本时只使用对应的集合
+#[derive(Debug, Clone)]
+pub struct AddressSets {
+    family: String,
+    table: String,
+    prefix: &'static str,
+}
+
+impl AddressSets {
+    pub fn new(family: impl ToString, table: impl Into<String>, prefix: &'static str) -> Self {
+        AddressSets {
+            family: family.to_string(),
+            table: table.into(),
+            prefix,
+        }
+    }
+
+    /// 集合所在的表
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    /// 协议族能容纳的集合：(集合名, 地址类型, 匹配协议)
+    fn sets(&self) -> Vec<(String, &'static str, &'static str)> {
+        let v4 = (format!("{}4", self.prefix), "ipv4_addr", "ip");
+        let v6 = (format!("{}6", self.prefix), "ipv6_addr", "ip6");
+        match self.family.as_str() {
+            "ip" => vec![v4],
+            "ip6" => vec![v6],
+            _ => vec![v4, v6],
+        }
+    }
+
+    /// 创建集合的命令
+    pub fn create_commands(&self) -> Vec<String> {
+        self.sets()
+            .into_iter()
+            .map(|(name, addr_type, _)| {
+                format!(
+                    "add set {} {} {} {{ type {} ; flags timeout ; }}",
+                    self.family, self.table, name, addr_type
+                )
+            })
+            .collect()
+    }
+
+    /// 匹配集合中地址的表达式，direction 为 saddr 或 daddr，例如 `ip saddr @banned4`
+    pub fn matchers(&self, direction: &str) -> Vec<String> {
+        self.sets()
+            .into_iter()
+            .map(|(name, _, protocol)| format!("{} {} @{}", protocol, direction, name))
+            .collect()
+    }
+
+    /// 元素命令，verb 为 add 或 delete；协议族容纳不了该 IP 版本时为 None
+    pub fn element_command(&self, verb: &str, ip: IpAddr, seconds: Option<u64>) -> Option<String> {
+        let suffix = match ip {
+            IpAddr::V4(_) => "4",
+            IpAddr::V6(_) => "6",
+        };
+        let (name, _, _) = self
+            .sets()
+            .into_iter()
+            .find(|(name, _, _)| name.ends_with(suffix))?;
+        let timeout = seconds
+            .map(|seconds| format!(" timeout {}s", seconds))
+            .unwrap_or_default();
+        Some(format!(
+            "{} element {} {} {} {{ {}{} }}",
+            verb, self.family, self.table, name, ip, timeout
+        ))
+    }
+
+    /// 清空全部集合的命令，在同一行中提交
+    pub fn flush_command(&self) -> String {
+        self.sets()
+            .into_iter()
+            .map(|(name, _, _)| format!("flush set {} {} {}", self.family, self.table, name))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
@@ -0,0 +1,114 @@
+use log::{debug, warn};
+use std::{fs, thread, time::Instant};
+use tokio::process::Command;
+
+/// 延迟探测次数
+const PROBE_ROUNDS: u32 = 3;
+
+/// 执行器池参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolTuning {
+    pub pool_size: usize,
+    pub max_age_secs: i64,
+    pub max_commands: usize,
+}
+
+impl Default for PoolTuning {
+    fn default() -> Self {
+        PoolTuning {
+            pool_size: 5,
+            max_age_secs: 300,
+            max_commands: 100,
+        }
+    }
+}
+
+/// 探测到的运行环境
+#[derive(Debug, Clone, Copy)]
+pub struct Environment {
+    /// 可用 CPU 核数
+    pub cpus: usize,
+    /// 1 分钟平均负载
+    pub load: f64,
+    /// 启动一次 nft 并完成查询的平均耗时，毫秒；nft 不可用时为 None
+    pub nft_latency_ms: Option<u64>,
+}
+
+impl Environment {
+    /// 探测 CPU 核数、系统负载及 nft 命令延迟
+    pub async fn probe(nft_available: bool) -> Self {
+        let cpus = thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        let load = read_loadavg().unwrap_or(0.0);
+        let nft_latency_ms = if nft_available {
+            probe_nft_latency().await
+        } else {
+            None
+        };
+
+        Environment {
+            cpus,
+            load,
+            nft_latency_ms,
+        }
+    }
+}
+
+impl PoolTuning {
+    /// 根据运行环境推算执行器池参数
+    ///
+    /// 池大小随核数增长，负载过高时减半；nft 进程启动越慢，单个进程保留越久、执行越多命令。
+    pub fn for_environment(env: &Environment) -> Self {
+        let mut pool_size = env.cpus / 2 + 1;
+        if env.load > env.cpus as f64 * 0.8 {
+            pool_size /= 2;
+        }
+        let pool_size = pool_size.clamp(2, 32);
+
+        let (max_age_secs, max_commands) = match env.nft_latency_ms {
+            Some(ms) if ms >= 100 => (1800, 500),
+            Some(ms) if ms >= 20 => (900, 250),
+            _ => (300, 100),
+        };
+
+        PoolTuning {
+            pool_size,
+            max_age_secs,
+            max_commands,
+        }
+    }
+}
+
+fn read_loadavg() -> Option<f64> {
+    let text = fs::read_to_string("/proc/loadavg").ok()?;
+    text.split_whitespace().next()?.parse().ok()
+}
+
+/// 测量一次性 nft 查询的耗时，即回收并重建一个执行器进程的大致代价
+async fn probe_nft_latency() -> Option<u64> {
+    let mut total_ms = 0;
+    for _ in 0..PROBE_ROUNDS {
+        let start = Instant::now();
+        match Command::new("nft").args(["list", "tables"]).output().await {
+            Ok(output) if output.status.success() => {
+                total_ms += start.elapsed().as_millis() as u64;
+            }
+            Ok(output) => {
+                warn!(
+                    "nft latency probe failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+                return None;
+            }
+            Err(e) => {
+                warn!("nft latency probe failed: {}", e);
+                return None;
+            }
+        }
+    }
+
+    let latency = total_ms / PROBE_ROUNDS as u64;
+    debug!("nft latency probe: {}ms", latency);
+    Some(latency)
+}
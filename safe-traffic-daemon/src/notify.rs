@@ -0,0 +1,327 @@
+//! 事件通知：按渠道把事件发送到 webhook。持续攻击时同一来源与规则的事件在 dedup_minutes 内只通知一次，
+//! 或每隔 digest_minutes 汇总为一条摘要；被抑制的事件数在窗口结束时或摘要中报告，不会悄悄丢失
+
+use crate::{controller::Firewall, outbound::ProxySettings};
+
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use log::{info, warn};
+use safe_traffic_common::{
+    config::NotifyChannel,
+    events::{Event, EventKind},
+};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{sync::broadcast::error::RecvError, time};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// 检查去重窗口与摘要是否到期的间隔
+const TICK: Duration = Duration::from_secs(10);
+/// 摘要中列出的分组数上限，其余只计数
+const MAX_DIGEST_LINES: usize = 20;
+
+/// 去重的分组：事件类型、来源与触发的规则序号
+type Key = (EventKind, Option<IpAddr>, Option<usize>);
+
+fn key(event: &Event) -> Key {
+    (
+        event.kind,
+        event.ip,
+        event.reason.as_ref().map(|reason| reason.rule),
+    )
+}
+
+/// 渠道在健康度中的名称
+fn integration(channel: &str) -> String {
+    format!("notify:{}", channel)
+}
+
+/// 单个事件的通知文本
+fn line(event: &Event) -> String {
+    match &event.reason {
+        Some(reason) => format!("[{}] {} ({})", event.kind, event.message, reason),
+        None => format!("[{}] {}", event.kind, event.message),
+    }
+}
+
+/// 一个分组的去重窗口
+#[derive(Debug)]
+struct Window {
+    until: DateTime<Utc>,
+    line: String,
+    suppressed: u64,
+}
+
+/// 摘要中的一个分组
+#[derive(Debug)]
+struct DigestGroup {
+    key: Key,
+    line: String,
+    count: u64,
+}
+
+/// 单个渠道的去重与摘要状态，不负责发送
+#[derive(Debug)]
+pub struct Channel {
+    name: String,
+    url: String,
+    kinds: Option<Vec<EventKind>>,
+    dedup: Option<ChronoDuration>,
+    digest: Option<ChronoDuration>,
+    windows: HashMap<Key, Window>,
+    groups: Vec<DigestGroup>,
+    /// 当前摘要的开始时间，没有待汇总的事件时为 None
+    digest_since: Option<DateTime<Utc>>,
+    /// 当前摘要期间被去重抑制的事件数
+    digest_suppressed: u64,
+}
+
+impl Channel {
+    pub fn new(cfg: &NotifyChannel) -> Self {
+        let minutes = |m: Option<u64>| m.map(|m| ChronoDuration::minutes(m.max(1) as i64));
+        Self {
+            name: cfg.name.clone(),
+            url: cfg.url.clone(),
+            kinds: cfg.kinds.clone(),
+            dedup: minutes(cfg.dedup_minutes),
+            digest: minutes(cfg.digest_minutes),
+            windows: HashMap::new(),
+            groups: Vec::new(),
+            digest_since: None,
+            digest_suppressed: 0,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 处理一个事件，需要立即发送时返回消息
+    pub fn offer(&mut self, event: &Event) -> Option<String> {
+        if self
+            .kinds
+            .as_ref()
+            .is_some_and(|kinds| !kinds.contains(&event.kind))
+        {
+            return None;
+        }
+        let key = key(event);
+        let mut text = line(event);
+        if let Some(dedup) = self.dedup {
+            match self.windows.get_mut(&key) {
+                Some(window) if event.time < window.until => {
+                    window.suppressed += 1;
+                    if self.digest.is_some() {
+                        self.digest_suppressed += 1;
+                    }
+                    return None;
+                }
+                previous => {
+                    // 到期但尚未被 tick 报告的窗口，抑制数随本条消息报告
+                    if let Some(window) = previous.filter(|window| window.suppressed > 0) {
+                        if self.digest.is_none() {
+                            text = format!(
+                                "{} ({} similar events suppressed before)",
+                                text, window.suppressed
+                            );
+                        }
+                    }
+                    self.windows.insert(
+                        key,
+                        Window {
+                            until: event.time + dedup,
+                            line: line(event),
+                            suppressed: 0,
+                        },
+                    );
+                }
+            }
+        }
+        if self.digest.is_none() {
+            return Some(text);
+        }
+        self.digest_since.get_or_insert(event.time);
+        match self.groups.iter_mut().find(|group| group.key == key) {
+            Some(group) => group.count += 1,
+            None => self.groups.push(DigestGroup {
+                key,
+                line: text,
+                count: 1,
+            }),
+        }
+        None
+    }
+
+    /// 定期调用：结束到期的去重窗口，逐条发送时报告其中被抑制的事件数；摘要到期时返回摘要
+    pub fn tick(&mut self, now: DateTime<Utc>) -> Vec<String> {
+        let mut messages = Vec::new();
+        let keys: Vec<Key> = self
+            .windows
+            .iter()
+            .filter(|(_, window)| window.until <= now)
+            .map(|(key, _)| *key)
+            .collect();
+        let mut expired: Vec<Window> = keys
+            .iter()
+            .filter_map(|key| self.windows.remove(key))
+            .filter(|window| window.suppressed > 0)
+            .collect();
+        // 摘要模式下抑制数已计入摘要
+        if self.digest.is_none() {
+            expired.sort_by_key(|window| window.until);
+            for window in expired.drain(..) {
+                messages.push(format!(
+                    "{}: {} similar events suppressed",
+                    window.line, window.suppressed
+                ));
+            }
+        }
+        if let (Some(period), Some(since)) = (self.digest, self.digest_since) {
+            if now - since >= period {
+                messages.push(self.take_digest(now));
+            }
+        }
+        messages
+    }
+
+    /// 汇总当前摘要并清空
+    fn take_digest(&mut self, now: DateTime<Utc>) -> String {
+        let since = self.digest_since.take().unwrap_or(now);
+        let mut groups = std::mem::take(&mut self.groups);
+        let suppressed = std::mem::take(&mut self.digest_suppressed);
+        let total = groups.iter().map(|group| group.count).sum::<u64>() + suppressed;
+        groups.sort_by_key(|group| std::cmp::Reverse(group.count));
+        let mut text = format!(
+            "{}: {} events in the last {} minutes",
+            self.name,
+            total,
+            (now - since).num_minutes().max(1)
+        );
+        if suppressed > 0 {
+            text.push_str(&format!(", {} duplicates suppressed", suppressed));
+        }
+        for group in groups.iter().take(MAX_DIGEST_LINES) {
+            text.push_str(&format!("\n{} x{}", group.line, group.count));
+        }
+        if groups.len() > MAX_DIGEST_LINES {
+            let rest: u64 = groups[MAX_DIGEST_LINES..]
+                .iter()
+                .map(|group| group.count)
+                .sum();
+            text.push_str(&format!(
+                "\n... and {} more events in {} other groups",
+                rest,
+                groups.len() - MAX_DIGEST_LINES
+            ));
+        }
+        text
+    }
+}
+
+/// 订阅事件并分发到各个渠道
+pub struct Notifier {
+    channels: Vec<Channel>,
+    /// 与 channels 一一对应
+    agents: Vec<ureq::Agent>,
+}
+
+impl Notifier {
+    pub fn new(channels: &[NotifyChannel]) -> Self {
+        let proxy = ProxySettings::default();
+        Self {
+            channels: channels.iter().map(Channel::new).collect(),
+            agents: channels
+                .iter()
+                .map(|channel| proxy.agent(&channel.url, REQUEST_TIMEOUT))
+                .collect(),
+        }
+    }
+
+    /// 各渠道按代理设置访问 webhook
+    pub fn with_proxy(mut self, proxy: &ProxySettings) -> Self {
+        self.agents = self
+            .channels
+            .iter()
+            .map(|channel| proxy.agent(&channel.url, REQUEST_TIMEOUT))
+            .collect();
+        self
+    }
+
+    /// 在后台发送，不阻塞事件的接收；发送结果计入渠道的健康度
+    fn send(&self, channel: usize, text: String, fw: &Firewall) {
+        let agent = self.agents[channel].clone();
+        let name = self.channels[channel].name.clone();
+        let url = self.channels[channel].url.clone();
+        let health = fw.health();
+        let clock = fw.clock();
+        tokio::spawn(async move {
+            let started = Instant::now();
+            let result = tokio::task::spawn_blocking(move || {
+                let body = serde_json::json!({ "text": text }).to_string();
+                agent
+                    .post(&url)
+                    .set("Content-Type", "application/json")
+                    .send_string(&body)
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            })
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()));
+            if let Err(e) = &result {
+                warn!("Failed to notify channel {}: {}", name, e);
+            }
+            health
+                .record(&integration(&name), clock.wall(), started.elapsed(), result)
+                .await;
+        });
+    }
+
+    fn dispatch(&mut self, event: &Event, fw: &Firewall) {
+        for index in 0..self.channels.len() {
+            if let Some(text) = self.channels[index].offer(event) {
+                self.send(index, text, fw);
+            }
+        }
+    }
+
+    fn tick(&mut self, fw: &Firewall) {
+        let now = fw.clock().wall();
+        for index in 0..self.channels.len() {
+            for text in self.channels[index].tick(now) {
+                self.send(index, text, fw);
+            }
+        }
+    }
+
+    pub async fn run(mut self, fw: Arc<Firewall>) {
+        info!(
+            "Sending event notifications to {}",
+            self.channels
+                .iter()
+                .map(Channel::name)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        for channel in &self.channels {
+            fw.health()
+                .register(&integration(channel.name()), None, fw.clock().wall());
+        }
+        let mut events = fw.events.subscribe();
+        let mut interval = time::interval(TICK);
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(event) => self.dispatch(&event, &fw),
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!("Notifications fell behind, {} events were not notified", missed)
+                    }
+                    Err(RecvError::Closed) => return,
+                },
+                _ = interval.tick() => self.tick(&fw),
+            }
+        }
+    }
+}
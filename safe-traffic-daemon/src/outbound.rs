@@ -0,0 +1,139 @@
+//! 出站 HTTP 请求的代理：优先使用 [proxy]，否则按 HTTPS_PROXY、HTTP_PROXY、ALL_PROXY 与 NO_PROXY 环境变量。
+//! 回环与链路本地地址（本机的 BGP 守护进程、云厂商元数据）总是直连
+
+use log::warn;
+use safe_traffic_common::config::{is_link_local, parse_network, ProxyConfig};
+use std::{net::IpAddr, time::Duration};
+
+/// 访问 https 地址时依次查找的环境变量
+const HTTPS_PROXY_VARS: [&str; 4] = ["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"];
+/// 访问 http 地址时依次查找的环境变量，大写的 HTTP_PROXY 可能由 CGI 请求头注入，排在小写之后
+const HTTP_PROXY_VARS: [&str; 4] = ["http_proxy", "HTTP_PROXY", "ALL_PROXY", "all_proxy"];
+const NO_PROXY_VARS: [&str; 2] = ["NO_PROXY", "no_proxy"];
+
+/// 解析后的代理设置
+#[derive(Debug, Clone, Default)]
+pub struct ProxySettings {
+    /// 访问 https 地址使用的代理
+    https: Option<String>,
+    /// 访问 http 地址使用的代理
+    http: Option<String>,
+    /// 直连的主机、域名后缀与网段
+    no_proxy: Vec<String>,
+}
+
+impl ProxySettings {
+    /// 按配置与进程环境变量解析
+    pub fn new(cfg: Option<&ProxyConfig>) -> Self {
+        Self::with_env(cfg, |name| std::env::var(name).ok())
+    }
+
+    /// 按配置与给定的环境变量解析，配置优先
+    pub fn with_env(cfg: Option<&ProxyConfig>, env: impl Fn(&str) -> Option<String>) -> Self {
+        let first = |names: &[&str]| {
+            names
+                .iter()
+                .filter_map(|name| env(name))
+                .find(|value| !value.trim().is_empty())
+        };
+        let env_no_proxy = || {
+            first(&NO_PROXY_VARS)
+                .map(|value| {
+                    value
+                        .split(',')
+                        .map(|entry| entry.trim().to_string())
+                        .filter(|entry| !entry.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        match cfg {
+            Some(cfg) => Self {
+                https: Some(cfg.url.clone()),
+                http: Some(cfg.url.clone()),
+                no_proxy: cfg.no_proxy.clone().unwrap_or_else(env_no_proxy),
+            },
+            None => Self {
+                https: first(&HTTPS_PROXY_VARS),
+                http: first(&HTTP_PROXY_VARS),
+                no_proxy: env_no_proxy(),
+            },
+        }
+    }
+
+    /// 访问 url 时使用的代理，直连时为 None
+    pub fn proxy_for(&self, url: &str) -> Option<&str> {
+        let (scheme, rest) = url.split_once("://")?;
+        let proxy = match scheme {
+            "https" => self.https.as_deref(),
+            _ => self.http.as_deref(),
+        }?;
+        let host = host_of(rest);
+        if always_direct(host) || self.no_proxy.iter().any(|entry| bypasses(entry, host)) {
+            return None;
+        }
+        Some(proxy)
+    }
+
+    /// 访问 url 的 HTTP 客户端；代理地址无效时记录警告并直连
+    pub fn agent(&self, url: &str, timeout: Duration) -> ureq::Agent {
+        let mut builder = ureq::AgentBuilder::new().timeout(timeout);
+        if let Some(proxy) = self.proxy_for(url) {
+            match ureq::Proxy::new(proxy) {
+                Ok(proxy) => builder = builder.proxy(proxy),
+                Err(e) => warn!("Ignoring invalid proxy {}: {}", redact(proxy), e),
+            }
+        }
+        builder.build()
+    }
+}
+
+/// 地址中的主机部分，去掉用户信息、端口与 IPv6 的方括号
+fn host_of(rest: &str) -> &str {
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority.rsplit('@').next().unwrap_or_default();
+    match host.strip_prefix('[') {
+        Some(v6) => v6.split(']').next().unwrap_or_default(),
+        None => host.split(':').next().unwrap_or_default(),
+    }
+}
+
+/// 本机与链路本地地址不经代理
+fn always_direct(host: &str) -> bool {
+    if host.eq_ignore_ascii_case("localhost") {
+        return true;
+    }
+    match host.parse::<IpAddr>() {
+        Ok(ip) => {
+            ip.is_loopback()
+                || is_link_local(&ip)
+                || matches!(ip, IpAddr::V4(v4) if v4.is_link_local())
+        }
+        Err(_) => false,
+    }
+}
+
+/// NO_PROXY 的一项是否覆盖该主机：`*`、IP 或网段、域名及其子域名
+fn bypasses(entry: &str, host: &str) -> bool {
+    let entry = entry.trim();
+    if entry == "*" {
+        return true;
+    }
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return parse_network(entry).is_ok_and(|network| network.contains(ip));
+    }
+    let domain = entry.trim_start_matches('.');
+    !domain.is_empty()
+        && (host.eq_ignore_ascii_case(domain)
+            || host
+                .to_ascii_lowercase()
+                .ends_with(&format!(".{}", domain.to_ascii_lowercase())))
+}
+
+/// 隐去代理地址中的密码，用于日志
+fn redact(proxy: &str) -> String {
+    match (proxy.split_once("://"), proxy.rsplit_once('@')) {
+        (Some((scheme, _)), Some((_, host))) => format!("{}://***@{}", scheme, host),
+        _ => proxy.to_string(),
+    }
+}
@@ -0,0 +1,225 @@
+//! WebSocket 推送：订阅者连接后按固定间隔收到仪表盘快照及期间产生的事件，
+//! 仪表盘与第三方不必轮询控制套接字。`/standby` 路径供备节点同步规则与白名单。
+//! 反复 token 校验失败的来源由 AuthGuard 锁定
+
+use crate::{
+    controller::Firewall, daemon::dashboard_snapshot, lockout::AuthGuard, rules::RuleEngine,
+};
+
+use anyhow::{Context, Result};
+use futures::{SinkExt, StreamExt};
+use log::{debug, info, warn};
+use safe_traffic_common::{
+    config::WebSocketConfig,
+    transport::{PushUpdate, StandbyState},
+};
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::{broadcast::error::TryRecvError, Semaphore},
+    time,
+};
+use tokio_tungstenite::{
+    tungstenite::{
+        handshake::server::{ErrorResponse, Request, Response},
+        http::StatusCode,
+        Message,
+    },
+    WebSocketStream,
+};
+
+/// 备节点同步状态的路径
+pub const STANDBY_PATH: &str = "/standby";
+
+/// WebSocket 推送服务
+pub struct PushServer {
+    listener: TcpListener,
+    firewall: Arc<Firewall>,
+    engine: Arc<RuleEngine>,
+    interval: Duration,
+    max_clients: usize,
+    token: Option<String>,
+    guard: Arc<AuthGuard>,
+}
+
+impl PushServer {
+    /// 绑定监听地址，端口为 0 时由系统分配
+    pub async fn bind(
+        cfg: &WebSocketConfig,
+        firewall: Arc<Firewall>,
+        engine: Arc<RuleEngine>,
+    ) -> Result<Self> {
+        let listener = TcpListener::bind(cfg.listen)
+            .await
+            .with_context(|| format!("failed to listen on {}", cfg.listen))?;
+        Ok(Self {
+            listener,
+            engine,
+            interval: Duration::from_secs(cfg.interval_secs.unwrap_or(1).max(1)),
+            max_clients: cfg.max_clients.unwrap_or(16),
+            token: cfg.token.clone(),
+            guard: firewall
+                .auth_guard()
+                .unwrap_or_else(|| Arc::new(AuthGuard::new(cfg))),
+            firewall,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// 接受连接，锁定中的来源与超出上限的连接在握手前关闭
+    pub async fn run(self) {
+        info!(
+            "Pushing dashboard snapshots over WebSocket on {} every {}s",
+            self.listener
+                .local_addr()
+                .map(|addr| addr.to_string())
+                .unwrap_or_default(),
+            self.interval.as_secs()
+        );
+        let semaphore = Arc::new(Semaphore::new(self.max_clients));
+        let this = Arc::new(self);
+        loop {
+            let (stream, peer) = match this.listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!("Failed to accept WebSocket connection: {}", e);
+                    continue;
+                }
+            };
+            if this
+                .guard
+                .is_locked(&peer.ip(), this.firewall.clock().monotonic())
+            {
+                debug!("Rejecting WebSocket client {}: locked out", peer);
+                continue;
+            }
+            let Ok(permit) = Arc::clone(&semaphore).try_acquire_owned() else {
+                warn!(
+                    "Rejecting WebSocket client {}: {} clients connected",
+                    peer, this.max_clients
+                );
+                continue;
+            };
+            let this = Arc::clone(&this);
+            tokio::spawn(async move {
+                let _permit = permit;
+                match this.serve(stream, peer).await {
+                    Ok(()) => debug!("WebSocket client {} disconnected", peer),
+                    Err(e) => debug!("WebSocket client {} dropped: {}", peer, e),
+                }
+            });
+        }
+    }
+
+    /// 校验查询参数中的 token
+    fn authorize(&self, request: &Request) -> bool {
+        let Some(token) = &self.token else {
+            return true;
+        };
+        request
+            .uri()
+            .query()
+            .unwrap_or_default()
+            .split('&')
+            .any(|pair| pair.strip_prefix("token=") == Some(token.as_str()))
+    }
+
+    // 握手回调的签名由 tungstenite 决定
+    #[allow(clippy::result_large_err)]
+    async fn serve(&self, stream: TcpStream, peer: SocketAddr) -> Result<()> {
+        let unauthorized = AtomicBool::new(false);
+        let standby = AtomicBool::new(false);
+        let callback = |request: &Request, response: Response| {
+            if self.authorize(request) {
+                standby.store(request.uri().path() == STANDBY_PATH, Ordering::Relaxed);
+                Ok(response)
+            } else {
+                unauthorized.store(true, Ordering::Relaxed);
+                let mut rejection = ErrorResponse::new(Some("invalid token".to_string()));
+                *rejection.status_mut() = StatusCode::UNAUTHORIZED;
+                Err(rejection)
+            }
+        };
+        let handshake = tokio_tungstenite::accept_hdr_async(stream, callback).await;
+        if unauthorized.load(Ordering::Relaxed) {
+            let ip = peer.ip();
+            warn!("WebSocket client {} failed authentication", peer);
+            if self
+                .guard
+                .record_failure(ip, self.firewall.clock().monotonic())
+            {
+                self.guard.enforce(&self.firewall, ip).await;
+            }
+        } else if handshake.is_ok() {
+            self.guard.record_success(&peer.ip());
+        }
+        let socket = handshake?;
+        if standby.load(Ordering::Relaxed) {
+            info!("Standby {} connected", peer);
+            return self.serve_standby(socket).await;
+        }
+        let (mut sink, mut source) = socket.split();
+        let mut events = self.firewall.events.subscribe();
+        let mut interval = time::interval(self.interval);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    let mut update = PushUpdate {
+                        snapshot: dashboard_snapshot(&self.firewall, &self.engine).await,
+                        events: Vec::new(),
+                        missed_events: 0,
+                    };
+                    loop {
+                        match events.try_recv() {
+                            Ok(event) => update.events.push(event),
+                            Err(TryRecvError::Lagged(missed)) => update.missed_events += missed,
+                            Err(_) => break,
+                        }
+                    }
+                    sink.send(Message::Text(serde_json::to_string(&update)?)).await?;
+                }
+                message = source.next() => match message {
+                    None | Some(Ok(Message::Close(_))) => return Ok(()),
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => return Err(e.into()),
+                },
+            }
+        }
+    }
+
+    /// 备节点每发来一条文本消息，回复一次当前的规则与白名单
+    async fn serve_standby(&self, socket: WebSocketStream<TcpStream>) -> Result<()> {
+        let (mut sink, mut source) = socket.split();
+        while let Some(message) = source.next().await {
+            match message? {
+                Message::Text(_) => {
+                    let state = standby_state(&self.firewall).await?;
+                    sink.send(Message::Text(serde_json::to_string(&state)?))
+                        .await?;
+                }
+                Message::Close(_) => break,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 备节点接管时重建所需的状态
+async fn standby_state(fw: &Firewall) -> Result<StandbyState> {
+    Ok(StandbyState {
+        rules: fw.get_active_rules().await?,
+        excludes: fw.get_excludes().await,
+        temporary_excludes: fw.temporary_excludes().await,
+    })
+}
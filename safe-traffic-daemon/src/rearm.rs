@@ -0,0 +1,183 @@
+//! 重启后重新装上封禁：运行期间定期记录生效中的封禁，停机前保存；启动时停机前 within_hours 内
+//! 生效过的封禁重新装上，停机期间已到期的只装 rearm_secs，攻击者在重启后立即恢复时仍被拦住
+//!
+//! 提前解除（手动解封、flush、为 max_active_ips 腾位置）的封禁不再记录，重启后也不会恢复。
+
+use crate::{
+    controller::Firewall,
+    state::{state_file, write_atomic},
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use safe_traffic_common::{
+    config::{Action, RearmConfig},
+    utils::FirewallRule,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io::ErrorKind,
+    net::IpAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{fs, time};
+
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// 一个来源最近一次生效的封禁
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BanRecord {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_ports: Option<Vec<u16>>,
+    /// 最近一次看到该封禁生效的时间
+    pub last_active: DateTime<Utc>,
+    /// 到期时间，永久封禁为 None
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// 状态文件内容
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RearmState {
+    pub saved_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub bans: BTreeMap<IpAddr, BanRecord>,
+}
+
+/// 启动时要重新装上的封禁
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rearmed {
+    pub ip: IpAddr,
+    pub source_ports: Option<Vec<u16>>,
+    /// 封禁时长，永久封禁为 None
+    pub seconds: Option<u64>,
+}
+
+/// 记录生效中的封禁，启动时重新装上
+pub struct Rearm {
+    path: PathBuf,
+    within: chrono::Duration,
+    rearm_secs: u64,
+    bans: Mutex<BTreeMap<IpAddr, BanRecord>>,
+}
+
+impl Rearm {
+    pub fn new(cfg: &RearmConfig, state_dir: Option<&str>) -> Self {
+        Self {
+            path: state_file(state_dir, "bans.json"),
+            within: chrono::Duration::hours(cfg.within_hours.unwrap_or(6).max(1) as i64),
+            rearm_secs: cfg.rearm_secs.unwrap_or(900).max(1),
+            bans: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// 用当前的活跃规则更新记录：生效中的封禁刷新最近生效时间，未到期却已不在的封禁视为被提前解除，
+    /// 超过 within_hours 未生效的记录丢弃
+    pub fn observe(&self, rules: &[FirewallRule], now: DateTime<Utc>) {
+        let mut bans = self.bans.lock().unwrap();
+        let mut active = BTreeMap::new();
+        for rule in rules {
+            // 按 MAC 执行的封禁依赖邻居表，重启后不恢复
+            if !matches!(rule.rule_type, Action::Ban { .. }) || rule.mac.is_some() {
+                continue;
+            }
+            active.insert(
+                rule.ip,
+                BanRecord {
+                    source_ports: rule.source_ports.clone(),
+                    last_active: now,
+                    expires_at: rule.expires_at(),
+                },
+            );
+        }
+        bans.retain(|ip, record| {
+            active.contains_key(ip)
+                || (record.expires_at.is_some_and(|at| at <= now)
+                    && now - record.last_active < self.within)
+        });
+        bans.extend(active);
+    }
+
+    pub async fn save(&self, now: DateTime<Utc>) -> Result<()> {
+        let state = RearmState {
+            saved_at: Some(now),
+            bans: self.bans.lock().unwrap().clone(),
+        };
+        write_atomic(&self.path, &serde_json::to_vec_pretty(&state)?).await
+    }
+
+    /// 读取上次运行保存的状态，文件不存在时为空
+    pub async fn load(&self) -> Result<RearmState> {
+        match fs::read(&self.path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("invalid ban state {}", self.path.display())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(RearmState::default()),
+            Err(e) => Err(e).with_context(|| format!("failed to read {}", self.path.display())),
+        }
+    }
+
+    /// 根据上次保存的状态计算启动时要装上的封禁
+    pub fn plan(&self, state: &RearmState, now: DateTime<Utc>) -> Vec<Rearmed> {
+        let Some(saved_at) = state.saved_at else {
+            return Vec::new();
+        };
+        state
+            .bans
+            .iter()
+            .filter(|(_, record)| saved_at - record.last_active < self.within)
+            .map(|(ip, record)| Rearmed {
+                ip: *ip,
+                source_ports: record.source_ports.clone(),
+                seconds: match record.expires_at {
+                    None => None,
+                    Some(at) if at > now => Some((at - now).num_seconds().max(1) as u64),
+                    Some(_) => Some(self.rearm_secs),
+                },
+            })
+            .collect()
+    }
+
+    /// 重新装上停机前不久生效过的封禁，返回装上的数量
+    pub async fn restore(&self, fw: &Firewall) -> Result<usize> {
+        let now = fw.clock().wall();
+        let state = self.load().await?;
+        let plan = self.plan(&state, now);
+        let mut restored = 0;
+        for ban in plan {
+            match fw
+                .ban_on_ports(ban.ip, ban.seconds, ban.source_ports.as_deref())
+                .await
+            {
+                Ok(_) => restored += 1,
+                Err(e) => warn!("Failed to re-arm the ban of {}: {}", ban.ip, e),
+            }
+        }
+        // 装上的封禁随后由 observe 重新记录
+        self.observe(&fw.get_active_rules().await?, now);
+        Ok(restored)
+    }
+
+    /// 记录当前的活跃规则并写入状态文件，停机前调用
+    pub async fn checkpoint(&self, fw: &Firewall) -> Result<()> {
+        let now = fw.clock().wall();
+        self.observe(&fw.get_active_rules().await?, now);
+        self.save(now).await
+    }
+
+    pub async fn run(self: Arc<Self>, fw: Arc<Firewall>) {
+        info!(
+            "Recording active bans to {} for re-arming after a restart",
+            self.path.display()
+        );
+        let mut interval = time::interval(SAVE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.checkpoint(&fw).await {
+                warn!("Failed to record active bans: {}", e);
+            }
+        }
+    }
+}
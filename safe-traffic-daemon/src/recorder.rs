@@ -0,0 +1,80 @@
+//! 流量快照记录：每个采样周期把各来源的流量统计以 JSON 行追加写入文件，
+//! 之后可用 `--replay` 以任意配置离线重放，调整阈值与容量规划不需要接触生产环境
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use log::warn;
+use safe_traffic_common::{
+    clock::Clock,
+    utils::{StatsSnapshot, TrafficStats},
+};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    net::IpAddr,
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::time;
+
+/// 追加写入的流量快照文件
+pub struct StatsRecorder {
+    writer: BufWriter<File>,
+}
+
+impl StatsRecorder {
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .with_context(|| format!("failed to open stats record {}", path.display()))?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+        })
+    }
+
+    /// 写入一个快照，没有流量的来源不记录，全部来源都没有流量时不写入
+    pub fn record(
+        &mut self,
+        time: DateTime<Utc>,
+        stats: &DashMap<IpAddr, TrafficStats>,
+    ) -> Result<()> {
+        let snapshot = StatsSnapshot {
+            time,
+            stats: stats
+                .iter()
+                .filter(|entry| entry.rx_delta > 0 || entry.tx_delta > 0)
+                .map(|entry| (*entry.key(), entry.value().clone()))
+                .collect(),
+        };
+        if snapshot.stats.is_empty() {
+            return Ok(());
+        }
+        serde_json::to_writer(&mut self.writer, &snapshot)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    pub async fn run(
+        mut self,
+        stats: Arc<DashMap<IpAddr, TrafficStats>>,
+        interval: Duration,
+        clock: Arc<dyn Clock>,
+    ) {
+        let mut interval = time::interval(interval);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.record(clock.wall(), &stats) {
+                warn!("Failed to record traffic snapshot: {}", e);
+            }
+        }
+    }
+}
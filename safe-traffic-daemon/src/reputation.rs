@@ -0,0 +1,123 @@
+use crate::state::write_atomic;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io::ErrorKind,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+use tokio::fs;
+
+pub const DEFAULT_HALF_LIFE_DAYS: f64 = 7.0;
+/// 衰减到该值以下的记录在保存时丢弃
+const FORGET_BELOW: f64 = 0.01;
+
+/// 单个来源的信誉记录，分数越高表示违规越多
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reputation {
+    /// 上次更新时的分数，每次违规加 1，随时间按半衰期衰减
+    pub score: f64,
+    /// 累计违规次数
+    pub offenses: u64,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Reputation {
+    /// 衰减到 now 时的分数
+    fn score_at(&self, now: DateTime<Utc>, half_life_secs: f64) -> f64 {
+        let elapsed = (now - self.updated_at).num_seconds().max(0) as f64;
+        self.score * 0.5f64.powf(elapsed / half_life_secs)
+    }
+}
+
+/// 按 IP 记录的信誉分，保存在状态目录中，重启后继续生效
+pub struct ReputationStore {
+    scores: DashMap<IpAddr, Reputation>,
+    half_life_secs: f64,
+    /// 状态文件路径，未设置时只保存在内存中
+    path: Option<PathBuf>,
+    /// 自上次保存后是否有变化
+    dirty: AtomicBool,
+}
+
+impl ReputationStore {
+    /// 新建仅保存在内存中的实例
+    pub fn new(half_life_days: f64) -> Self {
+        ReputationStore {
+            scores: DashMap::new(),
+            half_life_secs: half_life_days.max(f64::MIN_POSITIVE) * 86_400.0,
+            path: None,
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// 读取状态文件，文件不存在时从空记录开始
+    pub async fn load(path: &Path, half_life_days: f64) -> Result<Self> {
+        let mut store = Self::new(half_life_days);
+        store.path = Some(path.to_path_buf());
+        let records: BTreeMap<IpAddr, Reputation> = match fs::read(path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("invalid reputation state {}", path.display()))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        };
+        store.scores.extend(records);
+        Ok(store)
+    }
+
+    /// 有变化时写回状态文件，同时丢弃已衰减殆尽的记录
+    pub async fn save(&self, now: DateTime<Utc>) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        self.scores
+            .retain(|_, reputation| reputation.score_at(now, self.half_life_secs) >= FORGET_BELOW);
+        let records: BTreeMap<IpAddr, Reputation> = self
+            .scores
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect();
+        if let Err(e) = write_atomic(path, &serde_json::to_vec_pretty(&records)?).await {
+            self.dirty.store(true, Ordering::Relaxed);
+            return Err(e);
+        }
+        debug!("saved reputation of {} sources", records.len());
+        Ok(())
+    }
+
+    /// 当前分数，没有记录时为 0
+    pub fn score(&self, ip: &IpAddr, now: DateTime<Utc>) -> f64 {
+        self.scores
+            .get(ip)
+            .map(|reputation| reputation.score_at(now, self.half_life_secs))
+            .unwrap_or(0.0)
+    }
+
+    /// 最近一次违规的时间，没有记录时为 None
+    pub fn last_offense(&self, ip: &IpAddr) -> Option<DateTime<Utc>> {
+        self.scores.get(ip).map(|reputation| reputation.updated_at)
+    }
+
+    /// 记录一次违规，返回新的分数
+    pub fn record(&self, ip: IpAddr, now: DateTime<Utc>) -> f64 {
+        let mut entry = self.scores.entry(ip).or_insert_with(|| Reputation {
+            score: 0.0,
+            offenses: 0,
+            updated_at: now,
+        });
+        entry.score = entry.score_at(now, self.half_life_secs) + 1.0;
+        entry.offenses += 1;
+        entry.updated_at = now;
+        self.dirty.store(true, Ordering::Relaxed);
+        entry.score
+    }
+}
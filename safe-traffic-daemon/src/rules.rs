@@ -1,57 +1,638 @@
-use crate::controller::Firewall;
+use crate::{
+    controller::{Firewall, DEFAULT_INSPECT_SECS, DEFAULT_MIRROR_SECS},
+    history::{self, ByteHistory},
+    incidents::{self, IncidentTracker},
+    journal::{DecisionJournal, JournalRecord, Outcome},
+    logger::{self, RuleLogger, RuleScope},
+    neighbors::NeighborTable,
+    nft::is_unavailable,
+    reputation::{self, ReputationStore},
+    sketch::SourceSketches,
+    spoof::SpoofGuard,
+    upstream::{BanDecision, UpstreamChecker},
+};
 use safe_traffic_common::{
-    config::{Action, HookType, Rule},
-    utils::{ControlSignal, RunState, SignalController, TrafficStats},
+    clock::{Clock, SystemClock},
+    config::{
+        Action, ActionSource, FlowClass, HookType, Rule, DEFAULT_EXCLUDED_ABUSE_INTERVAL_SECS,
+        DEFAULT_EXCLUDED_ABUSE_MULTIPLE, MAX_WINDOW_SECS,
+    },
+    events::{Event, EventKind, Incident},
+    reason::Reason,
+    rule_id::RuleId,
+    transport::{PauseTarget, TargetedPause, WindowAverage, WindowSnapshot},
+    utils::{ControlSignal, ExcludedTraffic, RunState, SignalController, TrafficStats},
 };
 
 use chrono::{DateTime, Utc};
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
 use futures::stream::{self, StreamExt, TryStreamExt};
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use std::{
+    cmp::Reverse,
+    collections::VecDeque,
     net::IpAddr,
-    sync::{atomic::Ordering, Arc},
-    time::Duration,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 use tokio::{sync::mpsc, time};
 
-const MAX_WINDOW_BUFFER: usize = 60;
+const MAX_WINDOW_BUFFER: usize = MAX_WINDOW_SECS as usize;
+/// 低内存模式下窗口槽的定点单位，字节/秒；u32 槽最大可表示约 64 GiB/s
+const COMPACT_UNIT: u64 = 16;
 const CONCURRENT_SIZE: usize = 10;
+const MAX_DEFERRED_ACTIONS: usize = 10_000;
+/// 暂存动作的最长保留时长，nft 超过该时长仍不可用时放弃，永久动作也不会无限重试
+const MAX_DEFERRED_AGE: Duration = Duration::from_secs(3600);
+/// 规则日志采样汇总的输出周期
+const LOG_SUMMARY_INTERVAL: Duration = Duration::from_secs(60);
+/// 信誉分写回状态文件的周期
+const REPUTATION_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+/// 计算动作生效耗时分位数的最近样本数
+const LATENCY_SAMPLES: usize = 1000;
+/// 按滚动一天总量触发的规则在触发原因中使用的指标名
+const DAY_BYTES_METRIC: &str = "bytes_per_rolling_day";
+
+/// 滑动窗口的槽
+#[derive(Clone, Debug)]
+enum Slots {
+    /// 每槽一个 u64，精确到字节
+    Exact(Box<[u64]>),
+    /// 低内存模式：每槽一个 u32，以 COMPACT_UNIT 为单位四舍五入
+    Compact(Box<[u32]>),
+}
+
+impl Slots {
+    fn len(&self) -> usize {
+        match self {
+            Slots::Exact(slots) => slots.len(),
+            Slots::Compact(slots) => slots.len(),
+        }
+    }
+
+    fn get(&self, index: usize) -> u64 {
+        match self {
+            Slots::Exact(slots) => slots[index],
+            Slots::Compact(slots) => slots[index] as u64 * COMPACT_UNIT,
+        }
+    }
+
+    fn set(&mut self, index: usize, bps: u64) {
+        match self {
+            Slots::Exact(slots) => slots[index] = bps,
+            Slots::Compact(slots) => {
+                let units = bps.saturating_add(COMPACT_UNIT / 2) / COMPACT_UNIT;
+                slots[index] = units.min(u32::MAX as u64) as u32;
+            }
+        }
+    }
+
+    /// 槽占用的堆内存，字节
+    fn heap_bytes(&self) -> usize {
+        match self {
+            Slots::Exact(slots) => std::mem::size_of_val(&**slots),
+            Slots::Compact(slots) => std::mem::size_of_val(&**slots),
+        }
+    }
+}
 
 /// 单 IP 的滑动窗口记录
 #[derive(Clone, Debug)]
-struct Window {
+pub struct Window {
     /// 最近 bytes 的循环缓冲
-    buffer: Vec<u64>,
+    buffer: Slots,
     /// 缓冲当前填充位置
     pos: usize,
-    /// 上次更新时间
-    last_ts: DateTime<Utc>,
+    /// 上次更新的单调时间
+    last_ts: Duration,
+    /// 已写入的采样数
+    samples: u64,
+}
+
+impl Window {
+    pub fn new(now: Duration) -> Self {
+        Window {
+            buffer: Slots::Exact(vec![0; MAX_WINDOW_BUFFER].into()), // 最多支持 60 秒窗口
+            pos: 0,
+            last_ts: now,
+            samples: 0,
+        }
+    }
+
+    /// 低内存模式的窗口：len 个 u32 槽，以 COMPACT_UNIT 字节/秒为单位
+    pub fn compact(now: Duration, len: usize) -> Self {
+        Window {
+            buffer: Slots::Compact(vec![0; len.clamp(1, MAX_WINDOW_BUFFER)].into()),
+            pos: 0,
+            last_ts: now,
+            samples: 0,
+        }
+    }
+
+    /// 写入 now 时刻的采样：距上次写入的每一整秒占一个槽，采样间隔超过 1 秒时
+    /// 其间的各秒都按该采样填充；不足 1 秒的采样被忽略，不足的部分计入下一次
+    pub fn advance(&mut self, bps: u64, now: Duration) {
+        let secs = now.saturating_sub(self.last_ts).as_secs();
+        if secs == 0 {
+            return;
+        }
+        for _ in 0..secs.min(self.buffer.len() as u64) {
+            self.pos = (self.pos + 1) % self.buffer.len();
+            self.buffer.set(self.pos, bps);
+        }
+        self.last_ts += Duration::from_secs(secs);
+        self.samples += secs;
+    }
+
+    /// 采样数是否足以计算 window_secs 秒的平均流量
+    pub fn is_warm(&self, window_secs: u64) -> bool {
+        self.samples >= self.span(window_secs)
+    }
+
+    /// 计算最近 window_secs 秒的平均流量
+    pub fn average(&self, window_secs: u64) -> u64 {
+        self.sum(window_secs) / self.span(window_secs)
+    }
+
+    /// 最近 window_secs 秒的总流量
+    pub fn sum(&self, window_secs: u64) -> u64 {
+        let window_size = self.span(window_secs) as usize;
+        let len = self.buffer.len();
+        let start = (self.pos + len - window_size) % len;
+        (0..window_size)
+            .map(|offset| self.buffer.get((start + offset) % len))
+            .sum()
+    }
+
+    /// 实际计算的秒数：至少 1 秒，超出缓冲长度的窗口只按缓冲中的采样计算
+    fn span(&self, window_secs: u64) -> u64 {
+        window_secs.clamp(1, self.buffer.len() as u64)
+    }
+
+    /// 已写入的采样，按时间从旧到新排列，最多为缓冲长度
+    pub fn slots(&self) -> Vec<u64> {
+        let len = self.buffer.len();
+        let count = (self.samples as usize).min(len);
+        (0..count)
+            .rev()
+            .map(|back| self.buffer.get((self.pos + len - back) % len))
+            .collect()
+    }
+
+    /// 窗口占用的内存，字节
+    fn memory_bytes(&self) -> usize {
+        std::mem::size_of::<Self>() + self.buffer.heap_bytes()
+    }
+}
+
+/// 单 IP 按流量类别划分的滑动窗口
+#[derive(Clone, Debug)]
+pub struct FlowWindows {
+    all: Window,
+    new: Window,
+    established: Window,
+}
+
+impl FlowWindows {
+    pub fn new(now: Duration) -> Self {
+        FlowWindows {
+            all: Window::new(now),
+            new: Window::new(now),
+            established: Window::new(now),
+        }
+    }
+
+    /// 低内存模式的窗口，见 Window::compact
+    pub fn compact(now: Duration, len: usize) -> Self {
+        FlowWindows {
+            all: Window::compact(now, len),
+            new: Window::compact(now, len),
+            established: Window::compact(now, len),
+        }
+    }
+
+    /// 三个窗口占用的内存，字节
+    fn memory_bytes(&self) -> usize {
+        self.all.memory_bytes() + self.new.memory_bytes() + self.established.memory_bytes()
+    }
+
+    /// 写入总流量与新建连接流量，已建立连接的流量取两者之差
+    pub fn advance(&mut self, bps: u64, new_bps: u64, now: Duration) {
+        self.all.advance(bps, now);
+        self.new.advance(new_bps, now);
+        self.established.advance(bps.saturating_sub(new_bps), now);
+    }
+
+    /// 获取指定类别的窗口
+    pub fn get(&self, class: FlowClass) -> &Window {
+        match class {
+            FlowClass::All => &self.all,
+            FlowClass::New => &self.new,
+            FlowClass::Established => &self.established,
+        }
+    }
+}
+
+/// 所有来源与目的地址的滑动窗口，由流量监控器在写入采样时按采样时间推进，规则引擎只读取；
+/// 引擎的检查被延迟或暂停时窗口仍按秒对齐
+#[derive(Debug)]
+pub struct WindowStore {
+    /// 决定取入站还是出站流量
+    hook: HookType,
+    sources: DashMap<IpAddr, FlowWindows>,
+    destinations: DashMap<IpAddr, Window>,
+    /// 来源最近 24 小时的降采样历史，只在有按滚动一天总量触发的规则时保存
+    history: Option<ByteHistory>,
+    /// 低内存模式下每个窗口的槽数，None 时为精确的 u64 槽
+    compact_slots: Option<usize>,
+    /// 是否写入过采样
+    written: AtomicBool,
+}
+
+impl WindowStore {
+    pub fn new(hook: HookType) -> Self {
+        Self {
+            hook,
+            sources: DashMap::new(),
+            destinations: DashMap::new(),
+            history: None,
+            compact_slots: None,
+            written: AtomicBool::new(false),
+        }
+    }
+
+    /// 低内存模式：窗口只保留规则中最长窗口所需的槽，每槽为 COMPACT_UNIT 字节/秒精度的 u32
+    pub fn with_low_memory(mut self, rules: &[Rule]) -> Self {
+        let longest = rules
+            .iter()
+            .filter(|rule| !rule.counts_day_bytes())
+            .map(|rule| rule.window_secs as usize)
+            .max()
+            .unwrap_or(0);
+        // 比最长窗口多一槽，与精确模式取同样的采样
+        self.compact_slots = Some((longest + 1).min(MAX_WINDOW_BUFFER));
+        self
+    }
+
+    pub fn is_low_memory(&self) -> bool {
+        self.compact_slots.is_some()
+    }
+
+    fn new_window(&self, at: Duration) -> Window {
+        match self.compact_slots {
+            Some(len) => Window::compact(at, len),
+            None => Window::new(at),
+        }
+    }
+
+    fn new_flow_windows(&self, at: Duration) -> FlowWindows {
+        match self.compact_slots {
+            Some(len) => FlowWindows::compact(at, len),
+            None => FlowWindows::new(at),
+        }
+    }
+
+    /// 正在跟踪的来源数
+    pub fn tracked_sources(&self) -> usize {
+        self.sources.len()
+    }
+
+    /// 每个来源的窗口与流量统计占用的内存，字节，不含哈希表自身的开销
+    pub fn bytes_per_source(&self) -> usize {
+        2 * std::mem::size_of::<IpAddr>()
+            + self.new_flow_windows(Duration::ZERO).memory_bytes()
+            + std::mem::size_of::<TrafficStats>()
+    }
+
+    /// 同时保存来源最近 24 小时的降采样历史
+    pub fn with_history(mut self) -> Self {
+        self.history = Some(ByteHistory::new());
+        self
+    }
+
+    /// 写入来源在 at 时刻的采样，首次出现时创建窗口，该采样不计入
+    pub fn record(&self, ip: IpAddr, stats: &TrafficStats, at: Duration) {
+        let (bps, new_bps) = match self.hook {
+            HookType::Input => (stats.rx_delta, stats.rx_new_delta),
+            HookType::Output => (stats.tx_delta, stats.tx_new_delta),
+        };
+        self.sources
+            .entry(ip)
+            .or_insert_with(|| self.new_flow_windows(at))
+            .advance(bps, new_bps, at);
+        if let Some(history) = &self.history {
+            history.record(ip, bps, at);
+        }
+        self.written.store(true, Ordering::Relaxed);
+    }
+
+    /// 写入发往本机地址 dest 的入站流量采样
+    pub fn record_destination(&self, dest: IpAddr, bps: u64, at: Duration) {
+        self.destinations
+            .entry(dest)
+            .or_insert_with(|| self.new_window(at))
+            .advance(bps, at);
+        self.written.store(true, Ordering::Relaxed);
+    }
+
+    /// 是否有监控器写入过采样
+    pub fn is_written(&self) -> bool {
+        self.written.load(Ordering::Relaxed)
+    }
+
+    pub fn get(&self, ip: &IpAddr) -> Option<FlowWindows> {
+        self.sources.get(ip).map(|win| win.value().clone())
+    }
+
+    pub fn destination(&self, dest: &IpAddr) -> Option<Window> {
+        self.destinations.get(dest).map(|win| win.value().clone())
+    }
+
+    /// 来源最近 24 小时的总字节数，未保存历史时为 0
+    pub fn day_bytes(&self, ip: &IpAddr, now: Duration) -> u64 {
+        self.history
+            .as_ref()
+            .map(|history| history.total(ip, now))
+            .unwrap_or(0)
+    }
+
+    /// 只保留满足条件的来源，不再被监控的来源从头开始计数
+    pub fn retain(&self, keep: impl Fn(&IpAddr) -> bool) {
+        self.sources.retain(|ip, _| keep(ip));
+    }
+
+    /// 只保留满足条件的目的地址，不再被计数的目的地址从头开始计数
+    pub fn retain_destinations(&self, keep: impl Fn(&IpAddr) -> bool) {
+        self.destinations.retain(|dest, _| keep(dest));
+    }
+
+    /// 丢弃超过一天的历史；历史不随来源停止被监控而丢弃，间歇抓取的来源仍累计一天的总量
+    pub fn prune_history(&self, now: Duration) {
+        if let Some(history) = &self.history {
+            history.prune(now);
+        }
+    }
+}
+
+/// nft 不可用期间暂存的动作
+#[derive(Clone, Debug)]
+struct DeferredAction {
+    ip: IpAddr,
+    /// 触发的规则序号
+    rule: usize,
+    mac: Option<String>,
+    /// 入队时按违规记录选定的动作
+    action: Action,
+    reason: Reason,
+    /// 入队时的单调时间
+    queued_at: Duration,
 }
 
 /// 规则引擎管理所有 IP 的窗口并执行动作
 pub struct RuleEngine {
     rules: Vec<Rule>,
     stats: Arc<DashMap<IpAddr, TrafficStats>>,
-    handles: DashMap<IpAddr, Vec<String>>,
-    windows: DashMap<IpAddr, Window>,
+    handles: DashMap<IpAddr, Vec<RuleId>>,
+    /// 监控器推进的滑动窗口
+    windows: Arc<WindowStore>,
     signal_controller: SignalController,
+    /// 每条规则的累计命中次数
+    rule_hits: Vec<AtomicU64>,
+    /// 每条规则的日志级别与采样
+    rule_logs: RuleLogger,
+    /// 每条规则当前作用中的来源，及创建的规则与触发时的流量
+    active: Vec<DashMap<IpAddr, (RuleId, u64)>>,
+    /// 每条规则是否处于 max_active_ips 上限，只在状态变化时记录
+    capped: Vec<AtomicBool>,
+    /// 每条规则上次评估的单调时间，尚未评估为 None
+    last_checked: std::sync::Mutex<Vec<Option<Duration>>>,
+    /// 白名单来源的流量统计，及上次记录时监控器的累计字节计数
+    excluded: DashMap<IpAddr, (ExcludedTraffic, u64)>,
+    /// 白名单来源的流量超过规则阈值的该倍数时发出滥用警告
+    abuse_multiple: f64,
+    /// 同一白名单来源两次滥用警告的最短间隔
+    abuse_interval: Duration,
+    /// 白名单来源上次发出滥用警告的单调时间
+    abuse_warned: DashMap<IpAddr, Duration>,
+    /// 已发出预警、流量尚未回落到预警线以下的 (IP, 规则序号)
+    warned: DashSet<(IpAddr, usize)>,
+    /// 邻居表，用于按 MAC 地址执行动作
+    neighbors: Option<Arc<NeighborTable>>,
+    /// 决策日志，记录被抽样来源每一拍的判定
+    journal: Option<Arc<DecisionJournal>>,
+    /// 伪造源洪泛防护，开启期间不创建逐 IP 规则
+    spoof_guard: Option<Arc<SpoofGuard>>,
+    /// 监控器写入的不同来源数草图，按来源数触发的规则读取
+    sketches: Option<Arc<SourceSketches>>,
+    /// 上游封禁列表，用于避免重复封禁
+    upstream: Option<Arc<UpstreamChecker>>,
+    /// 将短时间内的大量动作归并为 incident
+    incidents: IncidentTracker,
+    /// 最近的自动动作从触发到规则生效的耗时，毫秒
+    latencies: std::sync::Mutex<VecDeque<u64>>,
+    /// 来源信誉分，重复违规者更快、更久地被处置
+    reputation: Arc<ReputationStore>,
+    /// nft 不可用期间暂存的动作，按入队顺序执行
+    deferred: std::sync::Mutex<VecDeque<DeferredAction>>,
+    /// 启动预热时长，期间样本不足的窗口不执行动作
+    warmup: Duration,
+    /// 首次评估的单调时间
+    started_at: std::sync::OnceLock<Duration>,
+    /// 是否已就窗口从未写入发出警告
+    unfed_warned: AtomicBool,
+    /// 定向暂停的 IP 与规则，及到期的单调时间与墙上时间；规则以 rule_key 为键
+    paused: DashMap<PauseTarget, Option<(Duration, DateTime<Utc>)>>,
+    /// 逐拍以 debug 级别输出评估细节的 IP
+    traced: std::sync::Mutex<Option<IpAddr>>,
+    clock: Arc<dyn Clock>,
 }
 
 impl RuleEngine {
     /// 新建实例
     pub fn new(rules: Vec<Rule>, stats: Arc<DashMap<IpAddr, TrafficStats>>) -> Self {
+        let rule_hits = rules.iter().map(|_| AtomicU64::new(0)).collect();
+        let rule_logs = RuleLogger::new(&rules);
+        let active = rules.iter().map(|_| DashMap::new()).collect();
+        let capped = rules.iter().map(|_| AtomicBool::new(false)).collect();
+        let last_checked = std::sync::Mutex::new(vec![None; rules.len()]);
+        // 按滚动一天总量触发的规则不使用窗口，不参与预热
+        let warmup = rules
+            .iter()
+            .filter(|rule| !rule.counts_day_bytes())
+            .map(|rule| rule.window_secs)
+            .max()
+            .unwrap_or(0);
         RuleEngine {
             rules,
+            rule_hits,
+            rule_logs,
+            active,
+            capped,
+            last_checked,
             stats,
             handles: DashMap::new(),
-            windows: DashMap::new(),
+            windows: Arc::new(WindowStore::new(HookType::Input)),
             signal_controller: SignalController::new(),
+            excluded: DashMap::new(),
+            abuse_multiple: DEFAULT_EXCLUDED_ABUSE_MULTIPLE,
+            abuse_interval: Duration::from_secs(DEFAULT_EXCLUDED_ABUSE_INTERVAL_SECS),
+            abuse_warned: DashMap::new(),
+            warned: DashSet::new(),
+            neighbors: None,
+            journal: None,
+            spoof_guard: None,
+            sketches: None,
+            upstream: None,
+            incidents: IncidentTracker::new(
+                incidents::DEFAULT_THRESHOLD,
+                incidents::DEFAULT_WINDOW_SECS,
+            ),
+            latencies: std::sync::Mutex::new(VecDeque::new()),
+            reputation: Arc::new(ReputationStore::new(reputation::DEFAULT_HALF_LIFE_DAYS)),
+            deferred: std::sync::Mutex::new(VecDeque::new()),
+            warmup: Duration::from_secs(warmup),
+            started_at: std::sync::OnceLock::new(),
+            unfed_warned: AtomicBool::new(false),
+            paused: DashMap::new(),
+            traced: std::sync::Mutex::new(None),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// 设置上游封禁列表，长期封禁前先查询上游
+    pub fn with_upstream(mut self, upstream: Arc<UpstreamChecker>) -> Self {
+        self.upstream = Some(upstream);
+        self
+    }
+
+    /// 设置 incident 归并的阈值与统计窗口
+    pub fn with_incidents(mut self, threshold: u64, window_secs: u64) -> Self {
+        self.incidents = IncidentTracker::new(threshold, window_secs);
+        self
+    }
+
+    /// 设置白名单来源滥用警告的阈值倍数与最短间隔
+    pub fn with_excluded_abuse(mut self, multiple: f64, interval_secs: u64) -> Self {
+        self.abuse_multiple = multiple;
+        self.abuse_interval = Duration::from_secs(interval_secs);
+        self
+    }
+
+    /// 设置信誉分存储，通常从状态目录加载
+    pub fn with_reputation(mut self, reputation: Arc<ReputationStore>) -> Self {
+        self.reputation = reputation;
+        self
+    }
+
+    /// 设置启动预热时长，0 表示关闭预热
+    pub fn with_warmup(mut self, secs: u64) -> Self {
+        self.warmup = Duration::from_secs(secs);
+        self
+    }
+
+    /// 替换时间来源，窗口按其单调时间推进
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// 设置邻居表，启用按 MAC 地址执行的规则
+    pub fn with_neighbors(mut self, neighbors: Arc<NeighborTable>) -> Self {
+        self.neighbors = Some(neighbors);
+        self
+    }
+
+    /// 使用与流量监控器共享的窗口；默认的独立窗口没有写入方，只供直接写入采样的调用者使用
+    pub fn with_windows(mut self, windows: Arc<WindowStore>) -> Self {
+        self.windows = windows;
+        self
+    }
+
+    pub fn windows(&self) -> Arc<WindowStore> {
+        Arc::clone(&self.windows)
+    }
+
+    /// 设置决策日志
+    pub fn with_journal(mut self, journal: Arc<DecisionJournal>) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// 设置伪造源洪泛防护
+    pub fn with_spoof_guard(mut self, guard: Arc<SpoofGuard>) -> Self {
+        self.spoof_guard = Some(guard);
+        self
+    }
+
+    /// 使用与流量监控器共享的不同来源数草图
+    pub fn with_source_sketches(mut self, sketches: Arc<SourceSketches>) -> Self {
+        self.sketches = Some(sketches);
+        self
+    }
+
+    /// 伪造源洪泛的整体防护是否开启
+    fn spoofed(&self) -> bool {
+        self.spoof_guard
+            .as_ref()
+            .is_some_and(|guard| guard.is_active())
+    }
+
+    /// 被抽样的来源写入决策日志，写入失败不影响规则执行
+    fn journal(
+        &self,
+        ip: IpAddr,
+        bps: u64,
+        new_bps: u64,
+        time: DateTime<Utc>,
+        decision: (Outcome, Option<usize>),
+    ) {
+        let Some(journal) = &self.journal else {
+            return;
+        };
+        if !journal.sampled(&ip) {
+            return;
+        }
+        let (outcome, rule) = decision;
+        let record = JournalRecord {
+            time,
+            ip,
+            bps,
+            new_bps,
+            rule,
+            outcome,
+        };
+        if let Err(e) = journal.record(&record) {
+            warn!("failed to write decision journal: {}", e);
+        }
+    }
+
+    /// 查询规则要求按 MAC 执行时 IP 对应的 MAC 地址
+    fn mac_for(&self, rule: &Rule, ip: &IpAddr, hook: &HookType) -> Option<String> {
+        if !rule.enforce_by_mac.unwrap_or(false) {
+            return None;
+        }
+        if !matches!(hook, HookType::Input) {
+            debug!(
+                "MAC enforcement only applies to inbound traffic, using IP for {}",
+                ip
+            );
+            return None;
+        }
+
+        let mac = self.neighbors.as_ref()?.lookup(ip);
+        if mac.is_none() {
+            debug!(
+                "no neighbor entry for {}, falling back to IP enforcement",
+                ip
+            );
         }
+        mac
     }
 
     /// 获取当前运行状态
-    #[allow(dead_code)]
     pub async fn get_state(&self) -> RunState {
         self.signal_controller.get_state().await
     }
@@ -71,35 +652,734 @@ impl RuleEngine {
         self.signal_controller.stop().await
     }
 
+    /// 规则在定向暂停中的标识：配置中的名称，未命名时为序号
+    fn rule_key(&self, index: usize) -> String {
+        self.rules[index]
+            .name
+            .clone()
+            .unwrap_or_else(|| index.to_string())
+    }
+
+    /// 将按名称或序号指定的规则规范为 rule_key，规则不存在时返回错误
+    fn normalize(&self, target: PauseTarget) -> anyhow::Result<PauseTarget> {
+        let PauseTarget::Rule(rule) = target else {
+            return Ok(target);
+        };
+        let index = self
+            .rules
+            .iter()
+            .position(|candidate| candidate.name.as_deref() == Some(rule.as_str()))
+            .or_else(|| {
+                rule.parse::<usize>()
+                    .ok()
+                    .filter(|index| *index < self.rules.len())
+            })
+            .ok_or_else(|| anyhow::anyhow!("unknown rule: {}", rule))?;
+        Ok(PauseTarget::Rule(self.rule_key(index)))
+    }
+
+    /// 暂停对单个 IP 或单条规则的处置，seconds 为 None 时直到手动恢复；已有的规则照常到期
+    pub fn pause_target(
+        &self,
+        target: PauseTarget,
+        seconds: Option<u64>,
+    ) -> anyhow::Result<TargetedPause> {
+        let target = self.normalize(target)?;
+        let expiry = seconds.map(|seconds| {
+            (
+                self.clock.monotonic() + Duration::from_secs(seconds),
+                self.clock.wall() + chrono::Duration::seconds(seconds as i64),
+            )
+        });
+        self.paused.insert(target.clone(), expiry);
+        let pause = TargetedPause {
+            target,
+            until: expiry.map(|(_, until)| until),
+        };
+        info!("{}", pause);
+        Ok(pause)
+    }
+
+    /// 恢复对单个 IP 或单条规则的处置，返回此前是否处于暂停
+    pub fn resume_target(&self, target: PauseTarget) -> anyhow::Result<bool> {
+        let target = self.normalize(target)?;
+        let resumed = self.paused.remove(&target).is_some();
+        if resumed {
+            info!("{} resumed", target);
+        }
+        Ok(resumed)
+    }
+
+    /// 生效中的定向暂停，同时清理已到期的项
+    pub fn pauses(&self) -> Vec<TargetedPause> {
+        let now = self.clock.monotonic();
+        self.paused
+            .retain(|_, expiry| expiry.is_none_or(|(at, _)| now < at));
+        self.paused
+            .iter()
+            .map(|entry| TargetedPause {
+                target: entry.key().clone(),
+                until: entry.value().map(|(_, until)| until),
+            })
+            .collect()
+    }
+
+    /// 对象是否处于定向暂停中，到期的暂停在此移除
+    fn is_paused(&self, target: &PauseTarget) -> bool {
+        let now = self.clock.monotonic();
+        if self
+            .paused
+            .remove_if(target, |_, expiry| expiry.is_some_and(|(at, _)| now >= at))
+            .is_some()
+        {
+            info!("pause of {} expired", target);
+            return false;
+        }
+        self.paused.contains_key(target)
+    }
+
+    /// 开始逐拍跟踪 ip 的规则评估，None 时停止；返回之前跟踪的 IP
+    pub fn trace(&self, ip: Option<IpAddr>) -> Option<IpAddr> {
+        let previous = std::mem::replace(&mut *self.traced.lock().unwrap(), ip);
+        match ip {
+            Some(ip) => info!("tracing rule evaluation of {} at debug level", ip),
+            None => info!("stopped tracing rule evaluation"),
+        }
+        previous
+    }
+
+    /// 正在跟踪的 IP
+    pub fn traced(&self) -> Option<IpAddr> {
+        *self.traced.lock().unwrap()
+    }
+
+    /// 获取每条规则的累计命中次数
+    pub fn rule_hits(&self) -> Vec<u64> {
+        self.rule_hits
+            .iter()
+            .map(|hits| hits.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    /// 获取白名单来源的流量统计，按累计字节数降序排列
+    pub fn excluded_stats(&self) -> Vec<ExcludedTraffic> {
+        let mut stats: Vec<ExcludedTraffic> = self
+            .excluded
+            .iter()
+            .map(|entry| entry.value().0.clone())
+            .collect();
+        stats.sort_by_key(|entry| Reverse(entry.total_bytes));
+        stats
+    }
+
+    /// 某个 IP 的窗口数据与各规则窗口的平均值，尚未观测到该 IP 时为 None
+    pub fn window(&self, ip: &IpAddr) -> Option<WindowSnapshot> {
+        let win = self.windows.get(ip)?;
+        let now = self.clock.monotonic();
+        let stats = self.stats.get(ip).map(|entry| entry.value().clone());
+        let stats = stats.unwrap_or_default();
+        let averages = self
+            .rules
+            .iter()
+            .enumerate()
+            .map(|(index, rule)| {
+                let (average_bps, window_secs, metric) =
+                    self.observe(rule, &win, ip, rule.window_secs, now);
+                WindowAverage {
+                    rule: index,
+                    rule_name: rule.name.clone(),
+                    metric: metric.to_string(),
+                    window_secs,
+                    average_bps,
+                    threshold_bps: rule.threshold_bps,
+                    warm: rule.counts_day_bytes()
+                        || win
+                            .get(rule.flow.unwrap_or_default())
+                            .is_warm(rule.window_secs),
+                }
+            })
+            .collect();
+        Some(WindowSnapshot {
+            ip: *ip,
+            rx_delta: stats.rx_delta,
+            tx_delta: stats.tx_delta,
+            rx_new_delta: stats.rx_new_delta,
+            tx_new_delta: stats.tx_new_delta,
+            samples: win.get(FlowClass::All).samples,
+            slots: win.get(FlowClass::All).slots(),
+            new_slots: win.get(FlowClass::New).slots(),
+            established_slots: win.get(FlowClass::Established).slots(),
+            averages,
+        })
+    }
+
+    /// 当前信誉分
+    pub fn reputation(&self, ip: &IpAddr) -> f64 {
+        self.reputation.score(ip, self.clock.wall())
+    }
+
+    /// 最近的 incident
+    pub fn incidents(&self) -> Vec<Incident> {
+        self.incidents.incidents()
+    }
+
+    /// 是否有进行中的 incident
+    pub fn attack_in_progress(&self) -> bool {
+        self.incidents
+            .incidents()
+            .iter()
+            .any(|incident| incident.ended_at.is_none())
+    }
+
+    /// 最近自动动作生效耗时的 p99，毫秒，尚无样本时为 None
+    pub fn enforcement_latency_p99(&self) -> Option<u64> {
+        let mut latencies: Vec<u64> = self.latencies.lock().unwrap().iter().copied().collect();
+        if latencies.is_empty() {
+            return None;
+        }
+        latencies.sort_unstable();
+        let index = (latencies.len() * 99).div_ceil(100) - 1;
+        Some(latencies[index])
+    }
+
+    /// 记录一次动作的生效耗时
+    fn record_latency(&self, elapsed: Duration) {
+        let mut latencies = self.latencies.lock().unwrap();
+        if latencies.len() >= LATENCY_SAMPLES {
+            latencies.pop_front();
+        }
+        latencies.push_back(elapsed.as_millis() as u64);
+    }
+
+    /// 等待 nft 恢复后执行的动作数
+    pub fn deferred_actions(&self) -> usize {
+        self.deferred.lock().unwrap().len()
+    }
+
+    /// 暂存动作，同一 IP 的同一规则只保留最早的一条
+    fn defer(&self, action: DeferredAction) {
+        let mut deferred = self.deferred.lock().unwrap();
+        if deferred
+            .iter()
+            .any(|queued| queued.ip == action.ip && queued.rule == action.rule)
+        {
+            return;
+        }
+        if deferred.len() >= MAX_DEFERRED_ACTIONS {
+            if let Some(dropped) = deferred.pop_front() {
+                warn!(
+                    "deferred action queue full, dropping action of rule {} for {}",
+                    dropped.rule, dropped.ip
+                );
+            }
+        }
+        warn!(
+            "nftables unavailable, deferring action of rule {} for {}",
+            action.rule, action.ip
+        );
+        deferred.push_back(action);
+    }
+
+    /// 按入队顺序将暂存的动作各重试一次：nft 仍不可用时移到队尾，不阻塞后面的动作；
+    /// 超过 MAX_DEFERRED_AGE 或因其他原因失败的动作放弃
+    async fn replay_deferred(&self, fw: &Firewall, now: Duration) {
+        let mut applied = 0;
+        let pending = self.deferred.lock().unwrap().len();
+        for _ in 0..pending {
+            let Some(action) = self.deferred.lock().unwrap().pop_front() else {
+                break;
+            };
+            if self.is_paused(&PauseTarget::Ip(action.ip))
+                || self.is_paused(&PauseTarget::Rule(self.rule_key(action.rule)))
+            {
+                debug!(
+                    "dropping deferred action of rule {} for {}, paused",
+                    action.rule, action.ip
+                );
+                continue;
+            }
+            let waited = now.saturating_sub(action.queued_at).as_secs();
+            let rule = &self.rules[action.rule];
+            match logger::with_reason(
+                action.reason.clone(),
+                self.apply_action(
+                    fw,
+                    action.ip,
+                    rule,
+                    &action.action,
+                    action.mac.as_deref(),
+                    waited,
+                ),
+            )
+            .await
+            {
+                Ok(Some(rule_id)) => {
+                    applied += 1;
+                    self.track(action.ip, action.rule, rule_id, 0);
+                    // 按目的地址触发的动作作用于本机地址，不计入来源的信誉与 incident
+                    if rule.is_destination_scoped() {
+                        continue;
+                    }
+                    self.reputation.record(action.ip, self.clock.wall());
+                    if let Some(event) = self.incidents.record(action.ip, 0, self.clock.wall()) {
+                        fw.events.push(event).await;
+                    }
+                }
+                Ok(None) => {}
+                Err(e) if is_unavailable(&e) => {
+                    if now.saturating_sub(action.queued_at) >= MAX_DEFERRED_AGE {
+                        warn!(
+                            "giving up deferred action of rule {} for {} after {}s: {}",
+                            action.rule, action.ip, waited, e
+                        );
+                    } else {
+                        self.deferred.lock().unwrap().push_back(action);
+                    }
+                }
+                Err(e) => error!(
+                    "failed to apply deferred action of rule {} for {}: {}",
+                    action.rule, action.ip, e
+                ),
+            }
+        }
+        if applied > 0 {
+            info!(
+                "applied {} deferred actions after nftables recovered",
+                applied
+            );
+        }
+    }
+
+    /// 来源在 repeat_window 内有过违规时执行 repeat_action，否则执行 action；
+    /// last_offense 为本拍开始评估前最近一次违规的时间
+    fn action_for<'a>(
+        &self,
+        rule: &'a Rule,
+        last_offense: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> &'a Action {
+        match &rule.repeat_action {
+            Some(repeat)
+                if last_offense.is_some_and(|at| {
+                    (now - at).num_seconds() < rule.repeat_window_secs() as i64
+                }) =>
+            {
+                repeat
+            }
+            _ => &rule.action,
+        }
+    }
+
+    /// 按来源信誉延长定时动作的时长
+    fn extended_secs(&self, rule: &Rule, ip: &IpAddr, seconds: u64) -> u64 {
+        let multiplier = rule.score_multiplier.unwrap_or(0.0);
+        let score = self.reputation.score(ip, self.clock.wall());
+        if multiplier <= 0.0 || score <= 0.0 {
+            return seconds;
+        }
+        let extended = (seconds as f64 * (1.0 + score * multiplier)) as u64;
+        debug!(
+            "{} has reputation {:.2}, extending action from {}s to {}s",
+            ip, score, seconds, extended
+        );
+        extended
+    }
+
+    /// 保存信誉分，失败时只记录错误
+    async fn save_reputation(&self) {
+        if let Err(e) = self.reputation.save(self.clock.wall()).await {
+            error!("failed to save reputation: {}", e);
+        }
+    }
+
+    /// 记录规则为 IP 创建的动作，过期后清理
+    fn track(&self, ip: IpAddr, index: usize, rule_id: RuleId, bps: u64) {
+        self.active[index].insert(ip, (rule_id.clone(), bps));
+        self.handles
+            .entry(ip)
+            .and_modify(|vec| vec.push(rule_id.clone()))
+            .or_insert_with(|| vec![rule_id]);
+    }
+
+    /// 执行规则的某个动作；waited 为动作被推迟的秒数，定时动作相应缩短，未创建规则时返回 None
+    async fn apply_action(
+        &self,
+        fw: &Firewall,
+        ip: IpAddr,
+        rule: &Rule,
+        action: &Action,
+        mac: Option<&str>,
+        waited: u64,
+    ) -> anyhow::Result<Option<RuleId>> {
+        // 推迟期间已到期的定时动作不再执行
+        let extend =
+            |seconds: Option<u64>| seconds.map(|seconds| self.extended_secs(rule, &ip, seconds));
+        let remaining = |seconds: Option<u64>| match seconds {
+            Some(seconds) if waited > 0 && seconds <= waited => None,
+            Some(seconds) => Some(Some(seconds - waited)),
+            None => Some(None),
+        };
+
+        // 防火墙接口按整秒计时
+        let seconds = action.seconds();
+        let rule_id = match *action {
+            Action::RateLimit { kbps, burst, .. } => {
+                let Some(seconds) = remaining(extend(seconds)) else {
+                    return Ok(None);
+                };
+                debug!("intend to limit the speed of {} to {}kbps", ip, kbps);
+
+                match mac {
+                    Some(mac) => fw.limit_mac(ip, mac, kbps, burst, seconds).await?,
+                    None => {
+                        fw.limit_on_ports(ip, kbps, burst, seconds, rule.source_ports.as_deref())
+                            .await?
+                    }
+                }
+            }
+            Action::Ban { .. } => {
+                let Some(seconds) = remaining(extend(seconds)) else {
+                    return Ok(None);
+                };
+                let seconds = match &self.upstream {
+                    Some(upstream) => match upstream.decide(ip, seconds).await {
+                        BanDecision::Apply(seconds) => seconds,
+                        BanDecision::Skip => {
+                            debug!("{} is blocked upstream, skipping local ban", ip);
+                            return Ok(None);
+                        }
+                    },
+                    None => seconds,
+                };
+                debug!("intend to ban {} for {} seconds", ip, seconds.unwrap_or(0));
+
+                match mac {
+                    Some(mac) => fw.ban_mac(ip, mac, seconds).await?,
+                    None => {
+                        fw.ban_on_ports(ip, seconds, rule.source_ports.as_deref())
+                            .await?
+                    }
+                }
+            }
+            Action::Mirror {
+                target, ref device, ..
+            } => {
+                let Some(seconds) = remaining(extend(Some(seconds.unwrap_or(DEFAULT_MIRROR_SECS))))
+                else {
+                    return Ok(None);
+                };
+                debug!("intend to mirror traffic of {} to {}", ip, target);
+
+                fw.mirror(ip, target, device.as_deref(), seconds).await?
+            }
+            Action::PoliceSources { kbps, burst, .. } => {
+                let Some(seconds) = remaining(extend(seconds)) else {
+                    return Ok(None);
+                };
+                debug!("intend to police sources of {} to {}kbps", ip, kbps);
+
+                fw.police_sources(ip, kbps, burst, seconds).await?
+            }
+            Action::Warn { .. } => {
+                let Some(seconds) = remaining(extend(seconds)) else {
+                    return Ok(None);
+                };
+                debug!("intend to warn {}", ip);
+
+                fw.warn(ip, seconds).await?
+            }
+            Action::Inspect { .. } => {
+                let Some(seconds) =
+                    remaining(extend(Some(seconds.unwrap_or(DEFAULT_INSPECT_SECS))))
+                else {
+                    return Ok(None);
+                };
+                debug!("intend to inspect packets of {}", ip);
+
+                match fw.inspect(ip, seconds).await? {
+                    Some(rule_id) => rule_id,
+                    None => return Ok(None),
+                }
+            }
+        };
+        Ok(Some(rule_id))
+    }
+
+    /// 找出本轮到期需要评估的规则，并记录其评估时间
+    fn due_rules(&self, now: Duration) -> Vec<bool> {
+        let mut last_checked = self.last_checked.lock().unwrap();
+        self.rules
+            .iter()
+            .zip(last_checked.iter_mut())
+            .map(|(rule, last)| {
+                let interval = Duration::from_secs(rule.check_interval.unwrap_or(0));
+                let due = last.is_none_or(|last| now.saturating_sub(last) >= interval);
+                if due {
+                    *last = Some(now);
+                }
+                due
+            })
+            .collect()
+    }
+
+    /// 规则作用的来源数达到 max_active_ips 时只保留流量最大的来源：新来源流量更大时解除最小来源的动作，
+    /// 否则不执行；返回是否可以执行动作
+    async fn make_room(
+        &self,
+        fw: &Firewall,
+        index: usize,
+        ip: IpAddr,
+        bps: u64,
+    ) -> anyhow::Result<bool> {
+        let Some(max) = self.rules[index].max_active_ips else {
+            return Ok(true);
+        };
+        let active = &self.active[index];
+        if active.contains_key(&ip) || active.len() < max {
+            if self.capped[index].swap(false, Ordering::Relaxed) {
+                info!("rule {} is below max_active_ips ({}) again", index, max);
+            }
+            return Ok(true);
+        }
+        if !self.capped[index].swap(true, Ordering::Relaxed) {
+            let message = format!(
+                "rule {} reached max_active_ips ({}), keeping the heaviest sources",
+                index, max
+            );
+            warn!("{}", message);
+            fw.events
+                .push(Event::new(EventKind::Capacity, message))
+                .await;
+        }
+
+        // 流量相同时先释放地址较大的来源，结果与遍历顺序无关
+        let lightest = active
+            .iter()
+            .map(|entry| {
+                (
+                    entry.value().1,
+                    Reverse(*entry.key()),
+                    entry.value().0.clone(),
+                )
+            })
+            .min_by_key(|(bps, ip, _)| (*bps, *ip));
+        let Some((lightest_bps, Reverse(lightest_ip), lightest_id)) = lightest else {
+            return Ok(true);
+        };
+        if bps <= lightest_bps {
+            debug!(
+                "rule {} is at max_active_ips, not acting on {} ({} bytes/s)",
+                index, ip, bps
+            );
+            return Ok(false);
+        }
+        info!(
+            "rule {} is at max_active_ips, releasing {} ({} bytes/s) for {} ({} bytes/s)",
+            index, lightest_ip, lightest_bps, ip, bps
+        );
+        fw.unblock(&lightest_id).await?;
+        active.remove(&lightest_ip);
+        Ok(true)
+    }
+
+    /// 规则在来源上观测到的指标值、计算所用的时长与指标名
+    fn observe(
+        &self,
+        rule: &Rule,
+        win: &FlowWindows,
+        ip: &IpAddr,
+        window_secs: u64,
+        now: Duration,
+    ) -> (u64, u64, &'static str) {
+        if rule.counts_day_bytes() {
+            return (
+                self.windows.day_bytes(ip, now),
+                history::DAY_SECS,
+                DAY_BYTES_METRIC,
+            );
+        }
+        let flow = rule.flow.unwrap_or_default();
+        (
+            win.get(flow).average(window_secs),
+            window_secs,
+            flow.metric(),
+        )
+    }
+
+    /// 流量达到规则的预警线但未超过阈值时发出一次预警事件，回落到预警线以下后可再次预警
+    async fn warn_if_near(&self, fw: &Firewall, ip: IpAddr, index: usize, avg_bps: u64) {
+        let rule = &self.rules[index];
+        let Some(percent) = rule.warn_at_percent else {
+            return;
+        };
+        let warn_bps = rule.threshold_bps.saturating_mul(percent) / 100;
+        if avg_bps < warn_bps {
+            self.warned.remove(&(ip, index));
+            return;
+        }
+        if avg_bps > rule.threshold_bps || !self.warned.insert((ip, index)) {
+            return;
+        }
+        let unit = if rule.counts_day_bytes() {
+            "bytes in 24h"
+        } else {
+            "bytes/s"
+        };
+        warn!(
+            "{} reached {}% of rule {} threshold ({} of {} {})",
+            ip, percent, index, avg_bps, rule.threshold_bps, unit
+        );
+        fw.events
+            .push(
+                Event::new(
+                    EventKind::Warning,
+                    format!(
+                        "{} at {} {}, {}% of rule {} threshold {} {}",
+                        ip, avg_bps, unit, percent, index, rule.threshold_bps, unit
+                    ),
+                )
+                .with_ip(ip),
+            )
+            .await;
+    }
+
+    /// 记录白名单 IP 本次采样的流量及被跳过的命中次数，首次被跳过时返回 true。
+    /// 累计字节数取监控器累计计数 bytes 的增量，与规则评估的间隔无关
+    fn record_excluded(
+        &self,
+        ip: IpAddr,
+        bytes: u64,
+        bps: u64,
+        suppressed: u64,
+        now: DateTime<Utc>,
+    ) -> bool {
+        let mut guard = self.excluded.entry(ip).or_insert_with(|| {
+            (
+                ExcludedTraffic {
+                    ip,
+                    total_bytes: 0,
+                    last_bps: 0,
+                    suppressed_hits: 0,
+                    last_seen: now,
+                },
+                0,
+            )
+        });
+        let (entry, counter) = &mut *guard;
+        // 计数小于上次时说明监控器重新开始计数（来源曾被淘汰或计数器被重置）
+        let added = bytes.checked_sub(*counter).unwrap_or(bytes);
+        *counter = bytes;
+        let first = suppressed > 0 && entry.suppressed_hits == 0;
+        if first {
+            warn!(
+                "excluded IP {} exceeded rule thresholds ({} bytes/s), no action taken",
+                ip, bps
+            );
+        }
+        entry.total_bytes = entry.total_bytes.saturating_add(added);
+        entry.last_bps = bps;
+        entry.suppressed_hits += suppressed;
+        entry.last_seen = now;
+        first
+    }
+
+    /// 白名单来源的流量超过某条规则阈值的 abuse_multiple 倍时发出警告事件，指明负责的白名单；
+    /// 同一来源每 abuse_interval 最多一次，避免事发时临时加入的宽泛白名单长期放过攻击者
+    async fn warn_if_abusive(&self, fw: &Firewall, ip: IpAddr, win: &FlowWindows, now: Duration) {
+        if self
+            .abuse_warned
+            .get(&ip)
+            .is_some_and(|last| now.saturating_sub(*last) < self.abuse_interval)
+        {
+            return;
+        }
+        // 超过倍数最多的规则，通常是阈值最严格的一条
+        let worst = self
+            .rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| !rule.is_destination_scoped() && rule.threshold_bps > 0)
+            .map(|(index, rule)| {
+                let observed = self.observe(rule, win, &ip, rule.window_secs, now);
+                let ratio = observed.0 as f64 / rule.threshold_bps as f64;
+                (index, rule, observed, ratio)
+            })
+            .filter(|(.., ratio)| *ratio >= self.abuse_multiple)
+            .max_by(|a, b| a.3.total_cmp(&b.3));
+        let Some((index, rule, (observed, window_secs, metric), ratio)) = worst else {
+            return;
+        };
+        self.abuse_warned.insert(ip, now);
+
+        let exclusion = fw
+            .exclusion_of(&ip)
+            .await
+            .unwrap_or_else(|| "an exclusion".to_string());
+        let message = format!(
+            "excluded {} at {:.0}x the threshold of rule {} ({} {} over {}s): {} may be hiding abuse",
+            ip, ratio, index, observed, metric, window_secs, exclusion
+        );
+        warn!("{}", message);
+        let reason = Reason {
+            rule: index,
+            rule_name: rule.name.clone(),
+            metric: metric.to_string(),
+            observed,
+            threshold: rule.threshold_bps,
+            window_secs,
+        };
+        fw.events
+            .push(
+                Event::new(EventKind::Warning, message)
+                    .with_ip(ip)
+                    .with_reason(reason),
+            )
+            .await;
+    }
+
     /// 检查所有 IP 并在必要时调用防火墙控制
     pub async fn check_and_apply(&self, fw_origin: Arc<Firewall>) -> anyhow::Result<()> {
-        let now = Utc::now();
+        let now = self.clock.monotonic();
+        let seen = self.clock.wall();
+        // 有流量统计而窗口从未写入，多半是没有通过 with_windows 共享监控器的窗口
+        if !self.stats.is_empty()
+            && !self.windows.is_written()
+            && !self.unfed_warned.swap(true, Ordering::Relaxed)
+        {
+            warn!("rule engine has traffic stats but its window store has never been written; rules cannot trigger until the monitor shares it");
+        }
+        let mut due = self.due_rules(now);
+        for (index, due) in due.iter_mut().enumerate() {
+            if *due && self.is_paused(&PauseTarget::Rule(self.rule_key(index))) {
+                debug!("rule {} is paused, skipping", index);
+                *due = false;
+            }
+        }
+        self.replay_deferred(&fw_origin, now).await;
+        if let Some(event) = self.incidents.close_idle(seen) {
+            fw_origin.events.push(event).await;
+        }
+        let warming = now.saturating_sub(*self.started_at.get_or_init(|| now)) < self.warmup;
+        let spoofed = self.spoofed();
+        self.check_destinations(&fw_origin, &due, now, warming, spoofed)
+            .await?;
         // 遍历每个 IP 的最新流量
         let entries: Vec<_> = self
             .stats
             .iter()
             // .filter(|entry| !fw_origin.is_excluded(entry.key()))
             .map(|entry| {
-                let bps = match fw_origin.hook {
-                    HookType::Input => entry.value().rx_delta,
-                    HookType::Output => entry.value().tx_delta,
+                let stats = entry.value();
+                let (bps, new_bps, bytes) = match fw_origin.hook {
+                    HookType::Input => (stats.rx_delta, stats.rx_new_delta, stats.rx_bytes),
+                    HookType::Output => (stats.tx_delta, stats.tx_new_delta, stats.tx_bytes),
                 };
-                // 获取或创建滑动窗口
-                let mut win = self.windows.entry(*entry.key()).or_insert_with(|| Window {
-                    buffer: vec![0; MAX_WINDOW_BUFFER], // 最多支持 60 秒窗口
-                    pos: 0,
-                    last_ts: now,
-                });
-
-                // 如果超过 1 秒，推进循环缓冲
-                if (now - win.last_ts).num_seconds() >= 1 {
-                    win.pos = (win.pos + 1) % win.buffer.len();
-                    let pos = win.pos;
-                    win.buffer[pos] = bps;
-                    win.last_ts = now;
-                }
-                let v = win.value().clone();
-                (*entry.key(), v)
+                // 尚未写入采样的来源窗口为空
+                let win = self
+                    .windows
+                    .get(entry.key())
+                    .unwrap_or_else(|| FlowWindows::new(now));
+                (*entry.key(), win, bps, new_bps, bytes)
             })
             .collect();
 
@@ -107,113 +1387,482 @@ impl RuleEngine {
             "starting checking rule: stats entries count: {}",
             entries.len()
         );
+        let traced = self.traced();
+        if let Some(ip) = traced.filter(|ip| entries.iter().all(|entry| entry.0 != *ip)) {
+            debug!("trace {}: no traffic sampled, no rule evaluated", ip);
+        }
 
+        let due = &due;
         // 异步并发处理
         stream::iter(entries)
-            .filter(|entry| {
-                let fw_origin = &fw_origin;
-                let ip = entry.0;
-                async move { !fw_origin.is_excluded(&ip).await }
-            })
             .map(Ok::<_, anyhow::Error>)
-            .try_for_each_concurrent(CONCURRENT_SIZE, |(ip, win)| {
+            .try_for_each_concurrent(CONCURRENT_SIZE, |(ip, win, bps, new_bps, bytes)| {
                 let fw = Arc::clone(&fw_origin);
                 async move {
+                    let trace = traced == Some(ip);
+                    if trace {
+                        debug!(
+                            "trace {}: sampled {} B/s ({} B/s new flows), warm-up {}",
+                            ip,
+                            bps,
+                            new_bps,
+                            if warming { "in progress" } else { "over" }
+                        );
+                    }
+                    // 全局白名单中的 IP 仍然计量，白名单优先于自动动作时不执行任何动作
+                    if fw.exclusion_prevails(&ip, ActionSource::Automatic).await {
+                        let suppressed = self
+                            .rules
+                            .iter()
+                            .zip(due)
+                            .filter(|(rule, due)| {
+                                **due
+                                    && !rule.is_destination_scoped()
+                                    && self.observe(rule, &win, &ip, rule.window_secs, now).0
+                                        > rule.threshold_bps
+                            })
+                            .count() as u64;
+                        if trace {
+                            debug!(
+                                "trace {}: excluded, exclusions take precedence over automatic actions; {} rules over threshold suppressed",
+                                ip, suppressed
+                            );
+                        }
+                        if self.record_excluded(ip, bytes, bps, suppressed, seen) {
+                            let message = format!(
+                                "refused automatic actions against excluded {}: exclusions take precedence",
+                                ip
+                            );
+                            fw.events
+                                .push(Event::new(EventKind::Conflict, message).with_ip(ip))
+                                .await;
+                        }
+                        self.warn_if_abusive(&fw, ip, &win, now).await;
+                        self.journal(ip, bps, new_bps, seen, (Outcome::Excluded, None));
+                        return Ok(());
+                    }
+
+                    // 定向暂停的 IP 不执行新的动作，已有的规则由 expire_rules 照常到期
+                    if self.is_paused(&PauseTarget::Ip(ip)) {
+                        debug!("enforcement against {} is paused", ip);
+                        if trace {
+                            debug!("trace {}: enforcement paused, no rule evaluated", ip);
+                        }
+                        self.journal(ip, bps, new_bps, seen, (Outcome::Paused, None));
+                        return Ok(());
+                    }
+
+                    let score = self.reputation.score(&ip, seen);
+                    // 本拍先执行动作的规则会记录违规，不应让后面的规则视为再犯
+                    let last_offense = self.reputation.last_offense(&ip);
+                    let sni = fw
+                        .inspector()
+                        .map(|inspector| inspector.sni(&ip))
+                        .unwrap_or_default();
+                    let dports = fw
+                        .flows()
+                        .map(|flows| flows.dports(&ip))
+                        .unwrap_or_default();
+                    let mut excluded = false;
+                    let mut suppressed = 0;
+                    // 本拍的判定，记录第一条执行了动作的规则
+                    let mut decision = (Outcome::NoAction, None);
                     // 对每条规则进行检测
-                    for rule in &self.rules {
-                        if rule.is_excluded(&ip) {
-                            debug!("skipping excluded IP: {}", ip);
+                    for (index, rule) in self.rules.iter().enumerate() {
+                        let label = if trace { self.rule_key(index) } else { String::new() };
+                        // 未到该规则的评估时间；按目的地址触发的规则单独评估
+                        if !due[index] || rule.is_destination_scoped() {
+                            if trace {
+                                debug!(
+                                    "trace {}: rule {} skipped: {}",
+                                    ip,
+                                    label,
+                                    if rule.is_destination_scoped() {
+                                        "triggered by destination traffic"
+                                    } else if self.is_paused(&PauseTarget::Rule(label.clone())) {
+                                        "rule paused"
+                                    } else {
+                                        "check_interval not elapsed"
+                                    }
+                                );
+                            }
+                            continue;
+                        }
+                        // 按 SNI 限定的规则只作用于检查中请求过对应域名的来源
+                        if !rule.matches_sni(&sni) {
+                            if trace {
+                                debug!("trace {}: rule {} skipped: no matching SNI", ip, label);
+                            }
+                            continue;
+                        }
+                        // 按目的端口限定的规则只作用于流表中访问过对应端口的来源
+                        if !rule.matches_dports(&dports) {
+                            if trace {
+                                debug!(
+                                    "trace {}: rule {} skipped: no flow to its ports",
+                                    ip, label
+                                );
+                            }
+                            continue;
+                        }
+                        let rule_win = win.get(rule.flow.unwrap_or_default());
+                        // 信誉分达到要求的重复违规者不等待完整窗口
+                        let window_secs = match rule.min_reputation_to_skip {
+                            Some(min) if score >= min => 1,
+                            _ => rule.window_secs,
+                        };
+                        let (avg_bps, window_secs, metric) =
+                            self.observe(rule, &win, &ip, window_secs, now);
+                        if trace {
+                            let sum = if rule.counts_day_bytes() {
+                                avg_bps
+                            } else {
+                                rule_win.sum(window_secs)
+                            };
+                            debug!(
+                                "trace {}: rule {}: {} over {}s sum {} average {} {} threshold {}; {}; {}",
+                                ip,
+                                label,
+                                metric,
+                                window_secs,
+                                sum,
+                                avg_bps,
+                                if avg_bps > rule.threshold_bps { ">" } else { "<=" },
+                                rule.threshold_bps,
+                                match self.active[index].get(&ip) {
+                                    Some(active) => format!("active rule {}", active.0),
+                                    None => "no active rule".to_string(),
+                                },
+                                match last_offense {
+                                    Some(at) => format!(
+                                        "last offense {}s ago (repeat_window {}s)",
+                                        (seen - at).num_seconds(),
+                                        rule.repeat_window_secs()
+                                    ),
+                                    None => "no previous offense".to_string(),
+                                }
+                            );
+                        }
+
+                        if let Some(entry) = rule.excluded_by(&ip) {
+                            debug!("skipping excluded IP: {} (matched {})", ip, entry);
+                            if trace {
+                                debug!(
+                                    "trace {}: rule {} not enforced: excluded by {}",
+                                    ip, label, entry
+                                );
+                            }
+                            excluded = true;
+                            if avg_bps > rule.threshold_bps {
+                                suppressed += 1;
+                            }
                             continue;
                         }
 
-                        let window_size = rule.window_secs as usize;
-                        // 计算滑动窗口内总流量
-                        let sum: u64 = win
-                            .buffer
-                            .iter()
-                            .cycle()
-                            .skip((win.pos + win.buffer.len() - window_size) % win.buffer.len())
-                            .take(window_size)
-                            .sum();
-                        let avg_bps = sum / rule.window_secs;
                         // 超过阈值 => 执行动作
                         debug!("{} average bps: {}", &ip, &avg_bps);
+                        self.warn_if_near(&fw, ip, index, avg_bps).await;
                         if avg_bps > rule.threshold_bps {
-                            match rule.action {
-                                Action::RateLimit {
-                                    kbps,
-                                    burst,
-                                    seconds,
-                                } => {
-                                    debug!("intend to limit the speed of {} to {}kbps", ip, kbps);
-
-                                    let rule_id =
-                                        fw.clone().limit(ip, kbps, burst, seconds).await?;
-                                    self.handles
-                                        .entry(ip)
-                                        .and_modify(|vec| vec.push(rule_id.clone()))
-                                        .or_insert_with(|| vec![rule_id]);
+                            // 预热期内窗口样本不足，平均值不可靠，只记录不执行
+                            if warming && !rule_win.is_warm(window_secs) {
+                                info!(
+                                    "warm-up: {} would trigger rule {} ({} bytes/s), not enforced",
+                                    ip, index, avg_bps
+                                );
+                                if trace {
+                                    debug!(
+                                        "trace {}: rule {} not enforced: window not warm",
+                                        ip, label
+                                    );
                                 }
-                                Action::Ban { seconds } => {
+                                if decision.0 == Outcome::NoAction {
+                                    decision = (Outcome::Warmup, Some(index));
+                                }
+                                continue;
+                            }
+                            // 伪造的来源封不完，交给整体防护
+                            if spoofed {
+                                debug!(
+                                    "spoofed-source flood: not enforcing rule {} against {}",
+                                    index, ip
+                                );
+                                if let Some(guard) = &self.spoof_guard {
+                                    guard.suppress();
+                                }
+                                if trace {
                                     debug!(
-                                        "intend to ban {} for {} seconds",
-                                        ip,
-                                        seconds.unwrap_or(0)
+                                        "trace {}: rule {} not enforced: spoofed-source flood",
+                                        ip, label
                                     );
-
-                                    let rule_id = fw.ban(ip, seconds).await?;
-                                    self.handles
-                                        .entry(ip)
-                                        .and_modify(|vec| vec.push(rule_id.clone()))
-                                        .or_insert_with(|| vec![rule_id]);
                                 }
+                                if decision.0 == Outcome::NoAction {
+                                    decision = (Outcome::Spoofed, Some(index));
+                                }
+                                continue;
+                            }
+                            self.rule_hits[index].fetch_add(1, Ordering::Relaxed);
+                            if !self.make_room(&fw, index, ip, avg_bps).await? {
+                                if trace {
+                                    debug!(
+                                        "trace {}: rule {} not enforced: max_active_ips reached",
+                                        ip, label
+                                    );
+                                }
+                                continue;
+                            }
+                            let mac = self.mac_for(rule, &ip, &fw.hook);
+                            let action = self.action_for(rule, last_offense, seen);
+                            let scope = RuleScope {
+                                level: self.rule_logs.admit(index),
+                                ip,
+                                rule: index,
+                                action: action.name(),
+                                bps: avg_bps,
+                            };
+                            let reason = Reason {
+                                rule: index,
+                                rule_name: rule.name.clone(),
+                                metric: metric.to_string(),
+                                observed: avg_bps,
+                                threshold: rule.threshold_bps,
+                                window_secs,
+                            };
+                            let started = Instant::now();
+                            let applied = logger::scope(
+                                scope,
+                                logger::with_reason(
+                                    reason.clone(),
+                                    self.apply_action(&fw, ip, rule, action, mac.as_deref(), 0),
+                                ),
+                            )
+                            .await;
+                            if trace {
+                                debug!(
+                                    "trace {}: rule {} {} action {}: {}",
+                                    ip,
+                                    label,
+                                    if std::ptr::eq(action, &rule.action) {
+                                        "applied"
+                                    } else {
+                                        "applied repeat"
+                                    },
+                                    action.name(),
+                                    match &applied {
+                                        Ok(Some(rule_id)) => format!("created {}", rule_id),
+                                        Ok(None) => "no new rule".to_string(),
+                                        Err(e) => format!("failed: {}", e),
+                                    }
+                                );
+                            }
+                            match applied {
+                                Ok(Some(rule_id)) => {
+                                    if decision.0 != Outcome::Applied {
+                                        decision = (Outcome::Applied, Some(index));
+                                    }
+                                    self.record_latency(started.elapsed());
+                                    self.track(ip, index, rule_id, avg_bps);
+                                    self.reputation.record(ip, seen);
+                                    if let Some(event) = self.incidents.record(ip, avg_bps, seen) {
+                                        fw.events.push(event).await;
+                                    }
+                                }
+                                Ok(None) => {}
+                                // nft 暂时不可用，动作留待恢复后执行
+                                Err(e) if is_unavailable(&e) => {
+                                    if decision.0 != Outcome::Applied {
+                                        decision = (Outcome::Deferred, Some(index));
+                                    }
+                                    self.defer(DeferredAction {
+                                        ip,
+                                        rule: index,
+                                        mac,
+                                        action: action.clone(),
+                                        reason,
+                                        queued_at: now,
+                                    })
+                                }
+                                Err(e) => return Err(e),
                             }
                         }
+                    }
+                    self.journal(ip, bps, new_bps, seen, decision);
 
-                        self.clean_expiration_rules(rule, ip, Arc::clone(&fw))
-                            .await?;
+                    if excluded {
+                        self.record_excluded(ip, bytes, bps, suppressed, seen);
                     }
                     Ok(())
                 }
             })
-            .await
+            .await?;
+
+        if let Some(journal) = &self.journal {
+            if let Err(e) = journal.flush() {
+                warn!("failed to flush decision journal: {}", e);
+            }
+        }
+        Ok(())
     }
 
-    // clean expiration rules
-    async fn clean_expiration_rules(
+    /// 评估按目的地址触发的规则：发往某个目的地址的总流量超过阈值时，对发往该地址的来源分别限速
+    async fn check_destinations(
         &self,
-        rule: &Rule,
-        ip: IpAddr,
-        fw: Arc<Firewall>,
+        fw: &Arc<Firewall>,
+        due: &[bool],
+        now: Duration,
+        warming: bool,
+        spoofed: bool,
     ) -> anyhow::Result<()> {
-        if let Some(ids) = self.handles.get(&ip) {
-            for id in ids.clone() {
-                match rule.action {
-                    Action::RateLimit {
-                        kbps: _,
-                        burst: _,
-                        seconds,
-                    } => {
-                        if let Some(seconds) = seconds {
-                            if fw.is_expiration(&id, seconds).await {
-                                debug!("intend to remove limit rule {} because of expiration", ip);
-                                fw.unblock(&id).await?;
-                            }
-                        }
+        for (index, rule) in self.rules.iter().enumerate() {
+            let Some(dests) = &rule.destinations else {
+                continue;
+            };
+            if !due[index] {
+                continue;
+            }
+            for dest in dests {
+                // 已在限速中的目的地址等待规则到期
+                if self.active[index].contains_key(dest) {
+                    continue;
+                }
+                // 按来源数触发的规则读取草图，其余读取目的地址的流量窗口
+                let (avg_bps, warm, metric) = if rule.counts_sources() {
+                    let Some(sketches) = &self.sketches else {
                         continue;
+                    };
+                    (
+                        sketches.estimate(index, dest, now),
+                        sketches.is_warm(now),
+                        "unique_sources_per_minute",
+                    )
+                } else {
+                    let Some(win) = self.windows.destination(dest) else {
+                        continue;
+                    };
+                    (
+                        win.average(rule.window_secs),
+                        win.is_warm(rule.window_secs),
+                        "dest_bps",
+                    )
+                };
+                debug!("{} {} to destination: {}", dest, metric, avg_bps);
+                if avg_bps <= rule.threshold_bps {
+                    continue;
+                }
+                if warming && !warm {
+                    info!(
+                        "warm-up: traffic to {} would trigger rule {} ({} {}), not enforced",
+                        dest, index, metric, avg_bps
+                    );
+                    continue;
+                }
+                // 限速发往该地址的每个来源同样会为伪造的来源逐个建规则
+                if spoofed {
+                    debug!("spoofed-source flood: not policing sources to {}", dest);
+                    continue;
+                }
+                self.rule_hits[index].fetch_add(1, Ordering::Relaxed);
+                let scope = RuleScope {
+                    level: self.rule_logs.admit(index),
+                    ip: *dest,
+                    rule: index,
+                    action: rule.action.name(),
+                    bps: avg_bps,
+                };
+                let reason = Reason {
+                    rule: index,
+                    rule_name: rule.name.clone(),
+                    metric: metric.to_string(),
+                    observed: avg_bps,
+                    threshold: rule.threshold_bps,
+                    window_secs: if rule.counts_sources() {
+                        60
+                    } else {
+                        rule.window_secs
+                    },
+                };
+                let started = Instant::now();
+                let applied = logger::scope(
+                    scope,
+                    logger::with_reason(
+                        reason.clone(),
+                        self.apply_action(fw, *dest, rule, &rule.action, None, 0),
+                    ),
+                )
+                .await;
+                match applied {
+                    Ok(Some(rule_id)) => {
+                        self.record_latency(started.elapsed());
+                        self.track(*dest, index, rule_id, avg_bps);
                     }
-                    Action::Ban { seconds } => {
-                        if let Some(seconds) = seconds {
-                            if fw.is_expiration(&id, seconds).await {
-                                debug!("intend to unban {} because of expiration", ip);
-                                fw.unblock(&id).await?;
-                            }
-                        }
-                    }
+                    Ok(None) => {}
+                    Err(e) if is_unavailable(&e) => self.defer(DeferredAction {
+                        ip: *dest,
+                        rule: index,
+                        mac: None,
+                        action: rule.action.clone(),
+                        reason,
+                        queued_at: now,
+                    }),
+                    Err(e) => return Err(e),
                 }
             }
         }
+        Ok(())
+    }
+
+    // clean expiration rules
+    async fn clean_expiration_rules(&self, ip: IpAddr, fw: Arc<Firewall>) -> anyhow::Result<()> {
+        // 先复制 id 列表，避免跨 await 持有 DashMap 的锁
+        let ids = match self.handles.get(&ip) {
+            Some(ids) => ids.clone(),
+            None => return Ok(()),
+        };
 
+        let mut finished = Vec::new();
+        for id in ids {
+            // 按规则自身记录的时长判断是否过期，手动延长后依然有效
+            if fw.is_expired(&id).await {
+                debug!(
+                    "intend to remove rule {} of {} because of expiration",
+                    id, ip
+                );
+                fw.unblock(&id).await?;
+                finished.push(id);
+            } else if !fw.has_rule(&id).await {
+                // 规则已被手动解除
+                finished.push(id);
+            }
+        }
+
+        if !finished.is_empty() {
+            self.handles
+                .entry(ip)
+                .and_modify(|ids| ids.retain(|id| !finished.contains(id)));
+            self.handles.remove_if(&ip, |_, ids| ids.is_empty());
+            for active in &self.active {
+                active.remove_if(&ip, |_, (id, _)| finished.contains(id));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 解除已过期的规则：先处理引擎创建的规则并更新作用中的来源，再处理引擎之外创建的规则（如手动封禁）；
+    /// 与检测分开执行，引擎暂停或来源不再出现在流量统计中时处置仍按时到期；临时白名单同样在此到期
+    pub async fn expire_rules(&self, fw: &Arc<Firewall>) -> anyhow::Result<()> {
+        let ips: Vec<IpAddr> = self.handles.iter().map(|entry| *entry.key()).collect();
+        for ip in ips {
+            self.clean_expiration_rules(ip, Arc::clone(fw)).await?;
+        }
+        for rule in fw.get_active_rules().await? {
+            if fw.is_expired(&rule.id).await {
+                debug!("removing expired rule {} of {}", rule.id, rule.ip);
+                fw.unblock(&rule.id).await?;
+            }
+        }
+        if let Err(e) = fw.expire_excludes().await {
+            warn!("failed to remove expired exclusions: {}", e);
+        }
         Ok(())
     }
 
@@ -232,6 +1881,9 @@ impl RuleEngine {
             .store(false, Ordering::Relaxed);
 
         let mut interval = time::interval(check_interval);
+        let mut expiry = time::interval(check_interval);
+        let mut log_summary = time::interval(LOG_SUMMARY_INTERVAL);
+        let mut reputation_save = time::interval(REPUTATION_SAVE_INTERVAL);
 
         info!("RuleEngine started successfully");
 
@@ -276,6 +1928,17 @@ impl RuleEngine {
                     }
                 }
 
+                // 到期处理不受暂停影响，暂停检测不会无限延长已有的处置
+                _ = expiry.tick() => {
+                    if let Err(e) = self.expire_rules(&fw).await {
+                        error!("expiring rules failed: {}", e);
+                    }
+                }
+
+                _ = log_summary.tick() => self.rule_logs.summary(),
+
+                _ = reputation_save.tick() => self.save_reputation().await,
+
                 // 在暂停状态下等待resume信号
                 _ = self.signal_controller.resume_notify.notified(),
                   if !self.signal_controller.state.load(Ordering::Relaxed) => {
@@ -289,8 +1952,242 @@ impl RuleEngine {
         // 清理资源
         info!("RuleEngine performing cleanup...");
         *self.signal_controller.control_tx.lock().await = None;
+        self.save_reputation().await;
 
         info!("RuleEngine stopped gracefully");
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nft::{MockFault, NftExecutor};
+    use safe_traffic_common::{clock::ManualClock, config::Config};
+
+    const CONFIG: &str = r#"
+        interface = "eth0"
+        state_dir = "/nonexistent/safe-traffic-rules"
+
+        [[rules]]
+        window_secs = 2
+        threshold_bps = 1000
+        action = { Ban = {} }
+    "#;
+
+    struct Fixture {
+        engine: RuleEngine,
+        fw: Arc<Firewall>,
+        executor: Arc<NftExecutor>,
+        stats: Arc<DashMap<IpAddr, TrafficStats>>,
+        clock: Arc<ManualClock>,
+    }
+
+    async fn fixture(config: &str) -> Fixture {
+        let cfg = Config::parse(config).unwrap();
+        let clock = Arc::new(ManualClock::new(Utc::now()));
+        let executor = Arc::new(NftExecutor::new(1, 300, 100, true).await);
+        let fw = Firewall::new(&cfg, Arc::clone(&executor))
+            .await
+            .unwrap()
+            .with_clock(clock.clone());
+        let stats = Arc::new(DashMap::new());
+        let engine = RuleEngine::new(cfg.rules.clone(), stats.clone()).with_clock(clock.clone());
+        Fixture {
+            engine,
+            fw: Arc::new(fw),
+            executor,
+            stats,
+            clock,
+        }
+    }
+
+    impl Fixture {
+        /// 推进一秒并为每个来源写入一次采样
+        fn tick(&self, sources: &[IpAddr], rx_delta: u64) {
+            self.clock.advance(Duration::from_secs(1));
+            for ip in sources {
+                let sample = TrafficStats {
+                    rx_delta,
+                    ..Default::default()
+                };
+                self.engine
+                    .windows()
+                    .record(*ip, &sample, self.clock.monotonic());
+                self.stats.insert(*ip, sample);
+            }
+        }
+
+        async fn banned(&self) -> Vec<IpAddr> {
+            let mut banned: Vec<IpAddr> = self
+                .fw
+                .get_active_rules()
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|rule| rule.ip)
+                .collect();
+            banned.sort();
+            banned
+        }
+    }
+
+    #[tokio::test]
+    async fn test_deferred_actions_replay_without_head_of_line_blocking() {
+        let f = fixture(CONFIG).await;
+        let stuck: IpAddr = "198.51.100.1".parse().unwrap();
+        let other: IpAddr = "198.51.100.2".parse().unwrap();
+        for _ in 0..3 {
+            f.tick(&[stuck, other], 5000);
+        }
+
+        // nft 不可用：两个动作都暂存
+        f.executor.inject_fault("", Some(MockFault::Unavailable));
+        f.engine.check_and_apply(Arc::clone(&f.fw)).await.unwrap();
+        assert_eq!(f.engine.deferred_actions(), 2);
+        assert!(f.banned().await.is_empty());
+        // 让仍会失败的动作排在队首
+        f.engine
+            .deferred
+            .lock()
+            .unwrap()
+            .make_contiguous()
+            .sort_by_key(|action| action.ip != stuck);
+
+        // nft 恢复，但其中一条仍然失败：移到队尾，不挡住其余动作
+        f.executor.inject_fault("", None);
+        f.executor
+            .inject_fault("198.51.100.1", Some(MockFault::Unavailable));
+        f.stats.clear();
+        f.clock.advance(Duration::from_secs(1));
+        f.engine.check_and_apply(Arc::clone(&f.fw)).await.unwrap();
+        assert_eq!(f.banned().await, [other]);
+        assert_eq!(f.engine.deferred_actions(), 1);
+
+        // 被 nft 拒绝的命令表现为超时，重试无益，直接放弃
+        f.executor
+            .inject_fault("198.51.100.1", Some(MockFault::Rejected));
+        f.clock.advance(Duration::from_secs(1));
+        f.engine.check_and_apply(Arc::clone(&f.fw)).await.unwrap();
+        assert_eq!(f.engine.deferred_actions(), 0);
+        assert_eq!(f.banned().await, [other]);
+    }
+
+    #[tokio::test]
+    async fn test_excluded_bytes_follow_the_counter() {
+        let f = fixture(&format!("global_exclude = [\"198.51.100.9\"]\n{}", CONFIG)).await;
+        let ip: IpAddr = "198.51.100.9".parse().unwrap();
+        // 监控器与规则评估都每 5 秒一次，每秒 2000 字节
+        let mut rx_bytes = 0;
+        for _ in 0..3 {
+            f.clock.advance(Duration::from_secs(5));
+            rx_bytes += 2000 * 5;
+            f.stats.insert(
+                ip,
+                TrafficStats {
+                    rx_bytes,
+                    rx_delta: 2000,
+                    ..Default::default()
+                },
+            );
+            f.engine.check_and_apply(Arc::clone(&f.fw)).await.unwrap();
+        }
+        let stats = f.engine.excluded_stats();
+        assert_eq!(stats[0].total_bytes, 30_000);
+        assert_eq!(stats[0].last_bps, 2000);
+
+        // 计数器重新开始时从 0 起算
+        f.stats.insert(
+            ip,
+            TrafficStats {
+                rx_bytes: 4000,
+                rx_delta: 2000,
+                ..Default::default()
+            },
+        );
+        f.engine.check_and_apply(Arc::clone(&f.fw)).await.unwrap();
+        assert_eq!(f.engine.excluded_stats()[0].total_bytes, 34_000);
+    }
+
+    #[tokio::test]
+    async fn test_deferred_actions_expire() {
+        let f = fixture(CONFIG).await;
+        let ip: IpAddr = "198.51.100.3".parse().unwrap();
+        for _ in 0..3 {
+            f.tick(&[ip], 5000);
+        }
+        f.executor.inject_fault("", Some(MockFault::Unavailable));
+        f.engine.check_and_apply(Arc::clone(&f.fw)).await.unwrap();
+        f.stats.clear();
+
+        f.clock.advance(Duration::from_secs(600));
+        f.engine.check_and_apply(Arc::clone(&f.fw)).await.unwrap();
+        assert_eq!(f.engine.deferred_actions(), 1);
+
+        // 永久动作在 nft 长期不可用时也不会无限重试
+        f.clock.advance(MAX_DEFERRED_AGE);
+        f.engine.check_and_apply(Arc::clone(&f.fw)).await.unwrap();
+        assert_eq!(f.engine.deferred_actions(), 0);
+        assert!(f.banned().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_check_interval_skips_ticks() {
+        let f = fixture(&format!(
+            "{}\n[[rules]]\nwindow_secs = 2\nthreshold_bps = 1000\ncheck_interval = 5\naction = {{ Ban = {{}} }}",
+            CONFIG
+        ))
+        .await;
+        let mut evaluated = [Vec::new(), Vec::new()];
+        for tick in 0..=10 {
+            let due = f.engine.due_rules(f.clock.monotonic());
+            for (index, due) in due.into_iter().enumerate() {
+                if due {
+                    evaluated[index].push(tick);
+                }
+            }
+            f.clock.advance(Duration::from_secs(1));
+        }
+        assert_eq!(evaluated[0], (0..=10).collect::<Vec<_>>());
+        assert_eq!(evaluated[1], [0, 5, 10]);
+    }
+
+    #[tokio::test]
+    async fn test_window_longer_than_buffer() {
+        let f = fixture(
+            "interface = \"eth0\"\nstate_dir = \"/nonexistent/safe-traffic-rules\"\n[[rules]]\nwindow_secs = 120\nthreshold_bps = 1000\naction = { Ban = {} }",
+        )
+        .await;
+        let ip: IpAddr = "198.51.100.5".parse().unwrap();
+        // 缓冲只有 60 秒：写满并回绕后按 60 秒窗口计算
+        for _ in 0..119 {
+            f.tick(&[ip], 500);
+        }
+        f.engine.check_and_apply(Arc::clone(&f.fw)).await.unwrap();
+        assert!(f.banned().await.is_empty());
+        for _ in 0..30 {
+            f.tick(&[ip], 3000);
+        }
+        f.engine.check_and_apply(Arc::clone(&f.fw)).await.unwrap();
+        assert_eq!(f.banned().await, [ip]);
+    }
+
+    #[test]
+    fn test_window_store_retain() {
+        let windows = WindowStore::new(HookType::Input);
+        assert!(!windows.is_written());
+        let source: IpAddr = "198.51.100.4".parse().unwrap();
+        let dest: IpAddr = "192.0.2.1".parse().unwrap();
+        let at = Duration::from_secs(1);
+        windows.record(source, &TrafficStats::default(), at);
+        windows.record_destination(dest, 1000, at);
+        assert!(windows.is_written());
+
+        // 来源与目的地址各自按条件丢弃
+        windows.retain(|_| false);
+        assert!(windows.get(&source).is_none());
+        assert!(windows.destination(&dest).is_some());
+        windows.retain_destinations(|_| false);
+        assert!(windows.destination(&dest).is_none());
+    }
+}
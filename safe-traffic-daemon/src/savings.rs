@@ -0,0 +1,171 @@
+//! 节省的带宽：定期读取受管链中丢弃规则的计数，累计被丢弃的字节与报文并按天（UTC）汇总，
+//! 保存在 state_dir/savings.json，重启后继续累计
+//!
+//! 每次读取只计入与上次读取之间的增量；规则删除后其计数随之消失，删除前最后一个读取间隔内的丢弃不计入。
+
+use crate::{
+    controller::Firewall,
+    state::{state_file, write_atomic},
+};
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use log::warn;
+use safe_traffic_common::transport::{SavingsDay, SystemRule};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{fs, time};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+/// 保留的天数
+const RETENTION_DAYS: usize = 90;
+
+/// 状态文件内容
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SavingsState {
+    pub dropped_bytes: u64,
+    pub dropped_packets: u64,
+    /// 按日期升序
+    #[serde(default)]
+    pub days: Vec<SavingsDay>,
+}
+
+/// 丢弃流量的累计
+#[derive(Debug)]
+pub struct Savings {
+    path: PathBuf,
+    /// 上次读取时各丢弃规则的计数（字节, 报文），按句柄
+    last: Mutex<HashMap<u64, (u64, u64)>>,
+    state: Mutex<SavingsState>,
+}
+
+impl Savings {
+    pub fn new(state_dir: Option<&str>) -> Self {
+        Self {
+            path: state_file(state_dir, "savings.json"),
+            last: Mutex::new(HashMap::new()),
+            state: Mutex::new(SavingsState::default()),
+        }
+    }
+
+    /// 计入一次读取到的链中规则；只统计带 drop 判决的规则，首次出现的规则计入全部计数
+    pub fn record(&self, rules: &[SystemRule], today: NaiveDate) {
+        let mut last = self.last.lock().unwrap();
+        let mut current = HashMap::new();
+        let (mut bytes, mut packets) = (0, 0);
+        for rule in rules {
+            let (Some(handle), Some(rule_bytes), Some(rule_packets)) =
+                (rule.handle, rule.bytes, rule.packets)
+            else {
+                continue;
+            };
+            if !rule.statements.iter().any(|statement| statement == "drop") {
+                continue;
+            }
+            let (last_bytes, last_packets) = last.get(&handle).copied().unwrap_or_default();
+            // 计数变小说明句柄已被新规则复用
+            if rule_bytes >= last_bytes && rule_packets >= last_packets {
+                bytes += rule_bytes - last_bytes;
+                packets += rule_packets - last_packets;
+            } else {
+                bytes += rule_bytes;
+                packets += rule_packets;
+            }
+            current.insert(handle, (rule_bytes, rule_packets));
+        }
+        *last = current;
+        drop(last);
+        self.add(today, bytes, packets);
+    }
+
+    fn add(&self, date: NaiveDate, bytes: u64, packets: u64) {
+        if bytes == 0 && packets == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.dropped_bytes += bytes;
+        state.dropped_packets += packets;
+        match state.days.last_mut() {
+            Some(day) if day.date == date => {
+                day.bytes += bytes;
+                day.packets += packets;
+            }
+            _ => state.days.push(SavingsDay {
+                date,
+                bytes,
+                packets,
+            }),
+        }
+        let excess = state.days.len().saturating_sub(RETENTION_DAYS);
+        state.days.drain(..excess);
+    }
+
+    /// 累计丢弃的（字节, 报文）
+    pub fn totals(&self) -> (u64, u64) {
+        let state = self.state.lock().unwrap();
+        (state.dropped_bytes, state.dropped_packets)
+    }
+
+    /// 保留的每天的丢弃流量，按日期升序
+    pub fn days(&self) -> Vec<SavingsDay> {
+        self.state.lock().unwrap().days.clone()
+    }
+
+    /// 某一天的丢弃流量，没有丢弃时为 0
+    pub fn day(&self, date: NaiveDate) -> SavingsDay {
+        self.state
+            .lock()
+            .unwrap()
+            .days
+            .iter()
+            .find(|day| day.date == date)
+            .cloned()
+            .unwrap_or(SavingsDay {
+                date,
+                bytes: 0,
+                packets: 0,
+            })
+    }
+
+    pub async fn save(&self) -> Result<()> {
+        let state = self.state.lock().unwrap().clone();
+        write_atomic(&self.path, &serde_json::to_vec_pretty(&state)?).await
+    }
+
+    /// 读取上次运行保存的累计，文件不存在时从 0 开始
+    pub async fn load(&self) -> Result<()> {
+        let state = match fs::read(&self.path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("invalid savings state {}", self.path.display()))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => SavingsState::default(),
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read {}", self.path.display()))
+            }
+        };
+        *self.state.lock().unwrap() = state;
+        Ok(())
+    }
+
+    pub async fn run(self: Arc<Self>, fw: Arc<Firewall>) {
+        let mut interval = time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            match fw.refresh_system_rules().await {
+                Ok(rules) => self.record(&rules, fw.clock().wall().date_naive()),
+                Err(e) => {
+                    warn!("Failed to read rule counters for dropped traffic: {}", e);
+                    continue;
+                }
+            }
+            if let Err(e) = self.save().await {
+                warn!("Failed to save dropped traffic totals: {}", e);
+            }
+        }
+    }
+}
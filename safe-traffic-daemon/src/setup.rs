@@ -0,0 +1,157 @@
+//! 配置辅助：导出配置的 JSON Schema，按网卡速率生成初始配置
+
+use anyhow::{Context, Result};
+use clap::Args;
+use safe_traffic_common::config::Config;
+use std::{
+    fs,
+    io::{self, BufRead, IsTerminal, Write},
+    path::{Path, PathBuf},
+};
+
+/// 读不到网卡速率（如部分虚拟网卡）时假定的速率，Mbit/s
+const DEFAULT_SPEED_MBPS: u64 = 1000;
+
+/// 生成初始配置的参数，未指定的项在终端中询问，非交互时使用探测值
+#[derive(Args, Debug)]
+pub struct GenerateArgs {
+    /// 监控的网卡，默认取默认路由所在的网卡
+    #[arg(long)]
+    pub interface: Option<String>,
+    /// 网卡速率（Mbit/s），默认读取 /sys/class/net/<网卡>/speed
+    #[arg(long)]
+    pub speed_mbps: Option<u64>,
+    /// 写入该文件，默认输出到 stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+    /// 不询问，未指定的参数直接使用探测值
+    #[arg(long)]
+    pub non_interactive: bool,
+}
+
+/// 以 JSON 输出配置的 Schema
+pub fn schema() -> Result<String> {
+    Ok(serde_json::to_string_pretty(&Config::json_schema())?)
+}
+
+/// 生成初始配置并写入文件或 stdout
+pub fn generate(args: GenerateArgs) -> Result<()> {
+    let interactive = !args.non_interactive && io::stdin().is_terminal();
+
+    let interface = match args.interface {
+        Some(interface) => interface,
+        None => {
+            let detected = default_interface().unwrap_or_else(|| "eth0".to_string());
+            if interactive {
+                prompt("Interface to monitor", &detected)?
+            } else {
+                detected
+            }
+        }
+    };
+    let speed_mbps = match args.speed_mbps {
+        Some(speed) => speed,
+        None => {
+            let detected = nic_speed_mbps(&interface).unwrap_or(DEFAULT_SPEED_MBPS);
+            if interactive {
+                prompt("NIC speed in Mbit/s", &detected.to_string())?
+                    .parse()
+                    .context("NIC speed must be a whole number of Mbit/s")?
+            } else {
+                detected
+            }
+        }
+    };
+
+    let text = starter_config(&interface, speed_mbps.max(1));
+    // 生成的配置必须能被守护进程加载
+    Config::parse(&text).context("generated configuration does not parse")?;
+
+    match args.output {
+        Some(path) => {
+            fs::write(&path, text)
+                .with_context(|| format!("failed to write {}", path.display()))?;
+            eprintln!("Wrote {}", path.display());
+        }
+        None => print!("{}", text),
+    }
+    Ok(())
+}
+
+/// 在 stderr 上询问，直接回车时取默认值
+fn prompt(question: &str, default: &str) -> Result<String> {
+    eprint!("{} [{}]: ", question, default);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    let answer = answer.trim();
+    Ok(if answer.is_empty() { default } else { answer }.to_string())
+}
+
+/// 默认路由（目的地址为 0.0.0.0）所在的网卡
+fn default_interface() -> Option<String> {
+    let routes = fs::read_to_string("/proc/net/route").ok()?;
+    routes.lines().skip(1).find_map(|line| {
+        let mut fields = line.split_whitespace();
+        let interface = fields.next()?;
+        (fields.next()? == "00000000").then(|| interface.to_string())
+    })
+}
+
+/// 网卡协商速率，未连接或虚拟网卡上可能为 -1 或不可读
+fn nic_speed_mbps(interface: &str) -> Option<u64> {
+    let path = Path::new("/sys/class/net").join(interface).join("speed");
+    let speed: i64 = fs::read_to_string(path).ok()?.trim().parse().ok()?;
+    u64::try_from(speed).ok().filter(|speed| *speed > 0)
+}
+
+/// 按链路带宽换算的初始规则：单个来源长时间占用 10% 带宽时限速，
+/// 短时占用 30% 时封禁，新建连接的流量超过 1% 时视为 SYN 洪水
+fn starter_config(interface: &str, speed_mbps: u64) -> String {
+    let link_bps = speed_mbps * 125_000;
+    let percent = |p: u64| (link_bps * p / 100).max(1);
+    format!(
+        r#"# Generated by `safe-traffic-daemon config generate` for a {speed} Mbit/s link on {interface}.
+# Thresholds are bytes per second from a single source; tune them after watching `safe-traffic-cli dashboard`.
+family = "Inet"
+table_name = "traffic_filter"
+chain_name = "input_chain"
+interface = "{interface}"
+hook = "Input"
+priority = 0
+policy = "Accept"
+monitor_interval = 1
+rule_check_interval = 1
+# global_exclude = ["192.0.2.10"] # management hosts that must never be limited
+# log_target = "Journald"
+
+# a single source using 10% of the link for 30s is slowed down to 1% for 5 minutes
+[[rules]]
+name = "heavy"
+window_secs = 30
+threshold_bps = {heavy}
+action = {{ RateLimit = {{ kbps = {limit_kbps}, seconds = 300 }} }}
+
+# a single source using 30% of the link for 10s is banned for 10 minutes
+[[rules]]
+name = "flood"
+window_secs = 10
+threshold_bps = {flood}
+action = {{ Ban = {{ seconds = 600 }} }}
+
+# new-connection traffic above 1% of the link, e.g. a SYN flood
+[[rules]]
+name = "syn-flood"
+window_secs = 10
+threshold_bps = {syn}
+flow = "New"
+action = {{ Ban = {{ seconds = 300 }} }}
+"#,
+        speed = speed_mbps,
+        interface = interface,
+        heavy = percent(10),
+        limit_kbps = (speed_mbps * 10).max(1),
+        flood = percent(30),
+        syn = percent(1),
+    )
+}
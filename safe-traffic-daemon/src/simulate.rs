@@ -0,0 +1,368 @@
+//! 规则模拟：用合成流量驱动规则引擎，得到带时间的决策序列，可在 CI 中断言规则文件的行为
+//!
+//! 模拟使用只记录命令的 nft 执行器与手动推进的时钟，不修改本机的 nftables，也不等待真实时间。
+//! 决策日志与流量快照的重放同样如此，流量取自记录的序列。
+
+use crate::{
+    controller::Firewall,
+    events::EventStore,
+    incidents,
+    journal::{JournalRecord, Outcome},
+    nft::NftExecutor,
+    rules::{RuleEngine, WindowStore},
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use safe_traffic_common::{
+    clock::{Clock, ManualClock},
+    config::{Config, Rule},
+    events::{Decision, EventKind},
+    utils::{StatsSnapshot, TrafficStats},
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt, fs,
+    net::IpAddr,
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+pub use safe_traffic_common::scenario::{Expectation, Scenario, Source};
+
+/// 模拟用的防火墙、规则引擎及驱动引擎的流量统计
+type Sandbox = (
+    Arc<Firewall>,
+    RuleEngine,
+    Arc<DashMap<IpAddr, TrafficStats>>,
+);
+
+/// 用只记录命令的执行器和手动时钟构建防火墙与规则引擎，sources 为每拍的最大来源数
+async fn sandbox(cfg: &Config, clock: Arc<ManualClock>, sources: usize) -> Result<Sandbox> {
+    let executor = Arc::new(NftExecutor::new(1, 300, 100, true).await);
+    let mut fw = Firewall::new(cfg, executor)
+        .await?
+        .with_clock(clock.clone());
+    // 每拍取走全部事件，容量只需容纳一拍内的事件
+    fw.events = Arc::new(EventStore::new(sources * (cfg.rules.len() + 2) + 64));
+    let fw = Arc::new(fw);
+
+    let stats = Arc::new(DashMap::<IpAddr, TrafficStats>::new());
+    let mut windows = WindowStore::new(fw.hook.clone());
+    if cfg.rules.iter().any(Rule::counts_day_bytes) {
+        windows = windows.with_history();
+    }
+    if cfg.low_memory.unwrap_or(false) {
+        windows = windows.with_low_memory(&cfg.rules);
+    }
+    let mut engine = RuleEngine::new(cfg.rules.clone(), stats.clone())
+        .with_clock(clock)
+        .with_windows(Arc::new(windows))
+        .with_excluded_abuse(
+            cfg.excluded_abuse_multiple(),
+            cfg.excluded_abuse_interval_secs(),
+        );
+    if let Some(warmup) = cfg.warmup_secs {
+        engine = engine.with_warmup(warmup);
+    }
+    if cfg.incident_threshold.is_some() || cfg.incident_window_secs.is_some() {
+        engine = engine.with_incidents(
+            cfg.incident_threshold
+                .unwrap_or(incidents::DEFAULT_THRESHOLD),
+            cfg.incident_window_secs
+                .unwrap_or(incidents::DEFAULT_WINDOW_SECS),
+        );
+    }
+    Ok((fw, engine, stats))
+}
+
+/// 与流量监控器一致：写入统计的同时按当前时间推进窗口
+fn ingest(
+    engine: &RuleEngine,
+    stats: &DashMap<IpAddr, TrafficStats>,
+    clock: &ManualClock,
+    ip: IpAddr,
+    sample: TrafficStats,
+) {
+    engine.windows().record(ip, &sample, clock.monotonic());
+    stats.insert(ip, sample);
+}
+
+fn traffic(bps: u64, new_bps: u64) -> TrafficStats {
+    TrafficStats {
+        rx_delta: bps,
+        tx_delta: bps,
+        rx_new_delta: new_bps,
+        tx_new_delta: new_bps,
+        ..Default::default()
+    }
+}
+
+/// 按 rule_check_interval 逐拍推进场景，返回全部决策
+pub async fn simulate(cfg: &Config, scenario: &Scenario) -> Result<Vec<Decision>> {
+    let clock = Arc::new(ManualClock::new(Utc::now()));
+    let (fw, engine, stats) = sandbox(cfg, clock.clone(), scenario.sources.len()).await?;
+
+    let tick = cfg.rule_check_interval.unwrap_or(1).max(1);
+    let mut decisions = Vec::new();
+    let mut at = 0;
+    while at < scenario.duration_secs {
+        for source in &scenario.sources {
+            let (bps, new_bps) = if source.is_active(at) {
+                (source.bps, source.new_bps.unwrap_or(0))
+            } else {
+                (0, 0)
+            };
+            ingest(&engine, &stats, &clock, source.ip, traffic(bps, new_bps));
+        }
+        engine.check_and_apply(Arc::clone(&fw)).await?;
+        engine.expire_rules(&fw).await?;
+        decisions.extend(
+            fw.events
+                .take_all()
+                .await
+                .into_iter()
+                .map(|event| Decision::from_event(at, event)),
+        );
+        clock.advance(Duration::from_secs(tick));
+        at += tick;
+    }
+    Ok(decisions)
+}
+
+/// 一个规则测试的结果
+#[derive(Debug, Clone)]
+pub struct RuleTestResult {
+    pub name: String,
+    /// 未满足的断言，为空即通过
+    pub failures: Vec<String>,
+}
+
+impl RuleTestResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// 逐个模拟配置中的规则测试，每个测试使用独立的沙箱
+pub async fn run_rule_tests(cfg: &Config) -> Result<Vec<RuleTestResult>> {
+    let mut results = Vec::new();
+    for test in cfg.rule_tests.iter().flatten() {
+        let decisions = simulate(cfg, &test.scenario)
+            .await
+            .with_context(|| format!("rule test {} failed to run", test.name))?;
+        results.push(RuleTestResult {
+            name: test.name.clone(),
+            failures: test.scenario.failures(&decisions),
+        });
+    }
+    Ok(results)
+}
+
+/// 记录与重放结果不一致的来源：记录中执行了动作而重放中没有，或相反
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplayChange {
+    pub ip: IpAddr,
+    /// 记录中首次执行动作的时间，自日志开始的秒数
+    pub recorded_secs: Option<u64>,
+    /// 重放中首次执行动作的时间
+    pub replayed_secs: Option<u64>,
+}
+
+impl fmt::Display for ReplayChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let describe = |secs: Option<u64>| match secs {
+            Some(secs) => format!("action at {}s", secs),
+            None => "no action".to_string(),
+        };
+        write!(
+            f,
+            "{}: recorded {}, replayed {}",
+            self.ip,
+            describe(self.recorded_secs),
+            describe(self.replayed_secs)
+        )
+    }
+}
+
+/// 决策日志的重放结果
+#[derive(Debug, Clone, Serialize)]
+pub struct Replay {
+    pub decisions: Vec<Decision>,
+    pub changes: Vec<ReplayChange>,
+}
+
+/// 用新的配置重放决策日志：按记录的时间逐拍驱动规则引擎，对比每个来源是否执行了动作
+pub async fn replay(cfg: &Config, records: &[JournalRecord]) -> Result<Replay> {
+    let mut ticks: BTreeMap<DateTime<Utc>, Vec<&JournalRecord>> = BTreeMap::new();
+    for record in records {
+        ticks.entry(record.time).or_default().push(record);
+    }
+    let Some(&start) = ticks.keys().next() else {
+        bail!("the journal has no records");
+    };
+    let sources = ticks.values().map(Vec::len).max().unwrap_or(0);
+    let clock = Arc::new(ManualClock::new(start));
+    let (fw, engine, stats) = sandbox(cfg, clock.clone(), sources).await?;
+
+    let mut recorded = HashMap::new();
+    let mut decisions = Vec::new();
+    let mut last = start;
+    for (time, tick) in &ticks {
+        clock.advance((*time - last).to_std()?);
+        last = *time;
+        let at = (*time - start).num_seconds().max(0) as u64;
+        // 只有本拍记录的来源参与评估，与守护进程中过期统计被清理一致
+        stats.clear();
+        for record in tick {
+            ingest(
+                &engine,
+                &stats,
+                &clock,
+                record.ip,
+                traffic(record.bps, record.new_bps),
+            );
+            if record.outcome == Outcome::Applied {
+                recorded.entry(record.ip).or_insert(at);
+            }
+        }
+        engine.check_and_apply(Arc::clone(&fw)).await?;
+        engine.expire_rules(&fw).await?;
+        decisions.extend(
+            fw.events
+                .take_all()
+                .await
+                .into_iter()
+                .map(|event| Decision::from_event(at, event)),
+        );
+    }
+
+    let mut replayed = HashMap::new();
+    for decision in &decisions {
+        if let (true, Some(ip)) = (is_action(decision.kind), decision.ip) {
+            replayed.entry(ip).or_insert(decision.at_secs);
+        }
+    }
+    let mut ips: Vec<IpAddr> = recorded.keys().chain(replayed.keys()).copied().collect();
+    ips.sort();
+    ips.dedup();
+    let changes = ips
+        .into_iter()
+        .filter(|ip| recorded.contains_key(ip) != replayed.contains_key(ip))
+        .map(|ip| ReplayChange {
+            ip,
+            recorded_secs: recorded.get(&ip).copied(),
+            replayed_secs: replayed.get(&ip).copied(),
+        })
+        .collect();
+    Ok(Replay { decisions, changes })
+}
+
+/// 读取记录的流量快照（每行一个 JSON 对象），按时间排序
+pub fn read_stats(path: &Path) -> Result<Vec<StatsSnapshot>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("failed to read stats {}", path.display()))?;
+    let mut snapshots = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("{}:{}: invalid snapshot", path.display(), index + 1))
+        })
+        .collect::<Result<Vec<StatsSnapshot>>>()?;
+    snapshots.sort_by_key(|snapshot| snapshot.time);
+    Ok(snapshots)
+}
+
+/// 流量快照离线重放的结果
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsReplay {
+    pub snapshots: usize,
+    /// 第一个到最后一个快照的时长，秒
+    pub duration_secs: u64,
+    pub decisions: Vec<Decision>,
+}
+
+impl fmt::Display for StatsReplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut kinds: BTreeMap<String, usize> = BTreeMap::new();
+        let mut sources = HashSet::new();
+        for decision in &self.decisions {
+            if let (true, Some(ip)) = (is_action(decision.kind), decision.ip) {
+                *kinds.entry(decision.kind.to_string()).or_default() += 1;
+                sources.insert(ip);
+            }
+        }
+        write!(
+            f,
+            "replayed {} snapshots covering {}s: ",
+            self.snapshots, self.duration_secs
+        )?;
+        if kinds.is_empty() {
+            return write!(f, "no actions");
+        }
+        let kinds: Vec<String> = kinds
+            .iter()
+            .map(|(kind, count)| format!("{} {}", count, kind))
+            .collect();
+        write!(f, "{} on {} sources", kinds.join(", "), sources.len())
+    }
+}
+
+/// 用配置中的规则离线重放流量快照：每个快照为一拍，时钟跳到快照的时间，不等待真实时间，
+/// 不修改本机的 nftables
+pub async fn replay_stats(cfg: &Config, snapshots: &[StatsSnapshot]) -> Result<StatsReplay> {
+    let Some(start) = snapshots.first().map(|snapshot| snapshot.time) else {
+        bail!("no stats snapshots recorded");
+    };
+    let sources = snapshots
+        .iter()
+        .map(|snapshot| snapshot.stats.len())
+        .max()
+        .unwrap_or(0);
+    let clock = Arc::new(ManualClock::new(start));
+    let (fw, engine, stats) = sandbox(cfg, clock.clone(), sources).await?;
+
+    let mut decisions = Vec::new();
+    let mut last = start;
+    for snapshot in snapshots {
+        clock.advance((snapshot.time - last).to_std().unwrap_or_default());
+        last = snapshot.time;
+        let at = (snapshot.time - start).num_seconds().max(0) as u64;
+        stats.clear();
+        for (ip, sample) in &snapshot.stats {
+            ingest(&engine, &stats, &clock, *ip, sample.clone());
+        }
+        engine.check_and_apply(Arc::clone(&fw)).await?;
+        engine.expire_rules(&fw).await?;
+        decisions.extend(
+            fw.events
+                .take_all()
+                .await
+                .into_iter()
+                .map(|event| Decision::from_event(at, event)),
+        );
+    }
+    Ok(StatsReplay {
+        snapshots: snapshots.len(),
+        duration_secs: (last - start).num_seconds().max(0) as u64,
+        decisions,
+    })
+}
+
+/// 是否为对来源执行的动作
+fn is_action(kind: EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Ban
+            | EventKind::Limit
+            | EventKind::Mirror
+            | EventKind::Inspect
+            | EventKind::Police
+            | EventKind::Warn
+    )
+}
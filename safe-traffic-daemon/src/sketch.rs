@@ -0,0 +1,173 @@
+//! 不同来源数的估计：每条按来源数触发的规则对每个目的地址维护 HyperLogLog 草图，
+//! 最近一分钟按 10 秒分片轮转，内存只随规则与目的地址数增长，不随来源数增长
+
+use safe_traffic_common::{config::Rule, transport::FlowEntry};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    net::IpAddr,
+    sync::Mutex,
+    time::Duration,
+};
+
+/// 寄存器数为 2^PRECISION，标准误差约 1.04 / sqrt(2^PRECISION)，即 3.25%
+const PRECISION: u32 = 10;
+const REGISTERS: usize = 1 << PRECISION;
+const SLICE_SECS: u64 = 10;
+const SLICES: usize = 6;
+
+/// HyperLogLog 草图
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self {
+            registers: vec![0; REGISTERS],
+        }
+    }
+}
+
+impl HyperLogLog {
+    pub fn insert(&mut self, ip: &IpAddr) {
+        let mut hasher = DefaultHasher::new();
+        ip.hash(&mut hasher);
+        let hash = hasher.finish();
+        let index = (hash >> (64 - PRECISION)) as usize;
+        // 剩余位中第一个 1 的位置，全为 0 时取最大值
+        let rank = ((hash << PRECISION).leading_zeros() + 1).min(64 - PRECISION + 1) as u8;
+        let register = &mut self.registers[index];
+        *register = (*register).max(rank);
+    }
+
+    /// 合并后的草图估计两者的并集
+    pub fn merge(&mut self, other: &Self) {
+        for (register, other) in self.registers.iter_mut().zip(&other.registers) {
+            *register = (*register).max(*other);
+        }
+    }
+
+    pub fn estimate(&self) -> u64 {
+        let m = REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|register| 2f64.powi(-(*register as i32)))
+            .sum();
+        let raw = alpha * m * m / sum;
+        let zeros = self
+            .registers
+            .iter()
+            .filter(|register| **register == 0)
+            .count();
+        // 基数较小时改用线性计数
+        if raw <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            raw.round() as u64
+        }
+    }
+}
+
+/// 一条规则对一个目的地址的草图，每个分片记录其序号
+#[derive(Debug)]
+struct Sketches {
+    rule: usize,
+    dest: IpAddr,
+    ports: Vec<u16>,
+    slices: Vec<(u64, HyperLogLog)>,
+}
+
+impl Sketches {
+    /// 序号为 id 的分片，所在位置仍是旧分片时先清空
+    fn slice(slices: &mut [(u64, HyperLogLog)], id: u64) -> &mut HyperLogLog {
+        let (slice_id, sketch) = &mut slices[id as usize % SLICES];
+        if *slice_id != id {
+            *slice_id = id;
+            *sketch = HyperLogLog::default();
+        }
+        sketch
+    }
+}
+
+/// 按来源数触发的规则所需的全部草图，监控器写入，规则引擎读取
+#[derive(Debug)]
+pub struct SourceSketches {
+    sketches: Mutex<Vec<Sketches>>,
+    /// 首次写入的单调时间，满一分钟前估计值偏小
+    started: Mutex<Option<Duration>>,
+}
+
+impl SourceSketches {
+    /// 为每条 UniqueSourcesPerMinute 规则的每个目的地址建立草图，没有这样的规则时返回 None
+    pub fn for_rules(rules: &[Rule]) -> Option<Self> {
+        let mut sketches = Vec::new();
+        for (index, rule) in rules.iter().enumerate() {
+            if !rule.counts_sources() {
+                continue;
+            }
+            for dest in rule.destinations.iter().flatten() {
+                sketches.push(Sketches {
+                    rule: index,
+                    dest: *dest,
+                    ports: rule.dports.clone().unwrap_or_default(),
+                    slices: vec![(u64::MAX, HyperLogLog::default()); SLICES],
+                });
+            }
+        }
+        (!sketches.is_empty()).then(|| Self {
+            sketches: Mutex::new(sketches),
+            started: Mutex::new(None),
+        })
+    }
+
+    /// 记录一次读取到的全部流，流的目的地址与端口符合时将其来源加入当前分片
+    pub fn observe(&self, flows: &[FlowEntry], at: Duration) {
+        self.started.lock().unwrap().get_or_insert(at);
+        let id = at.as_secs() / SLICE_SECS;
+        let mut sketches = self.sketches.lock().unwrap();
+        for sketches in sketches.iter_mut() {
+            let slice = Sketches::slice(&mut sketches.slices, id);
+            for flow in flows {
+                if flow.dst == sketches.dest
+                    && flow
+                        .dport
+                        .is_some_and(|port| sketches.ports.contains(&port))
+                {
+                    slice.insert(&flow.src);
+                }
+            }
+        }
+    }
+
+    /// 规则在目的地址上最近一分钟的不同来源数
+    pub fn estimate(&self, rule: usize, dest: &IpAddr, now: Duration) -> u64 {
+        let id = now.as_secs() / SLICE_SECS;
+        let oldest = id.saturating_sub(SLICES as u64 - 1);
+        let sketches = self.sketches.lock().unwrap();
+        let Some(sketches) = sketches
+            .iter()
+            .find(|sketches| sketches.rule == rule && sketches.dest == *dest)
+        else {
+            return 0;
+        };
+        let mut merged = HyperLogLog::default();
+        for (slice_id, sketch) in &sketches.slices {
+            if (oldest..=id).contains(slice_id) {
+                merged.merge(sketch);
+            }
+        }
+        merged.estimate()
+    }
+
+    /// 是否已记录满一分钟
+    pub fn is_warm(&self, now: Duration) -> bool {
+        self.started
+            .lock()
+            .unwrap()
+            .is_some_and(|started| now.saturating_sub(started) >= Duration::from_secs(60))
+    }
+}
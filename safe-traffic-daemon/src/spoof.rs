@@ -0,0 +1,224 @@
+//! 伪造源地址洪泛：大量不同来源各只发出一两个报文时，逐 IP 封禁只会让规则集膨胀而拦不住下一批地址。
+//! 此时改为整体防护（SYN cookies、SYN 代理、按端口限制 SYN 速率），规则引擎暂停逐 IP 创建规则，
+//! 条件消失 hold_secs 后解除
+//!
+//! SYN 代理要求 `net.netfilter.nf_conntrack_tcp_loose = 0`，否则握手的 ACK 不会被判为 invalid。
+
+use crate::{controller::Firewall, flows::FlowTable, nft::NftExecutor};
+
+use anyhow::Result;
+use log::{info, warn};
+use safe_traffic_common::{
+    config::SpoofGuardConfig,
+    events::{Event, EventKind},
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::time;
+
+const TABLE: &str = "inet traffic_spoof_guard";
+/// 连接跟踪之前，交给 SYN 代理的 SYN 不建立连接跟踪条目
+const RAW_PRIORITY: i32 = -300;
+const PRIORITY: i32 = 10;
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const SYNCOOKIES: &str = "/proc/sys/net/ipv4/tcp_syncookies";
+
+/// 整体防护状态的变化
+#[derive(Debug, PartialEq, Eq)]
+pub enum Transition {
+    /// 开启，附带本轮视为伪造的来源数
+    Engage(usize),
+    Release,
+}
+
+/// 按流表识别伪造源洪泛，并开启或解除整体防护
+pub struct SpoofGuard {
+    executor: Arc<NftExecutor>,
+    flows: Arc<FlowTable>,
+    min_sources: usize,
+    max_packets: u64,
+    hold: Duration,
+    syncookies: bool,
+    ports: Vec<u16>,
+    synproxy: bool,
+    syn_rate: Option<u64>,
+    /// 整体防护是否开启，开启期间规则引擎不创建逐 IP 规则
+    active: AtomicBool,
+    /// 本次防护期间规则引擎放弃的逐 IP 动作数
+    suppressed: AtomicU64,
+    /// 最近一次满足条件的单调时间
+    last_seen: Mutex<Option<Duration>>,
+    /// 开启前 tcp_syncookies 的值，解除时恢复
+    saved_syncookies: Mutex<Option<String>>,
+}
+
+impl SpoofGuard {
+    pub fn new(cfg: &SpoofGuardConfig, executor: Arc<NftExecutor>, flows: Arc<FlowTable>) -> Self {
+        Self {
+            executor,
+            flows,
+            min_sources: cfg.min_sources.unwrap_or(1000).max(1),
+            max_packets: cfg.max_packets.unwrap_or(2).max(1),
+            hold: Duration::from_secs(cfg.hold_secs.unwrap_or(120)),
+            syncookies: cfg.syncookies.unwrap_or(true),
+            ports: cfg.ports.clone().unwrap_or_default(),
+            synproxy: cfg.synproxy.unwrap_or(false),
+            syn_rate: cfg.syn_rate,
+            active: AtomicBool::new(false),
+            suppressed: AtomicU64::new(0),
+            last_seen: Mutex::new(None),
+            saved_syncookies: Mutex::new(None),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// 记录一个因整体防护而未创建的逐 IP 动作
+    pub fn suppress(&self) {
+        self.suppressed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 按本轮视为伪造的来源数更新状态，返回需要执行的变化
+    pub fn observe(&self, thin_sources: usize, now: Duration) -> Option<Transition> {
+        let mut last_seen = self.last_seen.lock().unwrap();
+        if thin_sources >= self.min_sources {
+            *last_seen = Some(now);
+            if !self.active.swap(true, Ordering::Relaxed) {
+                self.suppressed.store(0, Ordering::Relaxed);
+                return Some(Transition::Engage(thin_sources));
+            }
+            return None;
+        }
+        if self.is_active() && last_seen.is_some_and(|seen| now.saturating_sub(seen) >= self.hold) {
+            self.active.store(false, Ordering::Relaxed);
+            return Some(Transition::Release);
+        }
+        None
+    }
+
+    /// 开启防护时安装的 nft 规则；只开启 SYN cookies 时为空
+    pub fn engage_commands(&self) -> Vec<String> {
+        if !self.synproxy && self.syn_rate.is_none() {
+            return Vec::new();
+        }
+        let ports: Vec<String> = self.ports.iter().map(|port| port.to_string()).collect();
+        let ports = ports.join(", ");
+        let mut commands = vec![format!("add table {}", TABLE)];
+        if self.synproxy {
+            commands.push(format!(
+                "add chain {} prerouting {{ type filter hook prerouting priority {}; policy accept; }}",
+                TABLE, RAW_PRIORITY
+            ));
+            commands.push(format!(
+                "add rule {} prerouting tcp dport {{ {} }} tcp flags & (syn | ack) == syn notrack",
+                TABLE, ports
+            ));
+        }
+        commands.push(format!(
+            "add chain {} input {{ type filter hook input priority {}; policy accept; }}",
+            TABLE, PRIORITY
+        ));
+        if let Some(rate) = self.syn_rate {
+            // 每个端口一条规则，各自的限速互不挤占
+            for port in &self.ports {
+                commands.push(format!(
+                    "add rule {} input tcp dport {} tcp flags & (syn | ack) == syn limit rate over {}/second counter drop",
+                    TABLE, port, rate
+                ));
+            }
+        }
+        if self.synproxy {
+            commands.push(format!(
+                "add rule {} input tcp dport {{ {} }} ct state {{ invalid, untracked }} synproxy mss 1460 wscale 7 timestamp sack-perm",
+                TABLE, ports
+            ));
+            commands.push(format!(
+                "add rule {} input tcp dport {{ {} }} ct state invalid counter drop",
+                TABLE, ports
+            ));
+        }
+        commands
+    }
+
+    /// 删除防护表，表不存在时也不报错
+    pub fn release_commands() -> Vec<String> {
+        vec![
+            format!("add table {}", TABLE),
+            format!("delete table {}", TABLE),
+        ]
+    }
+
+    async fn engage(&self) -> Result<()> {
+        if self.syncookies {
+            // 2 表示始终使用 SYN cookies，无需修改
+            let previous = tokio::fs::read_to_string(SYNCOOKIES).await?;
+            if previous.trim() == "0" {
+                tokio::fs::write(SYNCOOKIES, "1").await?;
+                *self.saved_syncookies.lock().unwrap() = Some(previous.trim().to_string());
+            }
+        }
+        let commands = self.engage_commands();
+        if !commands.is_empty() {
+            self.executor.execute_batch(commands).await?;
+        }
+        Ok(())
+    }
+
+    async fn release(&self) -> Result<()> {
+        let saved = self.saved_syncookies.lock().unwrap().take();
+        if let Some(previous) = saved {
+            tokio::fs::write(SYNCOOKIES, previous).await?;
+        }
+        self.executor
+            .execute_batch(Self::release_commands())
+            .await?;
+        Ok(())
+    }
+
+    /// 清除上次运行遗留的防护表后周期性检查流表
+    pub async fn run(self: Arc<Self>, fw: Arc<Firewall>) {
+        if let Err(e) = self.executor.execute_batch(Self::release_commands()).await {
+            warn!("Failed to remove a stale spoof guard table: {}", e);
+        }
+        info!(
+            "Watching for spoofed-source floods of {} sources with at most {} packets each",
+            self.min_sources, self.max_packets
+        );
+        let clock = fw.clock();
+        let mut interval = time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let thin_sources = self.flows.thin_sources(self.max_packets);
+            let message = match self.observe(thin_sources, clock.monotonic()) {
+                Some(Transition::Engage(sources)) => {
+                    if let Err(e) = self.engage().await {
+                        warn!("Failed to engage spoofed-source flood protection: {}", e);
+                    }
+                    format!(
+                        "spoofed-source flood: {} sources with at most {} packets each, switched to aggregate protection",
+                        sources, self.max_packets
+                    )
+                }
+                Some(Transition::Release) => {
+                    if let Err(e) = self.release().await {
+                        warn!("Failed to release spoofed-source flood protection: {}", e);
+                    }
+                    format!(
+                        "spoofed-source flood subsided, aggregate protection released after suppressing {} per-IP actions",
+                        self.suppressed.load(Ordering::Relaxed)
+                    )
+                }
+                None => continue,
+            };
+            warn!("{}", message);
+            fw.events.push(Event::new(EventKind::Spoof, message)).await;
+        }
+    }
+}
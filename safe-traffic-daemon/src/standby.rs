@@ -0,0 +1,258 @@
+use crate::controller::Firewall;
+use crate::logger;
+use crate::nft::NftExecutor;
+use crate::push::STANDBY_PATH;
+use crate::tasks;
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use futures::{SinkExt, StreamExt};
+use log::{debug, error, info, warn};
+use safe_traffic_common::{
+    config::{Action, Config, StandbyConfig},
+    transport::StandbyState,
+    utils::FirewallRule,
+};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::net::TcpStream;
+use tokio::{signal, time};
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+/// 以备节点运行：跟随主节点，主节点失联后才创建 nft 表并接管执行；
+/// 接管期间主节点心跳恢复时撤下本地的表（fencing），重新跟随
+pub async fn run(cfg: Config, executor: Arc<NftExecutor>) -> Result<()> {
+    let standby = cfg
+        .standby
+        .clone()
+        .ok_or_else(|| anyhow!("standby mode needs a [standby] section"))?;
+    let mut follower = StandbyFollower::new(&standby);
+    loop {
+        tokio::select! {
+            _ = follower.follow() => {}
+            _ = signal::ctrl_c() => {
+                info!("Received Ctrl+C signal, standby shutting down");
+                return Ok(());
+            }
+        }
+
+        let fw = Arc::new(Firewall::new(&cfg, Arc::clone(&executor)).await?);
+        follower.take_over(&fw).await;
+        let mut fenced = false;
+        let primary_back = async {
+            follower.wait_for_primary().await;
+            fenced = true;
+        };
+        tasks::run(&cfg, Arc::clone(&fw), Arc::clone(&executor), primary_back).await?;
+
+        fw.cleanup().await?;
+        executor.input("delete table inet traffic_monitor").await?;
+        if !fenced {
+            return Ok(());
+        }
+        warn!("Primary is back, removed local enforcement and following again");
+    }
+}
+
+/// 备节点：跟随主节点的规则与白名单，主节点心跳消失后接管
+pub struct StandbyFollower {
+    primary: SocketAddr,
+    token: Option<String>,
+    heartbeat_interval: Duration,
+    failover_after: u32,
+    /// 与主节点的连接，请求失败后丢弃，下次同步时重连
+    socket: Option<WebSocketStream<MaybeTlsStream<TcpStream>>>,
+    rules: Vec<FirewallRule>,
+    excludes: Vec<IpAddr>,
+    /// 主节点临时白名单的到期时间
+    expiries: HashMap<IpAddr, DateTime<Utc>>,
+}
+
+impl StandbyFollower {
+    pub fn new(cfg: &StandbyConfig) -> Self {
+        Self {
+            primary: cfg.primary,
+            token: cfg.token.clone(),
+            heartbeat_interval: Duration::from_secs(cfg.heartbeat_interval.unwrap_or(2).max(1)),
+            failover_after: cfg.failover_after.unwrap_or(3).max(1),
+            socket: None,
+            rules: Vec::new(),
+            excludes: Vec::new(),
+            expiries: HashMap::new(),
+        }
+    }
+
+    /// 持续同步主节点状态，直到连续丢失心跳
+    pub async fn follow(&mut self) {
+        info!("Running as standby, following primary at {}", self.primary);
+        let mut interval = time::interval(self.heartbeat_interval);
+        let mut missed = 0;
+
+        loop {
+            interval.tick().await;
+
+            match self.sync().await {
+                Ok(()) => {
+                    if missed > 0 {
+                        info!("Primary heartbeat recovered");
+                    }
+                    missed = 0;
+                    debug!(
+                        "Synced {} rules and {} exclusions from primary",
+                        self.rules.len(),
+                        self.excludes.len()
+                    );
+                }
+                Err(e) => {
+                    missed += 1;
+                    warn!(
+                        "Primary heartbeat missed ({}/{}): {:#}",
+                        missed, self.failover_after, e
+                    );
+                    if missed >= self.failover_after {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// 接管后继续探测主节点，心跳恢复时返回
+    pub async fn wait_for_primary(&mut self) {
+        let mut interval = time::interval(self.heartbeat_interval);
+        loop {
+            interval.tick().await;
+            if self.sync().await.is_ok() {
+                warn!("Primary heartbeat is back, standing down");
+                return;
+            }
+        }
+    }
+
+    /// 拉取主节点的活跃规则和白名单，一次成功的拉取即一次心跳
+    async fn sync(&mut self) -> Result<()> {
+        let result = self.request_state().await;
+        if result.is_err() {
+            self.socket = None;
+        }
+        let state = result?;
+        self.rules = state.rules;
+        self.excludes = state.excludes;
+        self.expiries = state
+            .temporary_excludes
+            .into_iter()
+            .map(|exclude| (exclude.ip, exclude.until))
+            .collect();
+        Ok(())
+    }
+
+    fn url(&self) -> String {
+        match &self.token {
+            Some(token) => format!("ws://{}{}?token={}", self.primary, STANDBY_PATH, token),
+            None => format!("ws://{}{}", self.primary, STANDBY_PATH),
+        }
+    }
+
+    /// 发出一次请求，在一个心跳间隔内等待主节点回复状态
+    async fn request_state(&mut self) -> Result<StandbyState> {
+        let socket = match &mut self.socket {
+            Some(socket) => socket,
+            None => {
+                let (socket, _) = time::timeout(self.heartbeat_interval, connect_async(self.url()))
+                    .await
+                    .map_err(|_| anyhow!("timed out connecting to primary {}", self.primary))?
+                    .with_context(|| format!("Failed to connect to primary {}", self.primary))?;
+                self.socket.insert(socket)
+            }
+        };
+        socket.send(Message::Text("sync".to_string())).await?;
+        loop {
+            let message = time::timeout(self.heartbeat_interval, socket.next())
+                .await
+                .map_err(|_| anyhow!("primary did not answer within the heartbeat interval"))?;
+            match message {
+                Some(Ok(Message::Text(text))) => return Ok(serde_json::from_str(&text)?),
+                Some(Ok(Message::Close(_))) | None => bail!("primary closed the connection"),
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// 接管执行：在本地重建主节点最后一次同步的规则
+    pub async fn take_over(&self, fw: &Firewall) {
+        warn!(
+            "Primary lost, taking over enforcement of {} rules",
+            self.rules.len()
+        );
+
+        let now = fw.clock().wall();
+        for ip in &self.excludes {
+            // 临时白名单按剩余时长恢复，已到期的不再恢复
+            let seconds = match self.expiries.get(ip) {
+                Some(until) if *until <= now => continue,
+                Some(until) => Some((*until - now).num_seconds().max(1) as u64),
+                None => None,
+            };
+            if !fw.is_excluded(ip).await {
+                if let Err(e) = fw.add_exclude(ip, seconds).await {
+                    error!("Failed to restore exclusion {}: {}", ip, e);
+                }
+            }
+        }
+
+        // 主节点同步来的规则没有本地单调时间戳，按墙上时间估算已生效时长
+        let clock = fw.clock();
+        for rule in &self.rules {
+            let elapsed = Duration::from_secs(rule.elapsed(clock.as_ref()).as_secs());
+            let remaining = match rule.rule_type.remaining(elapsed) {
+                None => None,
+                Some(left) if !left.is_zero() => Some(left.as_secs()),
+                Some(_) => {
+                    debug!("Rule {} expired during failover, skipping", rule.id);
+                    continue;
+                }
+            };
+
+            let restore = async {
+                match (&rule.rule_type, &rule.mac) {
+                    (Action::Ban { .. }, None) => fw.ban(rule.ip, remaining).await,
+                    (Action::Ban { .. }, Some(mac)) => fw.ban_mac(rule.ip, mac, remaining).await,
+                    (Action::RateLimit { kbps, burst, .. }, None) => {
+                        fw.limit(rule.ip, *kbps, *burst, remaining).await
+                    }
+                    (Action::RateLimit { kbps, burst, .. }, Some(mac)) => {
+                        fw.limit_mac(rule.ip, mac, *kbps, *burst, remaining).await
+                    }
+                    (Action::Mirror { target, device, .. }, _) => {
+                        fw.mirror(rule.ip, *target, device.as_deref(), remaining)
+                            .await
+                    }
+                    (Action::PoliceSources { kbps, burst, .. }, _) => {
+                        fw.police_sources(rule.ip, *kbps, *burst, remaining).await
+                    }
+                    (Action::Warn { .. }, _) => fw.warn(rule.ip, remaining).await,
+                    (Action::Inspect { .. }, _) => {
+                        fw.inspect(rule.ip, remaining).await.and_then(|id| {
+                            id.ok_or_else(|| anyhow::anyhow!("too many IPs under inspection"))
+                        })
+                    }
+                }
+            };
+            // 恢复的规则沿用主节点记录的触发原因
+            let result = match rule.reason.clone() {
+                Some(reason) => logger::with_reason(reason, restore).await,
+                None => restore.await,
+            };
+
+            match result {
+                Ok(rule_id) => info!("Restored rule {} as {}", rule.id, rule_id),
+                Err(e) => error!("Failed to restore rule {}: {}", rule.id, e),
+            }
+        }
+    }
+}
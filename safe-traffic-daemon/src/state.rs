@@ -0,0 +1,195 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    io::ErrorKind,
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
+use tokio::fs;
+
+/// 运行时状态文件的默认目录
+pub const DEFAULT_STATE_DIR: &str = "/var/lib/safe-traffic";
+
+/// 状态目录下的文件路径
+pub fn state_file(state_dir: Option<&str>, name: &str) -> PathBuf {
+    Path::new(state_dir.unwrap_or(DEFAULT_STATE_DIR)).join(name)
+}
+
+/// 原子写入：先写临时文件再重命名，进程中途退出也不会留下半个文件
+pub async fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .await
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents)
+        .await
+        .with_context(|| format!("failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path)
+        .await
+        .with_context(|| format!("failed to replace {}", path.display()))?;
+    Ok(())
+}
+
+/// 运行时对全局白名单的修改，相对配置文件中的 global_exclude 记录增删
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ExcludeOverrides {
+    #[serde(default)]
+    pub added: BTreeSet<IpAddr>,
+    #[serde(default)]
+    pub removed: BTreeSet<IpAddr>,
+    /// added 中带有效期的 IP 及其到期时间，按墙上时间记录，重启后照常到期
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub expires: BTreeMap<IpAddr, DateTime<Utc>>,
+}
+
+impl ExcludeOverrides {
+    /// 读取状态文件，文件不存在时视为没有修改
+    pub async fn load(path: &Path) -> Result<Self> {
+        match fs::read(path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("invalid exclusion state {}", path.display())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, &serde_json::to_vec_pretty(self)?).await
+    }
+
+    /// 在配置的白名单基础上应用运行时修改；配置中已有的 IP 永久生效，不再到期
+    pub fn apply(&mut self, excludes: &mut HashSet<IpAddr>) {
+        self.expires.retain(|ip, _| !excludes.contains(ip));
+        excludes.extend(self.added.iter().copied());
+        excludes.retain(|ip| !self.removed.contains(ip));
+    }
+
+    /// 加入白名单，until 为空时永久生效
+    pub fn add(&mut self, ip: IpAddr, until: Option<DateTime<Utc>>) {
+        self.removed.remove(&ip);
+        self.added.insert(ip);
+        match until {
+            Some(until) => self.expires.insert(ip, until),
+            None => self.expires.remove(&ip),
+        };
+    }
+
+    pub fn remove(&mut self, ip: IpAddr) {
+        self.added.remove(&ip);
+        self.expires.remove(&ip);
+        self.removed.insert(ip);
+    }
+
+    /// 到期的 IP
+    pub fn expired(&self, now: DateTime<Utc>) -> Vec<IpAddr> {
+        self.expires
+            .iter()
+            .filter(|(_, until)| **until <= now)
+            .map(|(ip, _)| *ip)
+            .collect()
+    }
+
+    /// 移除到期的 IP，配置中没有这些 IP，不需要记为删除
+    pub fn expire(&mut self, ip: IpAddr) {
+        self.added.remove(&ip);
+        self.expires.remove(&ip);
+    }
+}
+
+/// 测试用的临时状态目录，离开作用域时删除
+#[cfg(test)]
+pub(crate) struct TempStateDir(PathBuf);
+
+#[cfg(test)]
+impl TempStateDir {
+    pub(crate) fn new(name: &str) -> Self {
+        static NEXT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "safe-traffic-{}-{}-{}",
+            name,
+            std::process::id(),
+            NEXT.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        Self(path)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempStateDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn applied(overrides: &mut ExcludeOverrides, configured: &[IpAddr]) -> BTreeSet<IpAddr> {
+        let mut excludes: HashSet<IpAddr> = configured.iter().copied().collect();
+        overrides.apply(&mut excludes);
+        excludes.into_iter().collect()
+    }
+
+    #[test]
+    fn test_overrides_add_remove_configured() {
+        let (a, b, c) = (ip("192.0.2.1"), ip("192.0.2.2"), ip("192.0.2.3"));
+        let configured = [a, b];
+        let mut overrides = ExcludeOverrides::default();
+
+        // 配置中已有的 IP 即使带有效期也永久生效
+        overrides.add(a, Some(Utc::now()));
+        overrides.add(c, None);
+        assert_eq!(applied(&mut overrides, &configured), [a, b, c].into());
+        assert!(overrides.expires.is_empty());
+
+        overrides.remove(a);
+        assert_eq!(applied(&mut overrides, &configured), [b, c].into());
+
+        // 重新加入后不再记为删除
+        overrides.add(a, None);
+        assert!(overrides.removed.is_empty());
+        assert_eq!(applied(&mut overrides, &configured), [a, b, c].into());
+
+        overrides.remove(c);
+        assert!(!overrides.added.contains(&c));
+        assert_eq!(applied(&mut overrides, &configured), [a, b].into());
+    }
+
+    #[tokio::test]
+    async fn test_overrides_round_trip() {
+        let dir = TempStateDir::new("state");
+        let path = dir.path().join("excludes.json");
+        assert!(ExcludeOverrides::load(&path)
+            .await
+            .unwrap()
+            .added
+            .is_empty());
+
+        let until = Utc::now();
+        let mut overrides = ExcludeOverrides::default();
+        overrides.add(ip("192.0.2.1"), None);
+        overrides.add(ip("2001:db8::1"), Some(until));
+        overrides.remove(ip("192.0.2.2"));
+        overrides.save(&path).await.unwrap();
+
+        let loaded = ExcludeOverrides::load(&path).await.unwrap();
+        assert_eq!(loaded.added, overrides.added);
+        assert_eq!(loaded.removed, overrides.removed);
+        assert_eq!(loaded.expires, overrides.expires);
+        assert_eq!(loaded.expired(until), [ip("2001:db8::1")]);
+    }
+}
@@ -0,0 +1,208 @@
+//! 扫描减速：流表中短时间内访问大量不同目的端口的来源被加入逐级收紧的限速集合，
+//! 超出速率的报文被丢弃，扫描工具的重传与超时使探测明显变慢，而不必完全封禁
+
+use crate::{controller::Firewall, flows::FlowTable, nft::NftExecutor};
+
+use anyhow::Result;
+use log::{debug, info, warn};
+use safe_traffic_common::{
+    config::TarpitConfig,
+    events::{Event, EventKind},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    net::IpAddr,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::time;
+
+const TABLE: &str = "inet traffic_tarpit";
+const CHAIN: &str = "slowdown";
+/// 低于主过滤链的优先级，已被封禁或限速的报文不会到达这里
+const PRIORITY: i32 = 10;
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 按流表识别扫描来源，并维护各级减速集合
+pub struct Tarpit {
+    executor: Arc<NftExecutor>,
+    flows: Arc<FlowTable>,
+    min_ports: usize,
+    window: Duration,
+    /// 每一级的报文速率，个/秒
+    stages: Vec<u64>,
+    ttl: Duration,
+    /// 每个来源访问过的目的端口及最后一次出现的单调时间
+    ports: HashMap<IpAddr, HashMap<u16, Duration>>,
+    /// 每个来源当前所在的级别及进入该级别的单调时间
+    levels: HashMap<IpAddr, (usize, Duration)>,
+}
+
+impl Tarpit {
+    pub fn new(cfg: &TarpitConfig, executor: Arc<NftExecutor>, flows: Arc<FlowTable>) -> Self {
+        Self {
+            executor,
+            flows,
+            min_ports: cfg.min_ports.unwrap_or(20).max(1),
+            window: Duration::from_secs(cfg.window_secs.unwrap_or(60).max(1)),
+            stages: cfg.stages.clone().unwrap_or_else(|| vec![50, 10, 2]),
+            ttl: Duration::from_secs(cfg.ttl_secs.unwrap_or(600).max(1)),
+            ports: HashMap::new(),
+            levels: HashMap::new(),
+        }
+    }
+
+    fn set_name(stage: usize, ip: IpAddr) -> String {
+        match ip {
+            IpAddr::V4(_) => format!("stage{}_v4", stage),
+            IpAddr::V6(_) => format!("stage{}_v6", stage),
+        }
+    }
+
+    /// 创建减速表、链，以及每一级的地址集合与按来源限速的规则
+    pub fn setup_commands(&self) -> Vec<String> {
+        let mut commands = vec![
+            format!("add table {}", TABLE),
+            format!(
+                "add chain {} {} {{ type filter hook input priority {}; policy accept; }}",
+                TABLE, CHAIN, PRIORITY
+            ),
+            format!("flush chain {} {}", TABLE, CHAIN),
+        ];
+        for (stage, rate) in self.stages.iter().enumerate() {
+            for (version, addr_type) in [("ip", "ipv4_addr"), ("ip6", "ipv6_addr")] {
+                let suffix = if version == "ip" { "v4" } else { "v6" };
+                commands.push(format!(
+                    "add set {} stage{}_{} {{ type {}; flags timeout; }}",
+                    TABLE, stage, suffix, addr_type
+                ));
+                commands.push(format!(
+                    "add rule {} {} {} saddr @stage{}_{} meter tarpit{}_{} {{ {} saddr limit rate over {}/second }} counter drop",
+                    TABLE, CHAIN, version, stage, suffix, stage, suffix, version, rate
+                ));
+            }
+        }
+        commands
+    }
+
+    /// 将来源加入某一级集合，到期后自动移出
+    pub fn element_command(&self, ip: IpAddr, stage: usize) -> String {
+        format!(
+            "add element {} {} {{ {} timeout {}s }}",
+            TABLE,
+            Self::set_name(stage, ip),
+            ip,
+            self.ttl.as_secs()
+        )
+    }
+
+    /// 按最新的端口分布更新窗口，返回本轮新加入或升级的来源及其级别，按地址排序
+    pub fn observe(
+        &mut self,
+        source_dports: HashMap<IpAddr, HashSet<u16>>,
+        now: Duration,
+    ) -> Vec<(IpAddr, usize)> {
+        for (src, dports) in source_dports {
+            let seen = self.ports.entry(src).or_default();
+            for dport in dports {
+                seen.insert(dport, now);
+            }
+        }
+        let window = self.window;
+        self.ports.retain(|_, seen| {
+            seen.retain(|_, last| now.saturating_sub(*last) < window);
+            !seen.is_empty()
+        });
+        let ttl = self.ttl;
+        self.levels
+            .retain(|_, (_, since)| now.saturating_sub(*since) < ttl);
+
+        let mut slowed = Vec::new();
+        for (src, seen) in &self.ports {
+            if seen.len() < self.min_ports {
+                continue;
+            }
+            let stage = match self.levels.get(src) {
+                None => 0,
+                Some((stage, since))
+                    if now.saturating_sub(*since) >= self.window
+                        && stage + 1 < self.stages.len() =>
+                {
+                    stage + 1
+                }
+                Some(_) => continue,
+            };
+            slowed.push((*src, stage));
+        }
+        for (src, stage) in &slowed {
+            self.levels.insert(*src, (*stage, now));
+        }
+        slowed.sort();
+        slowed
+    }
+
+    /// 当前处于减速中的来源数
+    pub fn slowed(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// 安装减速链后周期性检查流表；白名单中的来源不减速
+    pub async fn run(mut self, fw: Arc<Firewall>) {
+        if let Err(e) = self.executor.execute_batch(self.setup_commands()).await {
+            warn!("Failed to install the tarpit chain: {}", e);
+            return;
+        }
+        info!(
+            "Slowing down sources that touch {} ports within {}s ({} stages)",
+            self.min_ports,
+            self.window.as_secs(),
+            self.stages.len()
+        );
+        let clock = fw.clock();
+        let mut interval = time::interval(CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let slowed = self.observe(self.flows.source_dports(), clock.monotonic());
+            for (ip, stage) in slowed {
+                if fw.is_excluded(&ip).await {
+                    debug!("not slowing down excluded source {}", ip);
+                    self.levels.remove(&ip);
+                    continue;
+                }
+                if let Err(e) = self.apply(&fw, ip, stage).await {
+                    warn!("Failed to slow down {}: {}", ip, e);
+                    self.levels.remove(&ip);
+                }
+            }
+        }
+    }
+
+    async fn apply(&self, fw: &Firewall, ip: IpAddr, stage: usize) -> Result<()> {
+        self.executor
+            .execute(&self.element_command(ip, stage))
+            .await?;
+        let rate = self.stages[stage];
+        info!(
+            "Slowing down port scanner {} to {} packets/s (stage {}/{})",
+            ip,
+            rate,
+            stage + 1,
+            self.stages.len()
+        );
+        fw.events
+            .push(
+                Event::new(
+                    EventKind::Tarpit,
+                    format!(
+                        "slow down {} to {} packets/s for {}s",
+                        ip,
+                        rate,
+                        self.ttl.as_secs()
+                    ),
+                )
+                .with_ip(ip),
+            )
+            .await;
+        Ok(())
+    }
+}
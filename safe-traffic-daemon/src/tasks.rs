@@ -1,30 +1,309 @@
 use crate::{
-    controller::Firewall, daemon::TrafficDaemon, monitor::TrafficMonitor, nft::NftExecutor,
-    rules::RuleEngine,
+    bgp::BgpAnnouncer,
+    cloud::CloudExclusions,
+    controller::Firewall,
+    daemon::TrafficDaemon,
+    export::FlowExporter,
+    failsafe::Failsafe,
+    host::HostExclusions,
+    journal::DecisionJournal,
+    monitor::TrafficMonitor,
+    neighbors::NeighborTable,
+    nft::NftExecutor,
+    notify::Notifier,
+    outbound::ProxySettings,
+    push::PushServer,
+    rearm::Rearm,
+    recorder::StatsRecorder,
+    reputation::ReputationStore,
+    rules::{RuleEngine, WindowStore},
+    sketch::SourceSketches,
+    spoof::SpoofGuard,
+    state::state_file,
+    tarpit::Tarpit,
+    upstream::UpstreamChecker,
+    warnpage::WarnPage,
 };
 
 use dashmap::DashMap;
 use log::{error, info};
 use rtnetlink::new_connection;
-use safe_traffic_common::{config::Config, utils::TrafficStats};
-use std::{net::IpAddr, sync::Arc, time::Duration};
-use tokio::signal;
+use safe_traffic_common::{
+    config::{Config, HookType, Rule},
+    utils::TrafficStats,
+};
+use std::{
+    future::Future,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    signal,
+    task::{AbortHandle, JoinHandle},
+};
+
+/// 随 run 返回一并撤下的后台任务，备节点让位后不再留有执行中的任务
+#[derive(Default)]
+struct Background(Vec<AbortHandle>);
+
+impl Background {
+    fn spawn<F>(&mut self, task: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let handle = tokio::spawn(task);
+        self.0.push(handle.abort_handle());
+        handle
+    }
+}
+
+impl Drop for Background {
+    fn drop(&mut self) {
+        for handle in &self.0 {
+            handle.abort();
+        }
+    }
+}
 
-/// 运行主监控逻辑
-pub async fn run(cfg: Config, fw: Arc<Firewall>, executor: Arc<NftExecutor>) -> anyhow::Result<()> {
+/// 运行主监控逻辑，直到收到 Ctrl+C 或 stop 完成
+pub async fn run(
+    cfg: &Config,
+    fw: Arc<Firewall>,
+    executor: Arc<NftExecutor>,
+    stop: impl Future<Output = ()>,
+) -> anyhow::Result<()> {
+    let mut background = Background::default();
     let stats = Arc::new(DashMap::<IpAddr, TrafficStats>::new());
-    let engine = Arc::new(RuleEngine::new(cfg.rules.clone(), stats.clone()));
+    // 引擎与防火墙共用同一时间来源
+    // 监控器写入采样时推进窗口，引擎的检查延迟时窗口仍按秒对齐
+    let mut windows = WindowStore::new(fw.hook.clone());
+    if cfg.rules.iter().any(Rule::counts_day_bytes) {
+        info!("Keeping 24 hours of per-source traffic history for rolling-day quotas");
+        windows = windows.with_history();
+    }
+    if cfg.low_memory.unwrap_or(false) {
+        windows = windows.with_low_memory(&cfg.rules);
+        info!(
+            "Low-memory mode: about {} bytes of window state per tracked source",
+            windows.bytes_per_source()
+        );
+    }
+    let windows = Arc::new(windows);
+    let mut engine = RuleEngine::new(cfg.rules.clone(), stats.clone())
+        .with_clock(fw.clock())
+        .with_windows(Arc::clone(&windows))
+        .with_excluded_abuse(
+            cfg.excluded_abuse_multiple(),
+            cfg.excluded_abuse_interval_secs(),
+        );
     let (connection, handle, _messages) = new_connection()?;
-    tokio::spawn(connection);
+    background.spawn(connection);
+    // 通知、上游提供商与 BGP 的出站请求共用同一代理设置
+    let proxy = ProxySettings::new(cfg.proxy.as_ref());
+
+    // Output 钩子统计本机发出的流量，本机自身的地址不能成为动作的对象
+    if matches!(cfg.hook, Some(HookType::Output)) {
+        let interfaces = cfg
+            .devices
+            .clone()
+            .unwrap_or_else(|| vec![cfg.interface.clone()]);
+        let host = cfg.host_exclude.clone().unwrap_or_default();
+        info!(
+            "Excluding host addresses on {} from Output actions",
+            interfaces.join(", ")
+        );
+        let fw_clone = Arc::clone(&fw);
+        background.spawn(HostExclusions::new(handle.clone(), interfaces, host).run(fw_clone));
+    }
 
-    let monitor = Arc::new(TrafficMonitor::new(
+    let mut monitor = TrafficMonitor::new(
         handle,
         cfg.interface.clone(),
-        stats,
+        stats.clone(),
         Duration::from_secs(cfg.monitor_interval.unwrap_or(1)),
         executor.clone(),
-    ));
-    let daemon = Arc::new(TrafficDaemon::new(fw.clone(), engine.clone()));
+    )
+    .with_windows(windows, fw.clock());
+
+    if let Some(flows) = fw.flows() {
+        monitor = monitor.with_flows(flows);
+        if let Some(sketches) = SourceSketches::for_rules(&cfg.rules) {
+            info!("Estimating unique sources per minute to destination ports");
+            let sketches = Arc::new(sketches);
+            monitor = monitor.with_source_sketches(Arc::clone(&sketches));
+            engine = engine.with_source_sketches(sketches);
+        }
+    }
+
+    // 有规则按目的地址的流量触发时才统计发往这些地址的流量
+    let mut destinations: Vec<IpAddr> = cfg
+        .rules
+        .iter()
+        .filter(|rule| !rule.counts_sources())
+        .filter_map(|rule| rule.destinations.clone())
+        .flatten()
+        .collect();
+    destinations.sort();
+    destinations.dedup();
+    if !destinations.is_empty() {
+        info!(
+            "Counting traffic to {} destination addresses",
+            destinations.len()
+        );
+        let dest_stats = Arc::new(DashMap::<IpAddr, TrafficStats>::new());
+        monitor = monitor.with_destinations(destinations, dest_stats);
+    }
+
+    if let Some(max) = cfg.max_tracked_ips {
+        info!("Tracking at most {} IPs, shedding the lightest", max);
+        monitor = monitor.with_max_tracked_ips(max, Arc::clone(&fw.events));
+    }
+
+    // 有规则按 MAC 执行时才维护邻居表
+    if cfg
+        .rules
+        .iter()
+        .any(|rule| rule.enforce_by_mac.unwrap_or(false))
+    {
+        info!("MAC enforcement enabled, tracking neighbor table");
+        let neighbors = Arc::new(NeighborTable::new());
+        monitor = monitor.with_neighbors(Arc::clone(&neighbors));
+        engine = engine.with_neighbors(neighbors);
+    }
+
+    if let Some(upstream) = cfg.upstream.clone() {
+        info!(
+            "Checking {:?} blocklist before long-term bans",
+            upstream.provider
+        );
+        engine = engine.with_upstream(Arc::new(
+            UpstreamChecker::new(upstream)
+                .with_proxy(&proxy)
+                .with_health(fw.health()),
+        ));
+    }
+
+    if let Some(cloud) = cfg.cloud_exclude.clone() {
+        info!(
+            "Excluding {:?} instance addresses and networks",
+            cloud.provider
+        );
+        let fw_clone = Arc::clone(&fw);
+        background.spawn(CloudExclusions::new(cloud).run(fw_clone));
+    }
+
+    if cfg.incident_threshold.is_some() || cfg.incident_window_secs.is_some() {
+        engine = engine.with_incidents(
+            cfg.incident_threshold
+                .unwrap_or(crate::incidents::DEFAULT_THRESHOLD),
+            cfg.incident_window_secs
+                .unwrap_or(crate::incidents::DEFAULT_WINDOW_SECS),
+        );
+    }
+
+    let reputation = ReputationStore::load(
+        &state_file(cfg.state_dir.as_deref(), "reputation.json"),
+        cfg.reputation_half_life_days
+            .unwrap_or(crate::reputation::DEFAULT_HALF_LIFE_DAYS),
+    )
+    .await?;
+    engine = engine.with_reputation(Arc::new(reputation));
+
+    if let Some(warmup) = cfg.warmup_secs {
+        engine = engine.with_warmup(warmup);
+    }
+
+    if let Some(journal) = &cfg.journal {
+        let path = journal
+            .path
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| state_file(cfg.state_dir.as_deref(), "decisions.journal"));
+        let rate = journal.sample_rate.unwrap_or(1.0);
+        info!(
+            "Recording engine decisions of {:.0}% of sources to {}",
+            rate * 100.0,
+            path.display()
+        );
+        engine = engine.with_journal(Arc::new(DecisionJournal::open(&path, rate)?));
+    }
+
+    if let Some(path) = &cfg.record_stats {
+        info!("Recording traffic snapshots to {}", path);
+        let recorder = StatsRecorder::open(Path::new(path))?;
+        background.spawn(recorder.run(
+            stats.clone(),
+            Duration::from_secs(cfg.monitor_interval.unwrap_or(1).max(1)),
+            fw.clock(),
+        ));
+    }
+
+    // 伪造源洪泛期间规则引擎不创建逐 IP 规则
+    let spoof_guard = match (&cfg.spoof_guard, fw.flows()) {
+        (Some(guard), Some(flows)) => {
+            let guard = Arc::new(SpoofGuard::new(guard, executor.clone(), flows));
+            engine = engine.with_spoof_guard(Arc::clone(&guard));
+            Some(guard)
+        }
+        _ => None,
+    };
+
+    // 逐包判定阻塞在 netlink 套接字上，放在独立线程中
+    if let Some(inspector) = fw.inspector() {
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = inspector.run() {
+                error!(
+                    "NFQUEUE inspector stopped, queued packets are accepted: {}",
+                    e
+                );
+            }
+        });
+    }
+
+    // 备节点的规则来自主节点，不恢复自己上次运行的封禁
+    let rearm = match &cfg.rearm {
+        Some(rearm) if cfg.standby.is_none() => {
+            let rearm = Arc::new(Rearm::new(rearm, cfg.state_dir.as_deref()));
+            match rearm.restore(&fw).await {
+                Ok(0) => {}
+                Ok(restored) => info!("Re-armed {} bans active before the restart", restored),
+                Err(e) => error!("Failed to re-arm bans from the last run: {}", e),
+            }
+            background.spawn(Arc::clone(&rearm).run(Arc::clone(&fw)));
+            Some(rearm)
+        }
+        _ => None,
+    };
+
+    let savings = fw.savings();
+    if let Err(e) = savings.load().await {
+        error!(
+            "Failed to load dropped traffic totals, counting from zero: {}",
+            e
+        );
+    }
+    background.spawn(Arc::clone(&savings).run(Arc::clone(&fw)));
+    background.spawn(fw.health().run(fw.clock()));
+
+    // 模拟模式下没有真实的表，不挂定时器
+    let failsafe = match &cfg.failsafe {
+        Some(failsafe) if !executor.is_mock() => {
+            let failsafe = Arc::new(Failsafe::new(failsafe, &fw));
+            let task = background.spawn(Arc::clone(&failsafe).run());
+            Some((failsafe, task))
+        }
+        _ => None,
+    };
+
+    let engine = Arc::new(engine);
+    let monitor = Arc::new(monitor);
+    let daemon = Arc::new(
+        TrafficDaemon::new(fw.clone(), engine.clone())
+            .with_idempotency_retention(cfg.idempotency_retention_secs()),
+    );
 
     info!(
         "Traffic monitoring and rules engines have been started, monitoring interface: {}",
@@ -36,30 +315,85 @@ pub async fn run(cfg: Config, fw: Arc<Firewall>, executor: Arc<NftExecutor>) ->
     let engine_clone = engine.clone();
     let daemon_clone = daemon.clone();
 
-    let monitor_task = tokio::spawn(async move { monitor_clone.start().await });
+    let monitor_task = background.spawn(async move { monitor_clone.start().await });
 
     let fw_clone = Arc::clone(&fw);
-    let engine_task = tokio::spawn(async move {
-        engine_clone
-            .start(
-                fw_clone,
-                Duration::from_secs(cfg.rule_check_interval.unwrap_or(1)),
-            )
-            .await
-    });
-    let daemon_task = tokio::spawn(async move { daemon_clone.start().await });
+    let check_interval = Duration::from_secs(cfg.rule_check_interval.unwrap_or(1));
+    let engine_task =
+        background.spawn(async move { engine_clone.start(fw_clone, check_interval).await });
+    let daemon_task = background.spawn(async move { daemon_clone.start().await });
+
+    if let Some(websocket) = &cfg.websocket {
+        let server = PushServer::bind(websocket, Arc::clone(&fw), engine.clone()).await?;
+        background.spawn(server.run());
+    }
+
+    if let Some(bgp) = &cfg.bgp {
+        let announcer = BgpAnnouncer::new(bgp, fw.hook.clone()).with_proxy(&proxy);
+        background.spawn(announcer.run(Arc::clone(&fw)));
+    }
+
+    if let Some(channels) = cfg.notify.as_ref().filter(|channels| !channels.is_empty()) {
+        background.spawn(
+            Notifier::new(channels)
+                .with_proxy(&proxy)
+                .run(Arc::clone(&fw)),
+        );
+    }
+
+    if let Some(archive) = fw.events.archive() {
+        background.spawn(archive.run(fw.clock()));
+    }
+
+    if let Some(accounting) = fw.accounting() {
+        if let Err(e) = accounting.install().await {
+            error!("Failed to install accounting rules: {}", e);
+        }
+    }
+
+    if let Some(warn_page) = &cfg.warn_page {
+        let exporter = WarnPage::new(warn_page, cfg.state_dir.as_deref());
+        background.spawn(exporter.run(Arc::clone(&fw)));
+    }
+
+    if let (Some(tarpit), Some(flows)) = (&cfg.tarpit, fw.flows()) {
+        let tarpit = Tarpit::new(tarpit, executor.clone(), flows);
+        background.spawn(tarpit.run(Arc::clone(&fw)));
+    }
+
+    if let Some(guard) = spoof_guard {
+        background.spawn(guard.run(Arc::clone(&fw)));
+    }
+
+    if let Some(export_cfg) = &cfg.flow_export {
+        let exporter = FlowExporter::new(export_cfg, fw.hook.clone());
+        let fw_clone = Arc::clone(&fw);
+        background.spawn(async move {
+            if let Err(e) = exporter.run(fw_clone, stats).await {
+                error!("Flow exporter stopped: {}", e);
+            }
+        });
+    }
 
     // 创建 Ctrl+C 信号处理器
-    let ctrl_c = tokio::spawn(async {
+    let ctrl_c = background.spawn(async {
         signal::ctrl_c().await.expect("Failed to listen for ctrl+c");
         info!("Received Ctrl+C signal, initiating graceful shutdown...");
     });
 
+    // Ctrl+C 与调用方的 stop 同样优雅停止，备节点让位时由 stop 触发
+    let shutdown = async {
+        tokio::select! {
+            _ = ctrl_c => {}
+            _ = stop => info!("Stop requested, shutting down..."),
+        }
+    };
+
     // 等待任一任务完成或接收到 Ctrl+C 信号
     tokio::select! {
         // 监听 Ctrl+C 信号
 
-        _ = ctrl_c => {
+        _ = shutdown => {
             info!("Shutdown signal received, stopping all components...");
 
             // 优雅停止各个组件
@@ -69,6 +403,20 @@ pub async fn run(cfg: Config, fw: Arc<Firewall>, executor: Arc<NftExecutor>) ->
                 error!("Failed to stop rule engine: {}", e);
             }
 
+            // 退出时会清除全部规则，先记录下来供下次启动恢复
+            if let Some(rearm) = &rearm {
+                if let Err(e) = rearm.checkpoint(&fw).await {
+                    error!("Failed to record active bans: {}", e);
+                }
+            }
+            match fw.refresh_system_rules().await {
+                Ok(rules) => savings.record(&rules, fw.clock().wall().date_naive()),
+                Err(e) => error!("Failed to read rule counters for dropped traffic: {}", e),
+            }
+            if let Err(e) = savings.save().await {
+                error!("Failed to save dropped traffic totals: {}", e);
+            }
+
 
             // monitor.stop();
 
@@ -111,6 +459,12 @@ pub async fn run(cfg: Config, fw: Arc<Firewall>, executor: Arc<NftExecutor>) ->
 
     }
 
+    // 随后由 cleanup 删除表，先撤下定时器，免得它在下次启动后删掉新建的表
+    if let Some((failsafe, task)) = failsafe {
+        task.abort();
+        failsafe.disarm().await;
+    }
+
     Ok(())
 }
 
@@ -0,0 +1,154 @@
+use crate::{health::Health, outbound::ProxySettings};
+
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use dashmap::DashMap;
+use log::{debug, info, warn};
+use safe_traffic_common::config::{UpstreamConfig, UpstreamPolicy, UpstreamProvider};
+use std::{
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+const CLOUDFLARE_API: &str = "https://api.cloudflare.com/client/v4";
+/// 单次查询超时，超时视为上游未封禁
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 本地封禁的处理结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BanDecision {
+    /// 以该时长创建本地封禁，None 为永久
+    Apply(Option<u64>),
+    /// 上游已封禁，不创建本地规则
+    Skip,
+}
+
+/// 在创建长期封禁前查询上游提供商的封禁列表，避免重复占用 nft 规则
+pub struct UpstreamChecker {
+    cfg: UpstreamConfig,
+    agent: ureq::Agent,
+    /// IP 是否已被上游封禁及查询时间
+    cache: DashMap<IpAddr, (Instant, bool)>,
+    /// 查询结果计入的健康度
+    health: Option<Arc<Health>>,
+}
+
+impl UpstreamChecker {
+    pub fn new(cfg: UpstreamConfig) -> Self {
+        let agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build();
+        Self {
+            cfg,
+            agent,
+            cache: DashMap::new(),
+            health: None,
+        }
+    }
+
+    pub fn with_health(mut self, health: Arc<Health>) -> Self {
+        health.register("upstream", None, Utc::now());
+        self.health = Some(health);
+        self
+    }
+
+    /// 按代理设置访问提供商 API
+    pub fn with_proxy(mut self, proxy: &ProxySettings) -> Self {
+        self.agent = proxy.agent(CLOUDFLARE_API, REQUEST_TIMEOUT);
+        self
+    }
+
+    /// 根据上游状态和策略决定本地封禁时长
+    pub async fn decide(&self, ip: IpAddr, seconds: Option<u64>) -> BanDecision {
+        // 短期封禁不值得一次 API 查询
+        if seconds.is_some_and(|secs| secs < self.cfg.min_ban_secs.unwrap_or(3600)) {
+            return BanDecision::Apply(seconds);
+        }
+
+        let policy = self.cfg.policy.unwrap_or(UpstreamPolicy::Shorten);
+        if policy == UpstreamPolicy::Duplicate || !self.is_blocked(ip).await {
+            return BanDecision::Apply(seconds);
+        }
+
+        match policy {
+            UpstreamPolicy::Skip => BanDecision::Skip,
+            UpstreamPolicy::Shorten => {
+                let shorten = self.cfg.shorten_secs.unwrap_or(300);
+                BanDecision::Apply(Some(seconds.map_or(shorten, |secs| secs.min(shorten))))
+            }
+            UpstreamPolicy::Duplicate => BanDecision::Apply(seconds),
+        }
+    }
+
+    /// 查询 IP 是否已被上游封禁，查询失败时按未封禁处理
+    async fn is_blocked(&self, ip: IpAddr) -> bool {
+        let ttl = Duration::from_secs(self.cfg.cache_secs.unwrap_or(300));
+        if let Some(entry) = self.cache.get(&ip) {
+            let (checked_at, blocked) = *entry;
+            if checked_at.elapsed() < ttl {
+                return blocked;
+            }
+        }
+
+        let agent = self.agent.clone();
+        let cfg = self.cfg.clone();
+        let started = Instant::now();
+        let result = tokio::task::spawn_blocking(move || match cfg.provider {
+            UpstreamProvider::Cloudflare => {
+                query_cloudflare(&agent, &cfg.api_token, &cfg.zone_id, ip)
+            }
+        })
+        .await
+        .map_err(|e| anyhow!("upstream query task failed: {}", e))
+        .and_then(|result| result);
+        if let Some(health) = &self.health {
+            let outcome = result.as_ref().map(|_| ()).map_err(|e| e.to_string());
+            health
+                .record("upstream", Utc::now(), started.elapsed(), outcome)
+                .await;
+        }
+
+        match result {
+            Ok(blocked) => {
+                if blocked {
+                    info!("{} is already blocked by {:?}", ip, self.cfg.provider);
+                } else {
+                    debug!("{} is not blocked by {:?}", ip, self.cfg.provider);
+                }
+                self.cache.insert(ip, (Instant::now(), blocked));
+                blocked
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to query {:?} blocklist for {}: {}",
+                    self.cfg.provider, ip, e
+                );
+                false
+            }
+        }
+    }
+}
+
+/// 查询 Cloudflare 区域中针对该 IP 的 block 类型 IP Access Rule
+fn query_cloudflare(agent: &ureq::Agent, token: &str, zone_id: &str, ip: IpAddr) -> Result<bool> {
+    let target = if ip.is_ipv4() { "ip" } else { "ip6" };
+    let url = format!(
+        "{}/zones/{}/firewall/access_rules/rules",
+        CLOUDFLARE_API, zone_id
+    );
+    let body = agent
+        .get(&url)
+        .set("Authorization", &format!("Bearer {}", token))
+        .query("mode", "block")
+        .query("configuration.target", target)
+        .query("configuration.value", &ip.to_string())
+        .call()?
+        .into_string()?;
+
+    let body: serde_json::Value = serde_json::from_str(&body)?;
+    if body["success"].as_bool() != Some(true) {
+        return Err(anyhow!("cloudflare api error: {}", body["errors"]));
+    }
+    Ok(body["result"]
+        .as_array()
+        .is_some_and(|rules| !rules.is_empty()))
+}
@@ -0,0 +1,117 @@
+//! 提示页：Warn 与 RateLimit 作用中的来源写入 nginx geo 模块可读取的文件，
+//! Web 服务据此对这些来源返回 429 页面，误判的用户能看到原因而不是连接超时
+//!
+//! ```nginx
+//! geo $traffic_action {
+//!     default "";
+//!     include /var/lib/safe-traffic/limited.geo;
+//! }
+//! if ($traffic_action) { return 429; }
+//! ```
+
+use crate::{
+    controller::Firewall,
+    state::{state_file, write_atomic},
+};
+
+use anyhow::{bail, Result};
+use log::{debug, info, warn};
+use safe_traffic_common::{
+    clock::Clock,
+    config::{Action, WarnPageConfig},
+    utils::FirewallRule,
+};
+use std::{collections::BTreeMap, net::IpAddr, path::PathBuf, sync::Arc, time::Duration};
+use tokio::{process::Command, time};
+
+const HEADER: &str = "# generated by safe-traffic-daemon, do not edit\n";
+
+/// 定期与活跃规则核对，内容变化时重写 geo 文件并通知 Web 服务重新加载
+pub struct WarnPage {
+    path: PathBuf,
+    reload_command: Option<String>,
+    interval: Duration,
+    /// 上次写入的内容，首次核对时总会写入，保证 include 的文件存在
+    written: Option<String>,
+}
+
+impl WarnPage {
+    pub fn new(cfg: &WarnPageConfig, state_dir: Option<&str>) -> Self {
+        Self {
+            path: cfg
+                .path
+                .as_ref()
+                .map(PathBuf::from)
+                .unwrap_or_else(|| state_file(state_dir, "limited.geo")),
+            reload_command: cfg.reload_command.clone(),
+            interval: Duration::from_secs(cfg.interval.unwrap_or(5).max(1)),
+            written: None,
+        }
+    }
+
+    /// 生成 geo 文件内容：每个来源一行，值为 `warn` 或 `limit`，同一来源两者都有时取 `limit`
+    pub fn render(rules: &[FirewallRule], clock: &dyn Clock) -> String {
+        let mut sources: BTreeMap<IpAddr, &str> = BTreeMap::new();
+        for rule in rules.iter().filter(|rule| !rule.is_expired(clock)) {
+            match rule.rule_type {
+                Action::RateLimit { .. } => {
+                    sources.insert(rule.ip, "limit");
+                }
+                Action::Warn { .. } => {
+                    sources.entry(rule.ip).or_insert("warn");
+                }
+                _ => {}
+            }
+        }
+        let mut contents = HEADER.to_string();
+        for (ip, value) in sources {
+            contents.push_str(&format!("{} {};\n", ip, value));
+        }
+        contents
+    }
+
+    /// 核对一次，返回文件是否被重写
+    pub async fn sync(&mut self, rules: &[FirewallRule], clock: &dyn Clock) -> Result<bool> {
+        let contents = Self::render(rules, clock);
+        if self.written.as_ref() == Some(&contents) {
+            return Ok(false);
+        }
+        write_atomic(&self.path, contents.as_bytes()).await?;
+        self.written = Some(contents);
+        if let Some(command) = &self.reload_command {
+            let output = Command::new("sh").args(["-c", command]).output().await?;
+            if !output.status.success() {
+                bail!(
+                    "{}: {}",
+                    command,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+        }
+        Ok(true)
+    }
+
+    pub async fn run(mut self, fw: Arc<Firewall>) {
+        info!(
+            "Exporting warned and rate-limited sources to {}",
+            self.path.display()
+        );
+        let clock = fw.clock();
+        let mut interval = time::interval(self.interval);
+        loop {
+            interval.tick().await;
+            let rules = match fw.get_active_rules().await {
+                Ok(rules) => rules,
+                Err(e) => {
+                    warn!("Failed to get active rules for the warn page: {}", e);
+                    continue;
+                }
+            };
+            match self.sync(&rules, clock.as_ref()).await {
+                Ok(true) => debug!("Rewrote {}", self.path.display()),
+                Ok(false) => {}
+                Err(e) => warn!("Failed to update {}: {}", self.path.display(), e),
+            }
+        }
+    }
+}
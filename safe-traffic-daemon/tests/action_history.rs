@@ -0,0 +1,63 @@
+//! 单个 IP 的处置历史：只含处置动作，按时间倒序分页
+
+use safe_traffic_common::{
+    events::{Event, EventKind},
+    transport::{Response, ResponseData},
+};
+use safe_traffic_daemon::events::EventStore;
+use std::net::IpAddr;
+
+#[tokio::test]
+async fn test_history_pages_newest_first() {
+    let store = EventStore::default();
+    let customer: IpAddr = "203.0.113.7".parse().unwrap();
+    let other: IpAddr = "198.51.100.1".parse().unwrap();
+
+    for round in 0..3 {
+        for kind in [EventKind::Limit, EventKind::Ban, EventKind::Unblock] {
+            store
+                .push(Event::new(kind, format!("{} {}", kind, round)).with_ip(customer))
+                .await;
+        }
+        store
+            .push(Event::new(EventKind::Ban, "other").with_ip(other))
+            .await;
+    }
+    // 不是处置动作的事件不计入历史
+    store
+        .push(Event::new(EventKind::Conflict, "conflict").with_ip(customer))
+        .await;
+
+    let first = store.history(customer, 0, Some(4)).await.unwrap();
+    assert_eq!(first.total, 9);
+    assert_eq!(first.next_offset, Some(4));
+    let messages: Vec<&str> = first.events.iter().map(|e| e.message.as_str()).collect();
+    assert_eq!(messages, ["unblock 2", "ban 2", "limit 2", "unblock 1"]);
+
+    let last = store.history(customer, 8, Some(4)).await.unwrap();
+    assert_eq!(last.events.len(), 1);
+    assert_eq!(last.events[0].message, "limit 0");
+    assert_eq!(last.next_offset, None);
+    assert!(store
+        .history(customer, 9, None)
+        .await
+        .unwrap()
+        .events
+        .is_empty());
+
+    // 空页也以 History 返回给客户端
+    let empty = store
+        .history("192.0.2.1".parse().unwrap(), 0, None)
+        .await
+        .unwrap();
+    let json = serde_json::to_string(&Response::Success(ResponseData::History(empty))).unwrap();
+    assert!(matches!(
+        serde_json::from_str(&json).unwrap(),
+        Response::Success(ResponseData::History(history)) if history.total == 0
+    ));
+    let json = serde_json::to_string(&Response::Success(ResponseData::History(first))).unwrap();
+    assert!(matches!(
+        serde_json::from_str(&json).unwrap(),
+        Response::Success(ResponseData::History(history)) if history.events.len() == 4
+    ));
+}
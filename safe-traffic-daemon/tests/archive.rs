@@ -0,0 +1,138 @@
+//! 事件归档：结束的分区被压缩，晚到的事件合并进已压缩的分区，超出保留期限或大小上限的分区被删除
+
+use chrono::{DateTime, Duration, Utc};
+use safe_traffic_common::{
+    config::EventArchiveConfig,
+    events::{Event, EventKind},
+    reason::Reason,
+    transport::EventQuery,
+};
+use safe_traffic_daemon::{archive::EventArchive, events::EventStore};
+use std::{net::IpAddr, sync::Arc};
+
+fn event(kind: EventKind, ip: &str, time: DateTime<Utc>) -> Event {
+    let ip: IpAddr = ip.parse().unwrap();
+    let mut event = Event::new(kind, format!("{} {}", kind, ip)).with_ip(ip);
+    event.time = time;
+    event
+}
+
+fn files(archive: &EventArchive) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(archive.dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    names
+}
+
+#[tokio::test]
+async fn test_partitions_are_compressed_queried_and_aged_out() {
+    let dir = std::env::temp_dir().join(format!("safe-traffic-archive-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut cfg = EventArchiveConfig {
+        dir: Some(dir.display().to_string()),
+        partition_secs: Some(3600),
+        retention_days: Some(1),
+        max_bytes: None,
+    };
+    let archive = Arc::new(EventArchive::open(&cfg, None).unwrap());
+    let store = EventStore::new(16).with_archive(Arc::clone(&archive));
+
+    let now: DateTime<Utc> = "2026-10-16T12:30:00Z".parse().unwrap();
+    let at = |time: &str| {
+        format!("2026-10-16T{}Z", time)
+            .parse::<DateTime<Utc>>()
+            .unwrap()
+    };
+    store
+        .push(event(
+            EventKind::Ban,
+            "198.51.100.9",
+            now - Duration::days(2),
+        ))
+        .await;
+    store
+        .push(
+            event(EventKind::Ban, "198.51.100.7", at("10:05:00")).with_reason(Reason {
+                rule: 0,
+                rule_name: Some("flood".to_string()),
+                metric: "bps".to_string(),
+                observed: 2_000_000,
+                threshold: 1_000_000,
+                window_secs: 10,
+            }),
+        )
+        .await;
+    store
+        .push(event(EventKind::Limit, "2001:db8::8", at("11:15:00")))
+        .await;
+    store
+        .push(event(EventKind::Unblock, "198.51.100.7", at("12:10:00")))
+        .await;
+
+    let result = archive.maintain(now).unwrap();
+    assert_eq!((result.compressed, result.deleted), (2, 1));
+    assert_eq!(
+        files(&archive),
+        [
+            "events-20261016T100000Z.jsonl.gz",
+            "events-20261016T110000Z.jsonl.gz",
+            "events-20261016T120000Z.jsonl",
+        ]
+    );
+
+    // 晚到的事件写入已结束的分区，下次维护时追加到压缩文件
+    store
+        .push(event(EventKind::Extend, "198.51.100.7", at("10:30:00")))
+        .await;
+    assert_eq!(archive.maintain(now).unwrap().compressed, 1);
+    assert_eq!(files(&archive).len(), 3);
+
+    let query = |query: EventQuery| archive.query(&query).unwrap();
+    let all = query(EventQuery::default());
+    let kinds: Vec<EventKind> = all.iter().map(|event| event.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            EventKind::Ban,
+            EventKind::Extend,
+            EventKind::Limit,
+            EventKind::Unblock
+        ]
+    );
+    let by_ip = query(EventQuery {
+        ip: Some("198.51.100.7".parse().unwrap()),
+        ..Default::default()
+    });
+    assert_eq!(by_ip.len(), 3);
+    let by_rule = query(EventQuery {
+        rule: Some("flood".to_string()),
+        ..Default::default()
+    });
+    assert_eq!(by_rule.len(), 1);
+    assert_eq!(by_rule[0].time, at("10:05:00"));
+    let by_kind_and_range = query(EventQuery {
+        kind: Some(EventKind::Limit),
+        since: Some(at("11:00:00")),
+        until: Some(at("12:00:00")),
+        ..Default::default()
+    });
+    assert_eq!(by_kind_and_range.len(), 1);
+    let latest = store
+        .query(EventQuery {
+            limit: Some(1),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(latest[0].kind, EventKind::Unblock);
+
+    // 超出大小上限时只保留当前分区
+    cfg.max_bytes = Some(1);
+    let archive = EventArchive::open(&cfg, None).unwrap();
+    assert_eq!(archive.maintain(now).unwrap().deleted, 2);
+    assert_eq!(files(&archive), ["events-20261016T120000Z.jsonl"]);
+    assert_eq!(archive.query(&EventQuery::default()).unwrap().len(), 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,96 @@
+//! BGP 发布的命令格式与发布条件
+
+use safe_traffic_common::{
+    config::{Action, BgpConfig, HookType},
+    reason::Reason,
+    rule_id::{RuleId, RuleKind},
+    utils::FirewallRule,
+};
+use safe_traffic_daemon::bgp::BgpAnnouncer;
+use std::{net::IpAddr, time::Duration};
+
+fn config(text: &str) -> BgpConfig {
+    toml::from_str(text).unwrap()
+}
+
+fn ban(ip: IpAddr, seconds: Option<u64>, observed: Option<u64>) -> FirewallRule {
+    FirewallRule {
+        id: RuleId::for_ip(RuleKind::Ban, ip, None),
+        ip,
+        rule_type: Action::Ban {
+            duration: seconds.map(Duration::from_secs),
+        },
+        created_at: chrono::Utc::now(),
+        handle: None,
+        source_ports: None,
+        remaining_secs: None,
+        mac: None,
+        reason: observed.map(|observed| Reason {
+            rule: 0,
+            rule_name: None,
+            metric: "bps".to_string(),
+            observed,
+            threshold: 1,
+            window_secs: 10,
+        }),
+        created_mono: None,
+    }
+}
+
+#[test]
+fn test_blackhole_commands() {
+    let announcer = BgpAnnouncer::new(
+        &config("backend = \"Exabgp\"\napi = \"http://127.0.0.1:5000\""),
+        HookType::Input,
+    );
+    let v4: IpAddr = "198.51.100.7".parse().unwrap();
+    let v6: IpAddr = "2001:db8::7".parse().unwrap();
+    assert_eq!(
+        announcer.exabgp_command(true, v4),
+        "announce route 198.51.100.7/32 next-hop 192.0.2.1 community [65535:666]"
+    );
+    assert_eq!(
+        announcer.exabgp_command(false, v6),
+        "withdraw route 2001:db8::7/128 next-hop 100::1"
+    );
+    assert_eq!(
+        announcer.gobgp_args(true, v4).join(" "),
+        "global rib add -a ipv4 198.51.100.7/32 nexthop 192.0.2.1 community 65535:666"
+    );
+    assert_eq!(
+        announcer.gobgp_args(false, v4).join(" "),
+        "global rib del -a ipv4 198.51.100.7/32"
+    );
+}
+
+#[test]
+fn test_flowspec_commands() {
+    let announcer = BgpAnnouncer::new(
+        &config("backend = \"Gobgp\"\nmode = \"Flowspec\""),
+        HookType::Input,
+    );
+    let ip: IpAddr = "198.51.100.7".parse().unwrap();
+    assert_eq!(
+        announcer.exabgp_command(true, ip),
+        "announce flow route { match { source 198.51.100.7/32; } then { discard; } }"
+    );
+    assert_eq!(
+        announcer.gobgp_args(false, ip).join(" "),
+        "global rib -a ipv4-flowspec del match source 198.51.100.7/32 then discard"
+    );
+}
+
+#[test]
+fn test_only_long_heavy_bans_qualify() {
+    let announcer = BgpAnnouncer::new(
+        &config("backend = \"Gobgp\"\nmin_ban_secs = 600\nmin_bps = 1000"),
+        HookType::Input,
+    );
+    let ip: IpAddr = "198.51.100.7".parse().unwrap();
+    assert!(announcer.qualifies(&ban(ip, Some(600), Some(5000))));
+    assert!(announcer.qualifies(&ban(ip, None, Some(5000))));
+    assert!(!announcer.qualifies(&ban(ip, Some(60), Some(5000))));
+    assert!(!announcer.qualifies(&ban(ip, Some(600), Some(10))));
+    // 手动封禁没有触发流量
+    assert!(!announcer.qualifies(&ban(ip, None, None)));
+}
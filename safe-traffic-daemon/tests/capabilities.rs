@@ -0,0 +1,84 @@
+//! 启动能力报告：列出因缺少能力而无法生效的已配置特性
+
+use safe_traffic_common::config::Config;
+use safe_traffic_daemon::capabilities::{parse_nft_version, Capabilities};
+
+fn full() -> Capabilities {
+    Capabilities {
+        nft_usable: true,
+        nft_version: Some("1.0.6".to_string()),
+        kernel: Some("6.1.0-18-amd64".to_string()),
+        timeout_sets: true,
+        flowtables: true,
+        netdev: true,
+        conntrack_procfs: true,
+        conntrack_cli: true,
+        conntrack_acct: true,
+        systemd_run: true,
+        gobgp: true,
+    }
+}
+
+#[test]
+fn test_configured_features_missing_capabilities() {
+    let cfg = Config::parse(
+        r#"
+        interface = "eth0"
+        early_drop = true
+        rules = []
+
+        [flows]
+
+        [offload]
+
+        [failsafe]
+        "#,
+    )
+    .unwrap();
+    assert!(full().gaps(&cfg).is_empty());
+
+    let old_kernel = Capabilities {
+        flowtables: false,
+        netdev: false,
+        conntrack_cli: false,
+        conntrack_acct: false,
+        systemd_run: false,
+        ..full()
+    };
+    assert_eq!(
+        old_kernel.gaps(&cfg),
+        [
+            "early_drop: the kernel rejects netdev ingress chains",
+            "offload: the kernel rejects flowtables",
+            "flows: net.netfilter.nf_conntrack_acct is off, flow byte and packet counts stay 0",
+            "offload: the conntrack command is missing, flows of newly banned sources stay offloaded until they end",
+            "failsafe: systemd-run is missing, no dead-man's timer is armed",
+        ]
+    );
+
+    // 模拟模式下 nft 特性无从探测，只报告模拟模式本身
+    let mock = Capabilities {
+        nft_usable: false,
+        ..full()
+    };
+    assert_eq!(
+        mock.gaps(&cfg),
+        ["nftables is unavailable: running in mock mode, no traffic is dropped or limited"]
+    );
+    assert!(mock.to_string().contains("nft_usable=no"));
+    assert!(mock.to_string().contains("flowtables=unknown"));
+}
+
+#[test]
+fn test_report_line() {
+    assert_eq!(
+        full().to_string(),
+        "nft=1.0.6 nft_usable=yes kernel=6.1.0-18-amd64 timeout_sets=yes flowtables=yes netdev=yes \
+         conntrack=procfs conntrack_acct=yes systemd_run=yes gobgp=yes"
+    );
+    assert_eq!(
+        parse_nft_version("nftables v1.0.6 (Lester Gooch #5)\n").as_deref(),
+        Some("1.0.6")
+    );
+    assert_eq!(parse_nft_version("nftables"), None);
+}
@@ -0,0 +1,73 @@
+//! 集成测试共用的夹具：nft 命令只记录不执行，状态目录放在测试结束时删除的临时目录
+
+#![allow(dead_code)]
+
+use chrono::Utc;
+use safe_traffic_common::{clock::ManualClock, config::Config};
+use safe_traffic_daemon::{controller::Firewall, nft::NftExecutor};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// 临时状态目录，离开作用域时删除
+pub struct StateDir(PathBuf);
+
+impl StateDir {
+    pub fn new(name: &str) -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "safe-traffic-{}-{}-{}",
+            name,
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for StateDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// 解析配置片段，补上监控的网卡与状态目录
+pub fn config(state_dir: &StateDir, body: &str) -> Config {
+    Config::parse(&format!(
+        "interface = \"eth0\"\nstate_dir = \"{}\"\n{}",
+        state_dir.path().display(),
+        body
+    ))
+    .unwrap()
+}
+
+pub async fn executor() -> Arc<NftExecutor> {
+    Arc::new(NftExecutor::new(1, 300, 100, true).await)
+}
+
+pub fn clock() -> Arc<ManualClock> {
+    Arc::new(ManualClock::new(Utc::now()))
+}
+
+pub async fn firewall(cfg: &Config) -> Arc<Firewall> {
+    Arc::new(Firewall::new(cfg, executor().await).await.unwrap())
+}
+
+/// 使用手动时钟的防火墙
+pub async fn clocked_firewall(cfg: &Config, clock: &Arc<ManualClock>) -> Arc<Firewall> {
+    Arc::new(
+        Firewall::new(cfg, executor().await)
+            .await
+            .unwrap()
+            .with_clock(clock.clone()),
+    )
+}
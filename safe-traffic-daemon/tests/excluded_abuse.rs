@@ -0,0 +1,97 @@
+//! 白名单来源的滥用警告：流量超过规则阈值的倍数时周期性发出警告事件，并指明负责的白名单
+
+mod common;
+
+use dashmap::DashMap;
+use safe_traffic_common::{
+    clock::Clock,
+    events::{Event, EventKind},
+    utils::TrafficStats,
+};
+use safe_traffic_daemon::{controller::Firewall, rules::RuleEngine};
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+const CONFIG: &str = r#"
+    global_exclude = ["198.51.100.30", "198.51.100.31"]
+    excluded_abuse_interval = "10m"
+
+    [[rules]]
+    name = "flood"
+    window_secs = 2
+    threshold_bps = 1000
+    action = { Ban = { seconds = 60 } }
+
+    [[rules]]
+    name = "bulk"
+    window_secs = 2
+    threshold_bps = 5000
+    action = { Ban = { seconds = 60 } }
+"#;
+
+async fn warnings(fw: &Firewall) -> Vec<Event> {
+    fw.events
+        .recent(100)
+        .await
+        .into_iter()
+        .filter(|event| event.kind == EventKind::Warning)
+        .collect()
+}
+
+#[tokio::test]
+async fn test_abuse_behind_exclusion_is_reported_periodically() {
+    let dir = common::StateDir::new("abuse");
+    let cfg = common::config(&dir, CONFIG);
+    let clock = common::clock();
+    let fw = common::clocked_firewall(&cfg, &clock).await;
+    let stats = Arc::new(DashMap::new());
+    let engine = RuleEngine::new(cfg.rules.clone(), stats.clone())
+        .with_clock(clock.clone())
+        .with_excluded_abuse(
+            cfg.excluded_abuse_multiple(),
+            cfg.excluded_abuse_interval_secs(),
+        );
+    let abuser: IpAddr = "198.51.100.30".parse().unwrap();
+    let busy: IpAddr = "198.51.100.31".parse().unwrap();
+
+    // 滥用者的流量远超最严格的阈值，另一个白名单来源不到 10 倍
+    let tick = || {
+        clock.advance(Duration::from_secs(1));
+        for (ip, rx_delta) in [(abuser, 50_000), (busy, 5000)] {
+            let sample = TrafficStats {
+                rx_delta,
+                ..Default::default()
+            };
+            engine.windows().record(ip, &sample, clock.monotonic());
+            stats.insert(ip, sample);
+        }
+    };
+    for _ in 0..3 {
+        tick();
+    }
+    engine.check_and_apply(Arc::clone(&fw)).await.unwrap();
+    let events = warnings(&fw).await;
+    assert_eq!(events.len(), 1, "{:?}", events);
+    assert_eq!(events[0].ip, Some(abuser));
+    assert!(events[0]
+        .message
+        .starts_with("excluded 198.51.100.30 at 25x the threshold of rule 0 ("));
+    assert!(events[0]
+        .message
+        .ends_with("global_exclude entry 198.51.100.30 may be hiding abuse"));
+    let reason = events[0].reason.as_ref().unwrap();
+    assert_eq!(reason.rule_name.as_deref(), Some("flood"));
+    assert_eq!(reason.threshold, 1000);
+    assert!(fw.get_active_rules().await.unwrap().is_empty());
+
+    // 间隔内不再重复警告
+    tick();
+    engine.check_and_apply(Arc::clone(&fw)).await.unwrap();
+    assert_eq!(warnings(&fw).await.len(), 1);
+
+    clock.advance(Duration::from_secs(600));
+    for _ in 0..3 {
+        tick();
+    }
+    engine.check_and_apply(Arc::clone(&fw)).await.unwrap();
+    assert_eq!(warnings(&fw).await.len(), 2);
+}
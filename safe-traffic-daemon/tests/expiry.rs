@@ -0,0 +1,97 @@
+//! 规则到期与检测分开执行：引擎暂停或不再检查来源时，过期的处置照常解除
+
+mod common;
+
+use dashmap::DashMap;
+use safe_traffic_common::{
+    clock::Clock,
+    utils::{RunState, TrafficStats},
+};
+use safe_traffic_daemon::rules::RuleEngine;
+use std::{net::IpAddr, sync::Arc, time::Duration};
+use tokio::time::{sleep, timeout};
+
+const CONFIG: &str = r#"
+    [[rules]]
+    window_secs = 1
+    threshold_bps = 1_000_000
+    action = { Ban = { seconds = 10 } }
+"#;
+
+#[tokio::test]
+async fn test_expiry_does_not_depend_on_detection() {
+    let dir = common::StateDir::new("expiry");
+    let cfg = common::config(&dir, CONFIG);
+    let clock = common::clock();
+    let fw = common::clocked_firewall(&cfg, &clock).await;
+    let stats = Arc::new(DashMap::new());
+    let engine = RuleEngine::new(cfg.rules.clone(), stats.clone())
+        .with_clock(clock.clone())
+        .with_warmup(0);
+    let heavy: IpAddr = "198.51.100.7".parse().unwrap();
+    let sample = TrafficStats {
+        rx_delta: 2_000_000,
+        ..Default::default()
+    };
+    for _ in 0..3 {
+        engine.windows().record(heavy, &sample, clock.monotonic());
+        stats.insert(heavy, sample.clone());
+        clock.advance(Duration::from_secs(1));
+    }
+    engine.check_and_apply(Arc::clone(&fw)).await.unwrap();
+    fw.ban("198.51.100.8".parse().unwrap(), Some(5))
+        .await
+        .unwrap();
+    assert_eq!(fw.get_active_rules().await.unwrap().len(), 2);
+
+    // 来源已不在流量统计中，检测不会再经过它
+    stats.clear();
+    clock.advance(Duration::from_secs(6));
+    engine.expire_rules(&fw).await.unwrap();
+    let rules = fw.get_active_rules().await.unwrap();
+    assert_eq!(rules.len(), 1);
+    assert_eq!(rules[0].ip, heavy);
+
+    clock.advance(Duration::from_secs(5));
+    engine.expire_rules(&fw).await.unwrap();
+    assert!(fw.get_active_rules().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_paused_engine_still_expires_rules() {
+    let dir = common::StateDir::new("expiry");
+    let cfg = common::config(&dir, CONFIG);
+    let clock = common::clock();
+    let fw = common::clocked_firewall(&cfg, &clock).await;
+    let engine = Arc::new(
+        RuleEngine::new(cfg.rules.clone(), Arc::new(DashMap::new())).with_clock(clock.clone()),
+    );
+    let task = tokio::spawn({
+        let engine = Arc::clone(&engine);
+        let fw = Arc::clone(&fw);
+        async move { engine.start(fw, Duration::from_millis(10)).await }
+    });
+    // 主循环就绪后才能接收控制信号
+    while engine.pause().await.is_err() {
+        sleep(Duration::from_millis(10)).await;
+    }
+    while engine.get_state().await != RunState::Paused {
+        sleep(Duration::from_millis(10)).await;
+    }
+
+    fw.ban("198.51.100.9".parse().unwrap(), Some(5))
+        .await
+        .unwrap();
+    clock.advance(Duration::from_secs(6));
+    timeout(Duration::from_secs(5), async {
+        while !fw.get_active_rules().await.unwrap().is_empty() {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("expired ban was not removed while the engine is paused");
+    assert_eq!(engine.get_state().await, RunState::Paused);
+
+    engine.stop().await.unwrap();
+    task.await.unwrap().unwrap();
+}
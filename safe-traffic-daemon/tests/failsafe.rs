@@ -0,0 +1,40 @@
+//! 失控保护：定时器到点时删除守护进程建立的全部表，单元名按表名区分
+
+mod common;
+
+use safe_traffic_daemon::failsafe::Failsafe;
+
+async fn build(extra: &str) -> Failsafe {
+    let dir = common::StateDir::new("failsafe");
+    let cfg = common::config(
+        &dir,
+        &format!("rules = []\n{}\n[failsafe]\ntimeout_secs = 300", extra),
+    );
+    let fw = common::firewall(&cfg).await;
+    Failsafe::new(cfg.failsafe.as_ref().unwrap(), &fw)
+}
+
+#[tokio::test]
+async fn test_timer_removes_owned_tables() {
+    let failsafe = build("").await;
+    assert_eq!(failsafe.unit(), "safe-traffic-failsafe-traffic_filter");
+    assert_eq!(
+        failsafe.teardown_script(),
+        "nft delete table inet traffic_filter"
+    );
+    let command = failsafe.arm_command();
+    assert_eq!(
+        command[..4],
+        ["systemd-run", "--unit", failsafe.unit(), "--collect"]
+    );
+    let on_active = command.iter().position(|arg| arg == "--on-active").unwrap();
+    assert_eq!(command[on_active + 1], "300s");
+    assert_eq!(
+        command[command.len() - 3..],
+        ["/bin/sh", "-c", "nft delete table inet traffic_filter"]
+    );
+
+    let failsafe = build("table_name = \"edge\"").await;
+    assert_eq!(failsafe.unit(), "safe-traffic-failsafe-edge");
+    assert_eq!(failsafe.teardown_script(), "nft delete table inet edge");
+}
@@ -0,0 +1,47 @@
+//! 连接跟踪输出的解析与流表的容量上限
+
+use safe_traffic_daemon::flows::{parse_conntrack, FlowTable};
+use std::net::IpAddr;
+
+const PROC: &str = "\
+ipv4     2 tcp      6 431999 ESTABLISHED src=198.51.100.7 dst=192.0.2.1 sport=51234 dport=443 packets=12 bytes=3400 src=192.0.2.1 dst=198.51.100.7 sport=443 dport=51234 packets=10 bytes=9000 [ASSURED] mark=0 zone=0 use=2
+ipv4     2 udp      17 29 src=198.51.100.8 dst=192.0.2.1 sport=4000 dport=53 packets=1 bytes=80 [UNREPLIED] src=192.0.2.1 dst=198.51.100.8 sport=53 dport=4000 packets=0 bytes=0 mark=0 zone=0 use=2
+ipv4     2 icmp     1 29 src=198.51.100.9 dst=192.0.2.1 type=8 code=0 id=7 packets=3 bytes=252 src=192.0.2.1 dst=198.51.100.9 type=0 code=0 id=7 packets=3 bytes=252 mark=0 use=1
+";
+
+/// 未开启 nf_conntrack_acct 时 conntrack 命令的输出没有计数
+const CLI: &str = "\
+tcp      6 117 TIME_WAIT src=2001:db8::7 dst=2001:db8::1 sport=40000 dport=22 src=2001:db8::1 dst=2001:db8::7 sport=22 dport=40000 [ASSURED] mark=0 use=1
+";
+
+#[test]
+fn test_parse_conntrack() {
+    let flows = parse_conntrack(PROC);
+    assert_eq!(flows.len(), 3);
+    assert_eq!(flows[0].proto, "tcp");
+    assert_eq!(flows[0].src, "198.51.100.7".parse::<IpAddr>().unwrap());
+    assert_eq!(flows[0].dport, Some(443));
+    assert_eq!((flows[0].bytes, flows[0].packets), (3400, 12));
+    assert_eq!(flows[1].proto, "udp");
+    assert_eq!(flows[2].proto, "icmp");
+    assert_eq!(flows[2].dport, None);
+
+    let flows = parse_conntrack(CLI);
+    assert_eq!(flows.len(), 1);
+    assert_eq!(flows[0].dport, Some(22));
+    assert_eq!(flows[0].bytes, 0);
+}
+
+#[test]
+fn test_flow_table_keeps_heaviest() {
+    let table = FlowTable::new(2);
+    table.replace(parse_conntrack(PROC));
+    let flows = table.all();
+    assert_eq!(flows.len(), 2);
+    assert_eq!(flows[0].bytes, 3400);
+    assert_eq!(flows[1].bytes, 252);
+
+    let ip: IpAddr = "198.51.100.7".parse().unwrap();
+    assert_eq!(table.dports(&ip), vec![443]);
+    assert!(table.for_ip(&"198.51.100.8".parse().unwrap()).is_empty());
+}
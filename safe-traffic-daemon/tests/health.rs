@@ -0,0 +1,89 @@
+//! 外部集成的健康度：按最近调用的成功比例打分，降级与恢复各记录一次事件，周期性集成停滞时降为 0
+
+use chrono::{Duration as ChronoDuration, Utc};
+use safe_traffic_common::{
+    events::EventKind,
+    transport::{Response, ResponseData},
+};
+use safe_traffic_daemon::{events::EventStore, health::Health};
+use std::{sync::Arc, time::Duration};
+
+async fn health_events(events: &EventStore) -> Vec<String> {
+    events
+        .recent(100)
+        .await
+        .into_iter()
+        .filter(|event| event.kind == EventKind::Health)
+        .map(|event| event.message)
+        .collect()
+}
+
+#[tokio::test]
+async fn test_degradation_and_recovery() {
+    let events = Arc::new(EventStore::default());
+    let health = Health::new(Arc::clone(&events));
+    let now = Utc::now();
+    let latency = Duration::from_millis(40);
+
+    health.register("notify:ops", None, now);
+    assert_eq!(health.snapshot(now)[0].score, 100);
+
+    let unauthorized = || Err("401 Unauthorized".to_string());
+    for call in 1..=4 {
+        health
+            .record("upstream", now, latency, unauthorized())
+            .await;
+        // 调用次数不足时不判定降级
+        if call == 2 {
+            assert!(health_events(&events).await.is_empty());
+        }
+    }
+    assert_eq!(
+        health_events(&events).await,
+        ["integration upstream degraded: 3 of the last 3 calls failed, last error: 401 Unauthorized"]
+    );
+
+    for _ in 0..4 {
+        health.record("upstream", now, latency, Ok(())).await;
+    }
+    let snapshot = health.snapshot(now);
+    assert_eq!(snapshot[1].integration, "upstream");
+    assert_eq!(snapshot[1].score, 50);
+    assert_eq!(snapshot[1].calls, 8);
+    assert_eq!(snapshot[1].failures, 4);
+    assert_eq!(snapshot[1].latency_ms, Some(40));
+    assert!(!snapshot[1].degraded);
+    assert_eq!(
+        health_events(&events).await[1],
+        "integration upstream recovered, health 50"
+    );
+}
+
+#[tokio::test]
+async fn test_stale_periodic_integration() {
+    let events = Arc::new(EventStore::default());
+    let health = Health::new(Arc::clone(&events));
+    let start = Utc::now();
+
+    health.register("cloud", Some(Duration::from_secs(3600)), start);
+    health
+        .record("cloud", start, Duration::from_millis(5), Ok(()))
+        .await;
+    health.check(start + ChronoDuration::hours(3)).await;
+    assert!(health_events(&events).await.is_empty());
+
+    // 超过预期间隔 3 倍没有成功调用
+    let later = start + ChronoDuration::hours(4);
+    health.check(later).await;
+    let snapshot = health.snapshot(later);
+    assert_eq!(snapshot[0].score, 0);
+    assert!(snapshot[0].degraded);
+    assert!(health_events(&events).await[0]
+        .starts_with("integration cloud degraded: no successful call since "));
+
+    let json = serde_json::to_string(&Response::Success(ResponseData::Health(snapshot))).unwrap();
+    assert!(matches!(
+        serde_json::from_str(&json).unwrap(),
+        Response::Success(ResponseData::Health(health)) if health[0].integration == "cloud"
+    ));
+}
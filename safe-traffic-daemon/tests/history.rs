@@ -0,0 +1,81 @@
+//! 滚动一天的流量配额：降采样历史只保留最近 24 小时，低速但持续的来源累计超过配额后被处置
+
+mod common;
+
+use dashmap::DashMap;
+use safe_traffic_common::{clock::Clock, utils::TrafficStats};
+use safe_traffic_daemon::{
+    history::ByteHistory,
+    rules::{RuleEngine, WindowStore},
+};
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+const CONFIG: &str = r#"
+    [[rules]]
+    name = "scraper"
+    window_secs = 10
+    threshold = 1_000_000
+    metric = "BytesPerRollingDay"
+    action = { Ban = { seconds = 86400 } }
+"#;
+
+#[test]
+fn test_history_covers_the_last_day() {
+    let history = ByteHistory::new();
+    let ip: IpAddr = "198.51.100.9".parse().unwrap();
+    let secs = Duration::from_secs;
+
+    // 首个采样只记录时间
+    history.record(ip, 10, secs(0));
+    assert_eq!(history.total(&ip, secs(0)), 0);
+    history.record(ip, 10, secs(300));
+    history.record(ip, 20, secs(400));
+    assert_eq!(history.total(&ip, secs(400)), 3000 + 2000);
+    history.record(ip, 5, secs(3600));
+    assert_eq!(history.total(&ip, secs(3600)), 5000 + 16_000);
+
+    // 一天后前两个采样所在的桶已滚出
+    assert_eq!(history.total(&ip, secs(300 + 86400)), 16_000);
+    assert_eq!(history.total(&ip, secs(3600 + 86400)), 0);
+    history.prune(secs(3600 + 86400));
+    assert!(history.is_empty());
+}
+
+#[tokio::test]
+async fn test_slow_source_exceeding_the_daily_quota_is_banned() {
+    let dir = common::StateDir::new("history");
+    let cfg = common::config(&dir, CONFIG);
+    let clock = common::clock();
+    let fw = common::clocked_firewall(&cfg, &clock).await;
+    let stats = Arc::new(DashMap::new());
+    let windows = Arc::new(WindowStore::new(fw.hook.clone()).with_history());
+    let engine = RuleEngine::new(cfg.rules.clone(), stats.clone())
+        .with_clock(clock.clone())
+        .with_warmup(0)
+        .with_windows(Arc::clone(&windows));
+    let scraper: IpAddr = "198.51.100.9".parse().unwrap();
+    // 100 字节/秒远低于任何瞬时阈值，一小时约 360 KB
+    let sample = TrafficStats {
+        rx_delta: 100,
+        ..Default::default()
+    };
+
+    for _ in 0..3 {
+        windows.record(scraper, &sample, clock.monotonic());
+        stats.insert(scraper, sample.clone());
+        engine.check_and_apply(Arc::clone(&fw)).await.unwrap();
+        assert!(fw.get_active_rules().await.unwrap().is_empty());
+        clock.advance(Duration::from_secs(3600));
+    }
+    assert_eq!(windows.day_bytes(&scraper, clock.monotonic()), 720_000);
+
+    windows.record(scraper, &sample, clock.monotonic());
+    engine.check_and_apply(Arc::clone(&fw)).await.unwrap();
+    let rules = fw.get_active_rules().await.unwrap();
+    assert_eq!(rules.len(), 1);
+    assert_eq!(rules[0].ip, scraper);
+    let reason = rules[0].reason.as_ref().unwrap();
+    assert_eq!(reason.metric, "bytes_per_rolling_day");
+    assert_eq!(reason.observed, 1_080_000);
+    assert_eq!(reason.window_secs, 86400);
+}
@@ -0,0 +1,121 @@
+//! 幂等键：保留期内重复的键返回首次的结果，失败不保留，同一个键不能用于不同的请求
+
+use safe_traffic_common::transport::{Response, ResponseData};
+use safe_traffic_daemon::idempotency::IdempotencyStore;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+const FINGERPRINT: &str = r#"{"type":"Ban","data":{"ip":"198.51.100.7","seconds":60}}"#;
+
+/// 每次执行都创建一条新规则的请求
+async fn ban(created: &AtomicUsize) -> anyhow::Result<Response> {
+    let n = created.fetch_add(1, Ordering::SeqCst);
+    Ok(Response::Success(ResponseData::Message(format!(
+        "ban_198.51.100.7_{}",
+        n
+    ))))
+}
+
+fn message(response: Response) -> String {
+    match response {
+        Response::Success(ResponseData::Message(message)) => message,
+        other => panic!("unexpected response: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_retries_return_first_result() {
+    let store = IdempotencyStore::new(Duration::from_secs(3600));
+    let created = AtomicUsize::new(0);
+    let at = Duration::from_secs;
+
+    let first = store
+        .execute("retry-1", FINGERPRINT, at(10), ban(&created))
+        .await
+        .unwrap();
+    let retry = store
+        .execute("retry-1", FINGERPRINT, at(20), ban(&created))
+        .await
+        .unwrap();
+    assert_eq!(message(first), "ban_198.51.100.7_0");
+    assert_eq!(message(retry), "ban_198.51.100.7_0");
+    assert_eq!(created.load(Ordering::SeqCst), 1);
+
+    // 同一个键用于不同的请求
+    let other = r#"{"type":"Ban","data":{"ip":"198.51.100.8","seconds":60}}"#;
+    let err = store
+        .execute("retry-1", other, at(30), ban(&created))
+        .await
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("already used for a different request"));
+
+    // 保留期过后重新执行，过期的键被清理
+    let later = store
+        .execute("retry-2", FINGERPRINT, at(4000), ban(&created))
+        .await
+        .unwrap();
+    assert_eq!(message(later), "ban_198.51.100.7_1");
+    assert_eq!(store.len(), 1);
+
+    assert!(store
+        .execute("", FINGERPRINT, at(4000), ban(&created))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_failures_are_not_kept() {
+    let store = IdempotencyStore::new(Duration::from_secs(3600));
+    let created = AtomicUsize::new(0);
+    let now = Duration::from_secs(10);
+
+    let failed = store
+        .execute("flaky", FINGERPRINT, now, async {
+            Ok(Response::Error {
+                message: "nft is busy".to_string(),
+            })
+        })
+        .await
+        .unwrap();
+    assert!(matches!(failed, Response::Error { .. }));
+    let retried = store
+        .execute("flaky", FINGERPRINT, now, ban(&created))
+        .await
+        .unwrap();
+    assert_eq!(message(retried), "ban_198.51.100.7_0");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_retries_wait_for_first() {
+    let store = Arc::new(IdempotencyStore::new(Duration::from_secs(3600)));
+    let created = Arc::new(AtomicUsize::new(0));
+
+    let tasks: Vec<_> = (0..8)
+        .map(|_| {
+            let store = Arc::clone(&store);
+            let created = Arc::clone(&created);
+            tokio::spawn(async move {
+                let request = async {
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    ban(&created).await
+                };
+                let response = store
+                    .execute("burst", FINGERPRINT, Duration::from_secs(1), request)
+                    .await
+                    .unwrap();
+                message(response)
+            })
+        })
+        .collect();
+    for task in tasks {
+        assert_eq!(task.await.unwrap(), "ban_198.51.100.7_0");
+    }
+    assert_eq!(created.load(Ordering::SeqCst), 1);
+}
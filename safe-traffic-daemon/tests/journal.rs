@@ -0,0 +1,75 @@
+//! 决策日志：引擎的判定写入日志，用提高阈值后的配置重放时找出不再执行动作的来源
+
+mod common;
+
+use dashmap::DashMap;
+use safe_traffic_common::{clock::Clock, config::Config, utils::TrafficStats};
+use safe_traffic_daemon::{
+    journal::{read_journal, DecisionJournal, Outcome},
+    rules::RuleEngine,
+    simulate::replay,
+};
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+fn config(dir: &common::StateDir, threshold_bps: u64) -> Config {
+    common::config(
+        dir,
+        &format!(
+            r#"
+            [[rules]]
+            window_secs = 3
+            threshold_bps = {}
+            action = {{ Ban = {{ seconds = 60 }} }}
+            "#,
+            threshold_bps
+        ),
+    )
+}
+
+#[tokio::test]
+async fn test_journal_replay_with_higher_threshold() {
+    let dir = common::StateDir::new("journal");
+    let path = dir.path().join("decisions.journal");
+
+    let cfg = config(&dir, 1_000_000);
+    let clock = common::clock();
+    let fw = common::clocked_firewall(&cfg, &clock).await;
+    let stats = Arc::new(DashMap::new());
+    let engine = RuleEngine::new(cfg.rules.clone(), stats.clone())
+        .with_clock(clock.clone())
+        .with_journal(Arc::new(DecisionJournal::open(&path, 1.0).unwrap()));
+    let heavy: IpAddr = "198.51.100.7".parse().unwrap();
+    let light: IpAddr = "198.51.100.8".parse().unwrap();
+    for _ in 0..6 {
+        clock.advance(Duration::from_secs(1));
+        for (ip, bps) in [(heavy, 2_000_000), (light, 100_000)] {
+            let sample = TrafficStats {
+                rx_delta: bps,
+                ..Default::default()
+            };
+            engine.windows().record(ip, &sample, clock.monotonic());
+            stats.insert(ip, sample);
+        }
+        engine.check_and_apply(Arc::clone(&fw)).await.unwrap();
+    }
+
+    let records = read_journal(&path).unwrap();
+    assert_eq!(records.len(), 12);
+    assert!(records
+        .iter()
+        .any(|record| record.ip == heavy && record.outcome == Outcome::Applied));
+    assert!(records
+        .iter()
+        .filter(|record| record.ip == light)
+        .all(|record| record.outcome == Outcome::NoAction && record.bps == 100_000));
+
+    // 相同配置重放结果一致
+    let same = replay(&cfg, &records).await.unwrap();
+    assert!(same.changes.is_empty());
+
+    let raised = replay(&config(&dir, 5_000_000), &records).await.unwrap();
+    assert_eq!(raised.changes.len(), 1);
+    assert_eq!(raised.changes[0].ip, heavy);
+    assert!(raised.changes[0].recorded_secs.is_some());
+    assert_eq!(raised.changes[0].replayed_secs, None);
+}
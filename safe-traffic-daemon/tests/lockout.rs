@@ -0,0 +1,93 @@
+//! 管理接口的锁定：窗口内反复 token 校验失败的来源被锁定并封禁，锁定期间正确的 token 也被拒绝
+
+mod common;
+
+use dashmap::DashMap;
+use safe_traffic_common::{
+    config::{Action, WebSocketConfig},
+    events::EventKind,
+};
+use safe_traffic_daemon::{lockout::AuthGuard, push::PushServer, rules::RuleEngine};
+use std::{net::IpAddr, sync::Arc, time::Duration};
+use tokio_tungstenite::connect_async;
+
+const CONFIG: &str = r#"
+    rules = []
+
+    [websocket]
+    listen = "127.0.0.1:0"
+    token = "secret"
+    max_auth_failures = 3
+    auth_window_secs = 60
+    lockout_secs = 120
+"#;
+
+#[tokio::test]
+async fn test_repeated_failures_ban_source() {
+    let dir = common::StateDir::new("lockout");
+    let cfg = common::config(&dir, CONFIG);
+    let fw = common::firewall(&cfg).await;
+    let guard = AuthGuard::new(cfg.websocket.as_ref().unwrap());
+    let ip: IpAddr = "203.0.113.5".parse().unwrap();
+    let at = Duration::from_secs;
+
+    // 窗口外的失败不累计，校验通过后重新计数
+    assert!(!guard.record_failure(ip, at(0)));
+    assert!(!guard.record_failure(ip, at(100)));
+    assert!(!guard.record_failure(ip, at(110)));
+    guard.record_success(&ip);
+    assert!(!guard.record_failure(ip, at(120)));
+    assert!(!guard.record_failure(ip, at(130)));
+    assert!(guard.record_failure(ip, at(140)));
+    assert!(guard.is_locked(&ip, at(200)));
+    assert!(!guard.is_locked(&ip, at(260)));
+    assert_eq!(guard.failures_total(), 6);
+    assert_eq!(guard.lockouts_total(), 1);
+    assert_eq!(guard.locked_out(at(200)), 1);
+
+    guard.enforce(&fw, ip).await;
+    let rules = fw.get_active_rules().await.unwrap();
+    assert_eq!(rules.len(), 1);
+    assert_eq!(rules[0].ip, ip);
+    assert!(matches!(rules[0].rule_type, Action::Ban { .. }));
+    assert_eq!(rules[0].rule_type.seconds(), Some(120));
+
+    let disabled = AuthGuard::new(&WebSocketConfig {
+        max_auth_failures: Some(0),
+        ..cfg.websocket.clone().unwrap()
+    });
+    assert!((0..10).all(|n| !disabled.record_failure(ip, at(n))));
+    assert_eq!(disabled.failures_total(), 10);
+}
+
+#[tokio::test]
+async fn test_locked_out_client_is_rejected() {
+    let dir = common::StateDir::new("lockout");
+    let cfg = common::config(&dir, CONFIG);
+    let fw = common::firewall(&cfg).await;
+    let engine = Arc::new(RuleEngine::new(cfg.rules.clone(), Arc::new(DashMap::new())));
+    let server = PushServer::bind(cfg.websocket.as_ref().unwrap(), Arc::clone(&fw), engine)
+        .await
+        .unwrap();
+    let addr = server.local_addr().unwrap();
+    tokio::spawn(server.run());
+
+    for _ in 0..3 {
+        assert!(connect_async(format!("ws://{}/?token=wrong", addr))
+            .await
+            .is_err());
+    }
+    assert!(connect_async(format!("ws://{}/?token=secret", addr))
+        .await
+        .is_err());
+
+    let guard = fw.auth_guard().unwrap();
+    assert_eq!(guard.failures_total(), 3);
+    assert_eq!(guard.lockouts_total(), 1);
+    let events = fw.events.recent(10).await;
+    assert!(events.iter().any(|event| event.kind == EventKind::Warning
+        && event.message
+            == "management API locked out 127.0.0.1 for 120s after 3 failed authentications"));
+    // 回环地址只拒绝连接，不封禁
+    assert!(fw.get_active_rules().await.unwrap().is_empty());
+}
@@ -0,0 +1,53 @@
+//! 最近日志的保留与日志跟踪：跟踪期间提高全局级别，只推送本项目模块的日志
+
+use log::{debug, info, LevelFilter};
+use safe_traffic_common::{config::LogLevel, transport::LogFilter};
+use safe_traffic_daemon::logger;
+use std::time::Duration;
+use tokio::time::timeout;
+
+const TARGET: &str = "safe_traffic_daemon::rules";
+
+#[tokio::test]
+async fn test_recent_and_follow() {
+    // 未设置 RUST_LOG 时输出 info 及以上
+    logger::init();
+    assert_eq!(log::max_level(), LevelFilter::Info);
+
+    info!(target: TARGET, "ban 198.51.100.7 for 60s");
+    debug!(target: TARGET, "window of 198.51.100.7 has 3 samples");
+    let recent = logger::recent();
+    let last = recent.last().unwrap();
+    assert_eq!(last.message, "ban 198.51.100.7 for 60s");
+    assert_eq!(last.level, LogLevel::Info);
+    assert!(!recent.iter().any(|line| line.level == LogLevel::Debug));
+
+    let mut subscription = logger::subscribe(LogLevel::Debug);
+    assert_eq!(log::max_level(), LevelFilter::Debug);
+    debug!(target: "rtnetlink", "netlink message");
+    debug!(target: TARGET, "window of 198.51.100.70 has 4 samples");
+    debug!(target: TARGET, "window of 198.51.100.7: 5 samples");
+    let first = subscription.recv().await.unwrap();
+    let second = subscription.recv().await.unwrap();
+    assert_eq!(first.target, TARGET);
+    assert_eq!(first.level, LogLevel::Debug);
+    assert!(timeout(Duration::from_millis(50), subscription.recv())
+        .await
+        .is_err());
+    // 跟踪得到的调试日志不计入最近的日志
+    assert!(!logger::recent()
+        .iter()
+        .any(|line| line.level == LogLevel::Debug));
+
+    let filter = LogFilter {
+        level: Some(LogLevel::Debug),
+        ip: Some("198.51.100.7".parse().unwrap()),
+        ..LogFilter::default()
+    };
+    assert!(!filter.matches(&first));
+    assert!(filter.matches(&second));
+    assert!(!LogFilter::default().matches(&second));
+
+    drop(subscription);
+    assert_eq!(log::max_level(), LevelFilter::Info);
+}
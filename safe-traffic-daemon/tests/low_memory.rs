@@ -0,0 +1,91 @@
+//! 低内存模式：窗口改用定点 u32 槽并只保留最长规则窗口，平均值误差不超过定点单位，决策不变
+
+mod common;
+
+use safe_traffic_common::{
+    config::{FlowClass, HookType},
+    events::Decision,
+    utils::TrafficStats,
+};
+use safe_traffic_daemon::{
+    rules::WindowStore,
+    simulate::{simulate, Scenario},
+};
+use std::{net::IpAddr, time::Duration};
+
+const CONFIG: &str = r#"
+
+    [[rules]]
+    name = "flood"
+    window_secs = 5
+    threshold_bps = 1_000_000
+    action = { Ban = { seconds = 10 } }
+"#;
+
+const SCENARIO: &str = r#"
+    duration_secs = 30
+
+    [[sources]]
+    ip = "198.51.100.7"
+    bps = 1_200_000
+    from_secs = 2
+    until_secs = 12
+
+    [[sources]]
+    ip = "198.51.100.8"
+    bps = 800_000
+"#;
+
+#[test]
+fn test_compact_windows_match_exact_windows() {
+    let dir = common::StateDir::new("low-memory");
+    let cfg = common::config(&dir, CONFIG);
+    let exact = WindowStore::new(HookType::Input);
+    let compact = WindowStore::new(HookType::Input).with_low_memory(&cfg.rules);
+    let ip: IpAddr = "198.51.100.7".parse().unwrap();
+
+    for second in 0..20u64 {
+        let stats = TrafficStats {
+            rx_delta: 1_000 * second + 7,
+            rx_new_delta: 100 * second,
+            ..Default::default()
+        };
+        let at = Duration::from_secs(second);
+        exact.record(ip, &stats, at);
+        compact.record(ip, &stats, at);
+    }
+    let (exact, compact_windows) = (exact.get(&ip).unwrap(), compact.get(&ip).unwrap());
+    for class in [FlowClass::All, FlowClass::New, FlowClass::Established] {
+        let (exact, compact) = (exact.get(class), compact_windows.get(class));
+        assert!(compact.is_warm(5));
+        assert!(exact.average(5).abs_diff(compact.average(5)) <= 8);
+        assert_eq!(compact.slots().len(), 6);
+    }
+
+    // 一个 5 秒窗口的规则只需 6 个 u32 槽，远小于 60 个 u64 槽
+    let low = compact.bytes_per_source();
+    let full = WindowStore::new(HookType::Input).bytes_per_source();
+    assert!(low * 3 < full, "{} vs {}", low, full);
+    assert!(compact.is_low_memory());
+    assert_eq!(compact.tracked_sources(), 1);
+}
+
+#[tokio::test]
+async fn test_low_memory_keeps_decisions() {
+    let dir = common::StateDir::new("low-memory");
+    let scenario = Scenario::parse(SCENARIO).unwrap();
+    let exact = simulate(&common::config(&dir, CONFIG), &scenario)
+        .await
+        .unwrap();
+    let low = common::config(&dir, &format!("low_memory = true\n{}", CONFIG));
+    let compact = simulate(&low, &scenario).await.unwrap();
+
+    let summary = |decisions: &[Decision]| {
+        decisions
+            .iter()
+            .map(|decision| (decision.at_secs, decision.kind, decision.ip))
+            .collect::<Vec<_>>()
+    };
+    assert!(!exact.is_empty());
+    assert_eq!(summary(&exact), summary(&compact));
+}
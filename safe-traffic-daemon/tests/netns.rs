@@ -0,0 +1,397 @@
+//! 基于网络命名空间的端到端测试
+//!
+//! 在两个临时网络命名空间之间建立 veth 对，守护进程运行在服务端命名空间中，
+//! 客户端命名空间内置的流量生成器向服务端发送 TCP 流量，检查封禁与限速是否真正生效。
+//!
+//! 需要 root 权限以及 `ip`、`nft` 命令，运行：
+//! `sudo -E cargo test -p safe-traffic-daemon --features netns-tests --test netns`
+//!
+//! 守护进程会绑定 /run/traffic.sock，请勿在运行中的守护进程所在主机上执行。
+
+use std::{
+    fs::{self, File},
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    os::fd::AsRawFd,
+    path::PathBuf,
+    process::{Child, Command, Output, Stdio},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+const SERVER_ADDR: &str = "10.231.0.1";
+const CLIENT_ADDR: &str = "10.231.0.2";
+const PORT: u16 = 9000;
+const TABLE: &str = "st_test";
+const CHAIN: &str = "st_input";
+
+/// 守护进程共用控制 socket，测试需串行执行
+static SERIAL: Mutex<()> = Mutex::new(());
+
+/// 临时网络命名空间，析构时删除
+struct Netns {
+    name: String,
+}
+
+impl Netns {
+    fn new(name: &str) -> Self {
+        run("ip", &["netns", "add", name]);
+        let netns = Netns {
+            name: name.to_string(),
+        };
+        netns.exec(&["ip", "link", "set", "lo", "up"]);
+        netns
+    }
+
+    /// 在命名空间内执行命令，失败时 panic
+    fn exec(&self, args: &[&str]) -> Output {
+        let mut full = vec!["netns", "exec", &self.name];
+        full.extend_from_slice(args);
+        run("ip", &full)
+    }
+}
+
+impl Drop for Netns {
+    fn drop(&mut self) {
+        // 删除命名空间时 veth 对随之删除
+        let _ = Command::new("ip")
+            .args(["netns", "del", &self.name])
+            .status();
+    }
+}
+
+/// 服务端与客户端命名空间，以 veth 对相连
+struct Topology {
+    server: Netns,
+    client: Netns,
+}
+
+impl Topology {
+    fn new(tag: &str) -> Self {
+        let pid = std::process::id();
+        let server = Netns::new(&format!("st-srv-{}-{}", tag, pid));
+        let client = Netns::new(&format!("st-cli-{}-{}", tag, pid));
+        let (server_if, client_if) = (format!("sts{}", pid), format!("stc{}", pid));
+
+        run(
+            "ip",
+            &[
+                "link",
+                "add",
+                &server_if,
+                "netns",
+                &server.name,
+                "type",
+                "veth",
+                "peer",
+                "name",
+                &client_if,
+                "netns",
+                &client.name,
+            ],
+        );
+        for (netns, ifname, addr) in [
+            (&server, &server_if, SERVER_ADDR),
+            (&client, &client_if, CLIENT_ADDR),
+        ] {
+            netns.exec(&["ip", "addr", "add", &format!("{}/24", addr), "dev", ifname]);
+            netns.exec(&["ip", "link", "set", ifname, "up"]);
+        }
+
+        Topology { server, client }
+    }
+}
+
+/// 在服务端命名空间运行的守护进程，析构时终止
+struct Daemon {
+    child: Child,
+    dir: PathBuf,
+}
+
+impl Daemon {
+    fn start(netns: &Netns, rules: &str) -> Self {
+        Self::start_in(netns, "Inet", rules)
+    }
+
+    /// 以指定协议族（配置中的 FamilyType）创建防火墙链
+    fn start_in(netns: &Netns, family: &str, rules: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("{}-{}", netns.name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let config = format!(
+            r#"
+interface = "sts{pid}"
+family = "{family}"
+table_name = "{TABLE}"
+chain_name = "{CHAIN}"
+hook = "Input"
+monitor_interval = 1
+rule_check_interval = 1
+warmup_secs = 0
+state_dir = "{state}"
+
+{rules}
+"#,
+            pid = std::process::id(),
+            state = dir.display(),
+        );
+        let config_path = dir.join("config.toml");
+        fs::write(&config_path, config).unwrap();
+
+        let child = Command::new("ip")
+            .args(["netns", "exec", &netns.name])
+            .arg(env!("CARGO_BIN_EXE_safe-traffic-daemon"))
+            .arg("--config")
+            .arg(&config_path)
+            .current_dir(&dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .expect("spawn daemon");
+
+        Daemon { child, dir }
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// 将当前线程切换到指定命名空间
+fn enter_netns(name: &str) {
+    let file = File::open(format!("/var/run/netns/{}", name)).expect("open netns");
+    let ret = unsafe { libc::setns(file.as_raw_fd(), libc::CLONE_NEWNET) };
+    assert_eq!(ret, 0, "setns into {} failed", name);
+}
+
+fn run(program: &str, args: &[&str]) -> Output {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .unwrap_or_else(|e| panic!("failed to run {}: {}", program, e));
+    assert!(
+        output.status.success(),
+        "{} {:?} failed: {}",
+        program,
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+/// 在服务端命名空间监听并统计收到的字节数
+fn spawn_receiver(netns: &Netns) -> Arc<AtomicU64> {
+    let received = Arc::new(AtomicU64::new(0));
+    let counter = Arc::clone(&received);
+    let name = netns.name.clone();
+    let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+
+    thread::spawn(move || {
+        enter_netns(&name);
+        let listener = TcpListener::bind((SERVER_ADDR, PORT)).expect("bind receiver");
+        ready_tx.send(()).unwrap();
+        let (mut stream, _) = listener.accept().expect("accept generator");
+        let mut buf = vec![0u8; 64 * 1024];
+        while let Ok(n) = stream.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            counter.fetch_add(n as u64, Ordering::Relaxed);
+        }
+    });
+
+    ready_rx
+        .recv_timeout(Duration::from_secs(5))
+        .expect("receiver ready");
+    received
+}
+
+/// 内置流量生成器：在客户端命名空间以约 bytes_per_sec 的速率持续发送
+fn spawn_generator(netns: &Netns, bytes_per_sec: usize, duration: Duration) {
+    let name = netns.name.clone();
+    thread::spawn(move || {
+        enter_netns(&name);
+        let target: SocketAddr = format!("{}:{}", SERVER_ADDR, PORT).parse().unwrap();
+        let mut stream =
+            TcpStream::connect_timeout(&target, Duration::from_secs(5)).expect("connect receiver");
+        // 被封禁后写入会阻塞，超时后继续按节奏重试
+        stream
+            .set_write_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+
+        let tick = Duration::from_millis(50);
+        let chunk = vec![0u8; bytes_per_sec / 20];
+        let deadline = Instant::now() + duration;
+        while Instant::now() < deadline {
+            let _ = stream.write(&chunk);
+            thread::sleep(tick);
+        }
+    });
+}
+
+/// 服务端防火墙链中的规则
+fn chain_rules(netns: &Netns) -> String {
+    chain_rules_in(netns, "inet")
+}
+
+/// 服务端指定协议族（nft 名称）的防火墙链，包括链定义与规则
+fn chain_rules_in(netns: &Netns, family: &str) -> String {
+    let output = Command::new("ip")
+        .args([
+            "netns",
+            "exec",
+            &netns.name,
+            "nft",
+            "list",
+            "chain",
+            family,
+            TABLE,
+            CHAIN,
+        ])
+        .output()
+        .expect("run nft");
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+fn wait_for(timeout: Duration, mut condition: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if condition() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(500));
+    }
+    false
+}
+
+/// 统计一段时间内收到的字节数
+fn measure(received: &AtomicU64, period: Duration) -> u64 {
+    let before = received.load(Ordering::Relaxed);
+    thread::sleep(period);
+    received.load(Ordering::Relaxed) - before
+}
+
+#[test]
+fn ban_drops_flood() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let topology = Topology::new("ban");
+    let received = spawn_receiver(&topology.server);
+    let _daemon = Daemon::start(
+        &topology.server,
+        r#"
+[[rules]]
+window_secs = 2
+threshold_bps = 100_000
+action = { Ban = { seconds = 60 } }
+"#,
+    );
+
+    spawn_generator(&topology.client, 1_000_000, Duration::from_secs(30));
+    assert!(
+        measure(&received, Duration::from_secs(1)) > 100_000,
+        "generator traffic did not reach the server"
+    );
+
+    let banned = wait_for(Duration::from_secs(15), || {
+        let rules = chain_rules(&topology.server);
+        rules.contains(CLIENT_ADDR) && rules.contains("drop")
+    });
+    assert!(banned, "no ban rule for {}", CLIENT_ADDR);
+
+    // 等待已在途的数据到达后，封禁期间应几乎收不到数据
+    thread::sleep(Duration::from_secs(1));
+    let during_ban = measure(&received, Duration::from_secs(3));
+    assert!(
+        during_ban < 10_000,
+        "received {} bytes while banned",
+        during_ban
+    );
+}
+
+#[test]
+fn rate_limit_caps_throughput() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let topology = Topology::new("limit");
+    let received = spawn_receiver(&topology.server);
+    let _daemon = Daemon::start(
+        &topology.server,
+        r#"
+[[rules]]
+window_secs = 2
+threshold_bps = 100_000
+action = { RateLimit = { kbps = 32, burst = 8, seconds = 60 } }
+"#,
+    );
+
+    spawn_generator(&topology.client, 1_000_000, Duration::from_secs(30));
+    let before = measure(&received, Duration::from_secs(1));
+    assert!(
+        before > 100_000,
+        "generator traffic did not reach the server"
+    );
+
+    let limited = wait_for(Duration::from_secs(15), || {
+        let rules = chain_rules(&topology.server);
+        rules.contains(CLIENT_ADDR) && rules.contains("limit rate")
+    });
+    assert!(limited, "no rate limit rule for {}", CLIENT_ADDR);
+
+    thread::sleep(Duration::from_secs(1));
+    // 限速 32 KB/s，留出突发与重传的余量
+    let per_sec = measure(&received, Duration::from_secs(3)) / 3;
+    assert!(
+        per_sec < 64 * 1024,
+        "throughput {} bytes/s exceeds the limit",
+        per_sec
+    );
+}
+
+#[test]
+fn netdev_ingress_ban_drops_flood() {
+    let _serial = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+    let topology = Topology::new("netdev");
+    let received = spawn_receiver(&topology.server);
+    let _daemon = Daemon::start_in(
+        &topology.server,
+        "Netdev",
+        r#"
+[[rules]]
+window_secs = 2
+threshold_bps = 100_000
+action = { Ban = { seconds = 60 } }
+"#,
+    );
+
+    // 链挂在服务端网卡的 ingress hook 上
+    let attached = wait_for(Duration::from_secs(5), || {
+        chain_rules_in(&topology.server, "netdev").contains("hook ingress")
+    });
+    assert!(attached, "no netdev ingress chain");
+
+    spawn_generator(&topology.client, 1_000_000, Duration::from_secs(30));
+    assert!(
+        measure(&received, Duration::from_secs(1)) > 100_000,
+        "generator traffic did not reach the server"
+    );
+
+    let banned = wait_for(Duration::from_secs(15), || {
+        let rules = chain_rules_in(&topology.server, "netdev");
+        rules.contains(CLIENT_ADDR) && rules.contains("drop")
+    });
+    assert!(banned, "no ingress ban rule for {}", CLIENT_ADDR);
+
+    thread::sleep(Duration::from_secs(1));
+    let during_ban = measure(&received, Duration::from_secs(3));
+    assert!(
+        during_ban < 10_000,
+        "received {} bytes while banned at ingress",
+        during_ban
+    );
+}
@@ -0,0 +1,95 @@
+//! 通知渠道的去重与摘要
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use safe_traffic_common::{
+    config::NotifyChannel,
+    events::{Event, EventKind},
+    reason::Reason,
+};
+use safe_traffic_daemon::notify::Channel;
+use std::net::IpAddr;
+
+fn channel(text: &str) -> Channel {
+    let cfg: NotifyChannel = toml::from_str(&format!(
+        "name = \"ops\"\nurl = \"http://127.0.0.1:1\"\n{}",
+        text
+    ))
+    .unwrap();
+    Channel::new(&cfg)
+}
+
+fn ban(ip: &str, rule: usize, time: DateTime<Utc>) -> Event {
+    let ip: IpAddr = ip.parse().unwrap();
+    let mut event = Event::new(EventKind::Ban, format!("ban {} for 60s", ip)).with_ip(ip);
+    event.time = time;
+    event.reason = Some(Reason {
+        rule,
+        rule_name: Some("flood".to_string()),
+        metric: "bps".to_string(),
+        observed: 2000,
+        threshold: 1000,
+        window_secs: 10,
+    });
+    event
+}
+
+#[test]
+fn test_dedup_reports_suppressed_count() {
+    let start = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+    let mut ops = channel("dedup_minutes = 10\nkinds = [\"Ban\"]");
+
+    let first = ops.offer(&ban("198.51.100.7", 0, start)).unwrap();
+    assert!(first.starts_with("[ban] ban 198.51.100.7 for 60s (rule 0 (flood)"));
+    for minute in 1..5 {
+        assert!(ops
+            .offer(&ban("198.51.100.7", 0, start + Duration::minutes(minute)))
+            .is_none());
+    }
+    // 其他来源或规则不受影响，未订阅的类型不通知
+    assert!(ops.offer(&ban("198.51.100.8", 0, start)).is_some());
+    assert!(ops.offer(&ban("198.51.100.7", 1, start)).is_some());
+    let mut unblock = Event::new(EventKind::Unblock, "unblock");
+    unblock.time = start;
+    assert!(ops.offer(&unblock).is_none());
+
+    assert!(ops.tick(start + Duration::minutes(9)).is_empty());
+    let reports = ops.tick(start + Duration::minutes(10));
+    assert_eq!(reports.len(), 1);
+    assert!(reports[0].ends_with(": 4 similar events suppressed"));
+
+    // 窗口结束后再次通知
+    assert!(ops
+        .offer(&ban("198.51.100.7", 0, start + Duration::minutes(11)))
+        .is_some());
+}
+
+#[test]
+fn test_digest_batches_events() {
+    let start = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+    let mut ops = channel("dedup_minutes = 5\ndigest_minutes = 15");
+
+    for second in 0..3 {
+        assert!(ops
+            .offer(&ban("198.51.100.7", 0, start + Duration::seconds(second)))
+            .is_none());
+    }
+    assert!(ops.offer(&ban("198.51.100.8", 0, start)).is_none());
+    assert!(ops
+        .offer(&ban("198.51.100.7", 0, start + Duration::minutes(6)))
+        .is_none());
+
+    // 去重窗口到期不单独通知，抑制数计入摘要
+    assert!(ops.tick(start + Duration::minutes(14)).is_empty());
+    let digest = ops.tick(start + Duration::minutes(15));
+    assert_eq!(digest.len(), 1);
+    let lines: Vec<&str> = digest[0].lines().collect();
+    assert_eq!(
+        lines[0],
+        "ops: 5 events in the last 15 minutes, 2 duplicates suppressed"
+    );
+    assert!(lines[1].contains("198.51.100.7") && lines[1].ends_with(" x2"));
+    assert!(lines[2].contains("198.51.100.8") && lines[2].ends_with(" x1"));
+
+    // 摘要发出后清空，没有新事件时不再发送
+    assert!(ops.tick(start + Duration::minutes(40)).is_empty());
+}
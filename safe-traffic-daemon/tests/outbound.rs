@@ -0,0 +1,62 @@
+//! 出站请求的代理选择：配置优先于环境变量，NO_PROXY 与本机地址直连
+
+use safe_traffic_common::config::ProxyConfig;
+use safe_traffic_daemon::outbound::ProxySettings;
+use std::collections::HashMap;
+
+fn from_env(vars: &[(&str, &str)]) -> ProxySettings {
+    let vars: HashMap<String, String> = vars
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    ProxySettings::with_env(None, |name| vars.get(name).cloned())
+}
+
+#[test]
+fn test_proxy_from_env() {
+    let proxy = from_env(&[
+        ("HTTPS_PROXY", "http://proxy.corp:3128"),
+        ("http_proxy", "http://plain.corp:3128"),
+        ("NO_PROXY", "hooks.corp, .internal,10.0.0.0/8"),
+    ]);
+    assert_eq!(
+        proxy.proxy_for("https://api.cloudflare.com/client/v4"),
+        Some("http://proxy.corp:3128")
+    );
+    assert_eq!(
+        proxy.proxy_for("http://exabgp.example.net:5000/"),
+        Some("http://plain.corp:3128")
+    );
+    assert_eq!(proxy.proxy_for("https://hooks.corp/slack"), None);
+    assert_eq!(proxy.proxy_for("https://alerts.hooks.corp/slack"), None);
+    assert_eq!(proxy.proxy_for("https://metadata.internal/v1"), None);
+    assert_eq!(proxy.proxy_for("http://10.1.2.3:8080/"), None);
+    assert_eq!(proxy.proxy_for("http://user:pw@[::1]:50051/"), None);
+    assert_eq!(proxy.proxy_for("http://169.254.169.254/latest"), None);
+
+    assert_eq!(from_env(&[]).proxy_for("https://hooks.corp/"), None);
+    let all = from_env(&[("ALL_PROXY", "socks5://127.0.0.1:1080"), ("no_proxy", "*")]);
+    assert_eq!(all.proxy_for("https://hooks.corp/"), None);
+}
+
+#[test]
+fn test_config_overrides_env() {
+    let cfg = ProxyConfig {
+        url: "socks5://127.0.0.1:1080".to_string(),
+        no_proxy: Some(vec!["hooks.corp".to_string()]),
+    };
+    let proxy = ProxySettings::with_env(Some(&cfg), |name| match name {
+        "HTTPS_PROXY" => Some("http://proxy.corp:3128".to_string()),
+        "NO_PROXY" => Some("api.cloudflare.com".to_string()),
+        _ => None,
+    });
+    assert_eq!(
+        proxy.proxy_for("https://api.cloudflare.com/client/v4"),
+        Some("socks5://127.0.0.1:1080")
+    );
+    assert_eq!(
+        proxy.proxy_for("http://exabgp.example.net:5000/"),
+        Some("socks5://127.0.0.1:1080")
+    );
+    assert_eq!(proxy.proxy_for("https://hooks.corp/slack"), None);
+}
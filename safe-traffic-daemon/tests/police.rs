@@ -0,0 +1,60 @@
+//! 按目的地址触发的规则：发往被攻击地址的总流量超过阈值时对其来源分别限速
+
+mod common;
+
+use dashmap::DashMap;
+use safe_traffic_common::{clock::Clock, config::Action, utils::TrafficStats};
+use safe_traffic_daemon::rules::RuleEngine;
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+const CONFIG: &str = r#"
+    [[rules]]
+    name = "vip"
+    window_secs = 2
+    threshold_bps = 1_000_000
+    destinations = ["203.0.113.10"]
+    action = { PoliceSources = { kbps = 200, burst = 50, seconds = 60 } }
+"#;
+
+#[tokio::test]
+async fn test_destination_rule_polices_sources() {
+    let dir = common::StateDir::new("police");
+    let cfg = common::config(&dir, CONFIG);
+    let clock = common::clock();
+    let fw = common::clocked_firewall(&cfg, &clock).await;
+    let dest: IpAddr = "203.0.113.10".parse().unwrap();
+    assert_eq!(
+        fw.police_rule_command(dest, 200, 50),
+        "add rule inet traffic_filter traffic_input ip daddr 203.0.113.10 meter police_203_0_113_10 size 65535 { ip saddr limit rate over 200 kbytes/second burst 50 kbytes } counter drop"
+    );
+
+    // 源地址的流量不超过阈值，只有目的地址的总流量超过
+    let stats = Arc::new(DashMap::new());
+    let engine = RuleEngine::new(cfg.rules.clone(), stats.clone())
+        .with_clock(clock.clone())
+        .with_warmup(0);
+    let source: IpAddr = "198.51.100.7".parse().unwrap();
+    for _ in 0..4 {
+        clock.advance(Duration::from_secs(1));
+        let sample = TrafficStats {
+            rx_delta: 10_000,
+            ..Default::default()
+        };
+        engine.windows().record(source, &sample, clock.monotonic());
+        stats.insert(source, sample);
+        engine
+            .windows()
+            .record_destination(dest, 5_000_000, clock.monotonic());
+        engine.check_and_apply(Arc::clone(&fw)).await.unwrap();
+    }
+
+    let rules = fw.get_active_rules().await.unwrap();
+    assert_eq!(rules.len(), 1);
+    assert_eq!(rules[0].ip, dest);
+    assert!(matches!(
+        rules[0].rule_type,
+        Action::PoliceSources { kbps: 200, .. }
+    ));
+    assert_eq!(rules[0].reason.as_ref().unwrap().metric, "dest_bps");
+    assert_eq!(engine.rule_hits(), vec![1]);
+}
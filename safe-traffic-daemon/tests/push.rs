@@ -0,0 +1,71 @@
+//! WebSocket 推送：连接后立即收到快照，之后的推送带上期间产生的事件；token 不符的连接被拒绝
+
+mod common;
+
+use dashmap::DashMap;
+use futures::StreamExt;
+use safe_traffic_common::{events::EventKind, transport::PushUpdate};
+use safe_traffic_daemon::{push::PushServer, rules::RuleEngine};
+use std::{net::IpAddr, sync::Arc, time::Duration};
+use tokio::time::timeout;
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{Error, Message},
+};
+
+const CONFIG: &str = r#"
+    rules = []
+
+    [websocket]
+    listen = "127.0.0.1:0"
+    interval_secs = 1
+    token = "secret"
+"#;
+
+async fn next_update<S>(socket: &mut S) -> PushUpdate
+where
+    S: StreamExt<Item = Result<Message, Error>> + Unpin,
+{
+    let message = timeout(Duration::from_secs(5), socket.next())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    let Message::Text(text) = message else {
+        panic!("unexpected message: {:?}", message);
+    };
+    serde_json::from_str(&text).unwrap()
+}
+
+#[tokio::test]
+async fn test_push_snapshots_and_events() {
+    let dir = common::StateDir::new("push");
+    let cfg = common::config(&dir, CONFIG);
+    let fw = common::firewall(&cfg).await;
+    let engine = Arc::new(RuleEngine::new(cfg.rules.clone(), Arc::new(DashMap::new())));
+    let server = PushServer::bind(cfg.websocket.as_ref().unwrap(), Arc::clone(&fw), engine)
+        .await
+        .unwrap();
+    let addr = server.local_addr().unwrap();
+    tokio::spawn(server.run());
+
+    assert!(connect_async(format!("ws://{}/?token=wrong", addr))
+        .await
+        .is_err());
+
+    let (mut socket, _) = connect_async(format!("ws://{}/?token=secret", addr))
+        .await
+        .unwrap();
+    let first = next_update(&mut socket).await;
+    assert_eq!(first.snapshot.ban_rules, 0);
+    assert!(first.events.is_empty());
+
+    let ip: IpAddr = "198.51.100.9".parse().unwrap();
+    fw.ban(ip, Some(60)).await.unwrap();
+    let second = next_update(&mut socket).await;
+    assert_eq!(second.snapshot.ban_rules, 1);
+    assert_eq!(second.events.len(), 1);
+    assert_eq!(second.events[0].kind, EventKind::Ban);
+    assert_eq!(second.events[0].ip, Some(ip));
+    assert_eq!(second.missed_events, 0);
+}
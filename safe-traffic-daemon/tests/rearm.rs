@@ -0,0 +1,101 @@
+//! 重启后恢复封禁：停机前不久生效过的封禁在启动时重新装上，停机期间到期的只装 rearm_secs
+
+mod common;
+
+use chrono::{DateTime, Utc};
+use safe_traffic_common::{
+    clock::{Clock, ManualClock},
+    config::Action,
+};
+use safe_traffic_daemon::rearm::{BanRecord, Rearm, RearmState, Rearmed};
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+const CONFIG: &str = r#"
+    rules = []
+
+    [rearm]
+    within_hours = 6
+    rearm_secs = 900
+"#;
+
+#[tokio::test]
+async fn test_bans_are_rearmed_after_a_restart() {
+    let dir = common::StateDir::new("rearm");
+    let cfg = common::config(&dir, CONFIG);
+    let start: DateTime<Utc> = "2026-01-01T00:00:00Z".parse().unwrap();
+    let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+
+    let clock = Arc::new(ManualClock::new(start));
+    let fw = common::clocked_firewall(&cfg, &clock).await;
+    let rearm = Rearm::new(cfg.rearm.as_ref().unwrap(), cfg.state_dir.as_deref());
+    let short = fw.ban(ip("198.51.100.1"), Some(600)).await.unwrap();
+    fw.ban(ip("198.51.100.2"), Some(86400)).await.unwrap();
+    fw.ban(ip("198.51.100.3"), None).await.unwrap();
+    let unblocked = fw.ban(ip("198.51.100.4"), Some(86400)).await.unwrap();
+    fw.limit(ip("198.51.100.5"), 100, None, Some(600))
+        .await
+        .unwrap();
+    rearm.checkpoint(&fw).await.unwrap();
+
+    // 到期的封禁仍记录，提前解除的不再记录
+    fw.unblock(&unblocked).await.unwrap();
+    clock.advance(Duration::from_secs(700));
+    fw.unblock(&short).await.unwrap();
+    rearm.checkpoint(&fw).await.unwrap();
+
+    // 停机 3 小时后启动
+    let clock = Arc::new(ManualClock::new(clock.wall() + chrono::Duration::hours(3)));
+    let fw = common::clocked_firewall(&cfg, &clock).await;
+    let rearm = Rearm::new(cfg.rearm.as_ref().unwrap(), cfg.state_dir.as_deref());
+    assert_eq!(rearm.restore(&fw).await.unwrap(), 3);
+    let mut rules: Vec<(IpAddr, Option<u64>)> = fw
+        .get_active_rules()
+        .await
+        .unwrap()
+        .into_iter()
+        .inspect(|rule| assert!(matches!(rule.rule_type, Action::Ban { .. })))
+        .map(|rule| (rule.ip, rule.remaining_secs))
+        .collect();
+    rules.sort();
+    assert_eq!(
+        rules,
+        vec![
+            (ip("198.51.100.1"), Some(900)),
+            (ip("198.51.100.2"), Some(86400 - 700 - 3 * 3600)),
+            (ip("198.51.100.3"), None),
+        ]
+    );
+}
+
+#[test]
+fn test_bans_older_than_the_window_are_not_rearmed() {
+    let dir = common::StateDir::new("rearm");
+    let cfg = common::config(&dir, CONFIG);
+    let rearm = Rearm::new(cfg.rearm.as_ref().unwrap(), cfg.state_dir.as_deref());
+    let saved_at: DateTime<Utc> = "2026-01-01T12:00:00Z".parse().unwrap();
+    let record = |hours_before: i64| BanRecord {
+        source_ports: Some(vec![53]),
+        last_active: saved_at - chrono::Duration::hours(hours_before),
+        expires_at: Some(saved_at - chrono::Duration::hours(hours_before)),
+    };
+    let state = RearmState {
+        saved_at: Some(saved_at),
+        bans: [
+            ("203.0.113.1".parse().unwrap(), record(1)),
+            ("203.0.113.2".parse().unwrap(), record(7)),
+        ]
+        .into_iter()
+        .collect(),
+    };
+    // 停机时长不影响是否恢复，只看停机前多久生效过
+    let now = saved_at + chrono::Duration::days(2);
+    assert_eq!(
+        rearm.plan(&state, now),
+        vec![Rearmed {
+            ip: "203.0.113.1".parse().unwrap(),
+            source_ports: Some(vec![53]),
+            seconds: Some(900),
+        }]
+    );
+    assert!(rearm.plan(&RearmState::default(), now).is_empty());
+}
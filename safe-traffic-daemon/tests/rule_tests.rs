@@ -0,0 +1,65 @@
+//! 配置中嵌入的规则测试：`--check-config` 用真实的规则引擎逐个执行
+
+mod common;
+
+use safe_traffic_daemon::simulate::run_rule_tests;
+
+const CONFIG: &str = r#"
+
+    [[rules]]
+    name = "flood"
+    window_secs = 5
+    threshold_bps = 1_000_000
+    action = { Ban = { seconds = 10 } }
+
+    [[rule_tests]]
+    name = "flood is banned"
+    duration_secs = 20
+
+    [[rule_tests.sources]]
+    ip = "198.51.100.7"
+    bps = 5_000_000
+    until_secs = 8
+
+    [[rule_tests.expect]]
+    ip = "198.51.100.7"
+    action = "Ban"
+    within_secs = 5
+
+    [[rule_tests]]
+    name = "backup stays unbanned"
+    duration_secs = 20
+
+    [[rule_tests.sources]]
+    ip = "198.51.100.8"
+    bps = 2_000_000
+
+    [[rule_tests.expect]]
+    ip = "198.51.100.8"
+    action = "Ban"
+    never = true
+"#;
+
+#[tokio::test]
+async fn test_rule_tests_report_each_failure() {
+    let dir = common::StateDir::new("rule-tests");
+    let cfg = common::config(&dir, CONFIG);
+    let results = run_rule_tests(&cfg).await.unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].name, "flood is banned");
+    assert!(results[0].passed(), "{:?}", results[0].failures);
+
+    // 第二个测试捕捉到阈值过低，备份流量同样会被封禁
+    assert_eq!(results[1].name, "backup stays unbanned");
+    assert!(!results[1].passed());
+    assert_eq!(results[1].failures.len(), 1);
+
+    // 调高阈值后两个测试都通过
+    let cfg = common::config(&dir, &CONFIG.replace("1_000_000", "3_000_000"));
+    let results = run_rule_tests(&cfg).await.unwrap();
+    assert!(
+        results.iter().all(|result| result.passed()),
+        "{:?}",
+        results
+    );
+}
@@ -0,0 +1,65 @@
+//! 丢弃流量：只计入丢弃规则两次读取之间的增量，按天汇总，重启后从状态文件继续累计
+
+use chrono::NaiveDate;
+use safe_traffic_common::transport::{Response, ResponseData, SavingsDay, SystemRule};
+use safe_traffic_daemon::savings::Savings;
+
+fn rule(handle: u64, bytes: u64, verdict: &str) -> SystemRule {
+    SystemRule {
+        handle: Some(handle),
+        matchers: vec!["ip saddr == 198.51.100.7".to_string()],
+        packets: Some(bytes / 100),
+        bytes: Some(bytes),
+        statements: vec![verdict.to_string()],
+    }
+}
+
+#[tokio::test]
+async fn test_dropped_traffic_per_day() {
+    let dir = std::env::temp_dir().join(format!("safe-traffic-savings-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let state_dir = dir.to_str();
+    let savings = Savings::new(state_dir);
+    let day1 = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+    let day2 = day1.succ_opt().unwrap();
+
+    // 提示规则没有 drop 判决，不计入
+    savings.record(
+        &[rule(1, 1000, "drop"), rule(2, 5000, "meta mark set 0x1")],
+        day1,
+    );
+    savings.record(&[rule(1, 1500, "drop"), rule(3, 200, "drop")], day1);
+    // 规则 1 已删除，句柄 3 被新规则复用后计数从头开始
+    savings.record(&[rule(3, 100, "drop")], day2);
+    assert_eq!(savings.totals(), (1800, 18));
+    assert_eq!(
+        savings.days(),
+        [
+            SavingsDay {
+                date: day1,
+                bytes: 1700,
+                packets: 17
+            },
+            SavingsDay {
+                date: day2,
+                bytes: 100,
+                packets: 1
+            },
+        ]
+    );
+    assert_eq!(savings.day(day2.succ_opt().unwrap()).bytes, 0);
+
+    savings.save().await.unwrap();
+    let restarted = Savings::new(state_dir);
+    restarted.load().await.unwrap();
+    assert_eq!(restarted.totals(), (1800, 18));
+    assert_eq!(restarted.day(day1).bytes, 1700);
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let json =
+        serde_json::to_string(&Response::Success(ResponseData::Savings(savings.days()))).unwrap();
+    assert!(matches!(
+        serde_json::from_str(&json).unwrap(),
+        Response::Success(ResponseData::Savings(days)) if days.len() == 2
+    ));
+}
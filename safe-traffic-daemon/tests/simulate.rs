@@ -0,0 +1,105 @@
+//! 规则模拟的断言示例，基础设施仓库可按同样方式在 CI 中校验自己的规则文件
+
+mod common;
+
+use safe_traffic_common::events::EventKind;
+use safe_traffic_daemon::simulate::{simulate, Scenario};
+
+const CONFIG: &str = r#"
+
+    [[rules]]
+    name = "flood"
+    window_secs = 5
+    threshold_bps = 1_000_000
+    action = { Ban = { seconds = 10 } }
+"#;
+
+const SCENARIO: &str = r#"
+    duration_secs = 30
+
+    [[sources]]
+    ip = "198.51.100.7"
+    bps = 5_000_000
+    until_secs = 8
+
+    [[sources]]
+    ip = "198.51.100.8"
+    bps = 200_000
+
+    [[expect]]
+    ip = "198.51.100.7"
+    action = "Ban"
+    within_secs = 5
+
+    [[expect]]
+    ip = "198.51.100.7"
+    action = "Unblock"
+    within_secs = 20
+
+    [[expect]]
+    ip = "198.51.100.8"
+    action = "Ban"
+    never = true
+"#;
+
+#[tokio::test]
+async fn test_scenario_bans_within_window() {
+    let dir = common::StateDir::new("simulate");
+    let cfg = common::config(&dir, CONFIG);
+    let scenario = Scenario::parse(SCENARIO).unwrap();
+    let decisions = simulate(&cfg, &scenario).await.unwrap();
+
+    let ban = decisions
+        .iter()
+        .find(|decision| decision.kind == EventKind::Ban)
+        .unwrap();
+    assert_eq!(
+        ban.reason.as_ref().unwrap().rule_name.as_deref(),
+        Some("flood")
+    );
+    assert!(scenario.failures(&decisions).is_empty(), "{:?}", decisions);
+
+    let stricter =
+        Scenario::parse(&SCENARIO.replace("within_secs = 5", "within_secs = 1")).unwrap();
+    assert_eq!(stricter.failures(&decisions).len(), 1);
+}
+
+#[tokio::test]
+async fn test_repeat_offense_escalates() {
+    let dir = common::StateDir::new("simulate");
+    let cfg = common::config(
+        &dir,
+        r#"
+        [[rules]]
+        window_secs = 2
+        threshold_bps = 1_000_000
+        action = { RateLimit = { kbps = 100, seconds = 5 } }
+        repeat_action = { Ban = { seconds = 3600 } }
+        repeat_window = "1h"
+    "#,
+    );
+    let scenario = Scenario::parse(
+        r#"
+        duration_secs = 30
+
+        [[sources]]
+        ip = "198.51.100.7"
+        bps = 5_000_000
+    "#,
+    )
+    .unwrap();
+    let decisions = simulate(&cfg, &scenario).await.unwrap();
+
+    let kinds: Vec<EventKind> = decisions
+        .iter()
+        .map(|decision| decision.kind)
+        .filter(|kind| matches!(kind, EventKind::Limit | EventKind::Ban))
+        .collect();
+    // 首次违规限速，限速到期后再犯直接封禁
+    assert_eq!(
+        kinds,
+        vec![EventKind::Limit, EventKind::Ban],
+        "{:?}",
+        decisions
+    );
+}
@@ -0,0 +1,120 @@
+//! 不同来源数：HyperLogLog 的估计误差，草图按分片轮转，来源数超过阈值时限速发往该地址的来源
+
+mod common;
+
+use dashmap::DashMap;
+use safe_traffic_common::{
+    clock::Clock,
+    config::{Action, Config},
+    transport::FlowEntry,
+};
+use safe_traffic_daemon::{
+    rules::RuleEngine,
+    sketch::{HyperLogLog, SourceSketches},
+};
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::Arc,
+    time::Duration,
+};
+
+const CONFIG: &str = r#"
+    [flows]
+
+    [[rules]]
+    name = "ramp-up"
+    window_secs = 60
+    threshold = 150
+    metric = "UniqueSourcesPerMinute"
+    destinations = ["203.0.113.10"]
+    dports = [443]
+    action = { PoliceSources = { kbps = 100, seconds = 300 } }
+"#;
+
+fn flows(sources: std::ops::Range<u32>, dst: &str, dport: u16) -> Vec<FlowEntry> {
+    sources
+        .map(|i| FlowEntry {
+            src: IpAddr::V4(Ipv4Addr::from(0x0a000000 + i)),
+            dst: dst.parse().unwrap(),
+            proto: "tcp".to_string(),
+            dport: Some(dport),
+            bytes: 120,
+            packets: 2,
+        })
+        .collect()
+}
+
+#[test]
+fn test_hyperloglog_estimates_within_error() {
+    let mut sketch = HyperLogLog::default();
+    assert_eq!(sketch.estimate(), 0);
+    for i in 0..50_000u32 {
+        sketch.insert(&IpAddr::V4(Ipv4Addr::from(i)));
+        // 重复的来源不增加估计值
+        sketch.insert(&IpAddr::V4(Ipv4Addr::from(i)));
+    }
+    let estimate = sketch.estimate() as f64;
+    assert!((estimate - 50_000.0).abs() / 50_000.0 < 0.1, "{}", estimate);
+
+    let mut other = HyperLogLog::default();
+    for i in 0..100u128 {
+        other.insert(&IpAddr::V6(Ipv6Addr::from(i)));
+    }
+    let small = other.estimate() as f64;
+    assert!((small - 100.0).abs() < 5.0, "{}", small);
+    sketch.merge(&other);
+    let merged = sketch.estimate() as f64;
+    assert!((merged - 50_100.0).abs() / 50_100.0 < 0.1, "{}", merged);
+}
+
+#[test]
+fn test_sketches_cover_the_last_minute() {
+    let cfg = Config::parse(&format!("interface = \"eth0\"\n{}", CONFIG)).unwrap();
+    let sketches = SourceSketches::for_rules(&cfg.rules).unwrap();
+    let dest: IpAddr = "203.0.113.10".parse().unwrap();
+    let secs = Duration::from_secs;
+
+    sketches.observe(&flows(0..100, "203.0.113.10", 443), secs(0));
+    // 其他端口与其他目的地址不计入
+    sketches.observe(&flows(100..300, "203.0.113.10", 80), secs(15));
+    sketches.observe(&flows(100..300, "203.0.113.11", 443), secs(15));
+    sketches.observe(&flows(50..150, "203.0.113.10", 443), secs(30));
+    let estimate = sketches.estimate(0, &dest, secs(30));
+    assert!((145..=155).contains(&estimate), "{}", estimate);
+    assert!(!sketches.is_warm(secs(30)));
+
+    // 第一批来源所在的分片已超出一分钟
+    let estimate = sketches.estimate(0, &dest, secs(65));
+    assert!((95..=105).contains(&estimate), "{}", estimate);
+    assert!(sketches.is_warm(secs(65)));
+    assert_eq!(sketches.estimate(0, &dest, secs(200)), 0);
+    assert_eq!(sketches.estimate(1, &dest, secs(30)), 0);
+}
+
+#[tokio::test]
+async fn test_source_cardinality_spike_polices_the_destination() {
+    let dir = common::StateDir::new("sketch");
+    let cfg = common::config(&dir, CONFIG);
+    let clock = common::clock();
+    let fw = common::clocked_firewall(&cfg, &clock).await;
+    let sketches = Arc::new(SourceSketches::for_rules(&cfg.rules).unwrap());
+    let engine = RuleEngine::new(cfg.rules.clone(), Arc::new(DashMap::new()))
+        .with_clock(clock.clone())
+        .with_warmup(0)
+        .with_source_sketches(Arc::clone(&sketches));
+
+    sketches.observe(&flows(0..100, "203.0.113.10", 443), clock.monotonic());
+    engine.check_and_apply(Arc::clone(&fw)).await.unwrap();
+    assert!(fw.get_active_rules().await.unwrap().is_empty());
+
+    clock.advance(Duration::from_secs(10));
+    sketches.observe(&flows(100..300, "203.0.113.10", 443), clock.monotonic());
+    engine.check_and_apply(Arc::clone(&fw)).await.unwrap();
+    let rules = fw.get_active_rules().await.unwrap();
+    assert_eq!(rules.len(), 1);
+    assert_eq!(rules[0].ip, "203.0.113.10".parse::<IpAddr>().unwrap());
+    assert!(matches!(rules[0].rule_type, Action::PoliceSources { .. }));
+    let reason = rules[0].reason.as_ref().unwrap();
+    assert_eq!(reason.metric, "unique_sources_per_minute");
+    assert_eq!(reason.window_secs, 60);
+}
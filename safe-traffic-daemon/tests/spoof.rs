@@ -0,0 +1,150 @@
+//! 伪造源洪泛：大量只有一两个报文的来源触发整体防护，防护期间不创建逐 IP 规则
+
+mod common;
+
+use dashmap::DashMap;
+use safe_traffic_common::{clock::Clock, transport::FlowEntry, utils::TrafficStats};
+use safe_traffic_daemon::{
+    controller::Firewall,
+    flows::FlowTable,
+    rules::RuleEngine,
+    spoof::{SpoofGuard, Transition},
+};
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    sync::Arc,
+    time::Duration,
+};
+
+const CONFIG: &str = r#"
+    [[rules]]
+    window_secs = 1
+    threshold_bps = 1_000_000
+    action = { Ban = { seconds = 60 } }
+
+    [flows]
+
+    [spoof_guard]
+    min_sources = 100
+    hold_secs = 30
+    ports = [80, 443]
+    synproxy = true
+    syn_rate = 500
+"#;
+
+fn syn(src: IpAddr, packets: u64) -> FlowEntry {
+    FlowEntry {
+        src,
+        dst: "203.0.113.1".parse().unwrap(),
+        proto: "tcp".to_string(),
+        dport: Some(80),
+        bytes: packets * 60,
+        packets,
+    }
+}
+
+#[tokio::test]
+async fn test_thin_sources_engage_and_release_protection() {
+    let dir = common::StateDir::new("spoof");
+    let cfg = common::config(&dir, CONFIG);
+    let executor = common::executor().await;
+    // 流表只保留 10 条流，来源的报文数仍按全部流统计
+    let table = Arc::new(FlowTable::new(10));
+    let guard = SpoofGuard::new(cfg.spoof_guard.as_ref().unwrap(), executor, table.clone());
+
+    let mut flows: Vec<FlowEntry> = (0..150u32)
+        .map(|i| {
+            syn(
+                IpAddr::V4(Ipv4Addr::from(0x0a000000 + i)),
+                1 + (i % 2) as u64,
+            )
+        })
+        .collect();
+    // 正常客户端与未开启计数时的流不计入
+    flows.push(syn("198.51.100.2".parse().unwrap(), 40));
+    flows.push(syn("198.51.100.3".parse().unwrap(), 0));
+    table.replace(flows);
+    assert_eq!(table.thin_sources(2), 150);
+    assert_eq!(table.thin_sources(1), 75);
+
+    let secs = Duration::from_secs;
+    assert_eq!(
+        guard.observe(table.thin_sources(2), secs(0)),
+        Some(Transition::Engage(150))
+    );
+    assert!(guard.is_active());
+    assert_eq!(guard.observe(150, secs(5)), None);
+    // 条件消失后保持 hold_secs
+    assert_eq!(guard.observe(3, secs(20)), None);
+    assert!(guard.is_active());
+    assert_eq!(guard.observe(3, secs(35)), Some(Transition::Release));
+    assert!(!guard.is_active());
+    assert_eq!(guard.observe(3, secs(40)), None);
+
+    assert_eq!(
+        guard.engage_commands(),
+        vec![
+            "add table inet traffic_spoof_guard",
+            "add chain inet traffic_spoof_guard prerouting { type filter hook prerouting priority -300; policy accept; }",
+            "add rule inet traffic_spoof_guard prerouting tcp dport { 80, 443 } tcp flags & (syn | ack) == syn notrack",
+            "add chain inet traffic_spoof_guard input { type filter hook input priority 10; policy accept; }",
+            "add rule inet traffic_spoof_guard input tcp dport 80 tcp flags & (syn | ack) == syn limit rate over 500/second counter drop",
+            "add rule inet traffic_spoof_guard input tcp dport 443 tcp flags & (syn | ack) == syn limit rate over 500/second counter drop",
+            "add rule inet traffic_spoof_guard input tcp dport { 80, 443 } ct state { invalid, untracked } synproxy mss 1460 wscale 7 timestamp sack-perm",
+            "add rule inet traffic_spoof_guard input tcp dport { 80, 443 } ct state invalid counter drop",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_engine_creates_no_per_ip_rules_during_a_spoofed_flood() {
+    let dir = common::StateDir::new("spoof");
+    let cfg = common::config(&dir, CONFIG);
+    let clock = common::clock();
+    let executor = common::executor().await;
+    let fw = Arc::new(
+        Firewall::new(&cfg, executor.clone())
+            .await
+            .unwrap()
+            .with_clock(clock.clone()),
+    );
+    let guard = Arc::new(SpoofGuard::new(
+        cfg.spoof_guard.as_ref().unwrap(),
+        executor,
+        fw.flows().unwrap(),
+    ));
+    let stats = Arc::new(DashMap::new());
+    let engine = RuleEngine::new(cfg.rules.clone(), stats.clone())
+        .with_clock(clock.clone())
+        .with_warmup(0)
+        .with_spoof_guard(Arc::clone(&guard));
+    let heavy: IpAddr = "198.51.100.7".parse().unwrap();
+    let sample = TrafficStats {
+        rx_delta: 2_000_000,
+        ..Default::default()
+    };
+    for _ in 0..3 {
+        engine.windows().record(heavy, &sample, clock.monotonic());
+        stats.insert(heavy, sample.clone());
+        clock.advance(Duration::from_secs(1));
+    }
+
+    guard.observe(1000, clock.monotonic());
+    engine.check_and_apply(Arc::clone(&fw)).await.unwrap();
+    assert!(fw.get_active_rules().await.unwrap().is_empty());
+    assert_eq!(engine.rule_hits(), vec![0]);
+
+    clock.advance(Duration::from_secs(30));
+    assert_eq!(
+        guard.observe(0, clock.monotonic()),
+        Some(Transition::Release)
+    );
+    for _ in 0..2 {
+        engine.windows().record(heavy, &sample, clock.monotonic());
+        clock.advance(Duration::from_secs(1));
+    }
+    engine.check_and_apply(Arc::clone(&fw)).await.unwrap();
+    let rules = fw.get_active_rules().await.unwrap();
+    assert_eq!(rules.len(), 1);
+    assert_eq!(rules[0].ip, heavy);
+}
@@ -0,0 +1,78 @@
+//! 热备：备节点经主节点 WebSocket 的 `/standby` 路径同步规则与白名单，token 不符时视为心跳丢失；
+//! 接管时才在备节点上建表并重建规则，主节点恢复后心跳回来，调用方据此撤下本地执行
+
+mod common;
+
+use dashmap::DashMap;
+use safe_traffic_common::config::StandbyConfig;
+use safe_traffic_daemon::{push::PushServer, rules::RuleEngine, standby::StandbyFollower};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::time::timeout;
+
+const PRIMARY: &str = r#"
+    rules = []
+
+    [websocket]
+    listen = "127.0.0.1:0"
+    token = "secret"
+"#;
+
+fn standby(primary: SocketAddr, token: &str) -> StandbyConfig {
+    toml::from_str(&format!(
+        "primary = \"{}\"\ntoken = \"{}\"\nheartbeat_interval = 1\nfailover_after = 2",
+        primary, token
+    ))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_standby_follows_and_takes_over() {
+    let dir = common::StateDir::new("standby-primary");
+    let cfg = common::config(&dir, PRIMARY);
+    let primary = common::firewall(&cfg).await;
+    let engine = Arc::new(RuleEngine::new(cfg.rules.clone(), Arc::new(DashMap::new())));
+    let server = PushServer::bind(
+        cfg.websocket.as_ref().unwrap(),
+        Arc::clone(&primary),
+        engine,
+    )
+    .await
+    .unwrap();
+    let addr = server.local_addr().unwrap();
+    tokio::spawn(server.run());
+
+    let banned: IpAddr = "198.51.100.20".parse().unwrap();
+    let excluded: IpAddr = "198.51.100.21".parse().unwrap();
+    primary.ban(banned, Some(600)).await.unwrap();
+    primary.add_exclude(&excluded, Some(3600)).await.unwrap();
+
+    // token 不符时每次同步都失败，连续丢失心跳后结束跟随
+    let mut follower = StandbyFollower::new(&standby(addr, "wrong"));
+    timeout(Duration::from_secs(5), follower.follow())
+        .await
+        .unwrap();
+
+    // token 正确时持续同步，不会结束跟随
+    let mut follower = StandbyFollower::new(&standby(addr, "secret"));
+    assert!(timeout(Duration::from_secs(3), follower.follow())
+        .await
+        .is_err());
+
+    let standby_dir = common::StateDir::new("standby");
+    let standby_fw = common::firewall(&common::config(&standby_dir, "rules = []")).await;
+    follower.take_over(&standby_fw).await;
+    let rules = standby_fw.get_active_rules().await.unwrap();
+    assert_eq!(rules.len(), 1);
+    assert_eq!(rules[0].ip, banned);
+    assert!(standby_fw.is_excluded(&excluded).await);
+    assert_eq!(standby_fw.temporary_excludes().await.len(), 1);
+
+    // 主节点可达时心跳立即恢复
+    timeout(Duration::from_secs(5), follower.wait_for_primary())
+        .await
+        .unwrap();
+}
@@ -0,0 +1,61 @@
+//! 控制套接字的事件订阅：首行确认订阅，之后每条新记录的事件一行，直到客户端断开
+
+mod common;
+
+use dashmap::DashMap;
+use safe_traffic_common::{
+    events::{Event, EventKind},
+    transport::{Request, Response},
+};
+use safe_traffic_daemon::{daemon::TrafficDaemon, rules::RuleEngine};
+use std::{net::IpAddr, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::UnixStream,
+    time::{sleep, timeout},
+};
+
+#[tokio::test]
+async fn test_subscribe_events() {
+    let dir = common::StateDir::new("subscribe");
+    let cfg = common::config(&dir, "rules = []");
+    let fw = common::firewall(&cfg).await;
+    let engine = Arc::new(RuleEngine::new(cfg.rules.clone(), Arc::new(DashMap::new())));
+    std::fs::create_dir_all(dir.path()).unwrap();
+    let socket = dir.path().join("traffic.sock");
+    let daemon = TrafficDaemon::new(Arc::clone(&fw), engine).with_socket_path(&socket);
+    tokio::spawn(async move { daemon.start().await });
+
+    let mut stream = timeout(Duration::from_secs(5), async {
+        loop {
+            match UnixStream::connect(&socket).await {
+                Ok(stream) => return stream,
+                Err(_) => sleep(Duration::from_millis(10)).await,
+            }
+        }
+    })
+    .await
+    .unwrap();
+    stream
+        .write_all(&serde_json::to_vec(&Request::SubscribeEvents).unwrap())
+        .await
+        .unwrap();
+    let mut lines = BufReader::new(stream).lines();
+    let header = lines.next_line().await.unwrap().unwrap();
+    assert!(matches!(
+        serde_json::from_str(&header).unwrap(),
+        Response::Success(_)
+    ));
+
+    // 订阅之后记录的事件逐条推送
+    let ip: IpAddr = "198.51.100.40".parse().unwrap();
+    fw.ban(ip, Some(60)).await.unwrap();
+    let line = timeout(Duration::from_secs(5), lines.next_line())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    let event: Event = serde_json::from_str(&line).unwrap();
+    assert_eq!(event.kind, EventKind::Ban);
+    assert_eq!(event.ip, Some(ip));
+}
@@ -0,0 +1,76 @@
+//! 扫描减速：访问大量不同端口的来源逐级收紧，停止扫描后移出窗口
+
+mod common;
+
+use safe_traffic_common::transport::FlowEntry;
+use safe_traffic_daemon::{flows::FlowTable, tarpit::Tarpit};
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+const CONFIG: &str = r#"
+    rules = []
+
+    [flows]
+
+    [tarpit]
+    min_ports = 10
+    window_secs = 30
+    stages = [20, 5]
+    ttl_secs = 300
+"#;
+
+fn flows(src: IpAddr, ports: std::ops::Range<u16>) -> Vec<FlowEntry> {
+    ports
+        .map(|dport| FlowEntry {
+            src,
+            dst: "203.0.113.1".parse().unwrap(),
+            proto: "tcp".to_string(),
+            dport: Some(dport),
+            bytes: 60,
+            packets: 1,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_scanner_escalates_through_stages() {
+    let dir = common::StateDir::new("tarpit");
+    let cfg = common::config(&dir, CONFIG);
+    let executor = common::executor().await;
+    // 流表只保留 1 条流，端口分布仍按全部流统计
+    let table = Arc::new(FlowTable::new(1));
+    let mut tarpit = Tarpit::new(cfg.tarpit.as_ref().unwrap(), executor, Arc::clone(&table));
+    let scanner: IpAddr = "198.51.100.66".parse().unwrap();
+    let client: IpAddr = "198.51.100.2".parse().unwrap();
+
+    let mut all = flows(scanner, 1..16);
+    all.extend(flows(client, 443..446));
+    table.replace(all);
+    assert_eq!(table.all().len(), 1);
+
+    let secs = Duration::from_secs;
+    assert_eq!(
+        tarpit.observe(table.source_dports(), secs(0)),
+        vec![(scanner, 0)]
+    );
+    // 未满一个窗口不升级
+    assert!(tarpit.observe(table.source_dports(), secs(10)).is_empty());
+    assert_eq!(
+        tarpit.observe(table.source_dports(), secs(30)),
+        vec![(scanner, 1)]
+    );
+    // 已是最后一级
+    assert!(tarpit.observe(table.source_dports(), secs(60)).is_empty());
+    assert_eq!(tarpit.slowed(), 1);
+
+    assert_eq!(
+        tarpit.element_command(scanner, 1),
+        "add element inet traffic_tarpit stage1_v4 { 198.51.100.66 timeout 300s }"
+    );
+    let setup = tarpit.setup_commands();
+    assert!(setup.contains(&"add rule inet traffic_tarpit slowdown ip saddr @stage0_v4 meter tarpit0_v4 { ip saddr limit rate over 20/second } counter drop".to_string()));
+
+    // 停止扫描后端口窗口清空，集合到期后可重新从第一级开始
+    table.replace(Vec::new());
+    assert!(tarpit.observe(table.source_dports(), secs(400)).is_empty());
+    assert_eq!(tarpit.slowed(), 0);
+}
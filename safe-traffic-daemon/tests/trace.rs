@@ -0,0 +1,102 @@
+//! 评估跟踪：对指定的 IP 逐拍以 debug 级别输出各规则的窗口、阈值比较、白名单与已有规则
+
+mod common;
+
+use dashmap::DashMap;
+use safe_traffic_common::{clock::Clock, config::LogLevel, utils::TrafficStats};
+use safe_traffic_daemon::{logger, logger::LogSubscription, rules::RuleEngine};
+use std::{net::IpAddr, sync::Arc, time::Duration};
+use tokio::time::timeout;
+
+const CONFIG: &str = r#"
+    [[rules]]
+    name = "flood"
+    window_secs = 2
+    threshold_bps = 1000
+    action = { Ban = { seconds = 60 } }
+
+    [[rules]]
+    name = "slow"
+    window_secs = 2
+    threshold_bps = 1_000_000
+    check_interval = 3600
+    action = { Ban = { seconds = 60 } }
+"#;
+
+/// 取出目前为止的跟踪日志
+async fn traces(subscription: &mut LogSubscription) -> Vec<String> {
+    let mut lines = Vec::new();
+    while let Ok(Ok(line)) = timeout(Duration::from_millis(50), subscription.recv()).await {
+        if line.message.starts_with("trace ") {
+            lines.push(line.message);
+        }
+    }
+    lines
+}
+
+#[tokio::test]
+async fn test_trace_one_ip() {
+    logger::init();
+    let mut subscription = logger::subscribe(LogLevel::Debug);
+    let dir = common::StateDir::new("trace");
+    let cfg = common::config(&dir, CONFIG);
+    let clock = common::clock();
+    let fw = common::clocked_firewall(&cfg, &clock).await;
+    let stats = Arc::new(DashMap::new());
+    let engine = RuleEngine::new(cfg.rules.clone(), stats.clone()).with_clock(clock.clone());
+    let ip: IpAddr = "198.51.100.30".parse().unwrap();
+    let other: IpAddr = "198.51.100.31".parse().unwrap();
+    let sample = TrafficStats {
+        rx_delta: 5000,
+        ..Default::default()
+    };
+    for host in [ip, other] {
+        engine.windows().record(host, &sample, clock.monotonic());
+    }
+    for _ in 0..3 {
+        clock.advance(Duration::from_secs(1));
+        for host in [ip, other] {
+            engine.windows().record(host, &sample, clock.monotonic());
+            stats.insert(host, sample.clone());
+        }
+    }
+
+    assert_eq!(engine.trace(Some(ip)), None);
+    engine.check_and_apply(Arc::clone(&fw)).await.unwrap();
+    let lines = traces(&mut subscription).await;
+    assert_eq!(lines.len(), 4, "{:?}", lines);
+    assert_eq!(
+        lines[0],
+        "trace 198.51.100.30: sampled 5000 B/s (0 B/s new flows), warm-up in progress"
+    );
+    assert_eq!(
+        lines[1],
+        "trace 198.51.100.30: rule flood: bps over 2s sum 10000 average 5000 > threshold 1000; no active rule; no previous offense"
+    );
+    assert!(
+        lines[2].starts_with("trace 198.51.100.30: rule flood applied action ban: created ban_")
+    );
+    assert!(lines[3].contains("rule slow: bps over 2s sum 10000 average 5000 <= threshold 1000000"));
+
+    // 下一拍：已有规则与未到评估时间的规则
+    engine.check_and_apply(Arc::clone(&fw)).await.unwrap();
+    let lines = traces(&mut subscription).await;
+    assert!(lines[1].contains("> threshold 1000; active rule ban_"));
+    assert!(lines[1].contains("last offense 0s ago (repeat_window"));
+    assert_eq!(
+        lines.last().unwrap(),
+        "trace 198.51.100.30: rule slow skipped: check_interval not elapsed"
+    );
+
+    // 没有采样的 IP，停止跟踪后不再输出
+    let idle: IpAddr = "198.51.100.32".parse().unwrap();
+    assert_eq!(engine.trace(Some(idle)), Some(ip));
+    engine.check_and_apply(Arc::clone(&fw)).await.unwrap();
+    assert_eq!(
+        traces(&mut subscription).await,
+        ["trace 198.51.100.32: no traffic sampled, no rule evaluated"]
+    );
+    engine.trace(None);
+    engine.check_and_apply(Arc::clone(&fw)).await.unwrap();
+    assert!(traces(&mut subscription).await.is_empty());
+}
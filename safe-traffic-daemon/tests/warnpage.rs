@@ -0,0 +1,56 @@
+//! 提示页：Warn 规则只打标记不丢弃，受限来源写入 geo 文件，规则解除后移出
+
+mod common;
+
+use safe_traffic_daemon::warnpage::WarnPage;
+use std::net::IpAddr;
+
+#[tokio::test]
+async fn test_warned_and_limited_sources_are_exported() {
+    let dir = common::StateDir::new("warnpage");
+    let path = dir.path().join("limited.geo");
+    let cfg = common::config(
+        &dir,
+        &format!(
+            r#"
+            rules = []
+
+            [warn_page]
+            path = "{}"
+            mark = 0x429
+            "#,
+            path.display()
+        ),
+    );
+    let fw = common::firewall(&cfg).await;
+    let warned: IpAddr = "198.51.100.7".parse().unwrap();
+    let limited: IpAddr = "2001:db8::8".parse().unwrap();
+    assert_eq!(
+        fw.warn_rule_command(warned),
+        "add rule inet traffic_filter traffic_input ip saddr 198.51.100.7 counter meta mark set 0x429"
+    );
+
+    let warn_id = fw.warn(warned, Some(600)).await.unwrap();
+    // 重复触发不新建规则
+    assert_eq!(fw.warn(warned, Some(600)).await.unwrap(), warn_id);
+    fw.limit(limited, 100, None, Some(600)).await.unwrap();
+    fw.ban("198.51.100.9".parse().unwrap(), Some(600))
+        .await
+        .unwrap();
+
+    let mut page = WarnPage::new(cfg.warn_page.as_ref().unwrap(), cfg.state_dir.as_deref());
+    let clock = fw.clock();
+    let rules = fw.get_active_rules().await.unwrap();
+    assert!(page.sync(&rules, clock.as_ref()).await.unwrap());
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "# generated by safe-traffic-daemon, do not edit\n198.51.100.7 warn;\n2001:db8::8 limit;\n"
+    );
+    assert!(!page.sync(&rules, clock.as_ref()).await.unwrap());
+
+    fw.unblock(&warn_id).await.unwrap();
+    let rules = fw.get_active_rules().await.unwrap();
+    assert!(page.sync(&rules, clock.as_ref()).await.unwrap());
+    let contents = std::fs::read_to_string(&path).unwrap();
+    assert!(!contents.contains("198.51.100.7"));
+}
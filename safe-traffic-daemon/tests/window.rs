@@ -0,0 +1,97 @@
+//! 规则引擎对外暴露的窗口数据与规则检查使用的数据一致
+
+mod common;
+
+use dashmap::DashMap;
+use safe_traffic_common::{clock::Clock, utils::TrafficStats};
+use safe_traffic_daemon::rules::RuleEngine;
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+const CONFIG: &str = r#"
+    [[rules]]
+    name = "heavy"
+    window_secs = 3
+    threshold_bps = 1_000_000
+    action = { Ban = { seconds = 10 } }
+"#;
+
+#[tokio::test]
+async fn test_window_snapshot_matches_samples() {
+    let dir = common::StateDir::new("window");
+    let cfg = common::config(&dir, CONFIG);
+    let clock = common::clock();
+    let fw = common::clocked_firewall(&cfg, &clock).await;
+    let stats = Arc::new(DashMap::new());
+    let engine = RuleEngine::new(cfg.rules.clone(), stats.clone()).with_clock(clock.clone());
+    let ip: IpAddr = "198.51.100.20".parse().unwrap();
+    assert!(engine.window(&ip).is_none());
+
+    for bps in [100, 200, 300, 400] {
+        clock.advance(Duration::from_secs(1));
+        let sample = TrafficStats {
+            rx_delta: bps,
+            rx_new_delta: bps / 10,
+            ..Default::default()
+        };
+        engine.windows().record(ip, &sample, clock.monotonic());
+        stats.insert(ip, sample);
+        engine.check_and_apply(Arc::clone(&fw)).await.unwrap();
+    }
+
+    // 窗口在首次观测时创建，首个采样不计入
+    let window = engine.window(&ip).unwrap();
+    assert_eq!(window.rx_delta, 400);
+    assert_eq!(window.slots, vec![200, 300, 400]);
+    assert_eq!(window.new_slots, vec![20, 30, 40]);
+    assert_eq!(window.established_slots, vec![180, 270, 360]);
+    let average = &window.averages[0];
+    assert_eq!(average.rule_name.as_deref(), Some("heavy"));
+    assert!(average.average_bps < average.threshold_bps);
+    assert!(average.warm);
+}
+
+#[tokio::test]
+async fn test_windows_follow_sample_timestamps_not_engine_ticks() {
+    let dir = common::StateDir::new("window");
+    let cfg = common::config(&dir, CONFIG);
+    let clock = common::clock();
+    let fw = common::clocked_firewall(&cfg, &clock).await;
+    let stats = Arc::new(DashMap::new());
+    let engine = RuleEngine::new(cfg.rules.clone(), stats.clone())
+        .with_clock(clock.clone())
+        .with_warmup(0);
+    let ip: IpAddr = "198.51.100.21".parse().unwrap();
+    let sample = |bps| TrafficStats {
+        rx_delta: bps,
+        ..Default::default()
+    };
+
+    // 引擎的检查停顿期间，每秒的采样仍各占一个槽
+    engine.windows().record(ip, &sample(0), clock.monotonic());
+    for bps in [2_000_000, 3_000_000] {
+        clock.advance(Duration::from_millis(1_100));
+        engine.windows().record(ip, &sample(bps), clock.monotonic());
+    }
+    assert_eq!(
+        engine.window(&ip).unwrap().slots,
+        vec![2_000_000, 3_000_000]
+    );
+
+    // 间隔 2 秒的采样覆盖其间的每一秒，不足 1 秒的部分计入下一次，不会累积漂移
+    clock.advance(Duration::from_millis(1_800));
+    engine
+        .windows()
+        .record(ip, &sample(4_000_000), clock.monotonic());
+    let window = engine.window(&ip).unwrap();
+    assert_eq!(window.samples, 4);
+    assert_eq!(
+        window.slots,
+        vec![2_000_000, 3_000_000, 4_000_000, 4_000_000]
+    );
+
+    stats.insert(ip, sample(4_000_000));
+    engine.check_and_apply(Arc::clone(&fw)).await.unwrap();
+    let rules = fw.get_active_rules().await.unwrap();
+    assert_eq!(rules.len(), 1);
+    assert_eq!(rules[0].ip, ip);
+}